@@ -0,0 +1,387 @@
+/// Air quality level derived from the CO2 concentration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AirQuality {
+    /// Fresh outdoor-like air.
+    Excellent,
+
+    /// Well ventilated.
+    Good,
+
+    /// Ventilation recommended soon.
+    Fair,
+
+    /// Ventilation needed.
+    Poor,
+
+    /// Ventilate immediately.
+    Bad,
+}
+
+/// Implementation of `AirQuality`.
+impl AirQuality {
+    /// All levels from best to worst.
+    pub const ALL: [AirQuality; 5] = [
+        AirQuality::Excellent,
+        AirQuality::Good,
+        AirQuality::Fair,
+        AirQuality::Poor,
+        AirQuality::Bad,
+    ];
+
+    /// Get the level from its index.
+    ///
+    /// # Arguments
+    /// * `index` - The index, `0` (excellent) to `4` (bad).
+    ///
+    /// # Returns
+    /// * `Option<AirQuality>` - The level, or `None` if the index is out of range.
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    /// Get the index of the level, as published over BLE.
+    ///
+    /// # Returns
+    /// * `u8` - The index, `0` (excellent) to `4` (bad).
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the human readable label.
+    ///
+    /// # Returns
+    /// * `&'static str` - The label.
+    pub fn label(self) -> &'static str {
+        match self {
+            AirQuality::Excellent => "Excellent",
+            AirQuality::Good => "Good",
+            AirQuality::Fair => "Fair",
+            AirQuality::Poor => "Poor",
+            AirQuality::Bad => "Bad",
+        }
+    }
+
+    /// Get the 8x8 status icon, in the same column-major format as the font.
+    ///
+    /// # Returns
+    /// * `[u8; 8]` - The icon columns.
+    pub fn icon(self) -> [u8; 8] {
+        match self {
+            // Grinning face
+            AirQuality::Excellent => [0x3c, 0x42, 0xa5, 0x91, 0x91, 0xa5, 0x42, 0x3c],
+            // Smiling face
+            AirQuality::Good => [0x3c, 0x42, 0x95, 0xa1, 0xa1, 0x95, 0x42, 0x3c],
+            // Neutral face
+            AirQuality::Fair => [0x3c, 0x42, 0x95, 0x91, 0x91, 0x95, 0x42, 0x3c],
+            // Frowning face
+            AirQuality::Poor => [0x3c, 0x42, 0xa5, 0x95, 0x95, 0xa5, 0x42, 0x3c],
+            // Warning sign
+            AirQuality::Bad => [0xc0, 0xb0, 0x8c, 0xbb, 0xbb, 0x8c, 0xb0, 0xc0],
+        }
+    }
+}
+
+/// Invalid thresholds error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidThresholds;
+
+/// Implementation of the `Display` trait for `InvalidThresholds`.
+impl core::fmt::Display for InvalidThresholds {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Air quality thresholds must be strictly increasing")
+    }
+}
+
+/// Implementation of the `Error` trait for `InvalidThresholds`.
+impl std::error::Error for InvalidThresholds {}
+
+/// Upper CO2 bounds (exclusive, in ppm) of each level except `Bad`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    /// Below this is `Excellent`.
+    pub excellent: u16,
+
+    /// Below this is `Good`.
+    pub good: u16,
+
+    /// Below this is `Fair`.
+    pub fair: u16,
+
+    /// Below this is `Poor`; at or above is `Bad`.
+    pub poor: u16,
+}
+
+/// Implementation of the `Default` trait for `Thresholds`.
+impl Default for Thresholds {
+    /// Create the default thresholds: 600, 800, 1000 and 1400 ppm.
+    ///
+    /// # Returns
+    /// * `Thresholds` - The thresholds.
+    fn default() -> Self {
+        Self {
+            excellent: 600,
+            good: 800,
+            fair: 1000,
+            poor: 1400,
+        }
+    }
+}
+
+/// Implementation of `Thresholds`.
+impl Thresholds {
+    /// Create thresholds, checking that they are strictly increasing.
+    ///
+    /// # Arguments
+    /// * `excellent` - Upper bound of `Excellent`.
+    /// * `good` - Upper bound of `Good`.
+    /// * `fair` - Upper bound of `Fair`.
+    /// * `poor` - Upper bound of `Poor`.
+    ///
+    /// # Returns
+    /// * `Result<Thresholds, InvalidThresholds>` - The thresholds or an error.
+    pub fn new(excellent: u16, good: u16, fair: u16, poor: u16) -> Result<Self, InvalidThresholds> {
+        if excellent < good && good < fair && fair < poor {
+            Ok(Self {
+                excellent,
+                good,
+                fair,
+                poor,
+            })
+        } else {
+            Err(InvalidThresholds)
+        }
+    }
+
+    /// Classify a CO2 concentration without hysteresis.
+    ///
+    /// # Arguments
+    /// * `co2_ppm` - The CO2 concentration.
+    ///
+    /// # Returns
+    /// * `AirQuality` - The level.
+    pub fn classify(&self, co2_ppm: u16) -> AirQuality {
+        if co2_ppm < self.excellent {
+            AirQuality::Excellent
+        } else if co2_ppm < self.good {
+            AirQuality::Good
+        } else if co2_ppm < self.fair {
+            AirQuality::Fair
+        } else if co2_ppm < self.poor {
+            AirQuality::Poor
+        } else {
+            AirQuality::Bad
+        }
+    }
+}
+
+/// Stateful classifier that applies hysteresis to avoid flapping between levels.
+///
+/// The level worsens as soon as a threshold is reached, but only improves once
+/// the concentration is more than `hysteresis` ppm below the threshold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classifier {
+    /// The thresholds.
+    thresholds: Thresholds,
+
+    /// Hysteresis in ppm.
+    hysteresis: u16,
+
+    /// The current level, `None` before the first sample.
+    level: Option<AirQuality>,
+}
+
+/// Implementation of the `Default` trait for `Classifier`.
+impl Default for Classifier {
+    /// Create a classifier with the default thresholds and 50 ppm of hysteresis.
+    ///
+    /// # Returns
+    /// * `Classifier` - The classifier.
+    fn default() -> Self {
+        Self::new(Thresholds::default(), 50)
+    }
+}
+
+/// Implementation of `Classifier`.
+impl Classifier {
+    /// Create a classifier.
+    ///
+    /// # Arguments
+    /// * `thresholds` - The thresholds.
+    /// * `hysteresis` - The hysteresis in ppm.
+    ///
+    /// # Returns
+    /// * `Classifier` - The classifier.
+    pub fn new(thresholds: Thresholds, hysteresis: u16) -> Self {
+        Self {
+            thresholds,
+            hysteresis,
+            level: None,
+        }
+    }
+
+    /// Get the thresholds.
+    ///
+    /// # Returns
+    /// * `Thresholds` - The thresholds.
+    pub fn thresholds(&self) -> Thresholds {
+        self.thresholds
+    }
+
+    /// Get the current level.
+    ///
+    /// # Returns
+    /// * `Option<AirQuality>` - The level, or `None` before the first sample.
+    pub fn level(&self) -> Option<AirQuality> {
+        self.level
+    }
+
+    /// Feed a CO2 sample and get the resulting level.
+    ///
+    /// # Arguments
+    /// * `co2_ppm` - The CO2 concentration.
+    ///
+    /// # Returns
+    /// * `AirQuality` - The level after applying hysteresis.
+    pub fn classify(&mut self, co2_ppm: u16) -> AirQuality {
+        let raw = self.thresholds.classify(co2_ppm);
+
+        let level = match self.level {
+            Some(current) if raw < current => {
+                // Improve only as far as the hysteresis band allows.
+                let damped = self
+                    .thresholds
+                    .classify(co2_ppm.saturating_add(self.hysteresis));
+                damped.min(current)
+            }
+            _ => raw,
+        };
+
+        self.level = Some(level);
+        level
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_thresholds() {
+        let thresholds = Thresholds::default();
+        assert_eq!(thresholds.classify(400), AirQuality::Excellent);
+        assert_eq!(thresholds.classify(599), AirQuality::Excellent);
+        assert_eq!(thresholds.classify(600), AirQuality::Good);
+        assert_eq!(thresholds.classify(799), AirQuality::Good);
+        assert_eq!(thresholds.classify(800), AirQuality::Fair);
+        assert_eq!(thresholds.classify(1000), AirQuality::Poor);
+        assert_eq!(thresholds.classify(1399), AirQuality::Poor);
+        assert_eq!(thresholds.classify(1400), AirQuality::Bad);
+        assert_eq!(thresholds.classify(u16::MAX), AirQuality::Bad);
+    }
+
+    #[test]
+    fn thresholds_must_increase() {
+        assert!(Thresholds::new(500, 700, 900, 1200).is_ok());
+        assert_eq!(Thresholds::new(500, 500, 900, 1200), Err(InvalidThresholds));
+        assert_eq!(Thresholds::new(900, 700, 800, 1200), Err(InvalidThresholds));
+    }
+
+    #[test]
+    fn custom_thresholds() {
+        let thresholds = Thresholds::new(500, 700, 900, 1200).unwrap();
+        assert_eq!(thresholds.classify(550), AirQuality::Good);
+        assert_eq!(thresholds.classify(1200), AirQuality::Bad);
+    }
+
+    #[test]
+    fn first_sample_is_not_damped() {
+        let mut classifier = Classifier::default();
+        assert_eq!(classifier.level(), None);
+        assert_eq!(classifier.classify(590), AirQuality::Excellent);
+        assert_eq!(classifier.level(), Some(AirQuality::Excellent));
+    }
+
+    #[test]
+    fn worsening_is_immediate() {
+        let mut classifier = Classifier::default();
+        classifier.classify(500);
+        assert_eq!(classifier.classify(600), AirQuality::Good);
+        assert_eq!(classifier.classify(1500), AirQuality::Bad);
+    }
+
+    #[test]
+    fn improving_requires_hysteresis() {
+        let mut classifier = Classifier::default();
+        assert_eq!(classifier.classify(810), AirQuality::Fair);
+
+        // Just below the threshold: stay.
+        assert_eq!(classifier.classify(790), AirQuality::Fair);
+        assert_eq!(classifier.classify(750), AirQuality::Fair);
+
+        // Clear of the hysteresis band: improve.
+        assert_eq!(classifier.classify(749), AirQuality::Good);
+        assert_eq!(classifier.classify(790), AirQuality::Good);
+    }
+
+    #[test]
+    fn large_drop_skips_levels() {
+        let mut classifier = Classifier::default();
+        classifier.classify(2000);
+        assert_eq!(classifier.classify(400), AirQuality::Excellent);
+    }
+
+    #[test]
+    fn drop_within_band_of_lower_threshold() {
+        let mut classifier = Classifier::default();
+        classifier.classify(2000);
+        // 780 is Good, but within 50 ppm of the Fair threshold.
+        assert_eq!(classifier.classify(780), AirQuality::Fair);
+    }
+
+    #[test]
+    fn no_flapping_around_threshold() {
+        let mut classifier = Classifier::default();
+        let levels: Vec<_> = [995, 1005, 995, 1005, 990]
+            .into_iter()
+            .map(|co2| classifier.classify(co2))
+            .collect();
+        assert_eq!(
+            levels,
+            vec![
+                AirQuality::Fair,
+                AirQuality::Poor,
+                AirQuality::Poor,
+                AirQuality::Poor,
+                AirQuality::Poor
+            ]
+        );
+    }
+
+    #[test]
+    fn index_and_labels() {
+        for level in AirQuality::ALL {
+            assert_eq!(AirQuality::from_index(level.index()), Some(level));
+            assert!(!level.label().is_empty());
+        }
+        assert_eq!(AirQuality::from_index(5), None);
+        assert_eq!(AirQuality::Excellent.index(), 0);
+        assert_eq!(AirQuality::Bad.index(), 4);
+    }
+
+    #[test]
+    fn icons_are_distinct() {
+        for (i, a) in AirQuality::ALL.iter().enumerate() {
+            for b in &AirQuality::ALL[i + 1..] {
+                assert_ne!(a.icon(), b.icon());
+            }
+        }
+    }
+}
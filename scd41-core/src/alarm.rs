@@ -0,0 +1,529 @@
+/// Alarm state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmState {
+    /// CO2 is below the rising threshold; the alarm can trigger.
+    Armed,
+
+    /// CO2 reached the rising threshold and the trigger delay is running.
+    Pending {
+        /// Time the threshold was first reached, in milliseconds.
+        since_ms: u64,
+    },
+
+    /// The alarm is sounding.
+    Active,
+
+    /// The alarm was silenced by the user.
+    Snoozed {
+        /// Time the snooze ends, in milliseconds.
+        until_ms: u64,
+    },
+
+    /// CO2 has recovered but the latched alarm has not been acknowledged yet.
+    Latched,
+}
+
+/// Implementation of `AlarmState`.
+impl AlarmState {
+    /// Whether outputs such as the buzzer should be on.
+    ///
+    /// # Returns
+    /// * `bool` - `true` while active or latched.
+    pub fn is_alarming(self) -> bool {
+        matches!(self, AlarmState::Active | AlarmState::Latched)
+    }
+
+    /// Get the state code, as published over BLE.
+    ///
+    /// # Returns
+    /// * `u8` - `0` armed, `1` pending, `2` active, `3` snoozed, `4` latched.
+    pub fn code(self) -> u8 {
+        match self {
+            AlarmState::Armed => 0,
+            AlarmState::Pending { .. } => 1,
+            AlarmState::Active => 2,
+            AlarmState::Snoozed { .. } => 3,
+            AlarmState::Latched => 4,
+        }
+    }
+
+    /// Get the text of the display banner for this state.
+    ///
+    /// # Returns
+    /// * `Option<&'static str>` - The banner, or `None` if nothing should be shown.
+    pub fn banner(self) -> Option<&'static str> {
+        match self {
+            AlarmState::Armed | AlarmState::Pending { .. } => None,
+            AlarmState::Active => Some("VENTILATE!"),
+            AlarmState::Snoozed { .. } => Some("Alarm snoozed"),
+            AlarmState::Latched => Some("Alarm - press ack"),
+        }
+    }
+}
+
+/// Command sent to the alarm by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmCommand {
+    /// Silence the alarm for the snooze duration.
+    Snooze,
+
+    /// Acknowledge a latched alarm.
+    Acknowledge,
+}
+
+/// Implementation of `AlarmCommand`.
+impl AlarmCommand {
+    /// Get the command from its code.
+    ///
+    /// # Arguments
+    /// * `code` - `1` snooze, `2` acknowledge.
+    ///
+    /// # Returns
+    /// * `Option<AlarmCommand>` - The command, or `None` for unknown codes.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(AlarmCommand::Snooze),
+            2 => Some(AlarmCommand::Acknowledge),
+            _ => None,
+        }
+    }
+}
+
+/// Invalid alarm configuration error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidAlarmConfig;
+
+/// Implementation of the `Display` trait for `InvalidAlarmConfig`.
+impl core::fmt::Display for InvalidAlarmConfig {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Alarm falling threshold must not exceed the rising threshold"
+        )
+    }
+}
+
+/// Implementation of the `Error` trait for `InvalidAlarmConfig`.
+impl std::error::Error for InvalidAlarmConfig {}
+
+/// Alarm configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlarmConfig {
+    /// CO2 concentration (ppm) at or above which the alarm triggers.
+    pub rising_threshold: u16,
+
+    /// CO2 concentration (ppm) below which the alarm clears and re-arms.
+    pub falling_threshold: u16,
+
+    /// How long CO2 must stay at or above the rising threshold before triggering.
+    pub trigger_delay_ms: u64,
+
+    /// How long a snooze silences the alarm.
+    pub snooze_ms: u64,
+
+    /// Whether the alarm stays on after CO2 recovers until acknowledged.
+    pub latched: bool,
+}
+
+/// Implementation of the `Default` trait for `AlarmConfig`.
+impl Default for AlarmConfig {
+    /// Create the default configuration: trigger at 1400 ppm for one minute,
+    /// re-arm below 1000 ppm, snooze for 15 minutes, unlatched.
+    ///
+    /// # Returns
+    /// * `AlarmConfig` - The configuration.
+    fn default() -> Self {
+        Self {
+            rising_threshold: 1400,
+            falling_threshold: 1000,
+            trigger_delay_ms: 60_000,
+            snooze_ms: 15 * 60_000,
+            latched: false,
+        }
+    }
+}
+
+/// Implementation of `AlarmConfig`.
+impl AlarmConfig {
+    /// Check the configuration.
+    ///
+    /// # Returns
+    /// * `Result<(), InvalidAlarmConfig>` - An error if the thresholds are inverted.
+    pub fn validate(&self) -> Result<(), InvalidAlarmConfig> {
+        if self.falling_threshold <= self.rising_threshold {
+            Ok(())
+        } else {
+            Err(InvalidAlarmConfig)
+        }
+    }
+}
+
+/// Receiver of alarm state changes, e.g. a display banner, BLE or a buzzer.
+pub trait AlarmSink {
+    /// Called whenever the alarm state changes.
+    ///
+    /// # Arguments
+    /// * `state` - The new state.
+    fn on_alarm(&mut self, state: AlarmState);
+}
+
+/// CO2 alarm state machine.
+///
+/// The engine is driven by the caller with timestamps and samples; it has no
+/// clock or I/O of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlarmEngine {
+    /// The configuration.
+    config: AlarmConfig,
+
+    /// The current state.
+    state: AlarmState,
+}
+
+/// Implementation of `AlarmEngine`.
+impl AlarmEngine {
+    /// Create an armed alarm engine.
+    ///
+    /// # Arguments
+    /// * `config` - The configuration.
+    ///
+    /// # Returns
+    /// * `Result<AlarmEngine, InvalidAlarmConfig>` - The engine or an error.
+    pub fn new(config: AlarmConfig) -> Result<Self, InvalidAlarmConfig> {
+        config.validate()?;
+
+        Ok(Self {
+            config,
+            state: AlarmState::Armed,
+        })
+    }
+
+    /// Get the configuration.
+    ///
+    /// # Returns
+    /// * `AlarmConfig` - The configuration.
+    pub fn config(&self) -> AlarmConfig {
+        self.config
+    }
+
+    /// Replace the configuration and re-arm.
+    ///
+    /// # Arguments
+    /// * `config` - The new configuration.
+    ///
+    /// # Returns
+    /// * `Result<Option<AlarmState>, InvalidAlarmConfig>` - The new state if it changed,
+    ///   or an error if the configuration is invalid.
+    pub fn set_config(
+        &mut self,
+        config: AlarmConfig,
+    ) -> Result<Option<AlarmState>, InvalidAlarmConfig> {
+        config.validate()?;
+        self.config = config;
+
+        Ok(self.transition(AlarmState::Armed))
+    }
+
+    /// Get the current state.
+    ///
+    /// # Returns
+    /// * `AlarmState` - The state.
+    pub fn state(&self) -> AlarmState {
+        self.state
+    }
+
+    /// Feed a CO2 sample.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The current time in milliseconds.
+    /// * `co2_ppm` - The CO2 concentration.
+    ///
+    /// # Returns
+    /// * `Option<AlarmState>` - The new state if it changed.
+    pub fn update(&mut self, now_ms: u64, co2_ppm: u16) -> Option<AlarmState> {
+        let high = co2_ppm >= self.config.rising_threshold;
+        let recovered = co2_ppm < self.config.falling_threshold;
+
+        let next = match self.state {
+            AlarmState::Armed if high => {
+                if self.config.trigger_delay_ms == 0 {
+                    AlarmState::Active
+                } else {
+                    AlarmState::Pending { since_ms: now_ms }
+                }
+            }
+            AlarmState::Armed => AlarmState::Armed,
+            AlarmState::Pending { .. } if !high => AlarmState::Armed,
+            AlarmState::Pending { since_ms }
+                if now_ms.saturating_sub(since_ms) >= self.config.trigger_delay_ms =>
+            {
+                AlarmState::Active
+            }
+            AlarmState::Pending { since_ms } => AlarmState::Pending { since_ms },
+            AlarmState::Active if recovered => self.recovered_state(),
+            AlarmState::Active => AlarmState::Active,
+            AlarmState::Snoozed { .. } if recovered && !self.config.latched => AlarmState::Armed,
+            AlarmState::Snoozed { until_ms } if now_ms >= until_ms => {
+                if recovered {
+                    AlarmState::Latched
+                } else {
+                    AlarmState::Active
+                }
+            }
+            AlarmState::Snoozed { until_ms } => AlarmState::Snoozed { until_ms },
+            AlarmState::Latched => AlarmState::Latched,
+        };
+
+        self.transition(next)
+    }
+
+    /// Apply a user command.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The current time in milliseconds.
+    /// * `command` - The command.
+    ///
+    /// # Returns
+    /// * `Option<AlarmState>` - The new state if it changed.
+    pub fn command(&mut self, now_ms: u64, command: AlarmCommand) -> Option<AlarmState> {
+        let next = match (command, self.state) {
+            (AlarmCommand::Snooze, AlarmState::Active | AlarmState::Latched) => {
+                AlarmState::Snoozed {
+                    until_ms: now_ms.saturating_add(self.config.snooze_ms),
+                }
+            }
+            (AlarmCommand::Acknowledge, AlarmState::Latched) => AlarmState::Armed,
+            (_, state) => state,
+        };
+
+        self.transition(next)
+    }
+
+    /// State to enter when CO2 recovers while the alarm is active.
+    ///
+    /// # Returns
+    /// * `AlarmState` - `Latched` in latched mode, otherwise `Armed`.
+    fn recovered_state(&self) -> AlarmState {
+        if self.config.latched {
+            AlarmState::Latched
+        } else {
+            AlarmState::Armed
+        }
+    }
+
+    /// Move to a new state.
+    ///
+    /// # Arguments
+    /// * `next` - The new state.
+    ///
+    /// # Returns
+    /// * `Option<AlarmState>` - The new state if it differs from the current one.
+    fn transition(&mut self, next: AlarmState) -> Option<AlarmState> {
+        if next == self.state {
+            return None;
+        }
+
+        self.state = next;
+        Some(next)
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(latched: bool) -> AlarmConfig {
+        AlarmConfig {
+            rising_threshold: 1400,
+            falling_threshold: 1000,
+            trigger_delay_ms: 10_000,
+            snooze_ms: 60_000,
+            latched,
+        }
+    }
+
+    fn active_engine(latched: bool) -> AlarmEngine {
+        let mut engine = AlarmEngine::new(config(latched)).unwrap();
+        engine.update(0, 1500);
+        engine.update(10_000, 1500);
+        assert_eq!(engine.state(), AlarmState::Active);
+        engine
+    }
+
+    #[test]
+    fn rejects_inverted_thresholds() {
+        let config = AlarmConfig {
+            falling_threshold: 1500,
+            ..config(false)
+        };
+        assert_eq!(AlarmEngine::new(config), Err(InvalidAlarmConfig));
+        assert!(AlarmEngine::new(AlarmConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn triggers_after_delay() {
+        let mut engine = AlarmEngine::new(config(false)).unwrap();
+        assert_eq!(engine.update(0, 1300), None);
+        assert_eq!(
+            engine.update(1_000, 1400),
+            Some(AlarmState::Pending { since_ms: 1_000 })
+        );
+        assert_eq!(engine.update(10_999, 1450), None);
+        assert_eq!(engine.update(11_000, 1450), Some(AlarmState::Active));
+    }
+
+    #[test]
+    fn dip_below_threshold_restarts_delay() {
+        let mut engine = AlarmEngine::new(config(false)).unwrap();
+        engine.update(0, 1500);
+        assert_eq!(engine.update(5_000, 1399), Some(AlarmState::Armed));
+        engine.update(6_000, 1500);
+        assert_eq!(engine.update(12_000, 1500), None);
+        assert_eq!(engine.update(16_000, 1500), Some(AlarmState::Active));
+    }
+
+    #[test]
+    fn zero_delay_triggers_immediately() {
+        let mut engine = AlarmEngine::new(AlarmConfig {
+            trigger_delay_ms: 0,
+            ..config(false)
+        })
+        .unwrap();
+        assert_eq!(engine.update(0, 1400), Some(AlarmState::Active));
+    }
+
+    #[test]
+    fn rearm_requires_falling_threshold() {
+        let mut engine = active_engine(false);
+        assert_eq!(engine.update(20_000, 1200), None);
+        assert_eq!(engine.update(30_000, 1000), None);
+        assert_eq!(engine.update(40_000, 999), Some(AlarmState::Armed));
+    }
+
+    #[test]
+    fn latched_alarm_waits_for_acknowledge() {
+        let mut engine = active_engine(true);
+        assert_eq!(engine.update(20_000, 900), Some(AlarmState::Latched));
+        assert!(engine.state().is_alarming());
+        assert_eq!(engine.update(30_000, 500), None);
+
+        assert_eq!(
+            engine.command(31_000, AlarmCommand::Acknowledge),
+            Some(AlarmState::Armed)
+        );
+    }
+
+    #[test]
+    fn acknowledge_does_not_clear_active_alarm() {
+        let mut engine = active_engine(true);
+        assert_eq!(engine.command(20_000, AlarmCommand::Acknowledge), None);
+        assert_eq!(engine.state(), AlarmState::Active);
+    }
+
+    #[test]
+    fn snooze_silences_then_resumes() {
+        let mut engine = active_engine(false);
+        assert_eq!(
+            engine.command(20_000, AlarmCommand::Snooze),
+            Some(AlarmState::Snoozed { until_ms: 80_000 })
+        );
+        assert!(!engine.state().is_alarming());
+        assert_eq!(engine.update(79_999, 1500), None);
+        assert_eq!(engine.update(80_000, 1500), Some(AlarmState::Active));
+    }
+
+    #[test]
+    fn snooze_ends_early_when_recovered_unlatched() {
+        let mut engine = active_engine(false);
+        engine.command(20_000, AlarmCommand::Snooze);
+        assert_eq!(engine.update(30_000, 800), Some(AlarmState::Armed));
+    }
+
+    #[test]
+    fn snoozed_latched_alarm_latches_on_expiry() {
+        let mut engine = active_engine(true);
+        engine.command(20_000, AlarmCommand::Snooze);
+        assert_eq!(engine.update(30_000, 800), None);
+        assert_eq!(engine.update(80_000, 800), Some(AlarmState::Latched));
+    }
+
+    #[test]
+    fn snooze_ignored_when_not_alarming() {
+        let mut engine = AlarmEngine::new(config(false)).unwrap();
+        assert_eq!(engine.command(0, AlarmCommand::Snooze), None);
+        assert_eq!(engine.state(), AlarmState::Armed);
+    }
+
+    #[test]
+    fn set_config_rearms() {
+        let mut engine = active_engine(false);
+        assert_eq!(
+            engine.set_config(AlarmConfig::default()),
+            Ok(Some(AlarmState::Armed))
+        );
+        assert_eq!(
+            engine.set_config(AlarmConfig {
+                falling_threshold: 2000,
+                ..AlarmConfig::default()
+            }),
+            Err(InvalidAlarmConfig)
+        );
+        assert_eq!(engine.config(), AlarmConfig::default());
+    }
+
+    #[test]
+    fn state_codes_and_banners() {
+        assert_eq!(AlarmState::Armed.code(), 0);
+        assert_eq!(AlarmState::Pending { since_ms: 0 }.code(), 1);
+        assert_eq!(AlarmState::Active.code(), 2);
+        assert_eq!(AlarmState::Snoozed { until_ms: 0 }.code(), 3);
+        assert_eq!(AlarmState::Latched.code(), 4);
+
+        assert_eq!(AlarmState::Armed.banner(), None);
+        assert!(AlarmState::Active.banner().is_some());
+    }
+
+    #[test]
+    fn command_codes() {
+        assert_eq!(AlarmCommand::from_code(1), Some(AlarmCommand::Snooze));
+        assert_eq!(AlarmCommand::from_code(2), Some(AlarmCommand::Acknowledge));
+        assert_eq!(AlarmCommand::from_code(0), None);
+    }
+
+    #[test]
+    fn sinks_receive_transitions() {
+        struct Recorder(Vec<AlarmState>);
+
+        impl AlarmSink for Recorder {
+            fn on_alarm(&mut self, state: AlarmState) {
+                self.0.push(state);
+            }
+        }
+
+        let mut engine = AlarmEngine::new(config(false)).unwrap();
+        let mut recorder = Recorder(Vec::new());
+
+        for (now, co2) in [(0, 1500), (5_000, 1500), (10_000, 1500), (20_000, 900)] {
+            if let Some(state) = engine.update(now, co2) {
+                recorder.on_alarm(state);
+            }
+        }
+
+        assert_eq!(
+            recorder.0,
+            vec![
+                AlarmState::Pending { since_ms: 0 },
+                AlarmState::Active,
+                AlarmState::Armed
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Battery Service values.
+
+/// Battery Service UUID.
+pub const BATTERY_SERVICE_UUID: u16 = 0x180f;
+
+/// Battery Level characteristic UUID.
+pub const BATTERY_LEVEL_UUID: u16 = 0x2a19;
+
+/// Open-circuit voltage of a single-cell LiPo battery in millivolts and the
+/// matching charge in percent, highest first.
+const DISCHARGE_CURVE: [(u16, u8); 11] = [
+    (4200, 100),
+    (4100, 90),
+    (4000, 80),
+    (3920, 70),
+    (3860, 60),
+    (3810, 50),
+    (3770, 40),
+    (3740, 30),
+    (3700, 20),
+    (3600, 10),
+    (3300, 0),
+];
+
+/// Estimate the battery level from its voltage.
+///
+/// # Arguments
+/// * `millivolts` - The battery voltage in millivolts.
+///
+/// # Returns
+/// * `u8` - The charge in percent, interpolated along a LiPo discharge curve.
+pub fn battery_level(millivolts: u16) -> u8 {
+    let (full_mv, _) = DISCHARGE_CURVE[0];
+    if millivolts >= full_mv {
+        return 100;
+    }
+
+    DISCHARGE_CURVE
+        .windows(2)
+        .find_map(|points| {
+            let [(high_mv, high), (low_mv, low)] = [points[0], points[1]];
+            (millivolts >= low_mv).then(|| {
+                let span = u32::from(high - low);
+                let offset = u32::from(millivolts - low_mv);
+                low + (offset * span / u32::from(high_mv - low_mv)) as u8
+            })
+        })
+        .unwrap_or(0)
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_points() {
+        for (millivolts, level) in DISCHARGE_CURVE {
+            assert_eq!(battery_level(millivolts), level);
+        }
+    }
+
+    #[test]
+    fn interpolation_and_limits() {
+        assert_eq!(battery_level(4500), 100);
+        assert_eq!(battery_level(4150), 95);
+        assert_eq!(battery_level(3650), 15);
+        assert_eq!(battery_level(3450), 5);
+        assert_eq!(battery_level(3000), 0);
+        assert_eq!(battery_level(0), 0);
+    }
+}
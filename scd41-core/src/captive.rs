@@ -0,0 +1,228 @@
+//! Wi-Fi setup portal.
+//!
+//! While the device has no network to join, it opens an access point. Phones and
+//! laptops that join it probe for Internet access; every DNS query is answered
+//! with the device's own address and every unknown page is redirected to the
+//! setup form, so the operating system shows the form as a sign-in page.
+
+use crate::wifi::{Credentials, CredentialsError};
+
+/// Setup form, posted back to `/`.
+pub const SETUP_PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width,initial-scale=1">
+<title>CO2 monitor Wi-Fi setup</title>
+<style>body{font-family:sans-serif;max-width:22em;margin:2em auto;padding:0 1em}input{width:100%;margin:.3em 0 1em;padding:.4em;box-sizing:border-box}</style>
+</head><body><h1>Wi-Fi setup</h1>
+<form method="post" action="/">
+<label>Network name<input name="ssid" maxlength="32" required></label>
+<label>Password<input name="password" type="password" maxlength="64"></label>
+<input type="submit" value="Connect">
+</form></body></html>"#;
+
+/// Page shown once the credentials are saved.
+pub const SAVED_PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width,initial-scale=1">
+<title>CO2 monitor Wi-Fi setup</title></head>
+<body><h1>Saved</h1><p>The monitor is joining the network. Its address is shown on the status screen.</p></body></html>"#;
+
+/// DNS header length.
+const DNS_HEADER_LEN: usize = 12;
+
+/// DNS record type A.
+const DNS_TYPE_A: u16 = 1;
+
+/// DNS class IN.
+const DNS_CLASS_IN: u16 = 1;
+
+/// Time to live of the DNS answers in seconds, short so that clients forget the
+/// portal address once the device is set up.
+const DNS_TTL_S: u32 = 10;
+
+/// Parse the posted setup form.
+///
+/// # Arguments
+/// * `body` - The `application/x-www-form-urlencoded` body.
+///
+/// # Returns
+/// * `Result<Credentials, CredentialsError>` - The credentials or an error.
+pub fn parse_form(body: &[u8]) -> Result<Credentials, CredentialsError> {
+    let mut ssid = None;
+    let mut password = String::new();
+
+    for pair in body.split(|b| *b == b'&') {
+        let mut parts = pair.splitn(2, |b| *b == b'=');
+        let (Some(name), Some(value)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let value = url_decode(value).ok_or(CredentialsError::InvalidUtf8)?;
+        match name {
+            b"ssid" => ssid = Some(value),
+            b"password" => password = value,
+            _ => (),
+        }
+    }
+
+    Credentials::new(ssid.as_deref().unwrap_or_default(), &password)
+}
+
+/// Decode a form field, with `+` for spaces and `%XX` escapes.
+///
+/// # Arguments
+/// * `value` - The encoded field.
+///
+/// # Returns
+/// * `Option<String>` - The text, or `None` if an escape or the UTF-8 is invalid.
+fn url_decode(value: &[u8]) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        match byte {
+            b'+' => {
+                bytes.push(b' ');
+                rest = tail;
+            }
+            b'%' => {
+                let hex = core::str::from_utf8(tail.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Answer a DNS query with the portal address.
+///
+/// # Arguments
+/// * `query` - The query packet.
+/// * `ip` - The IPv4 address of the portal.
+///
+/// # Returns
+/// * `Option<Vec<u8>>` - The response, with an A record for A queries and no
+///   records for other types, or `None` if the packet is not a single-question
+///   standard query.
+pub fn dns_response(query: &[u8], ip: [u8; 4]) -> Option<Vec<u8>> {
+    let header = query.get(..DNS_HEADER_LEN)?;
+    let flags = u16::from_be_bytes([header[2], header[3]]);
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    // A query (QR clear) with the standard opcode and one question.
+    if flags & 0xf800 != 0 || questions != 1 {
+        return None;
+    }
+
+    // Skip the name labels to the type and class.
+    let mut end = DNS_HEADER_LEN;
+    loop {
+        let len = *query.get(end)? as usize;
+        end += 1;
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 != 0 {
+            return None;
+        }
+        end += len;
+    }
+    let fields = query.get(end..end + 4)?;
+    let qtype = u16::from_be_bytes([fields[0], fields[1]]);
+    let qclass = u16::from_be_bytes([fields[2], fields[3]]);
+    end += 4;
+
+    let answer = qtype == DNS_TYPE_A && qclass == DNS_CLASS_IN;
+    let mut response = Vec::with_capacity(end + 16);
+    response.extend_from_slice(&header[..2]);
+    // Response, authoritative, recursion desired copied, recursion available.
+    response.extend_from_slice(&(0x8480 | (flags & 0x0100)).to_be_bytes());
+    response.extend_from_slice(&[0, 1, 0, u8::from(answer), 0, 0, 0, 0]);
+    response.extend_from_slice(&query[DNS_HEADER_LEN..end]);
+
+    if answer {
+        // The name points back at the question.
+        response.extend_from_slice(&[0xc0, DNS_HEADER_LEN as u8]);
+        response.extend_from_slice(&DNS_TYPE_A.to_be_bytes());
+        response.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&DNS_TTL_S.to_be_bytes());
+        response.extend_from_slice(&4u16.to_be_bytes());
+        response.extend_from_slice(&ip);
+    }
+
+    Some(response)
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Query for `example.com`, type A, class IN, recursion desired.
+    const QUERY: &[u8] = b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+        \x07example\x03com\x00\x00\x01\x00\x01";
+
+    #[test]
+    fn form_parsing() {
+        assert_eq!(
+            parse_form(b"ssid=My+Office%21&password=p%40ss+word"),
+            Ok(Credentials::new("My Office!", "p@ss word").unwrap())
+        );
+        assert_eq!(
+            parse_form(b"password=&ssid=Caf%C3%A9&submit=Connect"),
+            Ok(Credentials::new("Café", "").unwrap())
+        );
+        assert_eq!(
+            parse_form(b"password=longenough"),
+            Err(CredentialsError::InvalidSsid)
+        );
+        assert_eq!(
+            parse_form(b"ssid=Lab&password=%4"),
+            Err(CredentialsError::InvalidUtf8)
+        );
+        assert_eq!(parse_form(b"ssid=%ff"), Err(CredentialsError::InvalidUtf8));
+    }
+
+    #[test]
+    fn dns_a_record() {
+        let response = dns_response(QUERY, [192, 168, 71, 1]).unwrap();
+
+        assert_eq!(
+            &response[..12],
+            b"\x12\x34\x85\x80\x00\x01\x00\x01\x00\x00\x00\x00"
+        );
+        assert_eq!(&response[12..QUERY.len()], &QUERY[12..]);
+        assert_eq!(
+            &response[QUERY.len()..],
+            b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x0a\x00\x04\xc0\xa8\x47\x01"
+        );
+    }
+
+    #[test]
+    fn dns_other_types_get_no_records() {
+        let mut query = QUERY.to_vec();
+        query[QUERY.len() - 3] = 0x1c; // AAAA
+
+        let response = dns_response(&query, [192, 168, 71, 1]).unwrap();
+        assert_eq!(&response[6..8], [0, 0]);
+        assert_eq!(response.len(), query.len());
+    }
+
+    #[test]
+    fn dns_rejects_malformed_queries() {
+        assert_eq!(dns_response(&QUERY[..20], [0; 4]), None);
+        assert_eq!(dns_response(&QUERY[..QUERY.len() - 1], [0; 4]), None);
+
+        // A response rather than a query.
+        let mut response = QUERY.to_vec();
+        response[2] |= 0x80;
+        assert_eq!(dns_response(&response, [0; 4]), None);
+
+        // Two questions.
+        let mut query = QUERY.to_vec();
+        query[5] = 2;
+        assert_eq!(dns_response(&query, [0; 4]), None);
+    }
+}
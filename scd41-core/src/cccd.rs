@@ -0,0 +1,332 @@
+//! Client Characteristic Configuration descriptor (CCCD) state.
+
+use std::collections::VecDeque;
+
+/// Client Characteristic Configuration value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cccd(u16);
+
+/// Invalid CCCD value error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidCccd {
+    /// The value is not two bytes long.
+    InvalidLength,
+
+    /// The value enables a mode the characteristic does not support.
+    Unsupported(Cccd),
+}
+
+/// Implementation of the `Display` trait for `InvalidCccd`.
+impl core::fmt::Display for InvalidCccd {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InvalidCccd::InvalidLength => write!(f, "CCCD value must be 2 bytes"),
+            InvalidCccd::Unsupported(cccd) => {
+                write!(f, "Unsupported CCCD value 0x{:04x}", cccd.bits())
+            }
+        }
+    }
+}
+
+/// Implementation of the `Error` trait for `InvalidCccd`.
+impl std::error::Error for InvalidCccd {}
+
+/// Implementation of `Cccd`.
+impl Cccd {
+    /// Notifications and indications disabled.
+    pub const NONE: Cccd = Cccd(0x0000);
+
+    /// Notifications enabled.
+    pub const NOTIFY: Cccd = Cccd(0x0001);
+
+    /// Indications enabled.
+    pub const INDICATE: Cccd = Cccd(0x0002);
+
+    /// Notifications and indications enabled.
+    pub const BOTH: Cccd = Cccd(0x0003);
+
+    /// Parse a CCCD write.
+    ///
+    /// # Arguments
+    /// * `value` - The written value, little-endian.
+    /// * `supported` - The modes supported by the characteristic.
+    ///
+    /// # Returns
+    /// * `Result<Cccd, InvalidCccd>` - The configuration or an error.
+    pub fn parse(value: &[u8], supported: Cccd) -> Result<Self, InvalidCccd> {
+        let [low, high] = value else {
+            return Err(InvalidCccd::InvalidLength);
+        };
+
+        let cccd = Cccd(u16::from_le_bytes([*low, *high]));
+        if cccd.0 & !supported.0 != 0 {
+            return Err(InvalidCccd::Unsupported(cccd));
+        }
+
+        Ok(cccd)
+    }
+
+    /// Get the raw bits.
+    ///
+    /// # Returns
+    /// * `u16` - The bits.
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Encode the value for a CCCD read.
+    ///
+    /// # Returns
+    /// * `[u8; 2]` - The little-endian value.
+    pub fn to_bytes(self) -> [u8; 2] {
+        self.0.to_le_bytes()
+    }
+
+    /// Whether notifications are enabled.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if enabled.
+    pub fn notify(self) -> bool {
+        self.0 & Self::NOTIFY.0 != 0
+    }
+
+    /// Whether indications are enabled.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if enabled.
+    pub fn indicate(self) -> bool {
+        self.0 & Self::INDICATE.0 != 0
+    }
+}
+
+/// CCCD values written by one connection, keyed by CCCD handle.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subscriptions {
+    /// The CCCD handle and value of every enabled subscription.
+    entries: Vec<(u16, Cccd)>,
+}
+
+/// Implementation of `Subscriptions`.
+impl Subscriptions {
+    /// Get the configuration of a CCCD.
+    ///
+    /// # Arguments
+    /// * `cccd_handle` - The CCCD handle.
+    ///
+    /// # Returns
+    /// * `Cccd` - The configuration, [`Cccd::NONE`] if never written.
+    pub fn get(&self, cccd_handle: u16) -> Cccd {
+        self.entries
+            .iter()
+            .find(|(handle, _)| *handle == cccd_handle)
+            .map_or(Cccd::NONE, |(_, cccd)| *cccd)
+    }
+
+    /// Set the configuration of a CCCD.
+    ///
+    /// # Arguments
+    /// * `cccd_handle` - The CCCD handle.
+    /// * `cccd` - The configuration.
+    pub fn set(&mut self, cccd_handle: u16, cccd: Cccd) {
+        self.entries.retain(|(handle, _)| *handle != cccd_handle);
+
+        if cccd != Cccd::NONE {
+            self.entries.push((cccd_handle, cccd));
+        }
+    }
+
+    /// Whether no CCCD is enabled.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if nothing is subscribed.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// An indication waiting to be sent or confirmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Indication {
+    /// The characteristic value handle.
+    pub handle: u16,
+
+    /// The value.
+    pub value: Vec<u8>,
+}
+
+/// Indications of one connection.
+///
+/// ATT allows a single outstanding indication per connection, so further
+/// indications wait until the client confirms. Only the latest value of each
+/// characteristic is kept while waiting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndicationQueue {
+    /// The handle of the indication awaiting confirmation.
+    in_flight: Option<u16>,
+
+    /// Indications waiting to be sent.
+    pending: VecDeque<Indication>,
+}
+
+/// Implementation of `IndicationQueue`.
+impl IndicationQueue {
+    /// Queue an indication.
+    ///
+    /// # Arguments
+    /// * `handle` - The characteristic value handle.
+    /// * `value` - The value.
+    ///
+    /// # Returns
+    /// * `Option<Indication>` - The indication to send now, or `None` if one is in flight.
+    pub fn push(&mut self, handle: u16, value: &[u8]) -> Option<Indication> {
+        if self.in_flight.is_none() {
+            self.in_flight = Some(handle);
+            return Some(Indication {
+                handle,
+                value: value.to_vec(),
+            });
+        }
+
+        match self.pending.iter_mut().find(|i| i.handle == handle) {
+            Some(pending) => pending.value = value.to_vec(),
+            None => self.pending.push_back(Indication {
+                handle,
+                value: value.to_vec(),
+            }),
+        }
+
+        None
+    }
+
+    /// Complete the indication in flight, confirmed or failed.
+    ///
+    /// # Arguments
+    /// * `handle` - The handle of the completed indication.
+    ///
+    /// # Returns
+    /// * `Option<Indication>` - The next indication to send, if any.
+    pub fn complete(&mut self, handle: u16) -> Option<Indication> {
+        if self.in_flight != Some(handle) {
+            return None;
+        }
+
+        let next = self.pending.pop_front();
+        self.in_flight = next.as_ref().map(|i| i.handle);
+
+        next
+    }
+
+    /// Get the handle of the indication awaiting confirmation.
+    ///
+    /// # Returns
+    /// * `Option<u16>` - The handle, if an indication is in flight.
+    pub fn in_flight(&self) -> Option<u16> {
+        self.in_flight
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cccd() {
+        assert_eq!(Cccd::parse(&[0x01, 0x00], Cccd::BOTH), Ok(Cccd::NOTIFY));
+        assert_eq!(Cccd::parse(&[0x02, 0x00], Cccd::BOTH), Ok(Cccd::INDICATE));
+        assert_eq!(Cccd::parse(&[0x00, 0x00], Cccd::NOTIFY), Ok(Cccd::NONE));
+        assert_eq!(
+            Cccd::parse(&[0x01], Cccd::BOTH),
+            Err(InvalidCccd::InvalidLength)
+        );
+        assert_eq!(
+            Cccd::parse(&[0x02, 0x00], Cccd::NOTIFY),
+            Err(InvalidCccd::Unsupported(Cccd::INDICATE))
+        );
+        assert!(Cccd::parse(&[0x00, 0x80], Cccd::BOTH).is_err());
+    }
+
+    #[test]
+    fn cccd_bits() {
+        assert!(Cccd::BOTH.notify() && Cccd::BOTH.indicate());
+        assert!(!Cccd::NONE.notify() && !Cccd::NONE.indicate());
+        assert_eq!(Cccd::INDICATE.to_bytes(), [0x02, 0x00]);
+    }
+
+    #[test]
+    fn subscriptions_are_per_handle() {
+        let mut subscriptions = Subscriptions::default();
+        assert!(subscriptions.is_empty());
+
+        subscriptions.set(10, Cccd::NOTIFY);
+        subscriptions.set(20, Cccd::INDICATE);
+        assert_eq!(subscriptions.get(10), Cccd::NOTIFY);
+        assert_eq!(subscriptions.get(20), Cccd::INDICATE);
+        assert_eq!(subscriptions.get(30), Cccd::NONE);
+
+        subscriptions.set(10, Cccd::BOTH);
+        assert_eq!(subscriptions.get(10), Cccd::BOTH);
+
+        subscriptions.set(10, Cccd::NONE);
+        subscriptions.set(20, Cccd::NONE);
+        assert!(subscriptions.is_empty());
+    }
+
+    #[test]
+    fn one_indication_in_flight() {
+        let mut queue = IndicationQueue::default();
+
+        let first = queue.push(1, &[1]).unwrap();
+        assert_eq!(first.handle, 1);
+        assert_eq!(queue.in_flight(), Some(1));
+        assert_eq!(queue.push(2, &[2]), None);
+        assert_eq!(queue.push(3, &[3]), None);
+
+        assert_eq!(
+            queue.complete(1),
+            Some(Indication {
+                handle: 2,
+                value: vec![2]
+            })
+        );
+        assert_eq!(queue.complete(2).map(|i| i.handle), Some(3));
+        assert_eq!(queue.complete(3), None);
+        assert_eq!(queue.in_flight(), None);
+        assert!(queue.push(4, &[4]).is_some());
+    }
+
+    #[test]
+    fn pending_indication_keeps_latest_value() {
+        let mut queue = IndicationQueue::default();
+        queue.push(1, &[1]);
+        queue.push(2, &[20]);
+        queue.push(2, &[21]);
+
+        assert_eq!(
+            queue.complete(1),
+            Some(Indication {
+                handle: 2,
+                value: vec![21]
+            })
+        );
+        assert_eq!(queue.complete(2), None);
+    }
+
+    #[test]
+    fn unrelated_confirmation_is_ignored() {
+        let mut queue = IndicationQueue::default();
+        queue.push(1, &[1]);
+        queue.push(2, &[2]);
+
+        // Notifications are confirmed locally too; they must not advance the queue.
+        assert_eq!(queue.complete(5), None);
+        assert_eq!(queue.in_flight(), Some(1));
+    }
+}
@@ -0,0 +1,1029 @@
+//! Wall-clock time and the Current Time Service encodings.
+//!
+//! The system clock is anchored to the uptime when it is set, so it keeps running
+//! from the monotonic uptime counter without a real-time clock.
+
+/// Current Time Service UUID.
+pub const CURRENT_TIME_SERVICE_UUID: u16 = 0x1805;
+
+/// Current Time characteristic UUID.
+pub const CURRENT_TIME_UUID: u16 = 0x2a2b;
+
+/// Local Time Information characteristic UUID.
+pub const LOCAL_TIME_INFORMATION_UUID: u16 = 0x2a0f;
+
+/// Current Time adjust reason: manual time update.
+pub const ADJUST_REASON_MANUAL: u8 = 0x01;
+
+/// Current Time adjust reason: change of time zone.
+pub const ADJUST_REASON_TIME_ZONE: u8 = 0x04;
+
+/// Seconds per day.
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Time error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeError {
+    /// The value has the wrong length.
+    InvalidLength,
+
+    /// The date, time or time zone is invalid or unknown.
+    OutOfRange,
+}
+
+/// Implementation of the `Display` trait for `TimeError`.
+impl core::fmt::Display for TimeError {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TimeError::InvalidLength => write!(f, "Invalid time value length"),
+            TimeError::OutOfRange => write!(f, "Invalid or unknown time"),
+        }
+    }
+}
+
+/// Implementation of the `Error` trait for `TimeError`.
+impl std::error::Error for TimeError {}
+
+/// Calendar date and time of day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    /// Year, 1970 to 9999.
+    pub year: u16,
+
+    /// Month, 1 to 12.
+    pub month: u8,
+
+    /// Day of the month, 1 to 31.
+    pub day: u8,
+
+    /// Hours, 0 to 23.
+    pub hour: u8,
+
+    /// Minutes, 0 to 59.
+    pub minute: u8,
+
+    /// Seconds, 0 to 59.
+    pub second: u8,
+}
+
+/// Implementation of `DateTime`.
+impl DateTime {
+    /// Convert seconds since the Unix epoch to a date and time.
+    ///
+    /// # Arguments
+    /// * `seconds` - Seconds since 1970-01-01 00:00:00, not negative.
+    ///
+    /// # Returns
+    /// * `DateTime` - The date and time.
+    pub fn from_unix(seconds: i64) -> Self {
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let time = seconds.rem_euclid(SECONDS_PER_DAY);
+
+        // Civil from days, with years starting in March.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    /// Convert the date and time to seconds since the Unix epoch.
+    ///
+    /// # Returns
+    /// * `Option<i64>` - The seconds, or `None` if the date or time is invalid.
+    pub fn to_unix(&self) -> Option<i64> {
+        let valid_date = (1970..=9999).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && (1..=days_in_month(self.year, self.month)).contains(&self.day);
+        if !valid_date || self.hour > 23 || self.minute > 59 || self.second > 59 {
+            return None;
+        }
+
+        // Days from civil, with years starting in March.
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5
+            + i64::from(self.day)
+            - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        Some(
+            days * SECONDS_PER_DAY
+                + i64::from(self.hour) * 3600
+                + i64::from(self.minute) * 60
+                + i64::from(self.second),
+        )
+    }
+
+    /// Get the day of the week.
+    ///
+    /// # Returns
+    /// * `u8` - The day, `1` for Monday to `7` for Sunday.
+    pub fn weekday(&self) -> u8 {
+        let days = self
+            .to_unix()
+            .unwrap_or_default()
+            .div_euclid(SECONDS_PER_DAY);
+
+        // 1970-01-01 was a Thursday.
+        ((days + 3).rem_euclid(7) + 1) as u8
+    }
+
+    /// Get the minutes since midnight.
+    ///
+    /// # Returns
+    /// * `u16` - The minutes, 0 to 1439.
+    pub fn minute_of_day(&self) -> u16 {
+        u16::from(self.hour) * 60 + u16::from(self.minute)
+    }
+}
+
+/// Get the number of days in a month.
+///
+/// # Arguments
+/// * `year` - The year.
+/// * `month` - The month, 1 to 12.
+///
+/// # Returns
+/// * `u8` - The number of days.
+fn days_in_month(year: u16, month: u8) -> u8 {
+    let leap_year = matches!((year % 4, year % 100, year % 400), (0, 1.., _) | (_, _, 0));
+
+    match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Encode a Current Time characteristic value.
+///
+/// # Arguments
+/// * `local` - The local date and time.
+/// * `fractions256` - The fraction of the second in 1/256 s.
+/// * `adjust_reason` - The adjust reason flags.
+///
+/// # Returns
+/// * `[u8; 10]` - The year, month, day, hours, minutes, seconds, day of week,
+///   fractions and adjust reason.
+pub fn encode_current_time(local: &DateTime, fractions256: u8, adjust_reason: u8) -> [u8; 10] {
+    let year = local.year.to_le_bytes();
+
+    [
+        year[0],
+        year[1],
+        local.month,
+        local.day,
+        local.hour,
+        local.minute,
+        local.second,
+        local.weekday(),
+        fractions256,
+        adjust_reason,
+    ]
+}
+
+/// Decode a Current Time characteristic write.
+///
+/// The day of the week and adjust reason are ignored.
+///
+/// # Arguments
+/// * `value` - The written value.
+///
+/// # Returns
+/// * `Result<(DateTime, u8), TimeError>` - The local date and time and the fraction
+///   of the second in 1/256 s, or an error if the date is invalid or unknown.
+pub fn decode_current_time(value: &[u8]) -> Result<(DateTime, u8), TimeError> {
+    let [year_low, year_high, month, day, hour, minute, second, _, fractions256, _] = value else {
+        return Err(TimeError::InvalidLength);
+    };
+
+    let local = DateTime {
+        year: u16::from_le_bytes([*year_low, *year_high]),
+        month: *month,
+        day: *day,
+        hour: *hour,
+        minute: *minute,
+        second: *second,
+    };
+    local.to_unix().ok_or(TimeError::OutOfRange)?;
+
+    Ok((local, *fractions256))
+}
+
+/// Local Time Information characteristic value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LocalTimeInfo {
+    /// Offset from UTC in 15 minute steps, -48 to +56.
+    pub time_zone: i8,
+
+    /// Daylight saving offset in 15 minute steps: 0, 2, 4 or 8.
+    pub dst_offset: u8,
+}
+
+/// Implementation of `LocalTimeInfo`.
+impl LocalTimeInfo {
+    /// Decode a Local Time Information write.
+    ///
+    /// # Arguments
+    /// * `value` - The written value.
+    ///
+    /// # Returns
+    /// * `Result<LocalTimeInfo, TimeError>` - The local time information or an error.
+    pub fn decode(value: &[u8]) -> Result<Self, TimeError> {
+        let [time_zone, dst_offset] = value else {
+            return Err(TimeError::InvalidLength);
+        };
+
+        let time_zone = *time_zone as i8;
+        if !(-48..=56).contains(&time_zone) || !matches!(dst_offset, 0 | 2 | 4 | 8) {
+            return Err(TimeError::OutOfRange);
+        }
+
+        Ok(Self {
+            time_zone,
+            dst_offset: *dst_offset,
+        })
+    }
+
+    /// Encode the value.
+    ///
+    /// # Returns
+    /// * `[u8; 2]` - The time zone and daylight saving offset.
+    pub fn encode(&self) -> [u8; 2] {
+        [self.time_zone as u8, self.dst_offset]
+    }
+
+    /// Get the offset of local time from UTC.
+    ///
+    /// # Returns
+    /// * `i64` - The offset in seconds, including daylight saving.
+    pub fn utc_offset_s(&self) -> i64 {
+        (i64::from(self.time_zone) + i64::from(self.dst_offset)) * 15 * 60
+    }
+}
+
+/// Daylight saving rules used when a POSIX TZ string names a daylight saving
+/// time zone without rules, as the C library does.
+const DEFAULT_DST_RULES: &str = "M3.2.0,M11.1.0";
+
+/// Time zone from a POSIX TZ string, such as `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeZone {
+    /// Offset of standard time from UTC in seconds, positive east of Greenwich.
+    std_offset_s: i32,
+
+    /// Daylight saving time, if observed.
+    dst: Option<DaylightSaving>,
+}
+
+/// Daylight saving time of a time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DaylightSaving {
+    /// Offset of daylight saving time from UTC in seconds, positive east.
+    offset_s: i32,
+
+    /// Start, in local standard time.
+    start: Transition,
+
+    /// End, in local daylight saving time.
+    end: Transition,
+}
+
+/// Yearly change between standard and daylight saving time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Transition {
+    /// The day of the year.
+    date: TransitionDate,
+
+    /// Seconds after local midnight, -167 to 167 hours.
+    time_s: i32,
+}
+
+/// Day of the year of a transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransitionDate {
+    /// `Jn`: day 1 to 365, never counting February 29.
+    Julian(u16),
+
+    /// `n`: day 0 to 365, counting February 29.
+    Day(u16),
+
+    /// `Mm.w.d`: day `d` of week `w` of month `m`.
+    Month {
+        /// Month, 1 to 12.
+        month: u8,
+
+        /// Week, 1 to 5, where 5 is the last week of the month.
+        week: u8,
+
+        /// Day of the week, 0 for Sunday to 6.
+        weekday: u8,
+    },
+}
+
+/// Implementation of `TimeZone`.
+impl TimeZone {
+    /// Parse a POSIX TZ string.
+    ///
+    /// # Arguments
+    /// * `text` - The string, `std offset [dst [offset] [,start[/time],end[/time]]]`,
+    ///   with offsets west of Greenwich positive; daylight saving time is an hour
+    ///   ahead and follows the US rules unless given.
+    ///
+    /// # Returns
+    /// * `Option<TimeZone>` - The time zone, or `None` if the string is invalid.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut reader = TzReader {
+            text: text.as_bytes(),
+        };
+
+        reader.name()?;
+        let std_offset_s = -reader.offset(24)?;
+        if reader.text.is_empty() {
+            return Some(Self {
+                std_offset_s,
+                dst: None,
+            });
+        }
+
+        reader.name()?;
+        let offset_s = match reader.text.first() {
+            Some(b',') | None => std_offset_s + 3600,
+            Some(_) => -reader.offset(24)?,
+        };
+        if reader.text.is_empty() {
+            reader.text = DEFAULT_DST_RULES.as_bytes();
+        } else {
+            reader.expect(b',')?;
+        }
+        let start = reader.transition()?;
+        reader.expect(b',')?;
+        let end = reader.transition()?;
+        if !reader.text.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            std_offset_s,
+            dst: Some(DaylightSaving {
+                offset_s,
+                start,
+                end,
+            }),
+        })
+    }
+
+    /// Get the offset from UTC at a time.
+    ///
+    /// # Arguments
+    /// * `unix_s` - Seconds since the Unix epoch.
+    ///
+    /// # Returns
+    /// * `i64` - The offset in seconds, including daylight saving.
+    pub fn utc_offset_s(&self, unix_s: i64) -> i64 {
+        let std_offset_s = i64::from(self.std_offset_s);
+        let Some(dst) = self.dst else {
+            return std_offset_s;
+        };
+
+        let year = DateTime::from_unix(unix_s + std_offset_s).year;
+        let start = dst.start.local_s(year) - std_offset_s;
+        let end = dst.end.local_s(year) - i64::from(dst.offset_s);
+        let daylight_saving = if start < end {
+            (start..end).contains(&unix_s)
+        } else {
+            // Southern hemisphere: daylight saving spans the new year.
+            !(end..start).contains(&unix_s)
+        };
+
+        if daylight_saving {
+            i64::from(dst.offset_s)
+        } else {
+            std_offset_s
+        }
+    }
+
+    /// Get the Local Time Information equivalent at a time.
+    ///
+    /// # Arguments
+    /// * `unix_s` - Seconds since the Unix epoch.
+    ///
+    /// # Returns
+    /// * `LocalTimeInfo` - The standard offset and any daylight saving in effect,
+    ///   in 15 minute steps.
+    pub fn local_time_info(&self, unix_s: i64) -> LocalTimeInfo {
+        let quarters = |seconds: i64| (seconds / (15 * 60)) as i8;
+        let std_offset_s = i64::from(self.std_offset_s);
+
+        LocalTimeInfo {
+            time_zone: quarters(std_offset_s),
+            dst_offset: quarters(self.utc_offset_s(unix_s) - std_offset_s) as u8,
+        }
+    }
+}
+
+/// Implementation of `Transition`.
+impl Transition {
+    /// Get the local time of the transition in a year.
+    ///
+    /// # Arguments
+    /// * `year` - The year.
+    ///
+    /// # Returns
+    /// * `i64` - Seconds since the Unix epoch as if local time were UTC.
+    fn local_s(&self, year: u16) -> i64 {
+        let day = |month, day| {
+            let midnight = DateTime {
+                year,
+                month,
+                day,
+                hour: 0,
+                minute: 0,
+                second: 0,
+            };
+            midnight.to_unix().unwrap_or_default() / SECONDS_PER_DAY
+        };
+
+        let unix_day = match self.date {
+            TransitionDate::Julian(n) => {
+                let leap_day = days_in_month(year, 2) == 29 && n >= 60;
+                day(1, 1) + i64::from(n) - 1 + i64::from(leap_day)
+            }
+            TransitionDate::Day(n) => day(1, 1) + i64::from(n),
+            TransitionDate::Month {
+                month,
+                week,
+                weekday,
+            } => {
+                let first = day(month, 1);
+                // 1970-01-01 was a Thursday.
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut unix_day = first
+                    + (i64::from(weekday) - first_weekday).rem_euclid(7)
+                    + 7 * (i64::from(week) - 1);
+                while unix_day >= first + i64::from(days_in_month(year, month)) {
+                    unix_day -= 7;
+                }
+                unix_day
+            }
+        };
+
+        unix_day * SECONDS_PER_DAY + i64::from(self.time_s)
+    }
+}
+
+/// Reads the parts of a POSIX TZ string.
+struct TzReader<'a> {
+    /// The rest of the string.
+    text: &'a [u8],
+}
+
+/// Implementation of `TzReader`.
+impl TzReader<'_> {
+    /// Skip a byte.
+    ///
+    /// # Arguments
+    /// * `byte` - The expected byte.
+    ///
+    /// # Returns
+    /// * `Option<()>` - `None` if the next byte is different.
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.text = self.text.strip_prefix(&[byte])?;
+        Some(())
+    }
+
+    /// Read a time zone name: three or more letters, or any sign, digit or
+    /// letter between `<` and `>`.
+    ///
+    /// # Returns
+    /// * `Option<()>` - `None` if the name is invalid.
+    fn name(&mut self) -> Option<()> {
+        let (len, skip) = if self.text.first() == Some(&b'<') {
+            let len = self.text.iter().position(|&b| b == b'>')?;
+            let quoted = &self.text[1..len];
+            if !quoted
+                .iter()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-'))
+            {
+                return None;
+            }
+            (len - 1, len + 1)
+        } else {
+            let len = self
+                .text
+                .iter()
+                .take_while(|b| b.is_ascii_alphabetic())
+                .count();
+            (len, len)
+        };
+
+        if len < 3 {
+            return None;
+        }
+        self.text = &self.text[skip..];
+        Some(())
+    }
+
+    /// Read a number.
+    ///
+    /// # Arguments
+    /// * `max` - The largest valid value.
+    ///
+    /// # Returns
+    /// * `Option<i32>` - The number, or `None` without digits or if too large.
+    fn number(&mut self, max: i32) -> Option<i32> {
+        let len = self.text.iter().take_while(|b| b.is_ascii_digit()).count();
+        if len == 0 || len > 3 {
+            return None;
+        }
+
+        let (digits, rest) = self.text.split_at(len);
+        self.text = rest;
+        let number = digits
+            .iter()
+            .fold(0, |number, digit| number * 10 + i32::from(digit - b'0'));
+        (number <= max).then_some(number)
+    }
+
+    /// Read a signed `hh[:mm[:ss]]` duration.
+    ///
+    /// # Arguments
+    /// * `max_hours` - The largest valid number of hours.
+    ///
+    /// # Returns
+    /// * `Option<i32>` - The duration in seconds.
+    fn offset(&mut self, max_hours: i32) -> Option<i32> {
+        let sign = match self.text.first() {
+            Some(b'-') => -1,
+            _ => 1,
+        };
+        if matches!(self.text.first(), Some(b'+' | b'-')) {
+            self.text = &self.text[1..];
+        }
+
+        let mut seconds = self.number(max_hours)? * 3600;
+        for unit in [60, 1] {
+            if self.expect(b':').is_none() {
+                break;
+            }
+            seconds += self.number(59)? * unit;
+        }
+
+        Some(sign * seconds)
+    }
+
+    /// Read a transition, `Jn`, `n` or `Mm.w.d`, with an optional `/time`.
+    ///
+    /// # Returns
+    /// * `Option<Transition>` - The transition, or `None` if invalid.
+    fn transition(&mut self) -> Option<Transition> {
+        let date = match self.text.first()? {
+            b'J' => {
+                self.expect(b'J')?;
+                let day = self.number(365)?;
+                TransitionDate::Julian(u16::try_from(day).ok().filter(|&day| day >= 1)?)
+            }
+            b'M' => {
+                self.expect(b'M')?;
+                let month = self.number(12)?;
+                self.expect(b'.')?;
+                let week = self.number(5)?;
+                self.expect(b'.')?;
+                let weekday = self.number(6)?;
+                if month == 0 || week == 0 {
+                    return None;
+                }
+                TransitionDate::Month {
+                    month: month as u8,
+                    week: week as u8,
+                    weekday: weekday as u8,
+                }
+            }
+            _ => TransitionDate::Day(self.number(365)? as u16),
+        };
+
+        let time_s = if self.expect(b'/').is_some() {
+            self.offset(167)?
+        } else {
+            2 * 3600
+        };
+
+        Some(Transition { date, time_s })
+    }
+}
+
+/// Wall clock anchored to the uptime counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SystemClock {
+    /// Unix time in milliseconds and the uptime in milliseconds when it was set.
+    reference: Option<(i64, u64)>,
+
+    /// The local time zone set over BLE.
+    local_time: LocalTimeInfo,
+
+    /// The configured time zone, taking precedence over `local_time`.
+    time_zone: Option<TimeZone>,
+}
+
+/// Implementation of `SystemClock`.
+impl SystemClock {
+    /// Set the time from UTC.
+    ///
+    /// # Arguments
+    /// * `unix_ms` - Milliseconds since the Unix epoch.
+    /// * `uptime_ms` - The uptime in milliseconds at that time.
+    pub fn set_utc(&mut self, unix_ms: i64, uptime_ms: u64) {
+        self.reference = Some((unix_ms, uptime_ms));
+    }
+
+    /// Set the time from local time in the current time zone.
+    ///
+    /// # Arguments
+    /// * `local` - The local date and time.
+    /// * `fractions256` - The fraction of the second in 1/256 s.
+    /// * `uptime_ms` - The uptime in milliseconds at that time.
+    ///
+    /// # Returns
+    /// * `Result<(), TimeError>` - An error if the date or time is invalid.
+    pub fn set_local(
+        &mut self,
+        local: &DateTime,
+        fractions256: u8,
+        uptime_ms: u64,
+    ) -> Result<(), TimeError> {
+        let local_s = local.to_unix().ok_or(TimeError::OutOfRange)?;
+        let standard_s = local_s - self.utc_offset_s(local_s);
+        let unix_s = local_s - self.utc_offset_s(standard_s);
+        let unix_ms = unix_s * 1000 + i64::from(fractions256) * 1000 / 256;
+        self.set_utc(unix_ms, uptime_ms);
+
+        Ok(())
+    }
+
+    /// Set the local time zone. The UTC time is unchanged, and a configured
+    /// time zone takes precedence.
+    ///
+    /// # Arguments
+    /// * `local_time` - The local time information.
+    pub fn set_local_time(&mut self, local_time: LocalTimeInfo) {
+        self.local_time = local_time;
+    }
+
+    /// Set the configured time zone. The UTC time is unchanged.
+    ///
+    /// # Arguments
+    /// * `time_zone` - The time zone, or `None` to use the local time information.
+    pub fn set_time_zone(&mut self, time_zone: Option<TimeZone>) {
+        self.time_zone = time_zone;
+    }
+
+    /// Get the local time zone.
+    ///
+    /// # Arguments
+    /// * `uptime_ms` - The uptime in milliseconds, for the daylight saving time
+    ///   of a configured time zone.
+    ///
+    /// # Returns
+    /// * `LocalTimeInfo` - The local time information.
+    pub fn local_time(&self, uptime_ms: u64) -> LocalTimeInfo {
+        match &self.time_zone {
+            Some(time_zone) => {
+                let unix_s = self.unix_ms(uptime_ms).unwrap_or_default().div_euclid(1000);
+                time_zone.local_time_info(unix_s)
+            }
+            None => self.local_time,
+        }
+    }
+
+    /// Get the current time.
+    ///
+    /// # Arguments
+    /// * `uptime_ms` - The uptime in milliseconds.
+    ///
+    /// # Returns
+    /// * `Option<i64>` - Milliseconds since the Unix epoch, or `None` if not set.
+    pub fn unix_ms(&self, uptime_ms: u64) -> Option<i64> {
+        let (unix_ms, reference_uptime_ms) = self.reference?;
+
+        Some(unix_ms + (uptime_ms as i64 - reference_uptime_ms as i64))
+    }
+
+    /// Get the current local date and time.
+    ///
+    /// # Arguments
+    /// * `uptime_ms` - The uptime in milliseconds.
+    ///
+    /// # Returns
+    /// * `Option<(DateTime, u8)>` - The local date and time and the fraction of the
+    ///   second in 1/256 s, or `None` if not set.
+    pub fn local(&self, uptime_ms: u64) -> Option<(DateTime, u8)> {
+        let unix_ms = self.unix_ms(uptime_ms)?;
+        let local_ms = unix_ms + self.utc_offset_s(unix_ms.div_euclid(1000)) * 1000;
+        let fractions256 = (local_ms.rem_euclid(1000) * 256 / 1000) as u8;
+
+        Some((DateTime::from_unix(local_ms.div_euclid(1000)), fractions256))
+    }
+
+    /// Get the Unix time at boot.
+    ///
+    /// # Arguments
+    /// * `uptime_ms` - The uptime in milliseconds.
+    ///
+    /// # Returns
+    /// * `Option<i64>` - Seconds since the Unix epoch at uptime zero, or `None` if
+    ///   not set.
+    pub fn boot_time_s(&self, uptime_ms: u64) -> Option<i64> {
+        Some((self.unix_ms(uptime_ms)? - uptime_ms as i64).div_euclid(1000))
+    }
+
+    /// Format the time for the display.
+    ///
+    /// # Arguments
+    /// * `uptime_ms` - The uptime in milliseconds.
+    ///
+    /// # Returns
+    /// * `String` - The local time as `hh:mm`, or while the clock is not set, the
+    ///   uptime as `<h>h<mm>`, or `<d>d<hh>h` after a day.
+    pub fn label(&self, uptime_ms: u64) -> String {
+        if let Some((local, _)) = self.local(uptime_ms) {
+            return format!("{:02}:{:02}", local.hour, local.minute);
+        }
+
+        let minutes = uptime_ms / 60_000;
+        let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+        if days > 0 {
+            format!("{days}d{hours:02}h")
+        } else {
+            format!("{hours}h{minutes:02}")
+        }
+    }
+
+    /// Get the offset of local time from UTC.
+    ///
+    /// # Arguments
+    /// * `unix_s` - Seconds since the Unix epoch.
+    ///
+    /// # Returns
+    /// * `i64` - The offset in seconds, from the configured time zone if any.
+    fn utc_offset_s(&self, unix_s: i64) -> i64 {
+        match &self.time_zone {
+            Some(time_zone) => time_zone.utc_offset_s(unix_s),
+            None => self.local_time.utc_offset_s(),
+        }
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn unix_conversion() {
+        let cases = [
+            (0, date_time(1970, 1, 1, 0, 0, 0)),
+            (951782400, date_time(2000, 2, 29, 0, 0, 0)),
+            (1709210096, date_time(2024, 2, 29, 12, 34, 56)),
+            (4102444799, date_time(2099, 12, 31, 23, 59, 59)),
+        ];
+
+        for (seconds, expected) in cases {
+            assert_eq!(DateTime::from_unix(seconds), expected);
+            assert_eq!(expected.to_unix(), Some(seconds));
+        }
+    }
+
+    #[test]
+    fn invalid_dates() {
+        assert_eq!(date_time(2023, 2, 29, 0, 0, 0).to_unix(), None);
+        assert_eq!(date_time(1900, 1, 1, 0, 0, 0).to_unix(), None);
+        assert_eq!(date_time(2024, 4, 31, 0, 0, 0).to_unix(), None);
+        assert_eq!(date_time(2024, 1, 1, 24, 0, 0).to_unix(), None);
+        assert_eq!(date_time(0, 0, 0, 0, 0, 0).to_unix(), None);
+    }
+
+    #[test]
+    fn weekday_and_minute_of_day() {
+        // 2024-02-29 was a Thursday.
+        let local = date_time(2024, 2, 29, 22, 30, 0);
+        assert_eq!(local.weekday(), 4);
+        assert_eq!(local.minute_of_day(), 1350);
+        assert_eq!(date_time(2024, 3, 3, 0, 0, 0).weekday(), 7);
+    }
+
+    #[test]
+    fn current_time_round_trip() {
+        let local = date_time(2024, 2, 29, 12, 34, 56);
+        let value = encode_current_time(&local, 128, ADJUST_REASON_MANUAL);
+
+        assert_eq!(
+            value,
+            [0xe8, 0x07, 0x02, 0x1d, 0x0c, 0x22, 0x38, 0x04, 0x80, 0x01]
+        );
+        assert_eq!(decode_current_time(&value), Ok((local, 128)));
+    }
+
+    #[test]
+    fn current_time_errors() {
+        assert_eq!(
+            decode_current_time(&[0xe8, 0x07, 0x02]),
+            Err(TimeError::InvalidLength)
+        );
+        // Unknown year, month and day.
+        assert_eq!(decode_current_time(&[0; 10]), Err(TimeError::OutOfRange));
+    }
+
+    #[test]
+    fn local_time_info() {
+        let info = LocalTimeInfo::decode(&[0x04, 0x04]).unwrap();
+        assert_eq!(info.utc_offset_s(), 7200);
+        assert_eq!(info.encode(), [0x04, 0x04]);
+
+        let info = LocalTimeInfo::decode(&[(-20i8) as u8, 0x00]).unwrap();
+        assert_eq!(info.utc_offset_s(), -18000);
+
+        assert_eq!(
+            LocalTimeInfo::decode(&[0x80, 0x00]),
+            Err(TimeError::OutOfRange)
+        );
+        assert_eq!(
+            LocalTimeInfo::decode(&[0x00, 0x01]),
+            Err(TimeError::OutOfRange)
+        );
+        assert_eq!(
+            LocalTimeInfo::decode(&[0x00]),
+            Err(TimeError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn clock_runs_from_uptime() {
+        let mut clock = SystemClock::default();
+        assert_eq!(clock.unix_ms(1000), None);
+        assert_eq!(clock.local(1000), None);
+
+        clock.set_utc(1_700_000_000_000, 10_000);
+        assert_eq!(clock.unix_ms(15_500), Some(1_700_000_005_500));
+        assert_eq!(clock.boot_time_s(15_500), Some(1_699_999_990));
+
+        let (local, fractions256) = clock.local(15_500).unwrap();
+        assert_eq!(local.to_unix(), Some(1_700_000_005));
+        assert_eq!(fractions256, 128);
+    }
+
+    #[test]
+    fn local_time_uses_time_zone() {
+        let mut clock = SystemClock::default();
+        clock.set_local_time(LocalTimeInfo {
+            time_zone: 4,
+            dst_offset: 4,
+        });
+
+        let local = date_time(2024, 7, 1, 12, 0, 0);
+        clock.set_local(&local, 0, 0).unwrap();
+        assert_eq!(
+            clock.unix_ms(0),
+            Some((local.to_unix().unwrap() - 7200) * 1000)
+        );
+        assert_eq!(clock.local(0), Some((local, 0)));
+
+        // Changing the time zone keeps UTC.
+        clock.set_local_time(LocalTimeInfo::default());
+        assert_eq!(clock.local(0).unwrap().0.hour, 10);
+    }
+
+    #[test]
+    fn posix_time_zones() {
+        let hour = 3600;
+
+        // Central Europe: 02:00 CET to 03:00 CEST, back at 03:00 CEST.
+        let berlin = TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(berlin.utc_offset_s(1_711_846_800 - 1), hour);
+        assert_eq!(berlin.utc_offset_s(1_711_846_800), 2 * hour);
+        assert_eq!(berlin.utc_offset_s(1_729_990_800 - 1), 2 * hour);
+        assert_eq!(berlin.utc_offset_s(1_729_990_800), hour);
+
+        // US Eastern, also with the default rules.
+        for text in ["EST5EDT,M3.2.0,M11.1.0", "EST5EDT", "EST+5EDT+4:00:00"] {
+            let new_york = TimeZone::parse(text).unwrap();
+            assert_eq!(new_york.utc_offset_s(1_710_054_000 - 1), -5 * hour);
+            assert_eq!(new_york.utc_offset_s(1_710_054_000), -4 * hour);
+            assert_eq!(new_york.utc_offset_s(1_730_613_600 - 1), -4 * hour);
+            assert_eq!(new_york.utc_offset_s(1_730_613_600), -5 * hour);
+        }
+
+        // Sydney, with daylight saving across the new year.
+        let sydney = TimeZone::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.utc_offset_s(1_705_320_000), 11 * hour);
+        assert_eq!(sydney.utc_offset_s(1_712_419_200 - 1), 11 * hour);
+        assert_eq!(sydney.utc_offset_s(1_712_419_200), 10 * hour);
+        assert_eq!(sydney.utc_offset_s(1_721_044_800), 10 * hour);
+        assert_eq!(sydney.utc_offset_s(1_728_144_000), 11 * hour);
+
+        // Quoted names, minutes and Julian days.
+        let kolkata = TimeZone::parse("<+0530>-5:30").unwrap();
+        assert_eq!(kolkata.utc_offset_s(1_721_044_800), 5 * hour + 30 * 60);
+        let julian = TimeZone::parse("AAA0BBB,J60/0,J300/0").unwrap();
+        // March 1 2024, a leap year, is day 61.
+        assert_eq!(julian.utc_offset_s(1_709_251_200 - 1), 0);
+        assert_eq!(julian.utc_offset_s(1_709_251_200), hour);
+        assert_eq!(
+            berlin.local_time_info(1_721_044_800),
+            LocalTimeInfo {
+                time_zone: 4,
+                dst_offset: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_time_zones() {
+        for text in [
+            "",
+            "CET",
+            "CE-1",
+            "CET-1CEST,M3.5.0",
+            "CET-1CEST,M13.5.0,M10.5.0/3",
+            "CET-1CEST,M3.0.0,M10.5.0/3",
+            "CET-1CEST,M3.5.7,M10.5.0/3",
+            "CET-25",
+            "CET-1:60",
+            "CET-1 ",
+            "<+05-5",
+            "UTC0,J0,J1",
+        ] {
+            assert_eq!(TimeZone::parse(text), None, "{text}");
+        }
+        for text in ["UTC0", "GMT0BST,M3.5.0/1,M10.5.0", "IST-5:30"] {
+            assert!(TimeZone::parse(text).is_some(), "{text}");
+        }
+    }
+
+    #[test]
+    fn clock_with_time_zone() {
+        let mut clock = SystemClock::default();
+        clock.set_time_zone(TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3"));
+        clock.set_utc(1_721_044_800_000, 0);
+
+        assert_eq!(clock.local(0).unwrap().0.hour, 14);
+        assert_eq!(clock.local_time(0).utc_offset_s(), 7200);
+
+        // Local time in summer is two hours ahead.
+        let local = date_time(2024, 7, 15, 14, 0, 0);
+        clock.set_local(&local, 0, 1000).unwrap();
+        assert_eq!(clock.unix_ms(1000), Some(1_721_044_800_000));
+    }
+
+    #[test]
+    fn labels() {
+        let mut clock = SystemClock::default();
+        assert_eq!(clock.label(0), "0h00");
+        assert_eq!(clock.label((3 * 60 + 5) * 60_000), "3h05");
+        assert_eq!(clock.label((26 * 60 + 5) * 60_000), "1d02h");
+
+        clock.set_time_zone(TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3"));
+        clock.set_utc(1_721_044_800_000 + 65_000, 0);
+        assert_eq!(clock.label(0), "14:01");
+    }
+}
@@ -0,0 +1,197 @@
+//! BLE connection parameters, idle detection and statistics.
+
+/// How long a connection may go without requests or subscriptions before it is
+/// closed to free its slot.
+pub const IDLE_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+
+/// Connection parameters in Bluetooth Core units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionParams {
+    /// Minimum connection interval in 1.25 ms units.
+    pub min_interval: u16,
+
+    /// Maximum connection interval in 1.25 ms units.
+    pub max_interval: u16,
+
+    /// Number of connection events the peripheral may skip.
+    pub latency: u16,
+
+    /// Supervision timeout in 10 ms units.
+    pub timeout: u16,
+}
+
+/// Implementation of `ConnectionParams`.
+impl ConnectionParams {
+    /// Parameters requested once a peer is connected: the peer is polled every
+    /// 100 to 200 ms and the device may sleep through four connection events.
+    /// They stay within Apple's accessory design guidelines.
+    pub const LOW_POWER: Self = Self::from_ms(100, 200, 4, 6000);
+
+    /// Create parameters from milliseconds.
+    ///
+    /// # Arguments
+    /// * `min_interval_ms` - The minimum connection interval in milliseconds.
+    /// * `max_interval_ms` - The maximum connection interval in milliseconds.
+    /// * `latency` - The peripheral latency in connection events.
+    /// * `timeout_ms` - The supervision timeout in milliseconds.
+    ///
+    /// # Returns
+    /// * `ConnectionParams` - The parameters, rounded down to whole units.
+    pub const fn from_ms(
+        min_interval_ms: u32,
+        max_interval_ms: u32,
+        latency: u16,
+        timeout_ms: u32,
+    ) -> Self {
+        Self {
+            min_interval: (min_interval_ms * 4 / 5) as u16,
+            max_interval: (max_interval_ms * 4 / 5) as u16,
+            latency,
+            timeout: (timeout_ms / 10) as u16,
+        }
+    }
+
+    /// Check the parameters against the Bluetooth Core ranges.
+    ///
+    /// # Returns
+    /// * `bool` - Whether the intervals are between 7.5 ms and 4 s and ordered, the
+    ///   latency is at most 499, the timeout is between 100 ms and 32 s, and the
+    ///   timeout is longer than twice the effective interval.
+    pub fn is_valid(&self) -> bool {
+        let intervals = (6..=3200).contains(&self.min_interval)
+            && (self.min_interval..=3200).contains(&self.max_interval);
+        let timeout = (10..=3200).contains(&self.timeout);
+
+        // Effective interval in 1.25 ms units against the timeout in 10 ms units.
+        let effective_interval = u32::from(self.max_interval) * (1 + u32::from(self.latency));
+        let timeout_covers = u32::from(self.timeout) * 8 > effective_interval * 2;
+
+        intervals && self.latency <= 499 && timeout && timeout_covers
+    }
+}
+
+/// Check whether a connection is idle.
+///
+/// # Arguments
+/// * `last_activity_ms` - When the peer last sent a request, in milliseconds since boot.
+/// * `now_ms` - The current time in milliseconds since boot.
+/// * `subscribed` - Whether the peer is subscribed to any characteristic.
+///
+/// # Returns
+/// * `bool` - Whether the peer is neither subscribed nor has sent a request for
+///   [`IDLE_TIMEOUT_MS`].
+pub fn is_idle(last_activity_ms: u64, now_ms: u64, subscribed: bool) -> bool {
+    !subscribed && now_ms.saturating_sub(last_activity_ms) >= IDLE_TIMEOUT_MS
+}
+
+/// BLE connection statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Connected peers.
+    pub active: usize,
+
+    /// Connection slots.
+    pub slots: usize,
+
+    /// Peers accepted since boot.
+    pub accepted: u32,
+
+    /// Peers disconnected because all slots were taken.
+    pub rejected: u32,
+
+    /// Peers disconnected for being idle.
+    pub idle_closed: u32,
+
+    /// Notifications and indications sent since boot.
+    pub notifications: u32,
+}
+
+/// Implementation of `ConnectionStats`.
+impl ConnectionStats {
+    /// Format the statistics for the status screen.
+    ///
+    /// # Returns
+    /// * `Vec<String>` - One short line per statistic.
+    pub fn status_lines(&self) -> Vec<String> {
+        vec![
+            format!("BLE {}/{}", self.active, self.slots),
+            format!("Accepted {}", self.accepted),
+            format!("Rejected {}", self.rejected),
+            format!("Idle {}", self.idle_closed),
+        ]
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_power_params() {
+        assert_eq!(
+            ConnectionParams::LOW_POWER,
+            ConnectionParams {
+                min_interval: 80,
+                max_interval: 160,
+                latency: 4,
+                timeout: 600,
+            }
+        );
+        assert!(ConnectionParams::LOW_POWER.is_valid());
+    }
+
+    #[test]
+    fn invalid_params() {
+        let valid = ConnectionParams::LOW_POWER;
+
+        for params in [
+            ConnectionParams {
+                min_interval: 5,
+                ..valid
+            },
+            ConnectionParams {
+                max_interval: 79,
+                ..valid
+            },
+            ConnectionParams {
+                latency: 500,
+                ..valid
+            },
+            ConnectionParams {
+                timeout: 3201,
+                ..valid
+            },
+            // 200 ms × 5 events needs a timeout above 2 s.
+            ConnectionParams::from_ms(100, 200, 4, 2000),
+        ] {
+            assert!(!params.is_valid(), "{params:?}");
+        }
+        assert!(ConnectionParams::from_ms(100, 200, 4, 2010).is_valid());
+    }
+
+    #[test]
+    fn idle_detection() {
+        assert!(!is_idle(0, IDLE_TIMEOUT_MS - 1, false));
+        assert!(is_idle(0, IDLE_TIMEOUT_MS, false));
+        assert!(!is_idle(0, IDLE_TIMEOUT_MS, true));
+        assert!(!is_idle(IDLE_TIMEOUT_MS, 0, false));
+    }
+
+    #[test]
+    fn status_lines() {
+        let stats = ConnectionStats {
+            active: 1,
+            slots: 3,
+            accepted: 7,
+            rejected: 2,
+            idle_closed: 1,
+            notifications: 40,
+        };
+
+        assert_eq!(
+            stats.status_lines(),
+            ["BLE 1/3", "Accepted 7", "Rejected 2", "Idle 1"]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Maintenance control point framing.
+//!
+//! Requests are an opcode followed by its parameters. Responses are indicated
+//! in the style of Bluetooth SIG control points: the response opcode `0x80`, the
+//! request opcode, a result code and any response parameters.
+
+/// Response opcode.
+pub const RESPONSE_OPCODE: u8 = 0x80;
+
+/// Lowest accepted forced recalibration reference in ppm.
+const FRC_MIN_PPM: u16 = 400;
+
+/// Highest accepted forced recalibration reference in ppm.
+const FRC_MAX_PPM: u16 = 5000;
+
+/// Control point request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlRequest {
+    /// Forced recalibration to a reference concentration (opcode `0x01`, `uint16` ppm).
+    ForcedRecalibration {
+        /// Reference CO2 concentration in ppm.
+        target_ppm: u16,
+    },
+
+    /// Sensor self-test (opcode `0x02`).
+    SelfTest,
+
+    /// Sensor factory reset and default configuration (opcode `0x03`).
+    FactoryReset,
+
+    /// Enable or disable automatic self-calibration (opcode `0x04`, `uint8` 0 or 1).
+    SetAutomaticSelfCalibration {
+        /// Whether ASC is enabled.
+        enabled: bool,
+    },
+
+    /// Restart the device (opcode `0x05`).
+    Reboot,
+
+    /// Forget all bonded peers (opcode `0x06`).
+    ClearBonds,
+}
+
+/// Implementation of `ControlRequest`.
+impl ControlRequest {
+    /// Decode a control point write.
+    ///
+    /// # Arguments
+    /// * `value` - The written value.
+    ///
+    /// # Returns
+    /// * `Result<ControlRequest, ControlResponse>` - The request, or the error response
+    ///   to indicate.
+    pub fn decode(value: &[u8]) -> Result<Self, ControlResponse> {
+        let Some((&opcode, parameter)) = value.split_first() else {
+            return Err(ControlResponse::failure(
+                0x00,
+                ResultCode::OpcodeNotSupported,
+            ));
+        };
+        let invalid = || ControlResponse::failure(opcode, ResultCode::InvalidParameter);
+
+        match (opcode, parameter) {
+            (0x01, [low, high]) => {
+                let target_ppm = u16::from_le_bytes([*low, *high]);
+                if (FRC_MIN_PPM..=FRC_MAX_PPM).contains(&target_ppm) {
+                    Ok(ControlRequest::ForcedRecalibration { target_ppm })
+                } else {
+                    Err(invalid())
+                }
+            }
+            (0x02, []) => Ok(ControlRequest::SelfTest),
+            (0x03, []) => Ok(ControlRequest::FactoryReset),
+            (0x04, [enabled @ (0 | 1)]) => Ok(ControlRequest::SetAutomaticSelfCalibration {
+                enabled: *enabled == 1,
+            }),
+            (0x05, []) => Ok(ControlRequest::Reboot),
+            (0x06, []) => Ok(ControlRequest::ClearBonds),
+            (0x01..=0x06, _) => Err(invalid()),
+            _ => Err(ControlResponse::failure(
+                opcode,
+                ResultCode::OpcodeNotSupported,
+            )),
+        }
+    }
+
+    /// Get the request opcode.
+    ///
+    /// # Returns
+    /// * `u8` - The opcode.
+    pub fn opcode(self) -> u8 {
+        match self {
+            ControlRequest::ForcedRecalibration { .. } => 0x01,
+            ControlRequest::SelfTest => 0x02,
+            ControlRequest::FactoryReset => 0x03,
+            ControlRequest::SetAutomaticSelfCalibration { .. } => 0x04,
+            ControlRequest::Reboot => 0x05,
+            ControlRequest::ClearBonds => 0x06,
+        }
+    }
+}
+
+/// Control point result code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultCode {
+    /// The operation succeeded.
+    Success = 0x01,
+
+    /// The opcode is not supported.
+    OpcodeNotSupported = 0x02,
+
+    /// The parameters are invalid.
+    InvalidParameter = 0x03,
+
+    /// The operation was attempted and failed.
+    OperationFailed = 0x04,
+}
+
+/// Control point response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlResponse {
+    /// The opcode of the request.
+    pub request_opcode: u8,
+
+    /// The result.
+    pub result: ResultCode,
+
+    /// Response parameters.
+    pub parameter: Vec<u8>,
+}
+
+/// Implementation of `ControlResponse`.
+impl ControlResponse {
+    /// Create a success response.
+    ///
+    /// # Arguments
+    /// * `request` - The request.
+    /// * `parameter` - The response parameters.
+    ///
+    /// # Returns
+    /// * `ControlResponse` - The response.
+    pub fn success(request: ControlRequest, parameter: &[u8]) -> Self {
+        Self {
+            request_opcode: request.opcode(),
+            result: ResultCode::Success,
+            parameter: parameter.to_vec(),
+        }
+    }
+
+    /// Create a failure response.
+    ///
+    /// # Arguments
+    /// * `request_opcode` - The opcode of the request.
+    /// * `result` - The result code.
+    ///
+    /// # Returns
+    /// * `ControlResponse` - The response.
+    pub fn failure(request_opcode: u8, result: ResultCode) -> Self {
+        Self {
+            request_opcode,
+            result,
+            parameter: Vec::new(),
+        }
+    }
+
+    /// Encode the response indication.
+    ///
+    /// # Returns
+    /// * `Vec<u8>` - The response opcode, request opcode, result code and parameters.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = vec![RESPONSE_OPCODE, self.request_opcode, self.result as u8];
+        value.extend_from_slice(&self.parameter);
+
+        value
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_requests() {
+        assert_eq!(
+            ControlRequest::decode(&[0x01, 0x90, 0x01]),
+            Ok(ControlRequest::ForcedRecalibration { target_ppm: 400 })
+        );
+        assert_eq!(
+            ControlRequest::decode(&[0x02]),
+            Ok(ControlRequest::SelfTest)
+        );
+        assert_eq!(
+            ControlRequest::decode(&[0x03]),
+            Ok(ControlRequest::FactoryReset)
+        );
+        assert_eq!(
+            ControlRequest::decode(&[0x04, 0x00]),
+            Ok(ControlRequest::SetAutomaticSelfCalibration { enabled: false })
+        );
+        assert_eq!(
+            ControlRequest::decode(&[0x04, 0x01]),
+            Ok(ControlRequest::SetAutomaticSelfCalibration { enabled: true })
+        );
+        assert_eq!(ControlRequest::decode(&[0x05]), Ok(ControlRequest::Reboot));
+        assert_eq!(
+            ControlRequest::decode(&[0x06]),
+            Ok(ControlRequest::ClearBonds)
+        );
+    }
+
+    #[test]
+    fn opcode_round_trip() {
+        for value in [
+            &[0x01, 0xe8, 0x03][..],
+            &[0x02],
+            &[0x03],
+            &[0x04, 1],
+            &[0x05],
+            &[0x06],
+        ] {
+            assert_eq!(ControlRequest::decode(value).unwrap().opcode(), value[0]);
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let unsupported = |opcode| ControlResponse::failure(opcode, ResultCode::OpcodeNotSupported);
+        let invalid = |opcode| ControlResponse::failure(opcode, ResultCode::InvalidParameter);
+
+        assert_eq!(ControlRequest::decode(&[]), Err(unsupported(0x00)));
+        assert_eq!(ControlRequest::decode(&[0x07]), Err(unsupported(0x07)));
+        assert_eq!(ControlRequest::decode(&[0x80]), Err(unsupported(0x80)));
+
+        assert_eq!(ControlRequest::decode(&[0x01, 0x90]), Err(invalid(0x01)));
+        assert_eq!(
+            ControlRequest::decode(&[0x01, 0x8f, 0x01]),
+            Err(invalid(0x01))
+        );
+        assert_eq!(
+            ControlRequest::decode(&[0x01, 0x89, 0x13]),
+            Err(invalid(0x01))
+        );
+        assert_eq!(ControlRequest::decode(&[0x02, 0x00]), Err(invalid(0x02)));
+        assert_eq!(ControlRequest::decode(&[0x04, 0x02]), Err(invalid(0x04)));
+        assert_eq!(ControlRequest::decode(&[0x04]), Err(invalid(0x04)));
+        assert_eq!(ControlRequest::decode(&[0x06, 0x00]), Err(invalid(0x06)));
+    }
+
+    #[test]
+    fn encode_responses() {
+        assert_eq!(
+            ControlResponse::success(
+                ControlRequest::ForcedRecalibration { target_ppm: 420 },
+                &(-25i16).to_le_bytes()
+            )
+            .encode(),
+            [0x80, 0x01, 0x01, 0xe7, 0xff]
+        );
+        assert_eq!(
+            ControlResponse::failure(0x02, ResultCode::OperationFailed).encode(),
+            [0x80, 0x02, 0x04]
+        );
+    }
+}
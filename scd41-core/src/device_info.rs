@@ -0,0 +1,152 @@
+//! Device Information Service values.
+
+/// Device Information Service UUID.
+pub const DEVICE_INFORMATION_SERVICE_UUID: u16 = 0x180a;
+
+/// Device Information Service characteristic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisCharacteristic {
+    /// Manufacturer Name String.
+    ManufacturerName,
+
+    /// Model Number String.
+    ModelNumber,
+
+    /// Serial Number String.
+    SerialNumber,
+
+    /// Hardware Revision String.
+    HardwareRevision,
+
+    /// Firmware Revision String.
+    FirmwareRevision,
+}
+
+/// Implementation of `DisCharacteristic`.
+impl DisCharacteristic {
+    /// All characteristics, in registration order.
+    pub const ALL: [DisCharacteristic; 5] = [
+        DisCharacteristic::ManufacturerName,
+        DisCharacteristic::ModelNumber,
+        DisCharacteristic::SerialNumber,
+        DisCharacteristic::HardwareRevision,
+        DisCharacteristic::FirmwareRevision,
+    ];
+
+    /// Look up a characteristic by UUID.
+    ///
+    /// # Arguments
+    /// * `uuid` - The 16-bit UUID.
+    ///
+    /// # Returns
+    /// * `Option<DisCharacteristic>` - The characteristic, or `None` if unknown.
+    pub fn from_uuid(uuid: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.uuid() == uuid)
+    }
+
+    /// Get the 16-bit UUID.
+    ///
+    /// # Returns
+    /// * `u16` - The UUID.
+    pub fn uuid(self) -> u16 {
+        match self {
+            DisCharacteristic::ManufacturerName => 0x2a29,
+            DisCharacteristic::ModelNumber => 0x2a24,
+            DisCharacteristic::SerialNumber => 0x2a25,
+            DisCharacteristic::HardwareRevision => 0x2a27,
+            DisCharacteristic::FirmwareRevision => 0x2a26,
+        }
+    }
+
+    /// Get the index into [`DisCharacteristic::ALL`].
+    ///
+    /// # Returns
+    /// * `usize` - The index.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Identification strings reported by the Device Information Service.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Manufacturer name.
+    pub manufacturer_name: String,
+
+    /// Model number.
+    pub model_number: String,
+
+    /// Serial number.
+    pub serial_number: String,
+
+    /// Hardware revision.
+    pub hardware_revision: String,
+
+    /// Firmware revision.
+    pub firmware_revision: String,
+}
+
+/// Implementation of `DeviceInfo`.
+impl DeviceInfo {
+    /// Get the value of a characteristic.
+    ///
+    /// # Arguments
+    /// * `characteristic` - The characteristic.
+    ///
+    /// # Returns
+    /// * `&str` - The UTF-8 value.
+    pub fn value(&self, characteristic: DisCharacteristic) -> &str {
+        match characteristic {
+            DisCharacteristic::ManufacturerName => &self.manufacturer_name,
+            DisCharacteristic::ModelNumber => &self.model_number,
+            DisCharacteristic::SerialNumber => &self.serial_number,
+            DisCharacteristic::HardwareRevision => &self.hardware_revision,
+            DisCharacteristic::FirmwareRevision => &self.firmware_revision,
+        }
+    }
+}
+
+/// Format an SCD41 serial number for the Serial Number String.
+///
+/// # Arguments
+/// * `serial_number` - The 48-bit serial number.
+///
+/// # Returns
+/// * `String` - Twelve upper-case hexadecimal digits.
+pub fn format_serial_number(serial_number: u64) -> String {
+    format!("{:012X}", serial_number & 0xffff_ffff_ffff)
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_lookup() {
+        for characteristic in DisCharacteristic::ALL {
+            assert_eq!(
+                DisCharacteristic::from_uuid(characteristic.uuid()),
+                Some(characteristic)
+            );
+            assert_eq!(
+                DisCharacteristic::ALL[characteristic.index()],
+                characteristic
+            );
+        }
+        assert_eq!(DisCharacteristic::from_uuid(0x2a28), None);
+    }
+
+    #[test]
+    fn values() {
+        let info = DeviceInfo {
+            serial_number: format_serial_number(0xf8969f073bbe),
+            firmware_revision: "0.1.0".into(),
+            ..DeviceInfo::default()
+        };
+
+        assert_eq!(info.value(DisCharacteristic::SerialNumber), "F8969F073BBE");
+        assert_eq!(info.value(DisCharacteristic::FirmwareRevision), "0.1.0");
+        assert_eq!(format_serial_number(0x2a), "00000000002A");
+    }
+}
@@ -0,0 +1,547 @@
+//! Environmental Sensing Service (ESS) encodings.
+
+use crate::gatt::{Format, PresentationFormat, UNIT_CELSIUS, UNIT_PERCENT, UNIT_PPM};
+
+/// Environmental Sensing Service UUID.
+pub const ESS_SERVICE_UUID: u16 = 0x181a;
+
+/// Environmental Sensing Measurement descriptor UUID.
+pub const ES_MEASUREMENT_UUID: u16 = 0x290c;
+
+/// Environmental Sensing Trigger Setting descriptor UUID.
+pub const ES_TRIGGER_SETTING_UUID: u16 = 0x290d;
+
+/// Valid Range descriptor UUID.
+pub const VALID_RANGE_UUID: u16 = 0x2906;
+
+/// ES Measurement sampling function: instantaneous.
+const SAMPLING_INSTANTANEOUS: u8 = 0x01;
+
+/// ES Measurement application: air.
+const APPLICATION_AIR: u8 = 0x01;
+
+/// ES Measurement uncertainty: information not available.
+const UNCERTAINTY_UNKNOWN: u8 = 0xff;
+
+/// Largest value of a 24-bit field.
+const U24_MAX: u32 = 0x00ff_ffff;
+
+/// ESS characteristic exposed by the monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EssCharacteristic {
+    /// Temperature (0x2A6E), `sint16` in 0.01 °C.
+    Temperature,
+
+    /// Humidity (0x2A6F), `uint16` in 0.01 %.
+    Humidity,
+
+    /// CO2 concentration (0x2B8C), `uint16` in ppm.
+    Co2,
+}
+
+/// Implementation of `EssCharacteristic`.
+impl EssCharacteristic {
+    /// All characteristics in registration order.
+    pub const ALL: [EssCharacteristic; 3] = [
+        EssCharacteristic::Temperature,
+        EssCharacteristic::Humidity,
+        EssCharacteristic::Co2,
+    ];
+
+    /// Get the 16-bit characteristic UUID.
+    ///
+    /// # Returns
+    /// * `u16` - The UUID.
+    pub fn uuid(self) -> u16 {
+        match self {
+            EssCharacteristic::Temperature => 0x2a6e,
+            EssCharacteristic::Humidity => 0x2a6f,
+            EssCharacteristic::Co2 => 0x2b8c,
+        }
+    }
+
+    /// Look up a characteristic by its 16-bit UUID.
+    ///
+    /// # Arguments
+    /// * `uuid` - The UUID.
+    ///
+    /// # Returns
+    /// * `Option<EssCharacteristic>` - The characteristic, or `None` if unknown.
+    pub fn from_uuid(uuid: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.uuid() == uuid)
+    }
+
+    /// Get the index of the characteristic in [`EssCharacteristic::ALL`].
+    ///
+    /// # Returns
+    /// * `usize` - The index.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Whether the value is signed.
+    ///
+    /// # Returns
+    /// * `bool` - `true` for temperature.
+    fn is_signed(self) -> bool {
+        matches!(self, EssCharacteristic::Temperature)
+    }
+
+    /// Get the SCD41 measurement range in characteristic units.
+    ///
+    /// # Returns
+    /// * `(i32, i32)` - The lower and upper bounds.
+    pub fn range(self) -> (i32, i32) {
+        match self {
+            EssCharacteristic::Temperature => (-1000, 6000),
+            EssCharacteristic::Humidity => (0, 10000),
+            EssCharacteristic::Co2 => (400, 5000),
+        }
+    }
+
+    /// Get the Characteristic Presentation Format descriptor.
+    ///
+    /// # Returns
+    /// * `PresentationFormat` - The format, exponent and unit of the value.
+    pub fn presentation_format(self) -> PresentationFormat {
+        let (format, exponent, unit) = match self {
+            EssCharacteristic::Temperature => (Format::Sint16, -2, UNIT_CELSIUS),
+            EssCharacteristic::Humidity => (Format::Uint16, -2, UNIT_PERCENT),
+            EssCharacteristic::Co2 => (Format::Uint16, 0, UNIT_PPM),
+        };
+
+        PresentationFormat {
+            format,
+            exponent,
+            unit,
+        }
+    }
+
+    /// Get the Characteristic User Description.
+    ///
+    /// # Returns
+    /// * `&'static str` - The description.
+    pub fn description(self) -> &'static str {
+        match self {
+            EssCharacteristic::Temperature => "Temperature",
+            EssCharacteristic::Humidity => "Relative humidity",
+            EssCharacteristic::Co2 => "CO2 concentration",
+        }
+    }
+
+    /// Encode a value in the characteristic format.
+    ///
+    /// # Arguments
+    /// * `value` - The value in characteristic units.
+    ///
+    /// # Returns
+    /// * `[u8; 2]` - The little-endian value, saturated to the format.
+    pub fn encode(self, value: i32) -> [u8; 2] {
+        if self.is_signed() {
+            (value.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes()
+        } else {
+            (value.clamp(0, u16::MAX as i32) as u16).to_le_bytes()
+        }
+    }
+
+    /// Decode a value in the characteristic format.
+    ///
+    /// # Arguments
+    /// * `bytes` - The little-endian value.
+    ///
+    /// # Returns
+    /// * `i32` - The value in characteristic units.
+    pub fn decode(self, bytes: [u8; 2]) -> i32 {
+        if self.is_signed() {
+            i16::from_le_bytes(bytes) as i32
+        } else {
+            u16::from_le_bytes(bytes) as i32
+        }
+    }
+
+    /// Encode the Valid Range descriptor.
+    ///
+    /// # Returns
+    /// * `[u8; 4]` - The lower and upper bounds in the characteristic format.
+    pub fn valid_range(self) -> [u8; 4] {
+        let (lower, upper) = self.range();
+        let (lower, upper) = (self.encode(lower), self.encode(upper));
+
+        [lower[0], lower[1], upper[0], upper[1]]
+    }
+
+    /// Encode the ES Measurement descriptor.
+    ///
+    /// # Arguments
+    /// * `interval_s` - The measurement period and update interval in seconds.
+    ///
+    /// # Returns
+    /// * `[u8; 11]` - The descriptor value.
+    pub fn measurement_descriptor(self, interval_s: u32) -> [u8; 11] {
+        // Uncertainty is in 0.5 % steps; SCD41 accuracy is ±5 % (CO2) and ±6 % (RH).
+        let uncertainty = match self {
+            EssCharacteristic::Temperature => UNCERTAINTY_UNKNOWN,
+            EssCharacteristic::Humidity => 12,
+            EssCharacteristic::Co2 => 10,
+        };
+        let interval = interval_s.min(U24_MAX).to_le_bytes();
+
+        [
+            0x00, // flags (reserved)
+            0x00,
+            SAMPLING_INSTANTANEOUS,
+            interval[0], // measurement period
+            interval[1],
+            interval[2],
+            interval[0], // update interval
+            interval[1],
+            interval[2],
+            APPLICATION_AIR,
+            uncertainty,
+        ]
+    }
+}
+
+/// Trigger setting error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TriggerError {
+    /// The value has the wrong length for its condition.
+    InvalidLength,
+
+    /// The condition is not supported.
+    UnsupportedCondition(u8),
+}
+
+/// Implementation of the `Display` trait for `TriggerError`.
+impl core::fmt::Display for TriggerError {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TriggerError::InvalidLength => write!(f, "Invalid trigger setting length"),
+            TriggerError::UnsupportedCondition(condition) => {
+                write!(f, "Unsupported trigger condition 0x{condition:02x}")
+            }
+        }
+    }
+}
+
+/// Implementation of the `Error` trait for `TriggerError`.
+impl std::error::Error for TriggerError {}
+
+/// ES Trigger Setting: when a notification should be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerSetting {
+    /// Never notify.
+    Inactive,
+
+    /// Notify at a fixed interval.
+    FixedInterval {
+        /// Interval in seconds.
+        seconds: u32,
+    },
+
+    /// Notify on change, but no more often than the given interval.
+    MinInterval {
+        /// Interval in seconds.
+        seconds: u32,
+    },
+
+    /// Notify whenever the value changes.
+    OnChange,
+
+    /// Notify while the value is less than the operand.
+    LessThan(i32),
+
+    /// Notify while the value is less than or equal to the operand.
+    LessOrEqual(i32),
+
+    /// Notify while the value is greater than the operand.
+    GreaterThan(i32),
+
+    /// Notify while the value is greater than or equal to the operand.
+    GreaterOrEqual(i32),
+
+    /// Notify while the value equals the operand.
+    Equal(i32),
+
+    /// Notify while the value differs from the operand.
+    NotEqual(i32),
+}
+
+/// Implementation of the `Default` trait for `TriggerSetting`.
+impl Default for TriggerSetting {
+    /// Notify whenever the value changes.
+    ///
+    /// # Returns
+    /// * `TriggerSetting` - The default trigger setting.
+    fn default() -> Self {
+        TriggerSetting::OnChange
+    }
+}
+
+/// Implementation of `TriggerSetting`.
+impl TriggerSetting {
+    /// Decode a trigger setting descriptor value.
+    ///
+    /// # Arguments
+    /// * `characteristic` - The characteristic the descriptor belongs to.
+    /// * `bytes` - The descriptor value.
+    ///
+    /// # Returns
+    /// * `Result<TriggerSetting, TriggerError>` - The trigger setting or an error.
+    pub fn decode(characteristic: EssCharacteristic, bytes: &[u8]) -> Result<Self, TriggerError> {
+        let (&condition, operand) = bytes.split_first().ok_or(TriggerError::InvalidLength)?;
+
+        let seconds = || match operand {
+            [a, b, c] => Ok(u32::from_le_bytes([*a, *b, *c, 0])),
+            _ => Err(TriggerError::InvalidLength),
+        };
+        let value = || match operand {
+            [a, b] => Ok(characteristic.decode([*a, *b])),
+            _ => Err(TriggerError::InvalidLength),
+        };
+        let none = || {
+            if operand.is_empty() {
+                Ok(())
+            } else {
+                Err(TriggerError::InvalidLength)
+            }
+        };
+
+        match condition {
+            0x00 => none().map(|_| TriggerSetting::Inactive),
+            0x01 => seconds().map(|seconds| TriggerSetting::FixedInterval { seconds }),
+            0x02 => seconds().map(|seconds| TriggerSetting::MinInterval { seconds }),
+            0x03 => none().map(|_| TriggerSetting::OnChange),
+            0x04 => value().map(TriggerSetting::LessThan),
+            0x05 => value().map(TriggerSetting::LessOrEqual),
+            0x06 => value().map(TriggerSetting::GreaterThan),
+            0x07 => value().map(TriggerSetting::GreaterOrEqual),
+            0x08 => value().map(TriggerSetting::Equal),
+            0x09 => value().map(TriggerSetting::NotEqual),
+            other => Err(TriggerError::UnsupportedCondition(other)),
+        }
+    }
+
+    /// Encode the trigger setting descriptor value.
+    ///
+    /// # Arguments
+    /// * `characteristic` - The characteristic the descriptor belongs to.
+    ///
+    /// # Returns
+    /// * `Vec<u8>` - The condition followed by its operand, if any.
+    pub fn encode(self, characteristic: EssCharacteristic) -> Vec<u8> {
+        let seconds = |condition: u8, seconds: u32| {
+            let bytes = seconds.min(U24_MAX).to_le_bytes();
+            vec![condition, bytes[0], bytes[1], bytes[2]]
+        };
+        let value = |condition: u8, value: i32| {
+            let bytes = characteristic.encode(value);
+            vec![condition, bytes[0], bytes[1]]
+        };
+
+        match self {
+            TriggerSetting::Inactive => vec![0x00],
+            TriggerSetting::FixedInterval { seconds: s } => seconds(0x01, s),
+            TriggerSetting::MinInterval { seconds: s } => seconds(0x02, s),
+            TriggerSetting::OnChange => vec![0x03],
+            TriggerSetting::LessThan(v) => value(0x04, v),
+            TriggerSetting::LessOrEqual(v) => value(0x05, v),
+            TriggerSetting::GreaterThan(v) => value(0x06, v),
+            TriggerSetting::GreaterOrEqual(v) => value(0x07, v),
+            TriggerSetting::Equal(v) => value(0x08, v),
+            TriggerSetting::NotEqual(v) => value(0x09, v),
+        }
+    }
+
+    /// Decide whether a new value should be notified.
+    ///
+    /// # Arguments
+    /// * `value` - The new value.
+    /// * `last` - The last notified value and the milliseconds elapsed since, if any.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if a notification should be sent.
+    pub fn should_notify(self, value: i32, last: Option<(i32, u64)>) -> bool {
+        let elapsed = |seconds: u32| !matches!(last, Some((_, ms)) if ms < seconds as u64 * 1000);
+        let changed = !matches!(last, Some((previous, _)) if previous == value);
+
+        match self {
+            TriggerSetting::Inactive => false,
+            TriggerSetting::FixedInterval { seconds } => elapsed(seconds),
+            TriggerSetting::MinInterval { seconds } => changed && elapsed(seconds),
+            TriggerSetting::OnChange => changed,
+            TriggerSetting::LessThan(operand) => value < operand,
+            TriggerSetting::LessOrEqual(operand) => value <= operand,
+            TriggerSetting::GreaterThan(operand) => value > operand,
+            TriggerSetting::GreaterOrEqual(operand) => value >= operand,
+            TriggerSetting::Equal(operand) => value == operand,
+            TriggerSetting::NotEqual(operand) => value != operand,
+        }
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_lookup() {
+        for characteristic in EssCharacteristic::ALL {
+            assert_eq!(
+                EssCharacteristic::from_uuid(characteristic.uuid()),
+                Some(characteristic)
+            );
+        }
+        assert_eq!(EssCharacteristic::from_uuid(0x2a6d), None);
+        assert_eq!(EssCharacteristic::Co2.uuid(), 0x2b8c);
+    }
+
+    #[test]
+    fn valid_ranges() {
+        // -10.00 °C .. 60.00 °C as sint16
+        assert_eq!(
+            EssCharacteristic::Temperature.valid_range(),
+            [0x18, 0xfc, 0x70, 0x17]
+        );
+        // 0 % .. 100.00 %
+        assert_eq!(
+            EssCharacteristic::Humidity.valid_range(),
+            [0x00, 0x00, 0x10, 0x27]
+        );
+        // 400 .. 5000 ppm
+        assert_eq!(
+            EssCharacteristic::Co2.valid_range(),
+            [0x90, 0x01, 0x88, 0x13]
+        );
+    }
+
+    #[test]
+    fn presentation_formats() {
+        assert_eq!(
+            EssCharacteristic::Temperature
+                .presentation_format()
+                .encode(),
+            [0x0e, 0xfe, 0x2f, 0x27, 0x01, 0x00, 0x00]
+        );
+        assert_eq!(
+            EssCharacteristic::Humidity.presentation_format().encode(),
+            [0x06, 0xfe, 0xad, 0x27, 0x01, 0x00, 0x00]
+        );
+        assert_eq!(
+            EssCharacteristic::Co2.presentation_format().encode(),
+            [0x06, 0x00, 0xc4, 0x27, 0x01, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn encode_saturates() {
+        assert_eq!(EssCharacteristic::Co2.encode(-5), [0, 0]);
+        assert_eq!(EssCharacteristic::Co2.encode(70_000), [0xff, 0xff]);
+        assert_eq!(EssCharacteristic::Temperature.encode(-40_000), [0x00, 0x80]);
+        assert_eq!(EssCharacteristic::Temperature.decode([0x00, 0x80]), -32768);
+    }
+
+    #[test]
+    fn measurement_descriptor_layout() {
+        assert_eq!(
+            EssCharacteristic::Co2.measurement_descriptor(5),
+            [0x00, 0x00, 0x01, 0x05, 0x00, 0x00, 0x05, 0x00, 0x00, 0x01, 0x0a]
+        );
+        assert_eq!(
+            EssCharacteristic::Temperature.measurement_descriptor(0x0123_4567)[3..6],
+            [0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn trigger_round_trip() {
+        let settings = [
+            TriggerSetting::Inactive,
+            TriggerSetting::FixedInterval { seconds: 60 },
+            TriggerSetting::MinInterval { seconds: 0x10203 },
+            TriggerSetting::OnChange,
+            TriggerSetting::LessThan(-500),
+            TriggerSetting::LessOrEqual(0),
+            TriggerSetting::GreaterThan(2500),
+            TriggerSetting::GreaterOrEqual(1),
+            TriggerSetting::Equal(7),
+            TriggerSetting::NotEqual(-1),
+        ];
+
+        for setting in settings {
+            let bytes = setting.encode(EssCharacteristic::Temperature);
+            assert_eq!(
+                TriggerSetting::decode(EssCharacteristic::Temperature, &bytes),
+                Ok(setting)
+            );
+        }
+    }
+
+    #[test]
+    fn trigger_known_bytes() {
+        assert_eq!(
+            TriggerSetting::decode(EssCharacteristic::Co2, &[0x06, 0xe8, 0x03]),
+            Ok(TriggerSetting::GreaterThan(1000))
+        );
+        assert_eq!(
+            TriggerSetting::FixedInterval { seconds: 300 }.encode(EssCharacteristic::Co2),
+            vec![0x01, 0x2c, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn trigger_decode_errors() {
+        let co2 = EssCharacteristic::Co2;
+        assert_eq!(
+            TriggerSetting::decode(co2, &[]),
+            Err(TriggerError::InvalidLength)
+        );
+        assert_eq!(
+            TriggerSetting::decode(co2, &[0x03, 0x00]),
+            Err(TriggerError::InvalidLength)
+        );
+        assert_eq!(
+            TriggerSetting::decode(co2, &[0x01, 0x00]),
+            Err(TriggerError::InvalidLength)
+        );
+        assert_eq!(
+            TriggerSetting::decode(co2, &[0x0a]),
+            Err(TriggerError::UnsupportedCondition(0x0a))
+        );
+    }
+
+    #[test]
+    fn should_notify_conditions() {
+        assert!(!TriggerSetting::Inactive.should_notify(1, None));
+
+        assert!(TriggerSetting::OnChange.should_notify(1, None));
+        assert!(!TriggerSetting::OnChange.should_notify(1, Some((1, 10_000))));
+        assert!(TriggerSetting::OnChange.should_notify(2, Some((1, 0))));
+
+        let fixed = TriggerSetting::FixedInterval { seconds: 10 };
+        assert!(fixed.should_notify(1, None));
+        assert!(!fixed.should_notify(2, Some((1, 9_999))));
+        assert!(fixed.should_notify(1, Some((1, 10_000))));
+
+        let min = TriggerSetting::MinInterval { seconds: 10 };
+        assert!(!min.should_notify(1, Some((1, 20_000))));
+        assert!(!min.should_notify(2, Some((1, 5_000))));
+        assert!(min.should_notify(2, Some((1, 10_000))));
+
+        assert!(TriggerSetting::GreaterThan(1000).should_notify(1001, None));
+        assert!(!TriggerSetting::GreaterThan(1000).should_notify(1000, None));
+        assert!(TriggerSetting::GreaterOrEqual(1000).should_notify(1000, None));
+        assert!(TriggerSetting::LessThan(0).should_notify(-1, None));
+        assert!(TriggerSetting::LessOrEqual(0).should_notify(0, None));
+        assert!(TriggerSetting::Equal(5).should_notify(5, None));
+        assert!(TriggerSetting::NotEqual(5).should_notify(6, None));
+    }
+}
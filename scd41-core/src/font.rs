@@ -1,4 +1,7 @@
 /// 6x8 font.
+///
+/// Glyphs are stored column-major, one byte per column with bit 0 at the top,
+/// starting at ASCII `0x20` (space).
 pub const FONT_6X8: [[u8; 6]; 160] = [
     [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
     [0x00, 0x00, 0x5f, 0x00, 0x00, 0x00],
@@ -161,3 +164,25 @@ pub const FONT_6X8: [[u8; 6]; 160] = [
     [0x38, 0x44, 0x44, 0x44, 0x38, 0x00],
     [0x7c, 0x04, 0x04, 0x04, 0x7c, 0x00],
 ];
+
+/// Width of a glyph in pixels.
+pub const GLYPH_WIDTH: u8 = 6;
+
+/// Height of a glyph in pixels.
+pub const GLYPH_HEIGHT: u8 = 8;
+
+/// First character covered by [`FONT_6X8`].
+const FIRST_CHAR: u32 = 0x20;
+
+/// Look up the glyph for a character.
+///
+/// # Arguments
+/// * `c` - The character to look up.
+///
+/// # Returns
+/// * `Option<&[u8; 6]>` - The glyph columns, or `None` if the font has no glyph for `c`.
+pub fn glyph(c: char) -> Option<&'static [u8; 6]> {
+    let index = (c as u32).checked_sub(FIRST_CHAR)? as usize;
+
+    FONT_6X8.get(index)
+}
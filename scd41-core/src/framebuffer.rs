@@ -0,0 +1,412 @@
+use crate::{font::glyph, layout::PlacedLine};
+
+/// Panel width in pixels.
+pub const PANEL_WIDTH: usize = 128;
+
+/// Panel height in pixels.
+pub const PANEL_HEIGHT: usize = 64;
+
+/// Size of a full frame in bytes (one bit per pixel).
+pub const FRAME_SIZE: usize = PANEL_WIDTH * PANEL_HEIGHT / 8;
+
+/// SSD1306 segment remap: column 0 mapped to SEG0.
+const SEG_REMAP_NORMAL: u8 = 0xa0;
+
+/// SSD1306 segment remap: column 127 mapped to SEG0.
+const SEG_REMAP_FLIPPED: u8 = 0xa1;
+
+/// SSD1306 COM scan direction: COM0 to COM[N-1].
+const COM_SCAN_NORMAL: u8 = 0xc0;
+
+/// SSD1306 COM scan direction: COM[N-1] to COM0.
+const COM_SCAN_FLIPPED: u8 = 0xc8;
+
+/// Display orientation, clockwise rotation of the content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    /// Landscape, the original mounting.
+    #[default]
+    Deg0,
+
+    /// Portrait.
+    Deg90,
+
+    /// Landscape, upside down.
+    Deg180,
+
+    /// Portrait, upside down.
+    Deg270,
+}
+
+/// Implementation of `Orientation`.
+impl Orientation {
+    /// All orientations in index order.
+    pub const ALL: [Orientation; 4] = [
+        Orientation::Deg0,
+        Orientation::Deg90,
+        Orientation::Deg180,
+        Orientation::Deg270,
+    ];
+
+    /// Get the orientation from its index (number of quarter turns).
+    ///
+    /// # Arguments
+    /// * `index` - The index, `0..=3`.
+    ///
+    /// # Returns
+    /// * `Option<Orientation>` - The orientation, or `None` if the index is out of range.
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    /// Get the index (number of quarter turns) of the orientation.
+    ///
+    /// # Returns
+    /// * `u8` - The index, `0..=3`.
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Whether the logical canvas is taller than it is wide.
+    ///
+    /// # Returns
+    /// * `bool` - `true` for 90° and 270°.
+    pub fn is_portrait(self) -> bool {
+        matches!(self, Orientation::Deg90 | Orientation::Deg270)
+    }
+
+    /// Segment remap and COM scan commands for this orientation.
+    ///
+    /// The 180° part of the rotation is done by the panel; the remaining
+    /// quarter turn is done by [`Framebuffer::to_panel`].
+    ///
+    /// # Returns
+    /// * `[u8; 2]` - The segment remap and COM scan direction commands.
+    pub fn remap_commands(self) -> [u8; 2] {
+        match self {
+            Orientation::Deg0 | Orientation::Deg90 => [SEG_REMAP_FLIPPED, COM_SCAN_FLIPPED],
+            Orientation::Deg180 | Orientation::Deg270 => [SEG_REMAP_NORMAL, COM_SCAN_NORMAL],
+        }
+    }
+}
+
+/// Monochrome framebuffer in the logical (rotated) coordinate space.
+///
+/// Pixels are stored in SSD1306 page format: each byte is a vertical strip of
+/// eight pixels, bit 0 at the top, pages laid out left to right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    /// Orientation of the logical canvas.
+    orientation: Orientation,
+
+    /// Pixel data.
+    pixels: [u8; FRAME_SIZE],
+}
+
+/// Implementation of the `Default` trait for `Framebuffer`.
+impl Default for Framebuffer {
+    /// Create a blank landscape framebuffer.
+    ///
+    /// # Returns
+    /// * `Framebuffer` - The framebuffer.
+    fn default() -> Self {
+        Self::new(Orientation::default())
+    }
+}
+
+/// Implementation of `Framebuffer`.
+impl Framebuffer {
+    /// Create a blank framebuffer.
+    ///
+    /// # Arguments
+    /// * `orientation` - The orientation of the logical canvas.
+    ///
+    /// # Returns
+    /// * `Framebuffer` - The framebuffer.
+    pub fn new(orientation: Orientation) -> Self {
+        Self {
+            orientation,
+            pixels: [0; FRAME_SIZE],
+        }
+    }
+
+    /// Get the orientation.
+    ///
+    /// # Returns
+    /// * `Orientation` - The orientation of the logical canvas.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Change the orientation. The contents are cleared.
+    ///
+    /// # Arguments
+    /// * `orientation` - The new orientation.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+        self.clear();
+    }
+
+    /// Logical width in pixels.
+    ///
+    /// # Returns
+    /// * `usize` - The width.
+    pub fn width(&self) -> usize {
+        if self.orientation.is_portrait() {
+            PANEL_HEIGHT
+        } else {
+            PANEL_WIDTH
+        }
+    }
+
+    /// Logical height in pixels.
+    ///
+    /// # Returns
+    /// * `usize` - The height.
+    pub fn height(&self) -> usize {
+        FRAME_SIZE * 8 / self.width()
+    }
+
+    /// Logical height in 8-pixel pages.
+    ///
+    /// # Returns
+    /// * `usize` - The number of pages.
+    pub fn pages(&self) -> usize {
+        self.height() / 8
+    }
+
+    /// Clear all pixels.
+    pub fn clear(&mut self) {
+        self.pixels = [0; FRAME_SIZE];
+    }
+
+    /// Read a pixel in logical coordinates.
+    ///
+    /// # Arguments
+    /// * `x` - The column.
+    /// * `y` - The row.
+    ///
+    /// # Returns
+    /// * `bool` - Whether the pixel is lit; `false` outside the canvas.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        if x >= self.width() || y >= self.height() {
+            return false;
+        }
+
+        self.pixels[(y / 8) * self.width() + x] & (1 << (y % 8)) != 0
+    }
+
+    /// Set a pixel in logical coordinates. Out-of-range pixels are ignored.
+    ///
+    /// # Arguments
+    /// * `x` - The column.
+    /// * `y` - The row.
+    /// * `on` - Whether the pixel is lit.
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        if x >= self.width() || y >= self.height() {
+            return;
+        }
+
+        let index = (y / 8) * self.width() + x;
+        let mask = 1 << (y % 8);
+
+        if on {
+            self.pixels[index] |= mask;
+        } else {
+            self.pixels[index] &= !mask;
+        }
+    }
+
+    /// Draw a bitmap of 8-pixel columns into a page, clipping at `right`.
+    ///
+    /// # Arguments
+    /// * `x` - The left edge in pixels.
+    /// * `page` - The page.
+    /// * `columns` - The column bytes, bit 0 at the top.
+    /// * `right` - The right edge of the clip area in pixels (exclusive).
+    pub fn draw_columns<'c>(
+        &mut self,
+        x: usize,
+        page: usize,
+        columns: impl IntoIterator<Item = &'c u8>,
+        right: usize,
+    ) {
+        let right = right.min(self.width());
+
+        if page >= self.pages() {
+            return;
+        }
+
+        for (x, &byte) in (x..right).zip(columns) {
+            self.pixels[page * self.width() + x] = byte;
+        }
+    }
+
+    /// Draw a line of text, clipping glyph columns at `right`.
+    ///
+    /// # Arguments
+    /// * `line` - The positioned line.
+    /// * `right` - The right edge of the clip area in pixels (exclusive).
+    pub fn draw_text(&mut self, line: &PlacedLine, right: usize) {
+        let columns = line.text.chars().filter_map(glyph).flatten();
+
+        self.draw_columns(line.x as usize, line.page as usize, columns, right);
+    }
+
+    /// Convert the logical canvas to the panel's native page layout.
+    ///
+    /// Portrait canvases are rotated a quarter turn here; the panel's remap
+    /// commands take care of the rest (see [`Orientation::remap_commands`]).
+    ///
+    /// # Returns
+    /// * `[u8; FRAME_SIZE]` - The frame, ready to be written to display RAM.
+    pub fn to_panel(&self) -> [u8; FRAME_SIZE] {
+        if !self.orientation.is_portrait() {
+            return self.pixels;
+        }
+
+        let mut panel = [0; FRAME_SIZE];
+
+        for px in 0..PANEL_WIDTH {
+            for py in 0..PANEL_HEIGHT {
+                // Rotate clockwise: logical (x, y) lands on panel (y, H - 1 - x).
+                if self.pixel(PANEL_HEIGHT - 1 - py, px) {
+                    panel[(py / 8) * PANEL_WIDTH + px] |= 1 << (py % 8);
+                }
+            }
+        }
+
+        panel
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panel_pixel(panel: &[u8; FRAME_SIZE], x: usize, y: usize) -> bool {
+        panel[(y / 8) * PANEL_WIDTH + x] & (1 << (y % 8)) != 0
+    }
+
+    #[test]
+    fn orientation_index_round_trip() {
+        for orientation in Orientation::ALL {
+            assert_eq!(
+                Orientation::from_index(orientation.index()),
+                Some(orientation)
+            );
+        }
+        assert_eq!(Orientation::from_index(4), None);
+    }
+
+    #[test]
+    fn remap_commands_flip_for_upside_down() {
+        assert_eq!(Orientation::Deg0.remap_commands(), [0xa1, 0xc8]);
+        assert_eq!(Orientation::Deg180.remap_commands(), [0xa0, 0xc0]);
+        assert_eq!(
+            Orientation::Deg90.remap_commands(),
+            Orientation::Deg0.remap_commands()
+        );
+        assert_eq!(
+            Orientation::Deg270.remap_commands(),
+            Orientation::Deg180.remap_commands()
+        );
+    }
+
+    #[test]
+    fn dimensions_follow_orientation() {
+        let landscape = Framebuffer::new(Orientation::Deg180);
+        assert_eq!((landscape.width(), landscape.height()), (128, 64));
+        assert_eq!(landscape.pages(), 8);
+
+        let portrait = Framebuffer::new(Orientation::Deg270);
+        assert_eq!((portrait.width(), portrait.height()), (64, 128));
+        assert_eq!(portrait.pages(), 16);
+    }
+
+    #[test]
+    fn set_and_clear_pixels() {
+        let mut fb = Framebuffer::default();
+        fb.set_pixel(3, 10, true);
+        assert!(fb.pixel(3, 10));
+        assert_eq!(fb.to_panel()[PANEL_WIDTH + 3], 0b100);
+
+        fb.set_pixel(3, 10, false);
+        assert!(!fb.pixel(3, 10));
+
+        fb.set_pixel(200, 200, true);
+        assert_eq!(fb, Framebuffer::default());
+    }
+
+    #[test]
+    fn landscape_is_copied_verbatim() {
+        let mut fb = Framebuffer::new(Orientation::Deg180);
+        fb.set_pixel(0, 0, true);
+        fb.set_pixel(127, 63, true);
+
+        let panel = fb.to_panel();
+        assert!(panel_pixel(&panel, 0, 0));
+        assert!(panel_pixel(&panel, 127, 63));
+    }
+
+    #[test]
+    fn portrait_is_rotated_clockwise() {
+        let mut fb = Framebuffer::new(Orientation::Deg90);
+        fb.set_pixel(0, 0, true);
+        fb.set_pixel(63, 127, true);
+        fb.set_pixel(10, 20, true);
+
+        let panel = fb.to_panel();
+        assert!(panel_pixel(&panel, 0, 63));
+        assert!(panel_pixel(&panel, 127, 0));
+        assert!(panel_pixel(&panel, 20, 53));
+        assert_eq!(panel.iter().map(|b| b.count_ones()).sum::<u32>(), 3);
+    }
+
+    #[test]
+    fn draw_text_clips_at_right_edge() {
+        let mut fb = Framebuffer::new(Orientation::Deg90);
+        let line = PlacedLine {
+            text: "AAAAAAAAAAAA".to_string(),
+            x: 0,
+            page: 15,
+        };
+        fb.draw_text(&line, 200);
+
+        // 'A' column 0 is 0x7e; the twelfth glyph would start at x = 66.
+        assert!(fb.pixel(60, 15 * 8 + 1));
+        assert!(!fb.pixel(0, 14 * 8 + 1));
+        assert_eq!(fb.to_panel().len(), FRAME_SIZE);
+
+        let mut clipped = Framebuffer::default();
+        clipped.draw_text(&PlacedLine { page: 0, ..line }, 6);
+        assert!(clipped.pixel(0, 1));
+        assert!(!clipped.pixel(6, 1));
+    }
+
+    #[test]
+    fn draw_columns_places_bitmap() {
+        let mut fb = Framebuffer::default();
+        fb.draw_columns(10, 3, &[0x01, 0x80], PANEL_WIDTH);
+        assert!(fb.pixel(10, 24));
+        assert!(fb.pixel(11, 31));
+        assert!(!fb.pixel(12, 24));
+
+        fb.draw_columns(127, 0, &[0xff, 0xff], PANEL_WIDTH);
+        assert!(fb.pixel(127, 0));
+        assert!(!fb.pixel(0, 8));
+    }
+
+    #[test]
+    fn draw_text_ignores_pages_outside_canvas() {
+        let mut fb = Framebuffer::default();
+        let line = PlacedLine {
+            text: "A".to_string(),
+            x: 0,
+            page: 8,
+        };
+        fb.draw_text(&line, PANEL_WIDTH);
+        assert_eq!(fb, Framebuffer::default());
+    }
+}
@@ -0,0 +1,873 @@
+//! Declarative GATT table.
+//!
+//! Services, characteristics and descriptors are declared up front, each
+//! characteristic tagged with an application key. The Bluetooth stack adds
+//! attributes one at a time and reports their handles back; the table drives that
+//! sequence, checks every reported UUID against the declaration and records the
+//! handles, so that reads and writes resolve to keys rather than handles.
+
+use crate::cccd::Cccd;
+
+/// Characteristic User Description descriptor UUID.
+pub const USER_DESCRIPTION_UUID: u16 = 0x2901;
+
+/// Client Characteristic Configuration descriptor UUID.
+pub const CCCD_UUID: u16 = 0x2902;
+
+/// Characteristic Presentation Format descriptor UUID.
+pub const PRESENTATION_FORMAT_UUID: u16 = 0x2904;
+
+/// Unit: unitless.
+pub const UNIT_UNITLESS: u16 = 0x2700;
+
+/// Unit: degree Celsius.
+pub const UNIT_CELSIUS: u16 = 0x272f;
+
+/// Unit: percentage.
+pub const UNIT_PERCENT: u16 = 0x27ad;
+
+/// Unit: concentration in parts per million.
+pub const UNIT_PPM: u16 = 0x27c4;
+
+/// Presentation Format namespace: Bluetooth SIG Assigned Numbers.
+const NAMESPACE_BLUETOOTH_SIG: u8 = 0x01;
+
+/// Attribute UUID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uuid {
+    /// 16-bit SIG-assigned UUID.
+    Short(u16),
+
+    /// 128-bit UUID.
+    Long(u128),
+}
+
+/// Implementation of `Uuid`.
+impl Uuid {
+    /// Parse a UUID as sent over the air.
+    ///
+    /// # Arguments
+    /// * `bytes` - The UUID, little-endian.
+    ///
+    /// # Returns
+    /// * `Option<Uuid>` - The UUID, or `None` if it is neither 2 nor 16 bytes long.
+    pub fn from_le_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [low, high] => Some(Uuid::Short(u16::from_le_bytes([*low, *high]))),
+            _ => Some(Uuid::Long(u128::from_le_bytes(bytes.try_into().ok()?))),
+        }
+    }
+}
+
+/// Characteristic properties, as declared to clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Properties(u8);
+
+/// Implementation of `Properties`.
+impl Properties {
+    /// The value can be read.
+    pub const READ: Properties = Properties(0x02);
+
+    /// The value can be written without a response.
+    pub const WRITE_WITHOUT_RESPONSE: Properties = Properties(0x04);
+
+    /// The value can be written with a response.
+    pub const WRITE: Properties = Properties(0x08);
+
+    /// The value can be notified.
+    pub const NOTIFY: Properties = Properties(0x10);
+
+    /// The value can be indicated.
+    pub const INDICATE: Properties = Properties(0x20);
+
+    /// Combine two sets of properties.
+    ///
+    /// # Arguments
+    /// * `other` - The properties to add.
+    ///
+    /// # Returns
+    /// * `Properties` - Both sets of properties.
+    pub const fn union(self, other: Properties) -> Self {
+        Properties(self.0 | other.0)
+    }
+
+    /// Whether every property of `other` is set.
+    ///
+    /// # Arguments
+    /// * `other` - The properties to look for.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if all are set.
+    pub fn contains(self, other: Properties) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Get the CCCD modes the properties allow.
+    ///
+    /// # Returns
+    /// * `Cccd` - Notifications and indications, as far as supported.
+    pub fn cccd_modes(self) -> Cccd {
+        match (self.contains(Self::NOTIFY), self.contains(Self::INDICATE)) {
+            (true, true) => Cccd::BOTH,
+            (true, false) => Cccd::NOTIFY,
+            (false, true) => Cccd::INDICATE,
+            (false, false) => Cccd::NONE,
+        }
+    }
+}
+
+/// Attribute access permissions, enforced by the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Permissions(u8);
+
+/// Implementation of `Permissions`.
+impl Permissions {
+    /// No access.
+    pub const NONE: Permissions = Permissions(0x00);
+
+    /// Readable by any peer.
+    pub const READ: Permissions = Permissions(0x01);
+
+    /// Writable by any peer.
+    pub const WRITE: Permissions = Permissions(0x02);
+
+    /// Writable over an encrypted link with an authenticated, MITM-protected key.
+    pub const WRITE_AUTHENTICATED: Permissions = Permissions(0x04);
+
+    /// Combine two sets of permissions.
+    ///
+    /// # Arguments
+    /// * `other` - The permissions to add.
+    ///
+    /// # Returns
+    /// * `Permissions` - Both sets of permissions.
+    pub const fn union(self, other: Permissions) -> Self {
+        Permissions(self.0 | other.0)
+    }
+
+    /// Whether every permission of `other` is granted.
+    ///
+    /// # Arguments
+    /// * `other` - The permissions to look for.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if all are granted.
+    pub fn contains(self, other: Permissions) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Value format of a Characteristic Presentation Format descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Unsigned 8-bit integer.
+    Uint8 = 0x04,
+
+    /// Unsigned 16-bit integer.
+    Uint16 = 0x06,
+
+    /// Unsigned 32-bit integer.
+    Uint32 = 0x08,
+
+    /// Signed 16-bit integer.
+    Sint16 = 0x0e,
+
+    /// Opaque structure, described by the User Description.
+    Struct = 0x1b,
+}
+
+/// Characteristic Presentation Format descriptor: how to interpret a value.
+///
+/// The represented value is the raw integer times ten to the power of the
+/// exponent, in the given unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentationFormat {
+    /// The value format.
+    pub format: Format,
+
+    /// The base 10 exponent.
+    pub exponent: i8,
+
+    /// The unit, a Bluetooth SIG assigned number.
+    pub unit: u16,
+}
+
+/// Implementation of `PresentationFormat`.
+impl PresentationFormat {
+    /// Encode the descriptor value.
+    ///
+    /// # Returns
+    /// * `[u8; 7]` - The format, exponent, unit, namespace and an unknown
+    ///   description.
+    pub fn encode(self) -> [u8; 7] {
+        let unit = self.unit.to_le_bytes();
+
+        [
+            self.format as u8,
+            self.exponent as u8,
+            unit[0],
+            unit[1],
+            NAMESPACE_BLUETOOTH_SIG,
+            0x00, // description: unknown
+            0x00,
+        ]
+    }
+}
+
+/// Descriptor declaration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorDef {
+    /// 16-bit UUID.
+    pub uuid: u16,
+
+    /// Access permissions.
+    pub permissions: Permissions,
+}
+
+/// Characteristic declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharacteristicDef<K> {
+    /// Application key the value and descriptors resolve to.
+    pub key: K,
+
+    /// UUID.
+    pub uuid: Uuid,
+
+    /// Properties.
+    pub properties: Properties,
+
+    /// Maximum value length in bytes.
+    pub max_len: usize,
+
+    /// Descriptors, in registration order.
+    pub descriptors: Vec<DescriptorDef>,
+}
+
+/// Implementation of `CharacteristicDef`.
+impl<K> CharacteristicDef<K> {
+    /// Declare a characteristic.
+    ///
+    /// Characteristics that notify or indicate get a CCCD, writable by any peer
+    /// so that subscribing does not require pairing.
+    ///
+    /// # Arguments
+    /// * `key` - The application key.
+    /// * `uuid` - The UUID.
+    /// * `properties` - The properties.
+    /// * `max_len` - The maximum value length in bytes.
+    ///
+    /// # Returns
+    /// * `CharacteristicDef<K>` - The declaration.
+    pub fn new(key: K, uuid: Uuid, properties: Properties, max_len: usize) -> Self {
+        let mut descriptors = Vec::new();
+        if properties.cccd_modes() != Cccd::NONE {
+            descriptors.push(DescriptorDef {
+                uuid: CCCD_UUID,
+                permissions: Permissions::READ.union(Permissions::WRITE),
+            });
+        }
+
+        Self {
+            key,
+            uuid,
+            properties,
+            max_len,
+            descriptors,
+        }
+    }
+
+    /// Add a descriptor after the ones already declared.
+    ///
+    /// # Arguments
+    /// * `uuid` - The 16-bit UUID.
+    /// * `permissions` - The access permissions.
+    ///
+    /// # Returns
+    /// * `CharacteristicDef<K>` - The declaration.
+    pub fn descriptor(mut self, uuid: u16, permissions: Permissions) -> Self {
+        self.descriptors.push(DescriptorDef { uuid, permissions });
+        self
+    }
+
+    /// Get the value permissions.
+    ///
+    /// # Returns
+    /// * `Permissions` - Readable if the value can be read, and writable over an
+    ///   authenticated link only if it can be written: every write changes the
+    ///   device, so none are accepted from unbonded peers.
+    pub fn permissions(&self) -> Permissions {
+        let mut permissions = Permissions::NONE;
+        if self.properties.contains(Properties::READ) {
+            permissions = permissions.union(Permissions::READ);
+        }
+        if self.properties.contains(Properties::WRITE)
+            || self.properties.contains(Properties::WRITE_WITHOUT_RESPONSE)
+        {
+            permissions = permissions.union(Permissions::WRITE_AUTHENTICATED);
+        }
+        permissions
+    }
+}
+
+/// Service declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDef<K> {
+    /// UUID.
+    pub uuid: Uuid,
+
+    /// Characteristics, in registration order.
+    pub characteristics: Vec<CharacteristicDef<K>>,
+}
+
+/// Implementation of `ServiceDef`.
+impl<K> ServiceDef<K> {
+    /// Declare a primary service.
+    ///
+    /// # Arguments
+    /// * `uuid` - The UUID.
+    /// * `characteristics` - The characteristics, in registration order.
+    ///
+    /// # Returns
+    /// * `ServiceDef<K>` - The declaration.
+    pub fn new(uuid: Uuid, characteristics: Vec<CharacteristicDef<K>>) -> Self {
+        Self {
+            uuid,
+            characteristics,
+        }
+    }
+
+    /// Get the number of handles the service needs.
+    ///
+    /// # Returns
+    /// * `u16` - One for the service declaration, plus a declaration and value per
+    ///   characteristic and one per descriptor.
+    pub fn num_handles(&self) -> u16 {
+        let attributes: usize = self
+            .characteristics
+            .iter()
+            .map(|c| 2 + c.descriptors.len())
+            .sum();
+        1 + attributes as u16
+    }
+}
+
+/// A registered attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribute<K> {
+    /// The value of a characteristic.
+    Value(K),
+
+    /// A descriptor of a characteristic, by UUID.
+    Descriptor(K, u16),
+}
+
+/// What to add next to a service being registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step<'a, K> {
+    /// Add a characteristic.
+    AddCharacteristic(&'a CharacteristicDef<K>),
+
+    /// Add a descriptor to the characteristic added last.
+    AddDescriptor(&'a DescriptorDef),
+
+    /// The service is complete.
+    Done,
+}
+
+/// GATT table registration error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GattTableError {
+    /// The stack created a service that is not declared.
+    UnknownService(Uuid),
+
+    /// The stack reported an attribute for a service that is not being registered.
+    UnknownServiceHandle(u16),
+
+    /// The stack reported an attribute other than the one being added.
+    UnexpectedAttribute(Uuid),
+}
+
+/// Implementation of the `Display` trait for `GattTableError`.
+impl core::fmt::Display for GattTableError {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            GattTableError::UnknownService(uuid) => write!(f, "Unknown service {:?}", uuid),
+            GattTableError::UnknownServiceHandle(handle) => {
+                write!(f, "Service handle {} is not being registered", handle)
+            }
+            GattTableError::UnexpectedAttribute(uuid) => {
+                write!(f, "Unexpected attribute {:?}", uuid)
+            }
+        }
+    }
+}
+
+/// Implementation of the `Error` trait for `GattTableError`.
+impl std::error::Error for GattTableError {}
+
+/// Registration progress of a service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Progress {
+    /// Service handle.
+    service_handle: u16,
+
+    /// Index of the characteristic being added, or of the one whose descriptors
+    /// are being added.
+    characteristic: usize,
+
+    /// Number of descriptors of that characteristic added so far, `None` while
+    /// the characteristic itself is being added.
+    descriptors: Option<usize>,
+}
+
+/// GATT table: the declared services and the handles the stack assigned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GattTable<K> {
+    /// Declared services and their registration progress.
+    services: Vec<(ServiceDef<K>, Option<Progress>)>,
+
+    /// Registered attributes: handle, service index and attribute.
+    attributes: Vec<(u16, usize, Attribute<K>)>,
+}
+
+/// Implementation of the `Default` trait for `GattTable`.
+impl<K> Default for GattTable<K> {
+    /// Create a table without services.
+    ///
+    /// # Returns
+    /// * `GattTable<K>` - The empty table.
+    fn default() -> Self {
+        Self {
+            services: Vec::new(),
+            attributes: Vec::new(),
+        }
+    }
+}
+
+/// Implementation of `GattTable`.
+impl<K: Copy + PartialEq> GattTable<K> {
+    /// Create a table.
+    ///
+    /// # Arguments
+    /// * `services` - The services, in creation order.
+    ///
+    /// # Returns
+    /// * `GattTable<K>` - The table, with nothing registered yet.
+    pub fn new(services: Vec<ServiceDef<K>>) -> Self {
+        Self {
+            services: services.into_iter().map(|s| (s, None)).collect(),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// List the services to create.
+    ///
+    /// # Returns
+    /// * `impl Iterator<Item = &ServiceDef<K>>` - The services, in creation order.
+    pub fn services(&self) -> impl Iterator<Item = &ServiceDef<K>> {
+        self.services.iter().map(|(service, _)| service)
+    }
+
+    /// Start registering a service once the stack has created it, forgetting any
+    /// handles from an earlier registration.
+    ///
+    /// # Arguments
+    /// * `uuid` - The service UUID.
+    /// * `service_handle` - The service handle.
+    ///
+    /// # Returns
+    /// * `Result<Step<K>, GattTableError>` - The first characteristic to add.
+    pub fn on_service_created(
+        &mut self,
+        uuid: Uuid,
+        service_handle: u16,
+    ) -> Result<Step<'_, K>, GattTableError> {
+        let index = self
+            .services
+            .iter()
+            .position(|(service, _)| service.uuid == uuid)
+            .ok_or(GattTableError::UnknownService(uuid))?;
+
+        self.attributes.retain(|(_, service, _)| *service != index);
+        self.services[index].1 = Some(Progress {
+            service_handle,
+            characteristic: 0,
+            descriptors: None,
+        });
+
+        Ok(self.step(index))
+    }
+
+    /// Record the handle of an added characteristic value.
+    ///
+    /// # Arguments
+    /// * `service_handle` - The service handle.
+    /// * `uuid` - The characteristic UUID.
+    /// * `handle` - The value handle.
+    ///
+    /// # Returns
+    /// * `Result<Step<K>, GattTableError>` - What to add next.
+    pub fn on_characteristic_added(
+        &mut self,
+        service_handle: u16,
+        uuid: Uuid,
+        handle: u16,
+    ) -> Result<Step<'_, K>, GattTableError> {
+        let index = self.registering(service_handle)?;
+        let (service, progress) = &mut self.services[index];
+        let Some(progress) = progress.as_mut().filter(|p| p.descriptors.is_none()) else {
+            return Err(GattTableError::UnexpectedAttribute(uuid));
+        };
+        let characteristic = service
+            .characteristics
+            .get(progress.characteristic)
+            .filter(|c| c.uuid == uuid)
+            .ok_or(GattTableError::UnexpectedAttribute(uuid))?;
+
+        self.attributes
+            .push((handle, index, Attribute::Value(characteristic.key)));
+        progress.descriptors = Some(0);
+
+        Ok(self.step(index))
+    }
+
+    /// Record the handle of an added descriptor.
+    ///
+    /// # Arguments
+    /// * `service_handle` - The service handle.
+    /// * `uuid` - The descriptor UUID.
+    /// * `handle` - The descriptor handle.
+    ///
+    /// # Returns
+    /// * `Result<Step<K>, GattTableError>` - What to add next.
+    pub fn on_descriptor_added(
+        &mut self,
+        service_handle: u16,
+        uuid: Uuid,
+        handle: u16,
+    ) -> Result<Step<'_, K>, GattTableError> {
+        let index = self.registering(service_handle)?;
+        let (service, progress) = &mut self.services[index];
+        let Some(progress) = progress.as_mut() else {
+            return Err(GattTableError::UnexpectedAttribute(uuid));
+        };
+        let Some(added) = progress.descriptors else {
+            return Err(GattTableError::UnexpectedAttribute(uuid));
+        };
+        let characteristic = &service.characteristics[progress.characteristic];
+        let descriptor = characteristic
+            .descriptors
+            .get(added)
+            .filter(|d| Uuid::Short(d.uuid) == uuid)
+            .ok_or(GattTableError::UnexpectedAttribute(uuid))?;
+
+        self.attributes.push((
+            handle,
+            index,
+            Attribute::Descriptor(characteristic.key, descriptor.uuid),
+        ));
+        progress.descriptors = Some(added + 1);
+
+        Ok(self.step(index))
+    }
+
+    /// Find the service being registered under a handle.
+    ///
+    /// # Arguments
+    /// * `service_handle` - The service handle.
+    ///
+    /// # Returns
+    /// * `Result<usize, GattTableError>` - The service index.
+    fn registering(&self, service_handle: u16) -> Result<usize, GattTableError> {
+        self.services
+            .iter()
+            .position(|(_, progress)| progress.is_some_and(|p| p.service_handle == service_handle))
+            .ok_or(GattTableError::UnknownServiceHandle(service_handle))
+    }
+
+    /// Advance the registration of a service past every attribute already added.
+    ///
+    /// # Arguments
+    /// * `index` - The service index.
+    ///
+    /// # Returns
+    /// * `Step<K>` - What to add next.
+    fn step(&mut self, index: usize) -> Step<'_, K> {
+        let (service, progress) = &mut self.services[index];
+        let Some(progress) = progress.as_mut() else {
+            return Step::Done;
+        };
+
+        if let Some(added) = progress.descriptors {
+            let descriptors = &service.characteristics[progress.characteristic].descriptors;
+            if let Some(descriptor) = descriptors.get(added) {
+                return Step::AddDescriptor(descriptor);
+            }
+
+            progress.characteristic += 1;
+            progress.descriptors = None;
+        }
+
+        match service.characteristics.get(progress.characteristic) {
+            Some(characteristic) => Step::AddCharacteristic(characteristic),
+            None => Step::Done,
+        }
+    }
+
+    /// Look up what an attribute handle refers to.
+    ///
+    /// # Arguments
+    /// * `handle` - The attribute handle.
+    ///
+    /// # Returns
+    /// * `Option<Attribute<K>>` - The attribute, or `None` if the handle is not registered.
+    pub fn resolve(&self, handle: u16) -> Option<Attribute<K>> {
+        self.attributes
+            .iter()
+            .find(|(h, _, _)| *h == handle)
+            .map(|(_, _, attribute)| *attribute)
+    }
+
+    /// Look up the handle of an attribute.
+    ///
+    /// # Arguments
+    /// * `attribute` - The attribute.
+    ///
+    /// # Returns
+    /// * `Option<u16>` - The handle, or `None` if the attribute is not registered.
+    pub fn attribute_handle(&self, attribute: Attribute<K>) -> Option<u16> {
+        self.attributes
+            .iter()
+            .find(|(_, _, a)| *a == attribute)
+            .map(|(handle, _, _)| *handle)
+    }
+
+    /// Look up the value handle of a characteristic.
+    ///
+    /// # Arguments
+    /// * `key` - The characteristic key.
+    ///
+    /// # Returns
+    /// * `Option<u16>` - The handle, or `None` if the characteristic is not registered.
+    pub fn handle(&self, key: K) -> Option<u16> {
+        self.attribute_handle(Attribute::Value(key))
+    }
+
+    /// Look up the CCCD handle of a characteristic.
+    ///
+    /// # Arguments
+    /// * `key` - The characteristic key.
+    ///
+    /// # Returns
+    /// * `Option<u16>` - The handle, or `None` if the characteristic has no
+    ///   registered CCCD.
+    pub fn cccd_handle(&self, key: K) -> Option<u16> {
+        self.attribute_handle(Attribute::Descriptor(key, CCCD_UUID))
+    }
+
+    /// Look up the declaration of a characteristic.
+    ///
+    /// # Arguments
+    /// * `key` - The characteristic key.
+    ///
+    /// # Returns
+    /// * `Option<&CharacteristicDef<K>>` - The declaration, or `None` if not declared.
+    pub fn characteristic(&self, key: K) -> Option<&CharacteristicDef<K>> {
+        self.services()
+            .flat_map(|service| &service.characteristics)
+            .find(|c| c.key == key)
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Characteristic keys.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Key {
+        Level,
+        Setting,
+        Name,
+    }
+
+    /// Descriptor UUID used by the tests.
+    const RANGE_UUID: u16 = 0x2906;
+
+    fn table() -> GattTable<Key> {
+        GattTable::new(vec![
+            ServiceDef::new(
+                Uuid::Long(0x1234),
+                vec![
+                    CharacteristicDef::new(
+                        Key::Level,
+                        Uuid::Short(0x2a19),
+                        Properties::READ.union(Properties::NOTIFY),
+                        1,
+                    )
+                    .descriptor(RANGE_UUID, Permissions::READ),
+                    CharacteristicDef::new(
+                        Key::Setting,
+                        Uuid::Long(0x5678),
+                        Properties::READ.union(Properties::WRITE),
+                        4,
+                    ),
+                ],
+            ),
+            ServiceDef::new(
+                Uuid::Short(0x180a),
+                vec![CharacteristicDef::new(
+                    Key::Name,
+                    Uuid::Short(0x2a29),
+                    Properties::READ,
+                    32,
+                )],
+            ),
+        ])
+    }
+
+    fn add_characteristic(step: Step<'_, Key>) -> Uuid {
+        match step {
+            Step::AddCharacteristic(characteristic) => characteristic.uuid,
+            step => panic!("expected a characteristic, got {step:?}"),
+        }
+    }
+
+    fn add_descriptor(step: Step<'_, Key>) -> Uuid {
+        match step {
+            Step::AddDescriptor(descriptor) => Uuid::Short(descriptor.uuid),
+            step => panic!("expected a descriptor, got {step:?}"),
+        }
+    }
+
+    /// Register every attribute, interleaving both services.
+    fn register(table: &mut GattTable<Key>) {
+        let level = add_characteristic(table.on_service_created(Uuid::Long(0x1234), 40).unwrap());
+        let name = add_characteristic(table.on_service_created(Uuid::Short(0x180a), 60).unwrap());
+
+        let cccd = add_descriptor(table.on_characteristic_added(40, level, 42).unwrap());
+        assert_eq!(
+            table.on_characteristic_added(60, name, 62).unwrap(),
+            Step::Done
+        );
+        let range = add_descriptor(table.on_descriptor_added(40, cccd, 43).unwrap());
+        let setting = add_characteristic(table.on_descriptor_added(40, range, 44).unwrap());
+        assert_eq!(
+            table.on_characteristic_added(40, setting, 46).unwrap(),
+            Step::Done
+        );
+    }
+
+    #[test]
+    fn handle_counts() {
+        let table = table();
+        let counts: Vec<u16> = table.services().map(ServiceDef::num_handles).collect();
+        assert_eq!(counts, [1 + 4 + 2, 1 + 2]);
+    }
+
+    #[test]
+    fn cccd_and_permissions() {
+        let table = table();
+        let level = table.characteristic(Key::Level).unwrap();
+        assert_eq!(level.descriptors[0].uuid, CCCD_UUID);
+        assert_eq!(level.properties.cccd_modes(), Cccd::NOTIFY);
+        assert_eq!(level.permissions(), Permissions::READ);
+
+        let setting = table.characteristic(Key::Setting).unwrap();
+        assert!(setting.descriptors.is_empty());
+        assert!(setting
+            .permissions()
+            .contains(Permissions::READ.union(Permissions::WRITE_AUTHENTICATED)));
+        assert!(!setting.permissions().contains(Permissions::WRITE));
+    }
+
+    #[test]
+    fn registration_and_lookup() {
+        let mut table = table();
+        register(&mut table);
+
+        assert_eq!(table.handle(Key::Level), Some(42));
+        assert_eq!(table.cccd_handle(Key::Level), Some(43));
+        assert_eq!(
+            table.attribute_handle(Attribute::Descriptor(Key::Level, RANGE_UUID)),
+            Some(44)
+        );
+        assert_eq!(table.handle(Key::Setting), Some(46));
+        assert_eq!(table.cccd_handle(Key::Setting), None);
+        assert_eq!(table.handle(Key::Name), Some(62));
+
+        assert_eq!(table.resolve(42), Some(Attribute::Value(Key::Level)));
+        assert_eq!(
+            table.resolve(43),
+            Some(Attribute::Descriptor(Key::Level, CCCD_UUID))
+        );
+        assert_eq!(table.resolve(62), Some(Attribute::Value(Key::Name)));
+        assert_eq!(table.resolve(45), None);
+    }
+
+    #[test]
+    fn recreation_forgets_handles() {
+        let mut table = table();
+        register(&mut table);
+
+        table.on_service_created(Uuid::Long(0x1234), 80).unwrap();
+        assert_eq!(table.handle(Key::Level), None);
+        assert_eq!(table.resolve(43), None);
+        assert_eq!(table.handle(Key::Name), Some(62));
+    }
+
+    #[test]
+    fn unexpected_events() {
+        let mut table = table();
+        assert_eq!(
+            table.on_service_created(Uuid::Short(0x180f), 1),
+            Err(GattTableError::UnknownService(Uuid::Short(0x180f)))
+        );
+        assert_eq!(
+            table.on_characteristic_added(40, Uuid::Short(0x2a19), 42),
+            Err(GattTableError::UnknownServiceHandle(40))
+        );
+
+        table.on_service_created(Uuid::Long(0x1234), 40).unwrap();
+        assert_eq!(
+            table.on_descriptor_added(40, Uuid::Short(CCCD_UUID), 41),
+            Err(GattTableError::UnexpectedAttribute(Uuid::Short(CCCD_UUID)))
+        );
+        assert_eq!(
+            table.on_characteristic_added(40, Uuid::Long(0x5678), 41),
+            Err(GattTableError::UnexpectedAttribute(Uuid::Long(0x5678)))
+        );
+        assert_eq!(table.resolve(41), None);
+    }
+
+    #[test]
+    fn presentation_format_bytes() {
+        let format = PresentationFormat {
+            format: Format::Sint16,
+            exponent: -2,
+            unit: UNIT_CELSIUS,
+        };
+        assert_eq!(format.encode(), [0x0e, 0xfe, 0x2f, 0x27, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn uuid_bytes() {
+        assert_eq!(
+            Uuid::from_le_bytes(&[0x02, 0x29]),
+            Some(Uuid::Short(CCCD_UUID))
+        );
+        assert_eq!(
+            Uuid::from_le_bytes(&0xc892f08b050249a68c52b959aa997e54u128.to_le_bytes()),
+            Some(Uuid::Long(0xc892f08b050249a68c52b959aa997e54))
+        );
+        assert_eq!(Uuid::from_le_bytes(&[1, 2, 3, 4]), None);
+    }
+}
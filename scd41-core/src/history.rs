@@ -0,0 +1,703 @@
+//! Measurement history and record access.
+//!
+//! The history is a ring buffer of timestamped readings, each with a sequence
+//! number that keeps increasing as records are added. Readings are stored with
+//! their uptime and reported in Unix time once the clock is known, so records
+//! taken before the clock was set get the right time too. Clients access it with a
+//! protocol modelled on the Bluetooth SIG Record Access Control Point (RACP):
+//! requests select records with an operator and an optional filter, records are
+//! streamed in MTU-sized chunks and every procedure ends with a response code.
+
+use std::collections::VecDeque;
+
+/// Encoded size of a record in bytes.
+pub const RECORD_LEN: usize = 14;
+
+/// ATT header bytes of a notification, subtracted from the MTU.
+const ATT_NOTIFICATION_HEADER_LEN: usize = 3;
+
+/// Default ATT MTU, used until a larger one is negotiated.
+const DEFAULT_ATT_MTU: u16 = 23;
+
+/// Response opcode carrying the number of records.
+const RACP_NUMBER_OF_RECORDS: u8 = 0x05;
+
+/// Response opcode carrying a response code.
+const RACP_RESPONSE_CODE: u8 = 0x06;
+
+/// A stored measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    /// Sequence number.
+    pub sequence: u32,
+
+    /// Time of the measurement in seconds since the Unix epoch, or since boot if the
+    /// clock is not set.
+    pub timestamp_s: u32,
+
+    /// CO2 in ppm.
+    pub co2: u16,
+
+    /// Temperature in 0.01 °C.
+    pub temperature: i16,
+
+    /// Relative humidity in 0.01 %.
+    pub humidity: u16,
+}
+
+/// Implementation of `Record`.
+impl Record {
+    /// Encode the record.
+    ///
+    /// # Returns
+    /// * `[u8; RECORD_LEN]` - The sequence number, timestamp, CO2, temperature and
+    ///   humidity, little-endian.
+    pub fn encode(&self) -> [u8; RECORD_LEN] {
+        let mut bytes = [0u8; RECORD_LEN];
+        bytes[0..4].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.timestamp_s.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.co2.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.temperature.to_le_bytes());
+        bytes[12..14].copy_from_slice(&self.humidity.to_le_bytes());
+
+        bytes
+    }
+
+    /// Decode a record.
+    ///
+    /// # Arguments
+    /// * `bytes` - The encoded record.
+    ///
+    /// # Returns
+    /// * `Option<Record>` - The record, or `None` if the length is wrong.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; RECORD_LEN] = bytes.try_into().ok()?;
+
+        Some(Self {
+            sequence: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            timestamp_s: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            co2: u16::from_le_bytes([bytes[8], bytes[9]]),
+            temperature: i16::from_le_bytes([bytes[10], bytes[11]]),
+            humidity: u16::from_le_bytes([bytes[12], bytes[13]]),
+        })
+    }
+}
+
+/// Record selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFilter {
+    /// All records.
+    All,
+
+    /// Records with a sequence number greater than or equal to the value.
+    SequenceAtLeast(u32),
+
+    /// Records with a timestamp greater than or equal to the value.
+    TimeAtLeast(u32),
+}
+
+/// Implementation of `RecordFilter`.
+impl RecordFilter {
+    /// Whether a record is selected.
+    ///
+    /// # Arguments
+    /// * `record` - The record.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if selected.
+    pub fn matches(self, record: &Record) -> bool {
+        match self {
+            RecordFilter::All => true,
+            RecordFilter::SequenceAtLeast(sequence) => record.sequence >= sequence,
+            RecordFilter::TimeAtLeast(timestamp_s) => record.timestamp_s >= timestamp_s,
+        }
+    }
+}
+
+/// Ring buffer of measurements, sampled at a fixed interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History {
+    /// The records, oldest first.
+    records: VecDeque<Record>,
+
+    /// Maximum number of records.
+    capacity: usize,
+
+    /// Minimum time between records in seconds.
+    interval_s: u32,
+
+    /// Sequence number of the next record.
+    next_sequence: u32,
+
+    /// Unix time at boot in seconds, `0` if unknown.
+    time_base_s: u32,
+}
+
+/// Implementation of `History`.
+impl History {
+    /// Create an empty history.
+    ///
+    /// # Arguments
+    /// * `capacity` - Maximum number of records; the oldest are dropped beyond it.
+    /// * `interval_s` - Minimum time between records in seconds.
+    ///
+    /// # Returns
+    /// * `History` - The history.
+    pub fn new(capacity: usize, interval_s: u32) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+            interval_s,
+            next_sequence: 0,
+            time_base_s: 0,
+        }
+    }
+
+    /// Set the Unix time at boot, used to report the record timestamps.
+    ///
+    /// # Arguments
+    /// * `time_base_s` - Seconds since the Unix epoch at boot.
+    pub fn set_time_base(&mut self, time_base_s: u32) {
+        self.time_base_s = time_base_s;
+    }
+
+    /// Whether the record timestamps are reported in Unix time.
+    ///
+    /// # Returns
+    /// * `bool` - `true` once the time base is set, `false` while they are uptimes.
+    pub fn is_unix_time(&self) -> bool {
+        self.time_base_s != 0
+    }
+
+    /// Get the minimum time between records.
+    ///
+    /// # Returns
+    /// * `u32` - The interval in seconds.
+    pub fn interval_s(&self) -> u32 {
+        self.interval_s
+    }
+
+    /// Record a measurement, unless the last record is more recent than the interval.
+    ///
+    /// # Arguments
+    /// * `uptime_s` - Time of the measurement in seconds since boot.
+    /// * `co2` - CO2 in ppm.
+    /// * `temperature` - Temperature in 0.01 °C.
+    /// * `humidity` - Relative humidity in 0.01 %.
+    ///
+    /// # Returns
+    /// * `Option<Record>` - The stored record, or `None` if it was skipped.
+    pub fn record(
+        &mut self,
+        uptime_s: u32,
+        co2: u16,
+        temperature: i16,
+        humidity: u16,
+    ) -> Option<Record> {
+        if let Some(last) = self.records.back() {
+            if uptime_s.saturating_sub(last.timestamp_s) < self.interval_s {
+                return None;
+            }
+        }
+
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+
+        let record = Record {
+            sequence: self.next_sequence,
+            timestamp_s: uptime_s,
+            co2,
+            temperature,
+            humidity,
+        };
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        if self.capacity > 0 {
+            self.records.push_back(record);
+        }
+
+        Some(self.reported(&record))
+    }
+
+    /// Iterate over the selected records, oldest first.
+    ///
+    /// # Arguments
+    /// * `filter` - The selection.
+    ///
+    /// # Returns
+    /// * `impl Iterator<Item = Record>` - The records.
+    pub fn records(&self, filter: RecordFilter) -> impl Iterator<Item = Record> + '_ {
+        self.records
+            .iter()
+            .map(|r| self.reported(r))
+            .filter(move |r| filter.matches(r))
+    }
+
+    /// Count the selected records.
+    ///
+    /// # Arguments
+    /// * `filter` - The selection.
+    ///
+    /// # Returns
+    /// * `usize` - The number of records.
+    pub fn count(&self, filter: RecordFilter) -> usize {
+        self.records(filter).count()
+    }
+
+    /// Delete the selected records.
+    ///
+    /// # Arguments
+    /// * `filter` - The selection.
+    ///
+    /// # Returns
+    /// * `usize` - The number of deleted records.
+    pub fn delete(&mut self, filter: RecordFilter) -> usize {
+        let time_base_s = self.time_base_s;
+        let before = self.records.len();
+        self.records.retain(|r| {
+            !filter.matches(&Record {
+                timestamp_s: r.timestamp_s.wrapping_add(time_base_s),
+                ..*r
+            })
+        });
+
+        before - self.records.len()
+    }
+
+    /// Convert a stored record to its reported time.
+    ///
+    /// # Arguments
+    /// * `record` - The stored record, timestamped with the uptime.
+    ///
+    /// # Returns
+    /// * `Record` - The record timestamped with the Unix time, if known.
+    fn reported(&self, record: &Record) -> Record {
+        Record {
+            timestamp_s: record.timestamp_s.wrapping_add(self.time_base_s),
+            ..*record
+        }
+    }
+
+    /// Encode the next chunk of a transfer.
+    ///
+    /// # Arguments
+    /// * `filter` - The selection.
+    /// * `cursor` - The lowest sequence number not yet sent.
+    /// * `max_len` - The maximum chunk size in bytes.
+    ///
+    /// # Returns
+    /// * `Option<(Vec<u8>, u32)>` - The encoded records and the cursor for the next
+    ///   chunk, or `None` once every selected record has been sent.
+    pub fn chunk(
+        &self,
+        filter: RecordFilter,
+        cursor: u32,
+        max_len: usize,
+    ) -> Option<(Vec<u8>, u32)> {
+        let per_chunk = (max_len / RECORD_LEN).max(1);
+        let mut chunk = Vec::with_capacity(per_chunk * RECORD_LEN);
+        let mut next_cursor = cursor;
+
+        for record in self
+            .records(filter)
+            .filter(|r| r.sequence >= cursor)
+            .take(per_chunk)
+        {
+            chunk.extend_from_slice(&record.encode());
+            next_cursor = record.sequence.wrapping_add(1);
+        }
+
+        (!chunk.is_empty()).then_some((chunk, next_cursor))
+    }
+}
+
+/// Get the notification payload size for a connection.
+///
+/// # Arguments
+/// * `mtu` - The negotiated ATT MTU, if any.
+///
+/// # Returns
+/// * `usize` - The largest value that fits in one notification.
+pub fn notification_payload_len(mtu: Option<u16>) -> usize {
+    mtu.unwrap_or(DEFAULT_ATT_MTU).max(DEFAULT_ATT_MTU) as usize - ATT_NOTIFICATION_HEADER_LEN
+}
+
+/// Record access request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RacpRequest {
+    /// Stream the selected records (opcode `0x01`).
+    ReportRecords(RecordFilter),
+
+    /// Delete the selected records (opcode `0x02`).
+    DeleteRecords(RecordFilter),
+
+    /// Abort the running transfer (opcode `0x03`).
+    Abort,
+
+    /// Report the number of selected records (opcode `0x04`).
+    ReportNumberOfRecords(RecordFilter),
+}
+
+/// Implementation of `RacpRequest`.
+impl RacpRequest {
+    /// Decode a record access request.
+    ///
+    /// The operator is `0x01` for all records or `0x03` for records greater than or
+    /// equal to an operand: filter type `0x01` with a `uint32` sequence number or
+    /// `0x02` with a `uint32` timestamp. Abort takes the null operator `0x00`.
+    ///
+    /// # Arguments
+    /// * `value` - The written value.
+    ///
+    /// # Returns
+    /// * `Result<RacpRequest, RacpResponse>` - The request, or the error response
+    ///   to indicate.
+    pub fn decode(value: &[u8]) -> Result<Self, RacpResponse> {
+        let Some((&opcode, rest)) = value.split_first() else {
+            return Err(RacpResponse::code(0x00, RacpCode::OpcodeNotSupported));
+        };
+        let error = |code| RacpResponse::code(opcode, code);
+
+        if !(0x01..=0x04).contains(&opcode) {
+            return Err(error(RacpCode::OpcodeNotSupported));
+        }
+        let Some((&operator, operand)) = rest.split_first() else {
+            return Err(error(RacpCode::InvalidOperator));
+        };
+
+        if opcode == 0x03 {
+            return match (operator, operand) {
+                (0x00, []) => Ok(RacpRequest::Abort),
+                (0x00, _) => Err(error(RacpCode::InvalidOperand)),
+                _ => Err(error(RacpCode::InvalidOperator)),
+            };
+        }
+
+        let filter = match (operator, operand) {
+            (0x01, []) => RecordFilter::All,
+            (0x01, _) => return Err(error(RacpCode::InvalidOperand)),
+            (0x03, [filter_type, a, b, c, d]) => {
+                let value = u32::from_le_bytes([*a, *b, *c, *d]);
+                match filter_type {
+                    0x01 => RecordFilter::SequenceAtLeast(value),
+                    0x02 => RecordFilter::TimeAtLeast(value),
+                    _ => return Err(error(RacpCode::OperandNotSupported)),
+                }
+            }
+            (0x03, _) => return Err(error(RacpCode::InvalidOperand)),
+            (0x02 | 0x04..=0x06, _) => return Err(error(RacpCode::OperatorNotSupported)),
+            _ => return Err(error(RacpCode::InvalidOperator)),
+        };
+
+        Ok(match opcode {
+            0x01 => RacpRequest::ReportRecords(filter),
+            0x02 => RacpRequest::DeleteRecords(filter),
+            _ => RacpRequest::ReportNumberOfRecords(filter),
+        })
+    }
+
+    /// Get the request opcode.
+    ///
+    /// # Returns
+    /// * `u8` - The opcode.
+    pub fn opcode(self) -> u8 {
+        match self {
+            RacpRequest::ReportRecords(_) => 0x01,
+            RacpRequest::DeleteRecords(_) => 0x02,
+            RacpRequest::Abort => 0x03,
+            RacpRequest::ReportNumberOfRecords(_) => 0x04,
+        }
+    }
+}
+
+/// Record access response code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RacpCode {
+    /// The procedure completed.
+    Success = 0x01,
+
+    /// The opcode is not supported.
+    OpcodeNotSupported = 0x02,
+
+    /// The operator is invalid for the opcode.
+    InvalidOperator = 0x03,
+
+    /// The operator is not supported.
+    OperatorNotSupported = 0x04,
+
+    /// The operand is malformed.
+    InvalidOperand = 0x05,
+
+    /// No record matched.
+    NoRecordsFound = 0x06,
+
+    /// The transfer was interrupted.
+    ProcedureNotCompleted = 0x08,
+
+    /// The filter type is not supported.
+    OperandNotSupported = 0x09,
+}
+
+/// Record access response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RacpResponse {
+    /// The number of selected records.
+    NumberOfRecords(u16),
+
+    /// The outcome of a procedure.
+    Code {
+        /// The opcode of the request.
+        request_opcode: u8,
+
+        /// The response code.
+        code: RacpCode,
+    },
+}
+
+/// Implementation of `RacpResponse`.
+impl RacpResponse {
+    /// Create a response code.
+    ///
+    /// # Arguments
+    /// * `request_opcode` - The opcode of the request.
+    /// * `code` - The response code.
+    ///
+    /// # Returns
+    /// * `RacpResponse` - The response.
+    pub fn code(request_opcode: u8, code: RacpCode) -> Self {
+        RacpResponse::Code {
+            request_opcode,
+            code,
+        }
+    }
+
+    /// Encode the response indication.
+    ///
+    /// # Returns
+    /// * `Vec<u8>` - The response opcode, the null operator and the operand.
+    pub fn encode(self) -> Vec<u8> {
+        match self {
+            RacpResponse::NumberOfRecords(count) => {
+                let count = count.to_le_bytes();
+                vec![RACP_NUMBER_OF_RECORDS, 0x00, count[0], count[1]]
+            }
+            RacpResponse::Code {
+                request_opcode,
+                code,
+            } => vec![RACP_RESPONSE_CODE, 0x00, request_opcode, code as u8],
+        }
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(count: u32) -> History {
+        let mut history = History::new(8, 60);
+        for i in 0..count {
+            history.record(i * 60, 400 + i as u16, 2000, 5000);
+        }
+        history
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let record = Record {
+            sequence: 0x01020304,
+            timestamp_s: 86400,
+            co2: 1234,
+            temperature: -550,
+            humidity: 4567,
+        };
+        let bytes = record.encode();
+
+        assert_eq!(
+            bytes,
+            [0x04, 0x03, 0x02, 0x01, 0x80, 0x51, 0x01, 0x00, 0xd2, 0x04, 0xda, 0xfd, 0xd7, 0x11]
+        );
+        assert_eq!(Record::decode(&bytes), Some(record));
+        assert_eq!(Record::decode(&bytes[1..]), None);
+    }
+
+    #[test]
+    fn records_respect_interval() {
+        let mut history = History::new(8, 60);
+
+        assert_eq!(history.record(0, 400, 0, 0).map(|r| r.sequence), Some(0));
+        assert_eq!(history.record(59, 410, 0, 0), None);
+        assert_eq!(history.record(60, 420, 0, 0).map(|r| r.sequence), Some(1));
+        assert_eq!(history.count(RecordFilter::All), 2);
+    }
+
+    #[test]
+    fn ring_buffer_drops_oldest() {
+        let history = history(10);
+
+        assert_eq!(history.count(RecordFilter::All), 8);
+        assert_eq!(
+            history.records(RecordFilter::All).next().unwrap().sequence,
+            2
+        );
+        assert_eq!(
+            history.records(RecordFilter::All).last().unwrap().sequence,
+            9
+        );
+    }
+
+    #[test]
+    fn filters() {
+        let history = history(5);
+
+        assert_eq!(history.count(RecordFilter::SequenceAtLeast(3)), 2);
+        assert_eq!(history.count(RecordFilter::TimeAtLeast(61)), 3);
+        assert_eq!(history.count(RecordFilter::TimeAtLeast(1000)), 0);
+    }
+
+    #[test]
+    fn time_base_applies_to_every_record() {
+        let mut history = history(3);
+        history.set_time_base(1_700_000_000);
+
+        let timestamps: Vec<u32> = history
+            .records(RecordFilter::All)
+            .map(|r| r.timestamp_s)
+            .collect();
+        assert_eq!(timestamps, [1_700_000_000, 1_700_000_060, 1_700_000_120]);
+        assert_eq!(history.count(RecordFilter::TimeAtLeast(1_700_000_060)), 2);
+        assert_eq!(
+            history.record(180, 400, 0, 0).map(|r| r.timestamp_s),
+            Some(1_700_000_180)
+        );
+        assert_eq!(history.delete(RecordFilter::TimeAtLeast(1_700_000_100)), 2);
+        assert_eq!(history.count(RecordFilter::All), 2);
+    }
+
+    #[test]
+    fn delete_keeps_sequence_numbers() {
+        let mut history = history(5);
+
+        assert_eq!(history.delete(RecordFilter::All), 5);
+        assert_eq!(history.count(RecordFilter::All), 0);
+        assert_eq!(history.record(1000, 400, 0, 0).map(|r| r.sequence), Some(5));
+    }
+
+    #[test]
+    fn chunks_fit_the_mtu() {
+        let history = history(5);
+
+        // Default MTU: one record per notification.
+        let payload = notification_payload_len(None);
+        assert_eq!(payload, 20);
+        let (chunk, cursor) = history.chunk(RecordFilter::All, 0, payload).unwrap();
+        assert_eq!(chunk.len(), RECORD_LEN);
+        assert_eq!(cursor, 1);
+
+        // MTU 50: three records per notification.
+        let payload = notification_payload_len(Some(50));
+        let (chunk, cursor) = history.chunk(RecordFilter::All, 0, payload).unwrap();
+        assert_eq!(chunk.len(), 3 * RECORD_LEN);
+        assert_eq!(Record::decode(&chunk[28..]).unwrap().sequence, 2);
+        let (chunk, cursor) = history.chunk(RecordFilter::All, cursor, payload).unwrap();
+        assert_eq!(chunk.len(), 2 * RECORD_LEN);
+        assert_eq!(history.chunk(RecordFilter::All, cursor, payload), None);
+    }
+
+    #[test]
+    fn chunks_follow_the_filter() {
+        let history = history(5);
+        let filter = RecordFilter::TimeAtLeast(120);
+
+        let (chunk, cursor) = history.chunk(filter, 0, 100).unwrap();
+        let sequences: Vec<u32> = chunk
+            .chunks(RECORD_LEN)
+            .map(|bytes| Record::decode(bytes).unwrap().sequence)
+            .collect();
+        assert_eq!(sequences, [2, 3, 4]);
+        assert_eq!(history.chunk(filter, cursor, 100), None);
+    }
+
+    #[test]
+    fn payload_len_ignores_invalid_mtu() {
+        assert_eq!(notification_payload_len(Some(10)), 20);
+        assert_eq!(notification_payload_len(Some(247)), 244);
+    }
+
+    #[test]
+    fn decode_requests() {
+        assert_eq!(
+            RacpRequest::decode(&[0x01, 0x01]),
+            Ok(RacpRequest::ReportRecords(RecordFilter::All))
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x01, 0x03, 0x01, 0x10, 0x00, 0x00, 0x00]),
+            Ok(RacpRequest::ReportRecords(RecordFilter::SequenceAtLeast(
+                16
+            )))
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x02, 0x03, 0x02, 0x80, 0x51, 0x01, 0x00]),
+            Ok(RacpRequest::DeleteRecords(RecordFilter::TimeAtLeast(86400)))
+        );
+        assert_eq!(RacpRequest::decode(&[0x03, 0x00]), Ok(RacpRequest::Abort));
+        assert_eq!(
+            RacpRequest::decode(&[0x04, 0x01]),
+            Ok(RacpRequest::ReportNumberOfRecords(RecordFilter::All))
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        let error = |opcode, code| Err(RacpResponse::code(opcode, code));
+
+        assert_eq!(
+            RacpRequest::decode(&[]),
+            error(0x00, RacpCode::OpcodeNotSupported)
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x05, 0x00]),
+            error(0x05, RacpCode::OpcodeNotSupported)
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x01]),
+            error(0x01, RacpCode::InvalidOperator)
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x01, 0x00]),
+            error(0x01, RacpCode::InvalidOperator)
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x03, 0x01]),
+            error(0x03, RacpCode::InvalidOperator)
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x01, 0x05]),
+            error(0x01, RacpCode::OperatorNotSupported)
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x01, 0x03, 0x01, 0x10]),
+            error(0x01, RacpCode::InvalidOperand)
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x01, 0x01, 0x00]),
+            error(0x01, RacpCode::InvalidOperand)
+        );
+        assert_eq!(
+            RacpRequest::decode(&[0x01, 0x03, 0x07, 0x00, 0x00, 0x00, 0x00]),
+            error(0x01, RacpCode::OperandNotSupported)
+        );
+    }
+
+    #[test]
+    fn encode_responses() {
+        assert_eq!(
+            RacpResponse::NumberOfRecords(300).encode(),
+            [0x05, 0x00, 0x2c, 0x01]
+        );
+        assert_eq!(
+            RacpResponse::code(0x01, RacpCode::NoRecordsFound).encode(),
+            [0x06, 0x00, 0x01, 0x06]
+        );
+    }
+}
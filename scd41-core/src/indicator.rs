@@ -0,0 +1,307 @@
+use crate::{air_quality::AirQuality, alarm::AlarmState};
+
+/// Default status LED brightness (out of 255).
+pub const DEFAULT_BRIGHTNESS: u8 = 32;
+
+/// Half period of the status LED blink while the alarm is active.
+const ALARM_BLINK_MS: u64 = 500;
+
+/// RGB colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    /// Red.
+    pub r: u8,
+
+    /// Green.
+    pub g: u8,
+
+    /// Blue.
+    pub b: u8,
+}
+
+/// Implementation of `Rgb`.
+impl Rgb {
+    /// Black, the LED switched off.
+    pub const OFF: Rgb = Rgb::new(0, 0, 0);
+
+    /// Create a colour.
+    ///
+    /// # Arguments
+    /// * `r` - Red.
+    /// * `g` - Green.
+    /// * `b` - Blue.
+    ///
+    /// # Returns
+    /// * `Rgb` - The colour.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Scale the colour by a brightness.
+    ///
+    /// # Arguments
+    /// * `brightness` - The brightness, `0` (off) to `255` (full).
+    ///
+    /// # Returns
+    /// * `Rgb` - The scaled colour.
+    pub fn scale(self, brightness: u8) -> Self {
+        let scale = |channel: u8| ((channel as u16 * brightness as u16 + 127) / 255) as u8;
+
+        Self::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+
+    /// Pack the colour in WS2812 wire order: green, red, blue, MSB first.
+    ///
+    /// # Returns
+    /// * `u32` - The 24-bit GRB value.
+    pub fn to_grb(self) -> u32 {
+        (self.g as u32) << 16 | (self.r as u32) << 8 | self.b as u32
+    }
+}
+
+/// Get the full-brightness colour for an air quality level.
+///
+/// # Arguments
+/// * `level` - The air quality level.
+///
+/// # Returns
+/// * `Rgb` - Green through yellow and orange to red.
+pub fn level_color(level: AirQuality) -> Rgb {
+    match level {
+        AirQuality::Excellent => Rgb::new(0, 255, 0),
+        AirQuality::Good => Rgb::new(128, 255, 0),
+        AirQuality::Fair => Rgb::new(255, 192, 0),
+        AirQuality::Poor => Rgb::new(255, 96, 0),
+        AirQuality::Bad => Rgb::new(255, 0, 0),
+    }
+}
+
+/// Get the status LED colour.
+///
+/// The LED shows the air quality level and blinks red while the alarm is active.
+///
+/// # Arguments
+/// * `level` - The air quality level, `None` before the first measurement.
+/// * `alarm` - The alarm state.
+/// * `brightness` - The brightness, `0` (off) to `255` (full).
+/// * `elapsed_ms` - Time since the alarm state last changed.
+///
+/// # Returns
+/// * `Rgb` - The colour to show.
+pub fn status_color(
+    level: Option<AirQuality>,
+    alarm: AlarmState,
+    brightness: u8,
+    elapsed_ms: u64,
+) -> Rgb {
+    let color = match alarm {
+        AlarmState::Active if elapsed_ms % (2 * ALARM_BLINK_MS) < ALARM_BLINK_MS => {
+            level_color(AirQuality::Bad)
+        }
+        AlarmState::Active => Rgb::OFF,
+        AlarmState::Latched => level_color(AirQuality::Bad),
+        _ => level.map_or(Rgb::OFF, level_color),
+    };
+
+    color.scale(brightness)
+}
+
+/// One beep of a pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeepStep {
+    /// Tone duration in milliseconds.
+    pub on_ms: u32,
+
+    /// Silence after the tone in milliseconds.
+    pub off_ms: u32,
+}
+
+/// Repeating buzzer pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeepPattern {
+    /// The beeps, played in order and then repeated.
+    pub steps: &'static [BeepStep],
+}
+
+/// Three short beeps followed by a pause.
+const ACTIVE_STEPS: &[BeepStep] = &[
+    BeepStep {
+        on_ms: 150,
+        off_ms: 100,
+    },
+    BeepStep {
+        on_ms: 150,
+        off_ms: 100,
+    },
+    BeepStep {
+        on_ms: 150,
+        off_ms: 1350,
+    },
+];
+
+/// A short reminder chirp every ten seconds.
+const LATCHED_STEPS: &[BeepStep] = &[BeepStep {
+    on_ms: 50,
+    off_ms: 9950,
+}];
+
+/// Implementation of `BeepPattern`.
+impl BeepPattern {
+    /// Length of one repetition in milliseconds.
+    ///
+    /// # Returns
+    /// * `u64` - The period.
+    pub fn period_ms(&self) -> u64 {
+        self.steps
+            .iter()
+            .map(|step| step.on_ms as u64 + step.off_ms as u64)
+            .sum()
+    }
+
+    /// Whether the tone is on at a point in the pattern.
+    ///
+    /// # Arguments
+    /// * `elapsed_ms` - Time since the pattern started.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if the buzzer should sound.
+    pub fn is_on(&self, elapsed_ms: u64) -> bool {
+        let period = self.period_ms();
+        if period == 0 {
+            return false;
+        }
+
+        let mut offset = elapsed_ms % period;
+
+        for step in self.steps {
+            let length = step.on_ms as u64 + step.off_ms as u64;
+            if offset < length {
+                return offset < step.on_ms as u64;
+            }
+
+            offset -= length;
+        }
+
+        false
+    }
+}
+
+/// Get the buzzer pattern for an alarm state.
+///
+/// # Arguments
+/// * `alarm` - The alarm state.
+///
+/// # Returns
+/// * `Option<BeepPattern>` - The pattern, or `None` for silence.
+pub fn beep_pattern(alarm: AlarmState) -> Option<BeepPattern> {
+    match alarm {
+        AlarmState::Active => Some(BeepPattern {
+            steps: ACTIVE_STEPS,
+        }),
+        AlarmState::Latched => Some(BeepPattern {
+            steps: LATCHED_STEPS,
+        }),
+        _ => None,
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_brightness() {
+        let color = Rgb::new(255, 128, 0);
+        assert_eq!(color.scale(255), color);
+        assert_eq!(color.scale(0), Rgb::OFF);
+        assert_eq!(color.scale(128), Rgb::new(128, 64, 0));
+    }
+
+    #[test]
+    fn grb_wire_order() {
+        assert_eq!(Rgb::new(0x11, 0x22, 0x33).to_grb(), 0x221133);
+        assert_eq!(Rgb::new(255, 0, 0).to_grb(), 0x00ff00);
+    }
+
+    #[test]
+    fn level_colors_go_from_green_to_red() {
+        assert_eq!(level_color(AirQuality::Excellent), Rgb::new(0, 255, 0));
+        assert_eq!(level_color(AirQuality::Bad), Rgb::new(255, 0, 0));
+
+        let reds: Vec<_> = AirQuality::ALL.iter().map(|&l| level_color(l).r).collect();
+        assert!(reds.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn status_color_follows_level() {
+        assert_eq!(status_color(None, AlarmState::Armed, 255, 0), Rgb::OFF);
+        assert_eq!(
+            status_color(Some(AirQuality::Good), AlarmState::Armed, 255, 0),
+            level_color(AirQuality::Good)
+        );
+        assert_eq!(
+            status_color(Some(AirQuality::Good), AlarmState::Armed, 0, 0),
+            Rgb::OFF
+        );
+    }
+
+    #[test]
+    fn status_color_blinks_red_while_active() {
+        let red = level_color(AirQuality::Bad).scale(DEFAULT_BRIGHTNESS);
+        let level = Some(AirQuality::Bad);
+
+        assert_eq!(
+            status_color(level, AlarmState::Active, DEFAULT_BRIGHTNESS, 0),
+            red
+        );
+        assert_eq!(
+            status_color(level, AlarmState::Active, DEFAULT_BRIGHTNESS, 500),
+            Rgb::OFF
+        );
+        assert_eq!(
+            status_color(level, AlarmState::Active, DEFAULT_BRIGHTNESS, 1000),
+            red
+        );
+        assert_eq!(
+            status_color(
+                Some(AirQuality::Excellent),
+                AlarmState::Latched,
+                DEFAULT_BRIGHTNESS,
+                500
+            ),
+            red
+        );
+    }
+
+    #[test]
+    fn active_pattern_timing() {
+        let pattern = beep_pattern(AlarmState::Active).unwrap();
+        assert_eq!(pattern.period_ms(), 2000);
+
+        assert!(pattern.is_on(0));
+        assert!(pattern.is_on(149));
+        assert!(!pattern.is_on(150));
+        assert!(pattern.is_on(250));
+        assert!(pattern.is_on(500));
+        assert!(!pattern.is_on(650));
+        assert!(!pattern.is_on(1999));
+        assert!(pattern.is_on(2000));
+    }
+
+    #[test]
+    fn latched_pattern_chirps() {
+        let pattern = beep_pattern(AlarmState::Latched).unwrap();
+        assert!(pattern.is_on(10));
+        assert!(!pattern.is_on(50));
+        assert!(pattern.is_on(10_020));
+    }
+
+    #[test]
+    fn silent_states() {
+        assert_eq!(beep_pattern(AlarmState::Armed), None);
+        assert_eq!(beep_pattern(AlarmState::Pending { since_ms: 0 }), None);
+        assert_eq!(beep_pattern(AlarmState::Snoozed { until_ms: 0 }), None);
+        assert!(!BeepPattern { steps: &[] }.is_on(0));
+    }
+}
@@ -0,0 +1,776 @@
+//! InfluxDB line protocol and the push exporter's batching and retries.
+//!
+//! Every measurement becomes one line such as
+//! `co2monitor,device=Office,serial=0A1B2C3D4E5F co2=812i,temperature=21.25,humidity=40.12,air_quality="Good" 1700000000`,
+//! with the timestamp in seconds. The [`Exporter`] keeps measurements in RAM and
+//! writes them in batches through a [`Transport`]; failed writes are retried with
+//! exponential backoff, and while the server is unreachable the buffer keeps the
+//! newest [`BUFFER_CAPACITY`] measurements.
+
+use crate::{air_quality::AirQuality, clock::SystemClock, json};
+use std::collections::VecDeque;
+
+/// Measurement name of the written points.
+pub const MEASUREMENT: &str = "co2monitor";
+
+/// Content type of a write request.
+pub const CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+/// Measurements kept while the server is unreachable, an hour at the default
+/// measurement interval.
+pub const BUFFER_CAPACITY: usize = 720;
+
+/// Measurements that trigger a write before [`FLUSH_INTERVAL_MS`] has passed.
+pub const BATCH_SIZE: usize = 12;
+
+/// Maximum measurements per write request, bounding its size.
+pub const MAX_BATCH_LINES: usize = 120;
+
+/// Longest time a measurement waits for a batch to fill, in milliseconds.
+pub const FLUSH_INTERVAL_MS: u64 = 60_000;
+
+/// Delay before the first retry, in milliseconds.
+pub const INITIAL_BACKOFF_MS: u64 = 5_000;
+
+/// Longest delay between retries, in milliseconds.
+pub const MAX_BACKOFF_MS: u64 = 300_000;
+
+/// A buffered measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Point {
+    /// Uptime in milliseconds when measured, turned into a timestamp once the
+    /// clock is set.
+    pub uptime_ms: u64,
+
+    /// CO2 in ppm.
+    pub co2: u16,
+
+    /// Temperature in 0.01 °C.
+    pub temperature: i16,
+
+    /// Relative humidity in 0.01 %.
+    pub humidity: u16,
+
+    /// The air quality level.
+    pub air_quality: AirQuality,
+}
+
+/// Failed write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteError {
+    /// The server could not be reached or the connection failed.
+    Network(String),
+
+    /// The server answered with an error status.
+    Status(u16),
+}
+
+/// Implementation of `WriteError`.
+impl WriteError {
+    /// Whether retrying cannot help, such as for malformed data or a bad token.
+    ///
+    /// # Returns
+    /// * `bool` - `true` for client errors other than timeouts and rate limits.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            WriteError::Network(_) => false,
+            WriteError::Status(status) => {
+                (400..500).contains(status) && ![408, 429].contains(status)
+            }
+        }
+    }
+}
+
+/// Implementation of the `Display` trait for `WriteError`.
+impl core::fmt::Display for WriteError {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WriteError::Network(message) => write!(f, "Network error: {message}"),
+            WriteError::Status(status) => write!(f, "Server answered with status {status}"),
+        }
+    }
+}
+
+/// Implementation of the `Error` trait for `WriteError`.
+impl std::error::Error for WriteError {}
+
+/// Sends write requests to the server.
+pub trait Transport {
+    /// Write a batch of lines.
+    ///
+    /// # Arguments
+    /// * `body` - The lines, each terminated by a line feed.
+    ///
+    /// # Returns
+    /// * `Result<(), WriteError>` - An error unless the server accepted the batch.
+    fn write(&mut self, body: &str) -> Result<(), WriteError>;
+}
+
+/// Result of a write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// The server accepted the measurements.
+    Sent(usize),
+
+    /// The server refused the measurements for good; they were discarded.
+    Rejected(usize, WriteError),
+
+    /// The write failed and is retried after a delay in milliseconds.
+    Retry(WriteError, u64),
+}
+
+/// Buffers measurements and decides when to write them.
+///
+/// A write is split into [`Exporter::begin`], which hands out the batch, and
+/// [`Exporter::finish`], so that measurements can be added while the request is
+/// in flight.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exporter {
+    /// Buffered measurements, oldest first.
+    points: VecDeque<Point>,
+
+    /// Measurements at the front of the buffer that are being written.
+    in_flight: usize,
+
+    /// Uptime in milliseconds before which no write is attempted.
+    retry_at_ms: u64,
+
+    /// Delay before the next retry, in milliseconds.
+    backoff_ms: u64,
+
+    /// Measurements discarded because the buffer was full.
+    dropped: u32,
+}
+
+/// Implementation of the `Default` trait for `Exporter`.
+impl Default for Exporter {
+    /// Create an empty exporter.
+    ///
+    /// # Returns
+    /// * `Exporter` - The exporter.
+    fn default() -> Self {
+        Self {
+            points: VecDeque::new(),
+            in_flight: 0,
+            retry_at_ms: 0,
+            backoff_ms: INITIAL_BACKOFF_MS,
+            dropped: 0,
+        }
+    }
+}
+
+/// Implementation of `Exporter`.
+impl Exporter {
+    /// Add a measurement, discarding the oldest one if the buffer is full.
+    ///
+    /// # Arguments
+    /// * `point` - The measurement.
+    pub fn push(&mut self, point: Point) {
+        if self.points.len() == BUFFER_CAPACITY {
+            self.points.pop_front();
+            self.in_flight = self.in_flight.saturating_sub(1);
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+
+        self.points.push_back(point);
+    }
+
+    /// Get the number of buffered measurements.
+    ///
+    /// # Returns
+    /// * `usize` - The number of measurements not yet written.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Check whether the buffer is empty.
+    ///
+    /// # Returns
+    /// * `bool` - `true` if every measurement has been written.
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Get the number of measurements discarded because the buffer was full.
+    ///
+    /// # Returns
+    /// * `u32` - The number of measurements lost since boot.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Start a write if one is due: a batch is full, the oldest measurement has
+    /// waited [`FLUSH_INTERVAL_MS`] or a failed write is due for a retry.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The uptime in milliseconds.
+    /// * `series` - The measurement and tags, from [`series_key`].
+    /// * `clock` - The clock, for the timestamps.
+    ///
+    /// # Returns
+    /// * `Option<String>` - The request body, or `None` if nothing is due.
+    pub fn begin(&mut self, now_ms: u64, series: &str, clock: &SystemClock) -> Option<String> {
+        if self.in_flight > 0 || self.points.is_empty() || now_ms < self.retry_at_ms {
+            return None;
+        }
+
+        let waited = self
+            .points
+            .front()
+            .map_or(0, |p| now_ms.saturating_sub(p.uptime_ms));
+        let backlog = self.points.len() >= BATCH_SIZE || self.retry_at_ms > 0;
+        if !backlog && waited < FLUSH_INTERVAL_MS {
+            return None;
+        }
+
+        self.in_flight = self.points.len().min(MAX_BATCH_LINES);
+
+        let body = self
+            .points
+            .iter()
+            .take(self.in_flight)
+            .map(|point| {
+                let timestamp_s = clock.unix_ms(point.uptime_ms).map(|ms| ms.div_euclid(1000));
+                encode_line(series, point, timestamp_s)
+            })
+            .collect();
+
+        Some(body)
+    }
+
+    /// Complete the write started by [`Exporter::begin`].
+    ///
+    /// # Arguments
+    /// * `now_ms` - The uptime in milliseconds.
+    /// * `result` - The result of the write.
+    ///
+    /// # Returns
+    /// * `WriteOutcome` - What happened to the batch.
+    pub fn finish(&mut self, now_ms: u64, result: Result<(), WriteError>) -> WriteOutcome {
+        let count = core::mem::take(&mut self.in_flight).min(self.points.len());
+
+        match result {
+            Ok(()) => {
+                self.points.drain(..count);
+                self.retry_at_ms = 0;
+                self.backoff_ms = INITIAL_BACKOFF_MS;
+                WriteOutcome::Sent(count)
+            }
+            Err(error) if error.is_permanent() => {
+                self.points.drain(..count);
+                WriteOutcome::Rejected(count, error)
+            }
+            Err(error) => {
+                let delay_ms = self.backoff_ms;
+                self.retry_at_ms = now_ms + delay_ms;
+                self.backoff_ms = (delay_ms * 2).min(MAX_BACKOFF_MS);
+                WriteOutcome::Retry(error, delay_ms)
+            }
+        }
+    }
+}
+
+/// Build the measurement and tags shared by every line of a device.
+///
+/// # Arguments
+/// * `device_name` - The device name.
+/// * `serial` - The sensor serial number, empty if unknown.
+///
+/// # Returns
+/// * `String` - The escaped measurement and tags; empty tags are left out, as the
+///   line protocol does not allow them.
+pub fn series_key(device_name: &str, serial: &str) -> String {
+    let mut key = escape_measurement(MEASUREMENT);
+
+    for (tag, value) in [("device", device_name), ("serial", serial)] {
+        if !value.is_empty() {
+            key.push(',');
+            key.push_str(tag);
+            key.push('=');
+            key.push_str(&escape_tag(value));
+        }
+    }
+
+    key
+}
+
+/// Encode a measurement as a line.
+///
+/// # Arguments
+/// * `series` - The measurement and tags, from [`series_key`].
+/// * `point` - The measurement.
+/// * `timestamp_s` - Seconds since the Unix epoch, or `None` to let the server
+///   use the time of arrival.
+///
+/// # Returns
+/// * `String` - The line, terminated by a line feed.
+pub fn encode_line(series: &str, point: &Point, timestamp_s: Option<i64>) -> String {
+    let mut line = format!(
+        "{} co2={}i,temperature={},humidity={},air_quality=\"{}\"",
+        series,
+        point.co2,
+        json::fixed(point.temperature.into(), 2),
+        json::fixed(point.humidity.into(), 2),
+        escape_string(point.air_quality.label())
+    );
+
+    if let Some(timestamp_s) = timestamp_s {
+        line.push(' ');
+        line.push_str(&timestamp_s.to_string());
+    }
+    line.push('\n');
+
+    line
+}
+
+/// Add second precision to a write URL, unless it sets a precision.
+///
+/// # Arguments
+/// * `url` - The configured URL, for the v2 `/api/v2/write` or v1 `/write`
+///   endpoint.
+///
+/// # Returns
+/// * `String` - The URL matching the timestamps of [`encode_line`].
+pub fn write_url(url: &str) -> String {
+    let query = url.split_once('?').map_or("", |(_, query)| query);
+    if query
+        .split('&')
+        .any(|param| param.starts_with("precision="))
+    {
+        return url.to_string();
+    }
+
+    let separator = if query.is_empty() && !url.ends_with('?') {
+        '?'
+    } else {
+        '&'
+    };
+    format!("{url}{separator}precision=s")
+}
+
+/// Build the authorization header value.
+///
+/// # Arguments
+/// * `token` - The API token, empty for none.
+///
+/// # Returns
+/// * `Option<String>` - The value, or `None` without a token.
+pub fn authorization(token: &str) -> Option<String> {
+    (!token.is_empty()).then(|| format!("Token {token}"))
+}
+
+/// Escape a measurement name: commas, spaces and backslashes.
+///
+/// # Arguments
+/// * `name` - The name.
+///
+/// # Returns
+/// * `String` - The escaped name.
+pub fn escape_measurement(name: &str) -> String {
+    escape(name, &[',', ' ', '\\'])
+}
+
+/// Escape a tag key or value: commas, equals signs, spaces and backslashes, so
+/// a trailing backslash cannot escape the separator after it.
+///
+/// # Arguments
+/// * `value` - The key or value.
+///
+/// # Returns
+/// * `String` - The escaped key or value.
+pub fn escape_tag(value: &str) -> String {
+    escape(value, &[',', '=', ' ', '\\'])
+}
+
+/// Escape the contents of a string field: double quotes and backslashes.
+///
+/// # Arguments
+/// * `value` - The value, without the surrounding quotes.
+///
+/// # Returns
+/// * `String` - The escaped value.
+pub fn escape_string(value: &str) -> String {
+    escape(value, &['"', '\\'])
+}
+
+/// Put a backslash before special characters.
+///
+/// # Arguments
+/// * `value` - The text.
+/// * `special` - The characters to escape.
+///
+/// # Returns
+/// * `String` - The escaped text.
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        thread,
+    };
+
+    /// A measurement taken at an uptime.
+    fn point(uptime_ms: u64) -> Point {
+        Point {
+            uptime_ms,
+            co2: 812,
+            temperature: 2125,
+            humidity: 4012,
+            air_quality: AirQuality::Good,
+        }
+    }
+
+    #[test]
+    fn line_protocol() {
+        let series = series_key("Office", "0A1B2C3D4E5F");
+
+        assert_eq!(
+            encode_line(&series, &point(0), Some(1_700_000_000)),
+            "co2monitor,device=Office,serial=0A1B2C3D4E5F \
+             co2=812i,temperature=21.25,humidity=40.12,air_quality=\"Good\" 1700000000\n"
+        );
+        assert_eq!(
+            encode_line(
+                "co2monitor",
+                &Point {
+                    temperature: -5,
+                    ..point(0)
+                },
+                None
+            ),
+            "co2monitor co2=812i,temperature=-0.05,humidity=40.12,air_quality=\"Good\"\n"
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            series_key("Lab 2, north=B", ""),
+            r"co2monitor,device=Lab\ 2\,\ north\=B"
+        );
+        assert_eq!(escape_measurement("a,b c=d"), r"a\,b\ c=d");
+        assert_eq!(escape_measurement(r"a\b"), r"a\\b");
+        assert_eq!(
+            series_key(r"Room\", "0A1B"),
+            r"co2monitor,device=Room\\,serial=0A1B"
+        );
+        assert_eq!(escape_string(r#"say "hi" \o/"#), r#"say \"hi\" \\o/"#);
+    }
+
+    #[test]
+    fn urls_and_headers() {
+        assert_eq!(
+            write_url("http://influx:8086/api/v2/write?org=home&bucket=air"),
+            "http://influx:8086/api/v2/write?org=home&bucket=air&precision=s"
+        );
+        assert_eq!(
+            write_url("http://influx:8086/write"),
+            "http://influx:8086/write?precision=s"
+        );
+        assert_eq!(
+            write_url("http://influx:8086/write?db=air&precision=ms"),
+            "http://influx:8086/write?db=air&precision=ms"
+        );
+        assert_eq!(authorization(""), None);
+        assert_eq!(authorization("abc").as_deref(), Some("Token abc"));
+    }
+
+    #[test]
+    fn batching() {
+        let clock = SystemClock::default();
+        let mut exporter = Exporter::default();
+
+        exporter.push(point(0));
+        assert_eq!(exporter.begin(1000, "m", &clock), None);
+        // The oldest measurement has waited long enough.
+        assert!(exporter.begin(FLUSH_INTERVAL_MS, "m", &clock).is_some());
+        assert_eq!(exporter.begin(FLUSH_INTERVAL_MS, "m", &clock), None);
+        assert_eq!(
+            exporter.finish(FLUSH_INTERVAL_MS, Ok(())),
+            WriteOutcome::Sent(1)
+        );
+        assert!(exporter.is_empty());
+
+        // A full batch is written right away.
+        for i in 0..BATCH_SIZE as u64 {
+            exporter.push(point(100_000 + i));
+        }
+        let body = exporter.begin(100_000, "m", &clock).unwrap();
+        assert_eq!(body.lines().count(), BATCH_SIZE);
+    }
+
+    #[test]
+    fn buffer_while_offline() {
+        let mut exporter = Exporter::default();
+
+        for i in 0..BUFFER_CAPACITY as u64 + 5 {
+            exporter.push(point(i));
+        }
+        assert_eq!(exporter.len(), BUFFER_CAPACITY);
+        assert_eq!(exporter.dropped(), 5);
+
+        // Measurements lost while in flight are not acknowledged twice.
+        let mut clock = SystemClock::default();
+        clock.set_utc(1_700_000_000_000, 0);
+        let body = exporter.begin(10_000, "m", &clock).unwrap();
+        assert_eq!(body.lines().count(), MAX_BATCH_LINES);
+        assert!(body.starts_with(
+            "m co2=812i,temperature=21.25,humidity=40.12,air_quality=\"Good\" 1700000000\n"
+        ));
+        exporter.push(point(10_000));
+        assert_eq!(
+            exporter.finish(10_000, Ok(())),
+            WriteOutcome::Sent(MAX_BATCH_LINES - 1)
+        );
+        assert_eq!(exporter.len(), BUFFER_CAPACITY - MAX_BATCH_LINES + 1);
+
+        // The backlog keeps draining without waiting for the flush interval.
+        assert!(exporter.begin(10_000, "m", &clock).is_some());
+    }
+
+    #[test]
+    fn backoff() {
+        let clock = SystemClock::default();
+        let mut exporter = Exporter::default();
+        for i in 0..BATCH_SIZE as u64 {
+            exporter.push(point(i));
+        }
+
+        let mut now_ms = 0;
+        let mut delays = Vec::new();
+        for _ in 0..8 {
+            assert!(exporter.begin(now_ms, "m", &clock).is_some());
+            match exporter.finish(now_ms, Err(WriteError::Status(503))) {
+                WriteOutcome::Retry(_, delay_ms) => {
+                    assert_eq!(exporter.begin(now_ms + delay_ms - 1, "m", &clock), None);
+                    now_ms += delay_ms;
+                    delays.push(delay_ms / 1000);
+                }
+                outcome => panic!("unexpected {outcome:?}"),
+            }
+        }
+        assert_eq!(delays, [5, 10, 20, 40, 80, 160, 300, 300]);
+        assert_eq!(exporter.len(), BATCH_SIZE);
+
+        // Success resets the backoff; rejected batches are discarded.
+        exporter.begin(now_ms, "m", &clock).unwrap();
+        assert_eq!(
+            exporter.finish(now_ms, Ok(())),
+            WriteOutcome::Sent(BATCH_SIZE)
+        );
+        for i in 0..BATCH_SIZE as u64 {
+            exporter.push(point(now_ms + i));
+        }
+        exporter.begin(now_ms, "m", &clock).unwrap();
+        assert_eq!(
+            exporter.finish(now_ms, Err(WriteError::Status(400))),
+            WriteOutcome::Rejected(BATCH_SIZE, WriteError::Status(400))
+        );
+        assert!(exporter.is_empty());
+        assert!(!WriteError::Status(429).is_permanent());
+    }
+
+    /// A request received by the stand-in server.
+    struct Received {
+        /// The request line and headers.
+        head: String,
+
+        /// The body.
+        body: String,
+    }
+
+    /// Start an HTTP stand-in that answers one request per status.
+    ///
+    /// # Arguments
+    /// * `statuses` - The status of each response.
+    ///
+    /// # Returns
+    /// * `(u16, mpsc::Receiver<Received>)` - The port and the received requests.
+    fn stand_in(statuses: Vec<u16>) -> (u16, mpsc::Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+                let len = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .map_or(0, |len| len.parse().unwrap());
+                let mut body = vec![0u8; len];
+                reader.read_exact(&mut body).unwrap();
+
+                let response = format!("HTTP/1.1 {status} X\r\nContent-Length: 0\r\n\r\n");
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                let body = String::from_utf8(body).unwrap();
+                sender.send(Received { head, body }).unwrap();
+            }
+        });
+
+        (port, receiver)
+    }
+
+    /// Minimal HTTP client for the stand-in.
+    struct HttpTransport {
+        /// The write URL.
+        url: String,
+
+        /// The API token.
+        token: String,
+    }
+
+    /// Implementation of the `Transport` trait for `HttpTransport`.
+    impl Transport for HttpTransport {
+        /// Post the batch and check the status.
+        ///
+        /// # Arguments
+        /// * `body` - The lines.
+        ///
+        /// # Returns
+        /// * `Result<(), WriteError>` - An error for a failed connection or status.
+        fn write(&mut self, body: &str) -> Result<(), WriteError> {
+            let network = |e: std::io::Error| WriteError::Network(e.to_string());
+            let rest = self.url.strip_prefix("http://").unwrap();
+            let (host, path) = rest.split_at(rest.find('/').unwrap());
+
+            let mut stream = TcpStream::connect(host).map_err(network)?;
+            let mut request = format!(
+                "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\n",
+                body.len()
+            );
+            if let Some(authorization) = authorization(&self.token) {
+                request.push_str(&format!("Authorization: {authorization}\r\n"));
+            }
+            request.push_str("\r\n");
+            request.push_str(body);
+            stream.write_all(request.as_bytes()).map_err(network)?;
+
+            let mut status_line = String::new();
+            BufReader::new(stream)
+                .read_line(&mut status_line)
+                .map_err(network)?;
+            let status: u16 = status_line
+                .split(' ')
+                .nth(1)
+                .and_then(|status| status.parse().ok())
+                .ok_or_else(|| WriteError::Network("Bad response".into()))?;
+
+            if (200..300).contains(&status) {
+                Ok(())
+            } else {
+                Err(WriteError::Status(status))
+            }
+        }
+    }
+
+    #[test]
+    fn delivery() {
+        let (port, received) = stand_in(vec![503, 204]);
+        let mut transport = HttpTransport {
+            url: write_url(&format!(
+                "http://127.0.0.1:{port}/api/v2/write?org=home&bucket=air"
+            )),
+            token: "secret".into(),
+        };
+        let mut clock = SystemClock::default();
+        clock.set_utc(1_700_000_000_000, 0);
+        let series = series_key("Office", "0A1B2C3D4E5F");
+        let mut exporter = Exporter::default();
+        for i in 0..BATCH_SIZE as u64 {
+            exporter.push(point(i * 5000));
+        }
+
+        // The server is unavailable at first.
+        let body = exporter.begin(60_000, &series, &clock).unwrap();
+        let outcome = exporter.finish(60_000, transport.write(&body));
+        assert_eq!(
+            outcome,
+            WriteOutcome::Retry(WriteError::Status(503), INITIAL_BACKOFF_MS)
+        );
+        received.recv().unwrap();
+        assert_eq!(exporter.begin(61_000, &series, &clock), None);
+
+        // The retry delivers the same batch.
+        let now_ms = 60_000 + INITIAL_BACKOFF_MS;
+        let body = exporter.begin(now_ms, &series, &clock).unwrap();
+        let outcome = exporter.finish(now_ms, transport.write(&body));
+        assert_eq!(outcome, WriteOutcome::Sent(BATCH_SIZE));
+        assert!(exporter.is_empty());
+
+        let request = received.recv().unwrap();
+        assert!(request
+            .head
+            .starts_with("POST /api/v2/write?org=home&bucket=air&precision=s HTTP/1.1\r\n"));
+        assert!(request.head.contains("Authorization: Token secret\r\n"));
+        assert!(request
+            .head
+            .contains("Content-Type: text/plain; charset=utf-8\r\n"));
+        let lines: Vec<&str> = request.body.lines().collect();
+        assert_eq!(lines.len(), BATCH_SIZE);
+        assert_eq!(
+            lines[1],
+            "co2monitor,device=Office,serial=0A1B2C3D4E5F \
+             co2=812i,temperature=21.25,humidity=40.12,air_quality=\"Good\" 1700000005"
+        );
+    }
+
+    #[test]
+    fn delivery_failure() {
+        // Nothing listens on the port once the listener is dropped.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut transport = HttpTransport {
+            url: format!("http://127.0.0.1:{port}/write"),
+            token: String::new(),
+        };
+        let mut exporter = Exporter::default();
+        exporter.push(point(0));
+
+        let body = exporter
+            .begin(FLUSH_INTERVAL_MS, "m", &SystemClock::default())
+            .unwrap();
+        let outcome = exporter.finish(FLUSH_INTERVAL_MS, transport.write(&body));
+        assert!(matches!(
+            outcome,
+            WriteOutcome::Retry(WriteError::Network(_), _)
+        ));
+        assert_eq!(exporter.len(), 1);
+    }
+}
@@ -0,0 +1,625 @@
+//! Minimal JSON writer and reader for the network payloads.
+//!
+//! Values are written in the order given, so payloads are stable and easy to
+//! compare in tests. The reader only accepts flat objects, which is all the
+//! requests need.
+
+use core::fmt::Write;
+
+/// JSON object under construction.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsonObject {
+    /// The members written so far, without the braces.
+    members: String,
+}
+
+/// Implementation of `JsonObject`.
+impl JsonObject {
+    /// Start an empty object.
+    ///
+    /// # Returns
+    /// * `JsonObject` - The object.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a string member.
+    ///
+    /// # Arguments
+    /// * `key` - The member name.
+    /// * `value` - The text, escaped as needed.
+    ///
+    /// # Returns
+    /// * `JsonObject` - The object.
+    pub fn string(self, key: &str, value: &str) -> Self {
+        self.raw(key, &string(value))
+    }
+
+    /// Add an integer member.
+    ///
+    /// # Arguments
+    /// * `key` - The member name.
+    /// * `value` - The number.
+    ///
+    /// # Returns
+    /// * `JsonObject` - The object.
+    pub fn integer(self, key: &str, value: impl Into<i64>) -> Self {
+        self.raw(key, &value.into().to_string())
+    }
+
+    /// Add a fixed-point number member.
+    ///
+    /// # Arguments
+    /// * `key` - The member name.
+    /// * `value` - The number in units of 10^-`decimals`.
+    /// * `decimals` - The number of decimal places.
+    ///
+    /// # Returns
+    /// * `JsonObject` - The object.
+    pub fn fixed(self, key: &str, value: i64, decimals: u32) -> Self {
+        self.raw(key, &fixed(value, decimals))
+    }
+
+    /// Add a boolean member.
+    ///
+    /// # Arguments
+    /// * `key` - The member name.
+    /// * `value` - The value.
+    ///
+    /// # Returns
+    /// * `JsonObject` - The object.
+    pub fn boolean(self, key: &str, value: bool) -> Self {
+        self.raw(key, if value { "true" } else { "false" })
+    }
+
+    /// Add a member whose value is already JSON, such as a nested object.
+    ///
+    /// # Arguments
+    /// * `key` - The member name.
+    /// * `json` - The encoded value.
+    ///
+    /// # Returns
+    /// * `JsonObject` - The object.
+    pub fn raw(mut self, key: &str, json: &str) -> Self {
+        if !self.members.is_empty() {
+            self.members.push(',');
+        }
+        self.members.push_str(&string(key));
+        self.members.push(':');
+        self.members.push_str(json);
+
+        self
+    }
+
+    /// Finish the object.
+    ///
+    /// # Returns
+    /// * `String` - The encoded object.
+    pub fn finish(self) -> String {
+        format!("{{{}}}", self.members)
+    }
+}
+
+/// Encode a string.
+///
+/// # Arguments
+/// * `value` - The text.
+///
+/// # Returns
+/// * `String` - The quoted string, with quotes, backslashes and control
+///   characters escaped.
+pub fn string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    json
+}
+
+/// Encode a fixed-point number.
+///
+/// # Arguments
+/// * `value` - The number in units of 10^-`decimals`.
+/// * `decimals` - The number of decimal places.
+///
+/// # Returns
+/// * `String` - The number with exactly `decimals` decimal places.
+pub fn fixed(value: i64, decimals: u32) -> String {
+    if decimals == 0 {
+        return value.to_string();
+    }
+
+    let scale = 10u64.pow(decimals);
+    let magnitude = value.unsigned_abs();
+    let sign = if value < 0 { "-" } else { "" };
+
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        magnitude / scale,
+        magnitude % scale,
+        width = decimals as usize
+    )
+}
+
+/// Encode an optional integer.
+///
+/// # Arguments
+/// * `value` - The value.
+///
+/// # Returns
+/// * `String` - The number, or `null`.
+pub fn optional(value: Option<i64>) -> String {
+    value.map_or("null".to_string(), |value| value.to_string())
+}
+
+/// Encode an array.
+///
+/// # Arguments
+/// * `items` - The encoded items.
+///
+/// # Returns
+/// * `String` - The array.
+pub fn array(items: impl IntoIterator<Item = String>) -> String {
+    let items: Vec<String> = items.into_iter().collect();
+
+    format!("[{}]", items.join(","))
+}
+
+/// Value of a member of a flat object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// `null`.
+    Null,
+
+    /// `true` or `false`.
+    Boolean(bool),
+
+    /// Number, as written.
+    Number(String),
+
+    /// String, unescaped.
+    String(String),
+}
+
+/// Implementation of `Value`.
+impl Value {
+    /// Read a number in fixed point, without rounding.
+    ///
+    /// # Arguments
+    /// * `decimals` - The number of decimal places.
+    ///
+    /// # Returns
+    /// * `Option<i64>` - The number in units of 10^-`decimals`, or `None` if the
+    ///   value is not a number, has more decimal places or an exponent, or is out of
+    ///   range.
+    pub fn fixed(&self, decimals: u32) -> Option<i64> {
+        let Value::Number(number) = self else {
+            return None;
+        };
+
+        let (negative, digits) = match number.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, number.as_str()),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if fraction.len() > decimals as usize
+            || !(integer.bytes().chain(fraction.bytes())).all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let value = format!(
+            "{}{:0<width$}",
+            integer,
+            fraction,
+            width = decimals as usize
+        )
+        .parse::<i64>()
+        .ok()?;
+
+        Some(if negative { -value } else { value })
+    }
+}
+
+/// JSON parse error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonError {
+    /// The text is not valid JSON.
+    Syntax {
+        /// Byte offset of the error.
+        offset: usize,
+    },
+
+    /// The text is valid JSON but not a flat object.
+    Unsupported {
+        /// Byte offset of the unsupported value.
+        offset: usize,
+    },
+}
+
+/// Implementation of the `Display` trait for `JsonError`.
+impl core::fmt::Display for JsonError {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            JsonError::Syntax { offset } => write!(f, "Invalid JSON at byte {offset}"),
+            JsonError::Unsupported { offset } => {
+                write!(
+                    f,
+                    "Expected a flat JSON object, found a nested value at byte {offset}"
+                )
+            }
+        }
+    }
+}
+
+/// Implementation of the `Error` trait for `JsonError`.
+impl std::error::Error for JsonError {}
+
+/// Parse a flat object, whose members are strings, numbers, booleans or `null`.
+///
+/// # Arguments
+/// * `text` - The JSON text.
+///
+/// # Returns
+/// * `Result<Vec<(String, Value)>, JsonError>` - The members in order, or an error.
+pub fn parse_object(text: &str) -> Result<Vec<(String, Value)>, JsonError> {
+    let mut reader = Reader { text, offset: 0 };
+    let mut members = Vec::new();
+
+    reader.expect(b'{')?;
+    if !reader.accept(b'}') {
+        loop {
+            let key = reader.string()?;
+            reader.expect(b':')?;
+            members.push((key, reader.value()?));
+            if reader.accept(b'}') {
+                break;
+            }
+            reader.expect(b',')?;
+        }
+    }
+
+    reader.skip_whitespace();
+    if reader.offset != text.len() {
+        return Err(reader.error());
+    }
+
+    Ok(members)
+}
+
+/// Cursor over JSON text.
+struct Reader<'a> {
+    /// The text.
+    text: &'a str,
+
+    /// Byte offset of the next character.
+    offset: usize,
+}
+
+/// Implementation of `Reader`.
+impl Reader<'_> {
+    /// Skip whitespace.
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.offset..];
+        self.offset += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    /// Peek at the next byte after whitespace.
+    ///
+    /// # Returns
+    /// * `Option<u8>` - The byte, or `None` at the end.
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.as_bytes().get(self.offset).copied()
+    }
+
+    /// Consume a byte if it comes next.
+    ///
+    /// # Arguments
+    /// * `byte` - The byte.
+    ///
+    /// # Returns
+    /// * `bool` - Whether it was consumed.
+    fn accept(&mut self, byte: u8) -> bool {
+        let found = self.peek() == Some(byte);
+        if found {
+            self.offset += 1;
+        }
+
+        found
+    }
+
+    /// Consume a byte that must come next.
+    ///
+    /// # Arguments
+    /// * `byte` - The byte.
+    ///
+    /// # Returns
+    /// * `Result<(), JsonError>` - An error if another byte comes next.
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        if self.accept(byte) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    /// Build a syntax error at the current offset.
+    ///
+    /// # Returns
+    /// * `JsonError` - The error.
+    fn error(&self) -> JsonError {
+        JsonError::Syntax {
+            offset: self.offset,
+        }
+    }
+
+    /// Read a member value.
+    ///
+    /// # Returns
+    /// * `Result<Value, JsonError>` - The value or an error.
+    fn value(&mut self) -> Result<Value, JsonError> {
+        let rest = match self.peek() {
+            Some(b'"') => return self.string().map(Value::String),
+            Some(b'{' | b'[') => {
+                return Err(JsonError::Unsupported {
+                    offset: self.offset,
+                })
+            }
+            Some(_) => &self.text[self.offset..],
+            None => return Err(self.error()),
+        };
+
+        for (literal, value) in [
+            ("true", Value::Boolean(true)),
+            ("false", Value::Boolean(false)),
+            ("null", Value::Null),
+        ] {
+            if rest.starts_with(literal) {
+                self.offset += literal.len();
+                return Ok(value);
+            }
+        }
+
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        let number = &rest[..len];
+        if !is_number(number) {
+            return Err(self.error());
+        }
+        self.offset += len;
+
+        Ok(Value::Number(number.to_string()))
+    }
+
+    /// Read a string.
+    ///
+    /// # Returns
+    /// * `Result<String, JsonError>` - The unescaped string or an error.
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+
+        let mut value = String::new();
+        let mut chars = self.text[self.offset..].char_indices();
+        let start = self.offset;
+        let error = |index: usize| JsonError::Syntax {
+            offset: start + index,
+        };
+
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.offset = start + index + 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    let (_, escape) = chars.next().ok_or(error(index))?;
+                    let unescaped = match escape {
+                        '"' | '\\' | '/' => escape,
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let hex = |chars: &mut core::str::CharIndices| {
+                                let digits: String = chars.take(4).map(|(_, c)| c).collect();
+                                u16::from_str_radix(&digits, 16)
+                                    .ok()
+                                    .filter(|_| digits.len() == 4)
+                                    .ok_or(error(index))
+                            };
+                            let unit = hex(&mut chars)?;
+                            let code = if (0xd800..0xdc00).contains(&unit) {
+                                let low = match (chars.next(), chars.next()) {
+                                    (Some((_, '\\')), Some((_, 'u'))) => hex(&mut chars)?,
+                                    _ => return Err(error(index)),
+                                };
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(error(index));
+                                }
+                                0x10000
+                                    + ((u32::from(unit) - 0xd800) << 10)
+                                    + (u32::from(low) - 0xdc00)
+                            } else {
+                                u32::from(unit)
+                            };
+                            char::from_u32(code).ok_or(error(index))?
+                        }
+                        _ => return Err(error(index)),
+                    };
+                    value.push(unescaped);
+                }
+                c if c.is_control() && (c as u32) < 0x20 => return Err(error(index)),
+                c => value.push(c),
+            }
+        }
+
+        Err(error(self.text.len() - start))
+    }
+}
+
+/// Check the number grammar: an optional minus sign, an integer without leading
+/// zeros, an optional fraction and an optional exponent.
+///
+/// # Arguments
+/// * `number` - The candidate.
+///
+/// # Returns
+/// * `bool` - Whether it is a number.
+fn is_number(number: &str) -> bool {
+    fn digits(text: &str) -> (usize, &str) {
+        let len = text.bytes().take_while(u8::is_ascii_digit).count();
+        (len, &text[len..])
+    }
+
+    let rest = number.strip_prefix('-').unwrap_or(number);
+    let (len, rest) = digits(rest);
+    if len == 0 || (len > 1 && number.trim_start_matches('-').starts_with('0')) {
+        return false;
+    }
+
+    let rest = match rest.strip_prefix('.') {
+        Some(fraction) => match digits(fraction) {
+            (0, _) => return false,
+            (_, rest) => rest,
+        },
+        None => rest,
+    };
+
+    match rest.strip_prefix(['e', 'E']) {
+        Some(exponent) => {
+            let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            matches!(digits(exponent), (1.., ""))
+        }
+        None => rest.is_empty(),
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_members_in_order() {
+        let json = JsonObject::new()
+            .string("name", "Office")
+            .integer("co2", 812u16)
+            .fixed("temperature", 2150, 2)
+            .boolean("ok", true)
+            .raw("tags", &array(["\"a\"".to_string(), "\"b\"".to_string()]))
+            .finish();
+
+        assert_eq!(
+            json,
+            r#"{"name":"Office","co2":812,"temperature":21.50,"ok":true,"tags":["a","b"]}"#
+        );
+        assert_eq!(JsonObject::new().finish(), "{}");
+        assert_eq!(array(Vec::new()), "[]");
+    }
+
+    #[test]
+    fn string_escaping() {
+        assert_eq!(string("plain"), r#""plain""#);
+        assert_eq!(string("say \"hi\"\\"), r#""say \"hi\"\\""#);
+        assert_eq!(string("a\nb\tc\u{1}"), r#""a\nb\tc\u0001""#);
+        assert_eq!(string("Café °C"), "\"Café °C\"");
+    }
+
+    #[test]
+    fn parse_flat_object() {
+        let members = parse_object(
+            r#" { "name" : "Caf\u00e9 \"N\"\n\ud83d\ude00", "offset": -4.5, "qos": 1,
+            "retain": true, "url": null } "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            members,
+            vec![
+                (
+                    "name".to_string(),
+                    Value::String("Café \"N\"\n😀".to_string())
+                ),
+                ("offset".to_string(), Value::Number("-4.5".to_string())),
+                ("qos".to_string(), Value::Number("1".to_string())),
+                ("retain".to_string(), Value::Boolean(true)),
+                ("url".to_string(), Value::Null),
+            ]
+        );
+        assert_eq!(parse_object("{}"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_object(""), Err(JsonError::Syntax { offset: 0 }));
+        assert_eq!(
+            parse_object(r#"{"a":1,}"#),
+            Err(JsonError::Syntax { offset: 7 })
+        );
+        assert_eq!(
+            parse_object(r#"{"a":01}"#),
+            Err(JsonError::Syntax { offset: 5 })
+        );
+        assert_eq!(
+            parse_object(r#"{"a":"\x"}"#),
+            Err(JsonError::Syntax { offset: 6 })
+        );
+        assert_eq!(
+            parse_object(r#"{"a":"open"#),
+            Err(JsonError::Syntax { offset: 10 })
+        );
+        assert_eq!(
+            parse_object(r#"{"a":1} x"#),
+            Err(JsonError::Syntax { offset: 8 })
+        );
+        assert_eq!(
+            parse_object(r#"{"a":{"b":1}}"#),
+            Err(JsonError::Unsupported { offset: 5 })
+        );
+    }
+
+    #[test]
+    fn fixed_point_values() {
+        let number = |text: &str| Value::Number(text.to_string());
+
+        assert_eq!(number("4.5").fixed(2), Some(450));
+        assert_eq!(number("-0.25").fixed(2), Some(-25));
+        assert_eq!(number("60").fixed(0), Some(60));
+        assert_eq!(number("4.125").fixed(2), None);
+        assert_eq!(number("1e3").fixed(0), None);
+        assert_eq!(Value::String("5".to_string()).fixed(0), None);
+    }
+
+    #[test]
+    fn fixed_point() {
+        assert_eq!(fixed(2150, 2), "21.50");
+        assert_eq!(fixed(-50, 2), "-0.50");
+        assert_eq!(fixed(-1205, 2), "-12.05");
+        assert_eq!(fixed(7, 1), "0.7");
+        assert_eq!(fixed(42, 0), "42");
+    }
+}
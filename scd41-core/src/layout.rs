@@ -0,0 +1,379 @@
+use crate::font::{glyph, GLYPH_WIDTH};
+
+/// Ellipsis appended to truncated text.
+pub const ELLIPSIS: &str = "...";
+
+/// Horizontal text alignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    /// Align to the left edge of the box.
+    #[default]
+    Left,
+
+    /// Center within the box.
+    Center,
+
+    /// Align to the right edge of the box.
+    Right,
+}
+
+/// Bounding box for text, in pixel columns and 8-pixel pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextBox {
+    /// Left edge in pixels.
+    pub x: u8,
+
+    /// Top page.
+    pub page: u8,
+
+    /// Width in pixels.
+    pub width: u8,
+
+    /// Height in pages (one line of text per page).
+    pub pages: u8,
+}
+
+/// A line of text positioned inside a [`TextBox`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacedLine {
+    /// The text of the line.
+    pub text: String,
+
+    /// Left edge in pixels.
+    pub x: u8,
+
+    /// Page.
+    pub page: u8,
+}
+
+/// Measure the inked width of a single character.
+///
+/// Trailing blank columns are not counted, so a space measures zero.
+///
+/// # Arguments
+/// * `c` - The character to measure.
+///
+/// # Returns
+/// * `u16` - The width in pixels.
+fn ink_width(c: char) -> u16 {
+    glyph(c)
+        .and_then(|columns| columns.iter().rposition(|&column| column != 0))
+        .map_or(0, |last| last as u16 + 1)
+}
+
+/// Measure the horizontal advance of a single character.
+///
+/// Characters without a glyph are skipped when drawing and advance by zero.
+///
+/// # Arguments
+/// * `c` - The character to measure.
+///
+/// # Returns
+/// * `u16` - The advance in pixels.
+pub fn char_advance(c: char) -> u16 {
+    if glyph(c).is_some() {
+        GLYPH_WIDTH as u16
+    } else {
+        0
+    }
+}
+
+/// Measure the pixel-exact width of a string rendered with `FONT_6X8`.
+///
+/// Every glyph advances by its full width except the last one, which only
+/// contributes its inked columns.
+///
+/// # Arguments
+/// * `text` - The text to measure.
+///
+/// # Returns
+/// * `u16` - The width in pixels.
+pub fn text_width(text: &str) -> u16 {
+    let mut width = 0;
+    let mut last = None;
+
+    for c in text.chars().filter(|&c| glyph(c).is_some()) {
+        if let Some(previous) = last {
+            width += char_advance(previous);
+        }
+        last = Some(c);
+    }
+
+    width + last.map_or(0, ink_width)
+}
+
+/// Compute the left offset of a line within a box of the given width.
+///
+/// # Arguments
+/// * `text` - The line of text.
+/// * `width` - The width of the box in pixels.
+/// * `align` - The alignment.
+///
+/// # Returns
+/// * `u8` - The offset from the left edge of the box in pixels.
+pub fn align_offset(text: &str, width: u8, align: Align) -> u8 {
+    let free = (width as u16).saturating_sub(text_width(text));
+
+    match align {
+        Align::Left => 0,
+        Align::Center => (free / 2) as u8,
+        Align::Right => free as u8,
+    }
+}
+
+/// Shorten text until it fits within a width together with an ellipsis.
+///
+/// # Arguments
+/// * `text` - The text to shorten.
+/// * `max_width` - The maximum width in pixels.
+///
+/// # Returns
+/// * `String` - The longest prefix of `text` that fits followed by [`ELLIPSIS`],
+///   or an empty string if not even the ellipsis fits.
+fn ellipsize(text: &str, max_width: u8) -> String {
+    let mut prefix = text.to_string();
+
+    loop {
+        let candidate = format!("{}{}", prefix.trim_end(), ELLIPSIS);
+        if text_width(&candidate) <= max_width as u16 {
+            return candidate;
+        }
+
+        if prefix.pop().is_none() {
+            return String::new();
+        }
+    }
+}
+
+/// Truncate text to fit within a width, appending an ellipsis if anything was cut.
+///
+/// # Arguments
+/// * `text` - The text to truncate.
+/// * `max_width` - The maximum width in pixels.
+///
+/// # Returns
+/// * `String` - The text unchanged if it fits, otherwise the longest prefix that
+///   fits together with [`ELLIPSIS`].
+pub fn truncate(text: &str, max_width: u8) -> String {
+    if text_width(text) <= max_width as u16 {
+        text.to_string()
+    } else {
+        ellipsize(text, max_width)
+    }
+}
+
+/// Break a single word that is wider than the line into line-sized pieces.
+///
+/// # Arguments
+/// * `word` - The word to break.
+/// * `max_width` - The maximum width in pixels.
+///
+/// # Returns
+/// * `Vec<String>` - The pieces, each fitting within `max_width`.
+fn break_word(word: &str, max_width: u8) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+
+    for c in word.chars() {
+        current.push(c);
+
+        if text_width(&current) > max_width as u16 && current.chars().count() > 1 {
+            current.pop();
+            pieces.push(core::mem::take(&mut current));
+            current.push(c);
+        }
+    }
+
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+
+    pieces
+}
+
+/// Word-wrap text to a maximum line width.
+///
+/// Words are split on whitespace; words wider than a line are broken.
+///
+/// # Arguments
+/// * `text` - The text to wrap.
+/// * `max_width` - The maximum width in pixels.
+///
+/// # Returns
+/// * `Vec<String>` - The wrapped lines.
+pub fn wrap(text: &str, max_width: u8) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if current.is_empty() {
+            word.to_string()
+        } else {
+            format!("{current} {word}")
+        };
+
+        if text_width(&candidate) <= max_width as u16 {
+            current = candidate;
+            continue;
+        }
+
+        if !current.is_empty() {
+            lines.push(core::mem::take(&mut current));
+        }
+
+        let mut pieces = break_word(word, max_width);
+        current = pieces.pop().unwrap_or_default();
+        lines.extend(pieces);
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+/// Lay out text inside a box: wrap, clip to the available pages and align.
+///
+/// If the text needs more lines than the box has pages, the last visible line
+/// is truncated with an ellipsis.
+///
+/// # Arguments
+/// * `text` - The text to lay out.
+/// * `bounds` - The bounding box.
+/// * `align` - The horizontal alignment of each line.
+///
+/// # Returns
+/// * `Vec<PlacedLine>` - The positioned lines, top to bottom.
+pub fn layout(text: &str, bounds: TextBox, align: Align) -> Vec<PlacedLine> {
+    let mut lines = wrap(text, bounds.width);
+    let max_lines = bounds.pages as usize;
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+
+        if let Some(last) = lines.last_mut() {
+            *last = ellipsize(last, bounds.width);
+        }
+    }
+
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(index, text)| PlacedLine {
+            x: bounds.x + align_offset(&text, bounds.width, align),
+            page: bounds.page + index as u8,
+            text,
+        })
+        .collect()
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL_WIDTH: u8 = 128;
+
+    #[test]
+    fn text_width_counts_ink_of_last_glyph() {
+        assert_eq!(text_width(""), 0);
+        // 'A' = [0x7e, 0x11, 0x11, 0x11, 0x7e, 0x00] -> five inked columns.
+        assert_eq!(text_width("A"), 5);
+        assert_eq!(text_width("AA"), 11);
+        // '.' = [0x00, 0x60, 0x60, 0x00, ...] -> three columns including the leading blank.
+        assert_eq!(text_width("..."), 15);
+        assert_eq!(text_width("A "), 6);
+    }
+
+    #[test]
+    fn text_width_skips_characters_without_glyph() {
+        assert_eq!(text_width("A\u{2603}A"), text_width("AA"));
+        assert_eq!(char_advance('\n'), 0);
+    }
+
+    #[test]
+    fn align_offsets() {
+        assert_eq!(align_offset("AA", 21, Align::Left), 0);
+        assert_eq!(align_offset("AA", 21, Align::Center), 5);
+        assert_eq!(align_offset("AA", 21, Align::Right), 10);
+        assert_eq!(align_offset("AAAAAA", 10, Align::Right), 0);
+    }
+
+    #[test]
+    fn truncate_keeps_text_that_fits() {
+        assert_eq!(truncate("CO2: 400 ppm", FULL_WIDTH), "CO2: 400 ppm");
+    }
+
+    #[test]
+    fn truncate_appends_ellipsis() {
+        let truncated = truncate("Failed to read measurement data", 60);
+        assert_eq!(truncated, "Failed...");
+        assert!(text_width(&truncated) <= 60);
+    }
+
+    #[test]
+    fn truncate_to_tiny_widths() {
+        assert_eq!(truncate("ABCDEF", 15), "...");
+        assert_eq!(truncate("ABCDEF", 4), "");
+    }
+
+    #[test]
+    fn wrap_on_word_boundaries() {
+        // 21 columns fit on a full-width line.
+        assert_eq!(
+            wrap("Failed to read measurement data from sensor", FULL_WIDTH),
+            vec!["Failed to read", "measurement data from", "sensor"]
+        );
+    }
+
+    #[test]
+    fn wrap_breaks_overlong_words() {
+        assert_eq!(wrap("ABCDEFGHIJ", 30), vec!["ABCDE", "FGHIJ"]);
+    }
+
+    #[test]
+    fn wrap_collapses_whitespace() {
+        assert_eq!(
+            wrap("  Sensor \n Error  ", FULL_WIDTH),
+            vec!["Sensor Error"]
+        );
+        assert!(wrap("   ", FULL_WIDTH).is_empty());
+    }
+
+    #[test]
+    fn layout_centers_lines_in_box() {
+        let bounds = TextBox {
+            x: 0,
+            page: 2,
+            width: FULL_WIDTH,
+            pages: 4,
+        };
+
+        let lines = layout("Sensor Error", bounds, Align::Center);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].page, 2);
+        assert_eq!(lines[0].x, (128 - text_width("Sensor Error") as u8) / 2);
+    }
+
+    #[test]
+    fn layout_clips_to_box_with_ellipsis() {
+        let bounds = TextBox {
+            x: 4,
+            page: 0,
+            width: 60,
+            pages: 2,
+        };
+
+        let lines = layout("one two three four five six", bounds, Align::Left);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "one two");
+        assert!(lines[1].text.ends_with(ELLIPSIS));
+        assert_eq!(lines[1].page, 1);
+
+        for line in &lines {
+            assert_eq!(line.x, 4);
+            assert!(text_width(&line.text) <= 60);
+        }
+    }
+}
@@ -1 +1,28 @@
+pub mod air_quality;
+pub mod alarm;
+pub mod api;
+pub mod battery;
+pub mod bthome;
+pub mod captive;
+pub mod cccd;
+pub mod clock;
+pub mod config;
+pub mod connection;
+pub mod control_point;
+pub mod device_info;
+pub mod ess;
+pub mod font;
+pub mod framebuffer;
+pub mod gatt;
+pub mod history;
+pub mod indicator;
+pub mod influx;
+pub mod json;
+pub mod layout;
+pub mod metrics;
+pub mod mqtt;
+pub mod ota;
+pub mod pairing;
+pub mod readings;
 pub mod scd41;
+pub mod wifi;
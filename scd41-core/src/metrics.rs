@@ -0,0 +1,377 @@
+//! Prometheus metrics in the text exposition format.
+//!
+//! The device keeps the counters and hands a [`Metrics`] snapshot to the web
+//! server, which encodes it for `GET /metrics`.
+
+use crate::{connection::ConnectionStats, json, scd41::ParseError};
+use core::fmt::Write;
+
+/// Content type of the exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Kind of failed sensor read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorErrorKind {
+    /// The data failed its CRC check.
+    CrcMismatch,
+
+    /// The sensor had no measurement ready.
+    NotReady,
+
+    /// The I2C transfer failed.
+    I2c,
+}
+
+/// Implementation of `SensorErrorKind`.
+impl SensorErrorKind {
+    /// All kinds, in exposition order.
+    pub const ALL: [SensorErrorKind; 3] = [
+        SensorErrorKind::CrcMismatch,
+        SensorErrorKind::NotReady,
+        SensorErrorKind::I2c,
+    ];
+
+    /// Classify a parse error.
+    ///
+    /// # Arguments
+    /// * `error` - The parse error.
+    ///
+    /// # Returns
+    /// * `SensorErrorKind` - The kind; a short read counts as a CRC mismatch, as
+    ///   the data is corrupt either way.
+    pub fn from_parse_error(error: &ParseError) -> Self {
+        match error {
+            ParseError::NotReadyAllZeros => SensorErrorKind::NotReady,
+            ParseError::CrcMismatch { .. } | ParseError::InvalidLength { .. } => {
+                SensorErrorKind::CrcMismatch
+            }
+        }
+    }
+
+    /// Get the metric label value.
+    ///
+    /// # Returns
+    /// * `&'static str` - The label value.
+    pub fn label(self) -> &'static str {
+        match self {
+            SensorErrorKind::CrcMismatch => "crc_mismatch",
+            SensorErrorKind::NotReady => "not_ready",
+            SensorErrorKind::I2c => "i2c",
+        }
+    }
+}
+
+/// Failed sensor reads since boot, by kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SensorErrorCounts {
+    /// The counts, indexed like [`SensorErrorKind::ALL`].
+    counts: [u32; 3],
+}
+
+/// Implementation of `SensorErrorCounts`.
+impl SensorErrorCounts {
+    /// Count a failed read.
+    ///
+    /// # Arguments
+    /// * `kind` - The kind of failure.
+    pub fn record(&mut self, kind: SensorErrorKind) {
+        let count = &mut self.counts[kind as usize];
+        *count = count.wrapping_add(1);
+    }
+
+    /// Get the count of a kind.
+    ///
+    /// # Arguments
+    /// * `kind` - The kind of failure.
+    ///
+    /// # Returns
+    /// * `u32` - The number of failed reads.
+    pub fn get(&self, kind: SensorErrorKind) -> u32 {
+        self.counts[kind as usize]
+    }
+}
+
+/// Snapshot of the device metrics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metrics {
+    /// Device name.
+    pub device_name: String,
+
+    /// Sensor serial number.
+    pub serial: String,
+
+    /// Firmware version.
+    pub firmware: String,
+
+    /// Latest CO2 in ppm, `None` before the first measurement.
+    pub co2: Option<u16>,
+
+    /// Latest temperature in 0.01 °C.
+    pub temperature: Option<i16>,
+
+    /// Latest relative humidity in 0.01 %.
+    pub humidity: Option<u16>,
+
+    /// Failed sensor reads.
+    pub sensor_errors: SensorErrorCounts,
+
+    /// BLE connection statistics.
+    pub ble: ConnectionStats,
+
+    /// Time since boot in seconds.
+    pub uptime_s: u64,
+
+    /// Free heap in bytes.
+    pub free_heap: u32,
+}
+
+/// Implementation of `Metrics`.
+impl Metrics {
+    /// Encode the metrics.
+    ///
+    /// # Returns
+    /// * `String` - The metrics in the Prometheus text exposition format; the
+    ///   measurement gauges have no sample before the first measurement.
+    pub fn encode(&self) -> String {
+        let mut text = String::new();
+
+        let info = format!(
+            "{{name={},serial={},firmware={}}}",
+            label_value(&self.device_name),
+            label_value(&self.serial),
+            label_value(&self.firmware)
+        );
+        family(&mut text, "co2monitor_info", "gauge", "Device identity.");
+        sample(&mut text, "co2monitor_info", &info, "1");
+
+        family(&mut text, "co2_ppm", "gauge", "CO2 concentration in ppm.");
+        if let Some(co2) = self.co2 {
+            sample(&mut text, "co2_ppm", "", &co2.to_string());
+        }
+        family(
+            &mut text,
+            "temperature_celsius",
+            "gauge",
+            "Temperature in degrees Celsius.",
+        );
+        if let Some(temperature) = self.temperature {
+            let value = json::fixed(temperature.into(), 2);
+            sample(&mut text, "temperature_celsius", "", &value);
+        }
+        family(
+            &mut text,
+            "humidity_percent",
+            "gauge",
+            "Relative humidity in percent.",
+        );
+        if let Some(humidity) = self.humidity {
+            let value = json::fixed(humidity.into(), 2);
+            sample(&mut text, "humidity_percent", "", &value);
+        }
+
+        family(
+            &mut text,
+            "sensor_read_errors_total",
+            "counter",
+            "Failed sensor reads by kind.",
+        );
+        for kind in SensorErrorKind::ALL {
+            let labels = format!("{{kind=\"{}\"}}", kind.label());
+            let count = self.sensor_errors.get(kind).to_string();
+            sample(&mut text, "sensor_read_errors_total", &labels, &count);
+        }
+
+        family(
+            &mut text,
+            "ble_connections_total",
+            "counter",
+            "BLE connections accepted.",
+        );
+        let accepted = self.ble.accepted.to_string();
+        sample(&mut text, "ble_connections_total", "", &accepted);
+        family(
+            &mut text,
+            "ble_connections",
+            "gauge",
+            "Connected BLE peers.",
+        );
+        sample(
+            &mut text,
+            "ble_connections",
+            "",
+            &self.ble.active.to_string(),
+        );
+        family(
+            &mut text,
+            "ble_notifications_total",
+            "counter",
+            "BLE notifications and indications sent.",
+        );
+        let notifications = self.ble.notifications.to_string();
+        sample(&mut text, "ble_notifications_total", "", &notifications);
+
+        family(&mut text, "uptime_seconds", "gauge", "Time since boot.");
+        sample(&mut text, "uptime_seconds", "", &self.uptime_s.to_string());
+        family(&mut text, "free_heap_bytes", "gauge", "Free heap.");
+        sample(
+            &mut text,
+            "free_heap_bytes",
+            "",
+            &self.free_heap.to_string(),
+        );
+
+        text
+    }
+}
+
+/// Write the help and type lines of a metric family.
+///
+/// # Arguments
+/// * `text` - The exposition.
+/// * `name` - The metric name.
+/// * `kind` - The metric type.
+/// * `help` - The description.
+fn family(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} {kind}");
+}
+
+/// Write a sample.
+///
+/// # Arguments
+/// * `text` - The exposition.
+/// * `name` - The metric name.
+/// * `labels` - The label set with braces, or empty.
+/// * `value` - The value.
+fn sample(text: &mut String, name: &str, labels: &str, value: &str) {
+    let _ = writeln!(text, "{name}{labels} {value}");
+}
+
+/// Quote a label value.
+///
+/// # Arguments
+/// * `value` - The value.
+///
+/// # Returns
+/// * `String` - The value in quotes, with backslashes, quotes and line feeds
+///   escaped.
+fn label_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+
+    format!("\"{escaped}\"")
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Metrics used by the tests.
+    fn metrics() -> Metrics {
+        let mut sensor_errors = SensorErrorCounts::default();
+        sensor_errors.record(SensorErrorKind::I2c);
+        sensor_errors.record(SensorErrorKind::NotReady);
+        sensor_errors.record(SensorErrorKind::NotReady);
+
+        Metrics {
+            device_name: "Office".into(),
+            serial: "0A1B2C3D4E5F".into(),
+            firmware: "0.1.0".into(),
+            co2: Some(812),
+            temperature: Some(-125),
+            humidity: Some(4012),
+            sensor_errors,
+            ble: ConnectionStats {
+                active: 1,
+                slots: 3,
+                accepted: 7,
+                notifications: 1234,
+                ..ConnectionStats::default()
+            },
+            uptime_s: 3600,
+            free_heap: 81234,
+        }
+    }
+
+    #[test]
+    fn exposition() {
+        assert_eq!(
+            metrics().encode(),
+            concat!(
+                "# HELP co2monitor_info Device identity.\n",
+                "# TYPE co2monitor_info gauge\n",
+                "co2monitor_info{name=\"Office\",serial=\"0A1B2C3D4E5F\",firmware=\"0.1.0\"} 1\n",
+                "# HELP co2_ppm CO2 concentration in ppm.\n",
+                "# TYPE co2_ppm gauge\n",
+                "co2_ppm 812\n",
+                "# HELP temperature_celsius Temperature in degrees Celsius.\n",
+                "# TYPE temperature_celsius gauge\n",
+                "temperature_celsius -1.25\n",
+                "# HELP humidity_percent Relative humidity in percent.\n",
+                "# TYPE humidity_percent gauge\n",
+                "humidity_percent 40.12\n",
+                "# HELP sensor_read_errors_total Failed sensor reads by kind.\n",
+                "# TYPE sensor_read_errors_total counter\n",
+                "sensor_read_errors_total{kind=\"crc_mismatch\"} 0\n",
+                "sensor_read_errors_total{kind=\"not_ready\"} 2\n",
+                "sensor_read_errors_total{kind=\"i2c\"} 1\n",
+                "# HELP ble_connections_total BLE connections accepted.\n",
+                "# TYPE ble_connections_total counter\n",
+                "ble_connections_total 7\n",
+                "# HELP ble_connections Connected BLE peers.\n",
+                "# TYPE ble_connections gauge\n",
+                "ble_connections 1\n",
+                "# HELP ble_notifications_total BLE notifications and indications sent.\n",
+                "# TYPE ble_notifications_total counter\n",
+                "ble_notifications_total 1234\n",
+                "# HELP uptime_seconds Time since boot.\n",
+                "# TYPE uptime_seconds gauge\n",
+                "uptime_seconds 3600\n",
+                "# HELP free_heap_bytes Free heap.\n",
+                "# TYPE free_heap_bytes gauge\n",
+                "free_heap_bytes 81234\n",
+            )
+        );
+    }
+
+    #[test]
+    fn no_measurement_yet() {
+        let text = Metrics {
+            co2: None,
+            temperature: None,
+            humidity: None,
+            ..metrics()
+        }
+        .encode();
+
+        assert!(text.contains("# TYPE co2_ppm gauge\n# HELP temperature_celsius"));
+        assert!(!text.contains("\nco2_ppm "));
+        assert!(!text.contains("\nhumidity_percent "));
+    }
+
+    #[test]
+    fn label_escaping() {
+        let text = Metrics {
+            device_name: "Lab \"B\"\\2\nnorth".into(),
+            ..metrics()
+        }
+        .encode();
+
+        assert!(text.contains(r#"co2monitor_info{name="Lab \"B\"\\2\nnorth","#));
+    }
+
+    #[test]
+    fn error_classification() {
+        assert_eq!(
+            SensorErrorKind::from_parse_error(&ParseError::NotReadyAllZeros),
+            SensorErrorKind::NotReady
+        );
+        assert_eq!(
+            SensorErrorKind::from_parse_error(&ParseError::CrcMismatch { chunk_index: 1 }),
+            SensorErrorKind::CrcMismatch
+        );
+    }
+}
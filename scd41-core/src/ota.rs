@@ -0,0 +1,885 @@
+//! Firmware update over BLE.
+//!
+//! A client sends a firmware image in three steps:
+//!
+//! 1. Begin (opcode `0x01`) on the OTA control point, with the image size,
+//!    CRC-32 and SHA-256. The response carries the offset to send from: `0` for a
+//!    new image, or the bytes already received when the same image was
+//!    interrupted, for example by a disconnect.
+//! 2. Chunks written without response to the OTA data characteristic, each the
+//!    offset as a little-endian `uint32` followed by image bytes. Chunks that do
+//!    not continue the image are dropped. Status (`0x02`) reports the offset
+//!    reached, for flow control and to resume after lost chunks.
+//! 3. Finish (`0x03`): the device reads the image back from flash and checks its
+//!    CRC-32 and SHA-256 before booting it.
+//!
+//! Abort (`0x04`) discards the transfer. Responses are indicated like those of the
+//! maintenance control point: the response opcode `0x80`, the request opcode, a
+//! result code and, for Begin and Status, the offset as a little-endian `uint32`.
+
+use crate::control_point::RESPONSE_OPCODE;
+use sha2::{Digest, Sha256};
+
+/// Length of the offset in front of every chunk.
+pub const CHUNK_HEADER_LEN: usize = 4;
+
+/// Flash sector size; erases cover whole sectors.
+pub const SECTOR_SIZE: u32 = 4096;
+
+/// How long a new image has to prove healthy after its first boot.
+pub const HEALTH_TIMEOUT_MS: u64 = 120_000;
+
+/// Failed measurements after which a new image is considered broken.
+const HEALTH_MAX_FAILURES: u32 = 3;
+
+/// Firmware image description sent with Begin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    /// Image size in bytes.
+    pub size: u32,
+
+    /// CRC-32 (IEEE 802.3) of the image.
+    pub crc32: u32,
+
+    /// SHA-256 of the image.
+    pub sha256: [u8; 32],
+}
+
+/// OTA control point request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtaRequest {
+    /// Start or resume a transfer (opcode `0x01`, `uint32` size, `uint32` CRC-32
+    /// and the 32-byte SHA-256).
+    Begin(ImageInfo),
+
+    /// Report the offset reached (opcode `0x02`).
+    Status,
+
+    /// Verify the image and boot it (opcode `0x03`).
+    Finish,
+
+    /// Discard the transfer (opcode `0x04`).
+    Abort,
+}
+
+/// Implementation of `OtaRequest`.
+impl OtaRequest {
+    /// Decode an OTA control point write.
+    ///
+    /// # Arguments
+    /// * `value` - The written value.
+    ///
+    /// # Returns
+    /// * `Result<OtaRequest, OtaResponse>` - The request, or the error response to
+    ///   indicate.
+    pub fn decode(value: &[u8]) -> Result<Self, OtaResponse> {
+        let Some((&opcode, parameter)) = value.split_first() else {
+            return Err(OtaResponse::failure(0x00, OtaResult::OpcodeNotSupported));
+        };
+
+        match (opcode, parameter.len()) {
+            (0x01, 40) => {
+                let word = |i: usize| u32::from_le_bytes(parameter[i..i + 4].try_into().unwrap());
+                let mut sha256 = [0; 32];
+                sha256.copy_from_slice(&parameter[8..]);
+                Ok(OtaRequest::Begin(ImageInfo {
+                    size: word(0),
+                    crc32: word(4),
+                    sha256,
+                }))
+            }
+            (0x02, 0) => Ok(OtaRequest::Status),
+            (0x03, 0) => Ok(OtaRequest::Finish),
+            (0x04, 0) => Ok(OtaRequest::Abort),
+            (0x01..=0x04, _) => Err(OtaResponse::failure(opcode, OtaResult::InvalidParameter)),
+            _ => Err(OtaResponse::failure(opcode, OtaResult::OpcodeNotSupported)),
+        }
+    }
+
+    /// Get the request opcode.
+    ///
+    /// # Returns
+    /// * `u8` - The opcode.
+    pub fn opcode(self) -> u8 {
+        match self {
+            OtaRequest::Begin(_) => 0x01,
+            OtaRequest::Status => 0x02,
+            OtaRequest::Finish => 0x03,
+            OtaRequest::Abort => 0x04,
+        }
+    }
+}
+
+/// Split an OTA data write into its offset and image bytes.
+///
+/// # Arguments
+/// * `value` - The written value.
+///
+/// # Returns
+/// * `Option<(u32, &[u8])>` - The offset and bytes, or `None` if the header is
+///   truncated.
+pub fn decode_chunk(value: &[u8]) -> Option<(u32, &[u8])> {
+    if value.len() < CHUNK_HEADER_LEN {
+        return None;
+    }
+    let (offset, data) = value.split_at(CHUNK_HEADER_LEN);
+
+    Some((u32::from_le_bytes(offset.try_into().ok()?), data))
+}
+
+/// OTA control point result code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtaResult {
+    /// The operation succeeded.
+    Success = 0x01,
+
+    /// The opcode is not supported.
+    OpcodeNotSupported = 0x02,
+
+    /// The parameters are invalid, or the image does not fit the partition.
+    InvalidParameter = 0x03,
+
+    /// Reading or writing flash failed.
+    OperationFailed = 0x04,
+
+    /// No transfer is running.
+    NotStarted = 0x05,
+
+    /// Finish was sent before the whole image was received.
+    Incomplete = 0x06,
+
+    /// The received image does not match its CRC-32 or SHA-256.
+    VerificationFailed = 0x07,
+}
+
+/// OTA control point response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OtaResponse {
+    /// The opcode of the request.
+    pub request_opcode: u8,
+
+    /// The result.
+    pub result: OtaResult,
+
+    /// The offset reached, for Begin and Status.
+    pub offset: Option<u32>,
+}
+
+/// Implementation of `OtaResponse`.
+impl OtaResponse {
+    /// Create a success response.
+    ///
+    /// # Arguments
+    /// * `request` - The request.
+    /// * `offset` - The offset reached, for Begin and Status.
+    ///
+    /// # Returns
+    /// * `OtaResponse` - The response.
+    pub fn success(request: OtaRequest, offset: Option<u32>) -> Self {
+        Self {
+            request_opcode: request.opcode(),
+            result: OtaResult::Success,
+            offset,
+        }
+    }
+
+    /// Create a failure response.
+    ///
+    /// # Arguments
+    /// * `request_opcode` - The opcode of the request.
+    /// * `result` - The result code.
+    ///
+    /// # Returns
+    /// * `OtaResponse` - The response.
+    pub fn failure(request_opcode: u8, result: OtaResult) -> Self {
+        Self {
+            request_opcode,
+            result,
+            offset: None,
+        }
+    }
+
+    /// Encode the response indication.
+    ///
+    /// # Returns
+    /// * `Vec<u8>` - The response opcode, request opcode, result code and offset.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = vec![RESPONSE_OPCODE, self.request_opcode, self.result as u8];
+        if let Some(offset) = self.offset {
+            value.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        value
+    }
+}
+
+/// CRC-32 (IEEE 802.3), computed incrementally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32(u32);
+
+/// Implementation of the `Default` trait for `Crc32`.
+impl Default for Crc32 {
+    /// Start a CRC over no data.
+    ///
+    /// # Returns
+    /// * `Crc32` - The initial CRC.
+    fn default() -> Self {
+        Crc32(0xffff_ffff)
+    }
+}
+
+/// Implementation of `Crc32`.
+impl Crc32 {
+    /// Add data to the CRC.
+    ///
+    /// # Arguments
+    /// * `data` - The data.
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.0 ^= u32::from(*byte);
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+    }
+
+    /// Get the CRC of the data added so far.
+    ///
+    /// # Returns
+    /// * `u32` - The CRC.
+    pub fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// Flash partition receiving the image.
+pub trait Partition {
+    /// The storage error.
+    type Error: core::fmt::Debug;
+
+    /// Get the partition size.
+    ///
+    /// # Returns
+    /// * `u32` - The size in bytes.
+    fn size(&self) -> u32;
+
+    /// Erase a range, setting every byte to `0xff`.
+    ///
+    /// # Arguments
+    /// * `offset` - The start, a multiple of [`SECTOR_SIZE`].
+    /// * `len` - The length, a multiple of [`SECTOR_SIZE`].
+    ///
+    /// # Returns
+    /// * `Result<(), Self::Error>` - The result of the erase.
+    fn erase(&mut self, offset: u32, len: u32) -> Result<(), Self::Error>;
+
+    /// Write to an erased range.
+    ///
+    /// # Arguments
+    /// * `offset` - The start.
+    /// * `data` - The data.
+    ///
+    /// # Returns
+    /// * `Result<(), Self::Error>` - The result of the write.
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Read a range.
+    ///
+    /// # Arguments
+    /// * `offset` - The start.
+    /// * `buf` - The buffer to fill.
+    ///
+    /// # Returns
+    /// * `Result<(), Self::Error>` - The result of the read.
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// Firmware update error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtaError<E> {
+    /// No transfer is running.
+    NotStarted,
+
+    /// The image is empty, does not fit the partition, or a chunk runs past its end.
+    InvalidImage,
+
+    /// Finish was requested before the whole image was received.
+    Incomplete,
+
+    /// The image does not match its CRC-32 or SHA-256.
+    VerificationFailed,
+
+    /// The partition failed.
+    Partition(E),
+}
+
+/// Implementation of the `Display` trait for `OtaError`.
+impl<E: core::fmt::Debug> core::fmt::Display for OtaError<E> {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OtaError::NotStarted => write!(f, "No firmware transfer running"),
+            OtaError::InvalidImage => write!(f, "Firmware image does not fit the partition"),
+            OtaError::Incomplete => write!(f, "Firmware image incomplete"),
+            OtaError::VerificationFailed => write!(f, "Firmware image checksum mismatch"),
+            OtaError::Partition(e) => write!(f, "Partition error: {:?}", e),
+        }
+    }
+}
+
+/// Implementation of the `Error` trait for `OtaError`.
+impl<E: core::fmt::Debug> std::error::Error for OtaError<E> {}
+
+/// Implementation of `OtaError`.
+impl<E> OtaError<E> {
+    /// Get the result code to report.
+    ///
+    /// # Returns
+    /// * `OtaResult` - The result code.
+    pub fn result(&self) -> OtaResult {
+        match self {
+            OtaError::NotStarted => OtaResult::NotStarted,
+            OtaError::InvalidImage => OtaResult::InvalidParameter,
+            OtaError::Incomplete => OtaResult::Incomplete,
+            OtaError::VerificationFailed => OtaResult::VerificationFailed,
+            OtaError::Partition(_) => OtaResult::OperationFailed,
+        }
+    }
+}
+
+/// A running transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Transfer {
+    /// The image being received.
+    image: ImageInfo,
+
+    /// Bytes received so far.
+    offset: u32,
+
+    /// Bytes erased so far, a multiple of [`SECTOR_SIZE`].
+    erased: u32,
+}
+
+/// Writes a firmware image into a partition.
+///
+/// The transfer outlives connections, so a client that reconnects and begins the
+/// same image again continues where it stopped.
+#[derive(Debug)]
+pub struct OtaUpdater<P> {
+    /// The partition receiving the image.
+    partition: P,
+
+    /// The running transfer.
+    transfer: Option<Transfer>,
+}
+
+/// Implementation of `OtaUpdater`.
+impl<P: Partition> OtaUpdater<P> {
+    /// Create an updater.
+    ///
+    /// # Arguments
+    /// * `partition` - The partition receiving images.
+    ///
+    /// # Returns
+    /// * `OtaUpdater<P>` - The updater, with no transfer running.
+    pub fn new(partition: P) -> Self {
+        Self {
+            partition,
+            transfer: None,
+        }
+    }
+
+    /// Get the partition receiving images.
+    ///
+    /// # Returns
+    /// * `&mut P` - The partition.
+    pub fn partition(&mut self) -> &mut P {
+        &mut self.partition
+    }
+
+    /// Start a transfer, or resume the running one if it is for the same image.
+    ///
+    /// # Arguments
+    /// * `image` - The image description.
+    ///
+    /// # Returns
+    /// * `Result<u32, OtaError<P::Error>>` - The offset to send from.
+    pub fn begin(&mut self, image: ImageInfo) -> Result<u32, OtaError<P::Error>> {
+        if let Some(transfer) = self.transfer.filter(|t| t.image == image) {
+            return Ok(transfer.offset);
+        }
+
+        if image.size == 0 || image.size > self.partition.size() {
+            return Err(OtaError::InvalidImage);
+        }
+
+        self.transfer = Some(Transfer {
+            image,
+            offset: 0,
+            erased: 0,
+        });
+        Ok(0)
+    }
+
+    /// Get the offset reached.
+    ///
+    /// # Returns
+    /// * `Option<u32>` - The bytes received, or `None` if no transfer is running.
+    pub fn offset(&self) -> Option<u32> {
+        self.transfer.map(|t| t.offset)
+    }
+
+    /// Write a chunk, erasing sectors ahead of it as needed.
+    ///
+    /// # Arguments
+    /// * `offset` - The offset of the chunk in the image.
+    /// * `data` - The image bytes.
+    ///
+    /// # Returns
+    /// * `Result<bool, OtaError<P::Error>>` - Whether the chunk was written; chunks
+    ///   that do not continue the image are dropped.
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<bool, OtaError<P::Error>> {
+        let transfer = self.transfer.as_mut().ok_or(OtaError::NotStarted)?;
+        if offset != transfer.offset {
+            return Ok(false);
+        }
+
+        let end = u32::try_from(data.len())
+            .ok()
+            .and_then(|len| offset.checked_add(len))
+            .filter(|end| *end <= transfer.image.size)
+            .ok_or(OtaError::InvalidImage)?;
+
+        if end > transfer.erased {
+            let erase_end = end.div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+            self.partition
+                .erase(transfer.erased, erase_end - transfer.erased)
+                .map_err(OtaError::Partition)?;
+            transfer.erased = erase_end;
+        }
+
+        self.partition
+            .write(offset, data)
+            .map_err(OtaError::Partition)?;
+        transfer.offset = end;
+
+        Ok(true)
+    }
+
+    /// End the transfer, reading the image back to check it.
+    ///
+    /// The transfer is kept if the image is incomplete, so the client can send the
+    /// rest; otherwise it is over, whether the image is valid or not.
+    ///
+    /// # Returns
+    /// * `Result<(), OtaError<P::Error>>` - Ok if the partition holds the image.
+    pub fn finish(&mut self) -> Result<(), OtaError<P::Error>> {
+        let transfer = self.transfer.ok_or(OtaError::NotStarted)?;
+        if transfer.offset < transfer.image.size {
+            return Err(OtaError::Incomplete);
+        }
+        self.transfer = None;
+
+        let mut crc = Crc32::default();
+        let mut sha256 = Sha256::new();
+        let mut buf = [0; SECTOR_SIZE as usize];
+        let mut offset = 0;
+        while offset < transfer.image.size {
+            let len = (transfer.image.size - offset).min(SECTOR_SIZE) as usize;
+            self.partition
+                .read(offset, &mut buf[..len])
+                .map_err(OtaError::Partition)?;
+            crc.update(&buf[..len]);
+            sha256.update(&buf[..len]);
+            offset += len as u32;
+        }
+
+        if crc.finish() != transfer.image.crc32
+            || sha256.finalize().as_slice() != transfer.image.sha256
+        {
+            return Err(OtaError::VerificationFailed);
+        }
+
+        Ok(())
+    }
+
+    /// Discard the running transfer.
+    pub fn abort(&mut self) {
+        self.transfer = None;
+    }
+}
+
+/// Outcome of the health check of a new image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthVerdict {
+    /// Still waiting for a measurement.
+    Pending,
+
+    /// The image works and is kept.
+    Healthy,
+
+    /// The image is broken and the previous one is restored.
+    Unhealthy,
+}
+
+/// Health check of an image booted for the first time after an update.
+///
+/// The image is kept once it has taken a measurement with BLE running, since BLE
+/// is the only way to update it again. It is rolled back if BLE failed to start,
+/// or if no measurement succeeds within [`HEALTH_TIMEOUT_MS`] or the first few
+/// attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthCheck {
+    /// When the check started, in milliseconds since boot.
+    started_ms: u64,
+
+    /// Whether BLE started.
+    ble_ready: bool,
+
+    /// Whether a measurement succeeded.
+    measured: bool,
+
+    /// Failed measurements.
+    failures: u32,
+}
+
+/// Implementation of `HealthCheck`.
+impl HealthCheck {
+    /// Start the check.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The current time in milliseconds since boot.
+    /// * `ble_ready` - Whether BLE started.
+    ///
+    /// # Returns
+    /// * `HealthCheck` - The check.
+    pub fn new(now_ms: u64, ble_ready: bool) -> Self {
+        Self {
+            started_ms: now_ms,
+            ble_ready,
+            measured: false,
+            failures: 0,
+        }
+    }
+
+    /// Record a measurement attempt.
+    ///
+    /// # Arguments
+    /// * `success` - Whether the measurement succeeded.
+    pub fn on_measurement(&mut self, success: bool) {
+        if success {
+            self.measured = true;
+        } else {
+            self.failures += 1;
+        }
+    }
+
+    /// Get the verdict.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The current time in milliseconds since boot.
+    ///
+    /// # Returns
+    /// * `HealthVerdict` - The verdict.
+    pub fn verdict(&self, now_ms: u64) -> HealthVerdict {
+        if !self.ble_ready {
+            HealthVerdict::Unhealthy
+        } else if self.measured {
+            HealthVerdict::Healthy
+        } else if self.failures >= HEALTH_MAX_FAILURES
+            || now_ms.saturating_sub(self.started_ms) >= HEALTH_TIMEOUT_MS
+        {
+            HealthVerdict::Unhealthy
+        } else {
+            HealthVerdict::Pending
+        }
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        fs::{File, OpenOptions},
+        io::{Read, Seek, SeekFrom, Write},
+        path::PathBuf,
+    };
+
+    /// Partition stand-in backed by a file, with NOR flash semantics: writes can
+    /// only clear bits, so a missing erase corrupts the image.
+    struct FilePartition {
+        file: File,
+        path: PathBuf,
+        size: u32,
+    }
+
+    impl FilePartition {
+        fn new(name: &str, size: u32) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "scd41-core-ota-{}-{}.bin",
+                name,
+                std::process::id()
+            ));
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)
+                .unwrap();
+            // Leave stale data behind, as a previous image would.
+            file.write_all(&vec![0x5a; size as usize]).unwrap();
+
+            Self { file, path, size }
+        }
+    }
+
+    impl Drop for FilePartition {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    impl Partition for FilePartition {
+        type Error = std::io::ErrorKind;
+
+        fn size(&self) -> u32 {
+            self.size
+        }
+
+        fn erase(&mut self, offset: u32, len: u32) -> Result<(), Self::Error> {
+            assert_eq!(offset % SECTOR_SIZE, 0);
+            assert_eq!(len % SECTOR_SIZE, 0);
+            assert!(offset + len <= self.size);
+            self.file
+                .seek(SeekFrom::Start(offset.into()))
+                .and_then(|_| self.file.write_all(&vec![0xff; len as usize]))
+                .map_err(|e| e.kind())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error> {
+            let mut flash = vec![0; data.len()];
+            self.read(offset, &mut flash)?;
+            for (cell, byte) in flash.iter_mut().zip(data) {
+                *cell &= byte;
+            }
+            self.file
+                .seek(SeekFrom::Start(offset.into()))
+                .and_then(|_| self.file.write_all(&flash))
+                .map_err(|e| e.kind())
+        }
+
+        fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error> {
+            self.file
+                .seek(SeekFrom::Start(offset.into()))
+                .and_then(|_| self.file.read_exact(buf))
+                .map_err(|e| e.kind())
+        }
+    }
+
+    fn image(len: usize) -> (Vec<u8>, ImageInfo) {
+        let data: Vec<u8> = (0..len).map(|i| (i * 7 + i / 251) as u8).collect();
+        let mut crc = Crc32::default();
+        crc.update(&data);
+        let info = ImageInfo {
+            size: len as u32,
+            crc32: crc.finish(),
+            sha256: Sha256::digest(&data).into(),
+        };
+        (data, info)
+    }
+
+    fn send(updater: &mut OtaUpdater<FilePartition>, data: &[u8], from: usize, chunk: usize) {
+        for (i, bytes) in data[from..].chunks(chunk).enumerate() {
+            let offset = (from + i * chunk) as u32;
+            assert_eq!(updater.write(offset, bytes), Ok(true));
+        }
+    }
+
+    #[test]
+    fn decode_requests() {
+        let mut begin = vec![0x01];
+        begin.extend_from_slice(&10_000u32.to_le_bytes());
+        begin.extend_from_slice(&0xcbf4_3926u32.to_le_bytes());
+        begin.extend_from_slice(&[0xab; 32]);
+
+        assert_eq!(
+            OtaRequest::decode(&begin),
+            Ok(OtaRequest::Begin(ImageInfo {
+                size: 10_000,
+                crc32: 0xcbf4_3926,
+                sha256: [0xab; 32],
+            }))
+        );
+        assert_eq!(OtaRequest::decode(&[0x02]), Ok(OtaRequest::Status));
+        assert_eq!(OtaRequest::decode(&[0x03]), Ok(OtaRequest::Finish));
+        assert_eq!(OtaRequest::decode(&[0x04]), Ok(OtaRequest::Abort));
+
+        let invalid = |opcode| OtaResponse::failure(opcode, OtaResult::InvalidParameter);
+        assert_eq!(OtaRequest::decode(&begin[..40]), Err(invalid(0x01)));
+        assert_eq!(OtaRequest::decode(&[0x03, 0x00]), Err(invalid(0x03)));
+        assert_eq!(
+            OtaRequest::decode(&[0x05]),
+            Err(OtaResponse::failure(0x05, OtaResult::OpcodeNotSupported))
+        );
+        assert_eq!(
+            OtaRequest::decode(&[]),
+            Err(OtaResponse::failure(0x00, OtaResult::OpcodeNotSupported))
+        );
+    }
+
+    #[test]
+    fn chunks_and_responses() {
+        assert_eq!(
+            decode_chunk(&[0x00, 0x10, 0x00, 0x00, 0xe9, 0x03]),
+            Some((4096, &[0xe9, 0x03][..]))
+        );
+        assert_eq!(decode_chunk(&[0x00, 0x10, 0x00]), None);
+
+        assert_eq!(
+            OtaResponse::success(OtaRequest::Status, Some(4096)).encode(),
+            [0x80, 0x02, 0x01, 0x00, 0x10, 0x00, 0x00]
+        );
+        assert_eq!(
+            OtaResponse::failure(0x03, OtaResult::VerificationFailed).encode(),
+            [0x80, 0x03, 0x07]
+        );
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        let mut crc = Crc32::default();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+        assert_eq!(Crc32::default().finish(), 0);
+    }
+
+    #[test]
+    fn transfer_and_verify() {
+        let (data, info) = image(10_000);
+        let mut updater = OtaUpdater::new(FilePartition::new("transfer", 16_384));
+
+        assert_eq!(updater.begin(info), Ok(0));
+        send(&mut updater, &data, 0, 244);
+        assert_eq!(updater.offset(), Some(10_000));
+        assert_eq!(updater.finish(), Ok(()));
+        assert_eq!(updater.offset(), None);
+
+        let mut flash = vec![0; data.len()];
+        updater.partition().read(0, &mut flash).unwrap();
+        assert_eq!(flash, data);
+    }
+
+    #[test]
+    fn resume_after_disconnect() {
+        let (data, info) = image(9_000);
+        let mut updater = OtaUpdater::new(FilePartition::new("resume", 16_384));
+
+        assert_eq!(updater.begin(info), Ok(0));
+        send(&mut updater, &data[..5_000], 0, 500);
+
+        // A chunk from before the disconnect arrives again, then one is lost.
+        assert_eq!(updater.write(4_500, &data[4_500..5_000]), Ok(false));
+        assert_eq!(updater.write(5_500, &data[5_500..6_000]), Ok(false));
+
+        assert_eq!(updater.begin(info), Ok(5_000));
+        send(&mut updater, &data, 5_000, 500);
+        assert_eq!(updater.finish(), Ok(()));
+    }
+
+    #[test]
+    fn new_image_restarts() {
+        let (data, info) = image(6_000);
+        let (other, other_info) = image(5_000);
+        let mut updater = OtaUpdater::new(FilePartition::new("restart", 8_192));
+
+        updater.begin(info).unwrap();
+        send(&mut updater, &data[..3_000], 0, 1_000);
+
+        assert_eq!(updater.begin(other_info), Ok(0));
+        send(&mut updater, &other, 0, 1_000);
+        assert_eq!(updater.finish(), Ok(()));
+    }
+
+    #[test]
+    fn verification_failures() {
+        let (data, info) = image(5_000);
+        let mut updater = OtaUpdater::new(FilePartition::new("verify", 8_192));
+
+        assert_eq!(updater.finish(), Err(OtaError::NotStarted));
+        assert_eq!(updater.write(0, &data), Err(OtaError::NotStarted));
+
+        let corrupted = ImageInfo {
+            sha256: [0; 32],
+            ..info
+        };
+        updater.begin(corrupted).unwrap();
+        send(&mut updater, &data[..4_000], 0, 1_000);
+        assert_eq!(updater.finish(), Err(OtaError::Incomplete));
+        assert_eq!(updater.write(4_000, &data[4_000..4_001]), Ok(true));
+        assert_eq!(
+            updater.write(4_001, &[0; 1_000]),
+            Err(OtaError::InvalidImage)
+        );
+        send(&mut updater, &data, 4_001, 1_000);
+        assert_eq!(updater.finish(), Err(OtaError::VerificationFailed));
+        assert_eq!(updater.offset(), None);
+        assert_eq!(
+            OtaError::<std::io::ErrorKind>::VerificationFailed.result(),
+            OtaResult::VerificationFailed
+        );
+    }
+
+    #[test]
+    fn oversized_images() {
+        let (_, info) = image(100);
+        let mut updater = OtaUpdater::new(FilePartition::new("oversized", 4_096));
+
+        for size in [0, 4_097] {
+            assert_eq!(
+                updater.begin(ImageInfo { size, ..info }),
+                Err(OtaError::InvalidImage)
+            );
+        }
+        assert_eq!(
+            updater.begin(ImageInfo {
+                size: 4_096,
+                ..info
+            }),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn health_check() {
+        let mut check = HealthCheck::new(1_000, true);
+        assert_eq!(check.verdict(1_000), HealthVerdict::Pending);
+        check.on_measurement(false);
+        assert_eq!(check.verdict(2_000), HealthVerdict::Pending);
+        check.on_measurement(true);
+        assert_eq!(check.verdict(2_000), HealthVerdict::Healthy);
+
+        let mut check = HealthCheck::new(1_000, true);
+        assert_eq!(
+            check.verdict(1_000 + HEALTH_TIMEOUT_MS),
+            HealthVerdict::Unhealthy
+        );
+        for _ in 0..HEALTH_MAX_FAILURES {
+            check.on_measurement(false);
+        }
+        assert_eq!(check.verdict(1_000), HealthVerdict::Unhealthy);
+
+        let mut check = HealthCheck::new(0, false);
+        check.on_measurement(true);
+        assert_eq!(check.verdict(0), HealthVerdict::Unhealthy);
+    }
+}
@@ -0,0 +1,176 @@
+//! Pairing progress shown on the display.
+//!
+//! The device has a display but no input, so pairing uses the passkey entry
+//! method: the device shows a random six-digit passkey that the user types on
+//! the phone. The passkey stays on screen until pairing completes or times out,
+//! followed briefly by the outcome.
+
+/// How long a passkey is shown without the pairing completing, matching the
+/// Security Manager Protocol timeout.
+pub const PASSKEY_TIMEOUT_MS: u64 = 30_000;
+
+/// How long the outcome of a pairing is shown.
+pub const RESULT_TIMEOUT_MS: u64 = 3_000;
+
+/// Pairing screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingScreen {
+    /// The passkey to type on the phone.
+    Passkey(u32),
+
+    /// Pairing succeeded and the peer is bonded.
+    Paired,
+
+    /// Pairing failed.
+    Failed,
+}
+
+/// Implementation of `PairingScreen`.
+impl PairingScreen {
+    /// Get the title line.
+    ///
+    /// # Returns
+    /// * `&'static str` - The title.
+    pub fn title(self) -> &'static str {
+        match self {
+            PairingScreen::Passkey(_) => "Pairing code",
+            PairingScreen::Paired => "Paired",
+            PairingScreen::Failed => "Pairing failed",
+        }
+    }
+
+    /// Get the detail line.
+    ///
+    /// # Returns
+    /// * `Option<String>` - The passkey, split into two groups of three digits for
+    ///   readability, or `None` if the screen only has a title.
+    pub fn detail(self) -> Option<String> {
+        match self {
+            PairingScreen::Passkey(passkey) => {
+                let digits = format!("{:06}", passkey % 1_000_000);
+                Some(format!("{} {}", &digits[..3], &digits[3..]))
+            }
+            PairingScreen::Paired | PairingScreen::Failed => None,
+        }
+    }
+}
+
+/// Pairing progress.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pairing {
+    /// The screen to show and when it was entered, in milliseconds since boot.
+    screen: Option<(PairingScreen, u64)>,
+}
+
+/// Implementation of `Pairing`.
+impl Pairing {
+    /// Start showing a passkey.
+    ///
+    /// # Arguments
+    /// * `passkey` - The passkey.
+    /// * `now_ms` - The current time in milliseconds since boot.
+    pub fn passkey(&mut self, passkey: u32, now_ms: u64) {
+        self.screen = Some((PairingScreen::Passkey(passkey), now_ms));
+    }
+
+    /// Record the outcome of a pairing.
+    ///
+    /// Reconnections of an already bonded peer also complete authentication; they
+    /// are only shown if a passkey was on screen.
+    ///
+    /// # Arguments
+    /// * `success` - Whether pairing succeeded.
+    /// * `now_ms` - The current time in milliseconds since boot.
+    pub fn complete(&mut self, success: bool, now_ms: u64) {
+        if !matches!(self.screen, Some((PairingScreen::Passkey(_), _))) {
+            return;
+        }
+
+        let screen = if success {
+            PairingScreen::Paired
+        } else {
+            PairingScreen::Failed
+        };
+        self.screen = Some((screen, now_ms));
+    }
+
+    /// Get the screen to show, dropping it once it has timed out.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The current time in milliseconds since boot.
+    ///
+    /// # Returns
+    /// * `Option<PairingScreen>` - The screen, or `None` if no pairing is in progress.
+    pub fn screen(&mut self, now_ms: u64) -> Option<PairingScreen> {
+        let (screen, since_ms) = self.screen?;
+        let timeout_ms = match screen {
+            PairingScreen::Passkey(_) => PASSKEY_TIMEOUT_MS,
+            PairingScreen::Paired | PairingScreen::Failed => RESULT_TIMEOUT_MS,
+        };
+
+        if now_ms.saturating_sub(since_ms) >= timeout_ms {
+            self.screen = None;
+        }
+
+        self.screen.map(|(screen, _)| screen)
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passkey_then_result() {
+        let mut pairing = Pairing::default();
+        assert_eq!(pairing.screen(0), None);
+
+        pairing.passkey(42, 1_000);
+        assert_eq!(pairing.screen(2_000), Some(PairingScreen::Passkey(42)));
+
+        pairing.complete(true, 10_000);
+        assert_eq!(pairing.screen(12_999), Some(PairingScreen::Paired));
+        assert_eq!(pairing.screen(13_000), None);
+    }
+
+    #[test]
+    fn passkey_times_out() {
+        let mut pairing = Pairing::default();
+        pairing.passkey(123_456, 1_000);
+
+        assert_eq!(
+            pairing.screen(30_999),
+            Some(PairingScreen::Passkey(123_456))
+        );
+        assert_eq!(pairing.screen(31_000), None);
+
+        pairing.complete(false, 32_000);
+        assert_eq!(pairing.screen(32_000), None);
+    }
+
+    #[test]
+    fn bonded_reconnection_is_not_shown() {
+        let mut pairing = Pairing::default();
+        pairing.complete(true, 0);
+        assert_eq!(pairing.screen(0), None);
+
+        pairing.passkey(1, 0);
+        pairing.complete(false, 100);
+        assert_eq!(pairing.screen(100), Some(PairingScreen::Failed));
+    }
+
+    #[test]
+    fn screen_text() {
+        assert_eq!(
+            PairingScreen::Passkey(42).detail().as_deref(),
+            Some("000 042")
+        );
+        assert_eq!(
+            PairingScreen::Passkey(987_654).detail().as_deref(),
+            Some("987 654")
+        );
+        assert_eq!(PairingScreen::Paired.detail(), None);
+        assert_eq!(PairingScreen::Failed.title(), "Pairing failed");
+    }
+}
@@ -0,0 +1,146 @@
+//! Packed "all readings" characteristic.
+//!
+//! One notification carries a complete sample, so clients never combine a CO2
+//! reading with the temperature of the previous measurement. The value is 11
+//! bytes, little-endian:
+//!
+//! | Offset | Field | Format |
+//! |--------|-------|--------|
+//! | 0 | CO2 in ppm | `uint16` |
+//! | 2 | Temperature in 0.01 °C | `sint16` |
+//! | 4 | Relative humidity in 0.01 % | `uint16` |
+//! | 6 | Status: air quality level in bits 0–3, alarm state in bits 4–7 | `uint8` |
+//! | 7 | Sequence number, incremented with every measurement | `uint32` |
+
+use crate::{air_quality::AirQuality, alarm::AlarmState};
+
+/// Length of the encoded value.
+pub const READINGS_LEN: usize = 11;
+
+/// A complete sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Readings {
+    /// CO2 concentration in ppm.
+    pub co2: u16,
+
+    /// Temperature in 0.01 °C.
+    pub temperature: i16,
+
+    /// Relative humidity in 0.01 %.
+    pub humidity: u16,
+
+    /// Air quality level index, as from [`AirQuality::index`].
+    pub air_quality: u8,
+
+    /// Alarm state code, as from [`AlarmState::code`].
+    pub alarm: u8,
+
+    /// Sequence number of the measurement.
+    pub sequence: u32,
+}
+
+/// Implementation of `Readings`.
+impl Readings {
+    /// Create a sample.
+    ///
+    /// # Arguments
+    /// * `co2` - The CO2 concentration in ppm.
+    /// * `temperature` - The temperature in 0.01 °C.
+    /// * `humidity` - The relative humidity in 0.01 %.
+    /// * `air_quality` - The air quality level.
+    /// * `alarm` - The alarm state.
+    /// * `sequence` - The sequence number of the measurement.
+    ///
+    /// # Returns
+    /// * `Readings` - The sample.
+    pub fn new(
+        co2: u16,
+        temperature: i16,
+        humidity: u16,
+        air_quality: AirQuality,
+        alarm: AlarmState,
+        sequence: u32,
+    ) -> Self {
+        Self {
+            co2,
+            temperature,
+            humidity,
+            air_quality: air_quality.index(),
+            alarm: alarm.code(),
+            sequence,
+        }
+    }
+
+    /// Encode the characteristic value.
+    ///
+    /// # Returns
+    /// * `[u8; READINGS_LEN]` - The packed sample.
+    pub fn encode(&self) -> [u8; READINGS_LEN] {
+        let mut value = [0; READINGS_LEN];
+        value[0..2].copy_from_slice(&self.co2.to_le_bytes());
+        value[2..4].copy_from_slice(&self.temperature.to_le_bytes());
+        value[4..6].copy_from_slice(&self.humidity.to_le_bytes());
+        value[6] = (self.air_quality & 0x0f) | (self.alarm << 4);
+        value[7..11].copy_from_slice(&self.sequence.to_le_bytes());
+
+        value
+    }
+
+    /// Decode a characteristic value.
+    ///
+    /// # Arguments
+    /// * `value` - The packed sample.
+    ///
+    /// # Returns
+    /// * `Option<Readings>` - The sample, or `None` if the length is wrong.
+    pub fn decode(value: &[u8]) -> Option<Self> {
+        let value: &[u8; READINGS_LEN] = value.try_into().ok()?;
+
+        Some(Self {
+            co2: u16::from_le_bytes([value[0], value[1]]),
+            temperature: i16::from_le_bytes([value[2], value[3]]),
+            humidity: u16::from_le_bytes([value[4], value[5]]),
+            air_quality: value[6] & 0x0f,
+            alarm: value[6] >> 4,
+            sequence: u32::from_le_bytes([value[7], value[8], value[9], value[10]]),
+        })
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_bytes() {
+        let readings = Readings::new(
+            1234,
+            -550,
+            4567,
+            AirQuality::Fair,
+            AlarmState::Active,
+            0x0102_0304,
+        );
+
+        assert_eq!(
+            readings.encode(),
+            [0xd2, 0x04, 0xda, 0xfd, 0xd7, 0x11, 0x22, 0x04, 0x03, 0x02, 0x01]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let readings = Readings::new(
+            5000,
+            2150,
+            10000,
+            AirQuality::Bad,
+            AlarmState::Latched,
+            u32::MAX,
+        );
+
+        assert_eq!(Readings::decode(&readings.encode()), Some(readings));
+        assert_eq!(Readings::decode(&readings.encode()[..10]), None);
+    }
+}
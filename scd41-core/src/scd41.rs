@@ -124,6 +124,102 @@ pub fn parse_measurement(buffer: &[u8]) -> Result<Measurement, ParseError> {
     })
 }
 
+/// Parse a single-word response: `word(2) + CRC(1)`.
+///
+/// # Arguments
+/// * `buffer` - The buffer containing the response.
+///
+/// # Returns
+/// * `Result<u16, ParseError>` - The word or an error.
+pub fn parse_word(buffer: &[u8]) -> Result<u16, ParseError> {
+    let [high, low, crc] = buffer else {
+        return Err(ParseError::InvalidLength {
+            expected: 3,
+            actual: buffer.len(),
+        });
+    };
+
+    if crc8_sensirion(&[*high, *low]) != *crc {
+        return Err(ParseError::CrcMismatch { chunk_index: 0 });
+    }
+
+    Ok(u16::from_be_bytes([*high, *low]))
+}
+
+/// Parse the `get_serial_number` response: three words, each followed by a CRC.
+///
+/// # Arguments
+/// * `buffer` - The buffer containing the response.
+///
+/// # Returns
+/// * `Result<u64, ParseError>` - The 48-bit serial number or an error.
+pub fn parse_serial_number(buffer: &[u8]) -> Result<u64, ParseError> {
+    if buffer.len() != 9 {
+        return Err(ParseError::InvalidLength {
+            expected: 9,
+            actual: buffer.len(),
+        });
+    }
+
+    buffer
+        .chunks(3)
+        .enumerate()
+        .try_fold(0u64, |serial, (chunk_index, chunk)| {
+            let word = parse_word(chunk).map_err(|_| ParseError::CrcMismatch { chunk_index })?;
+            Ok(serial << 16 | u64::from(word))
+        })
+}
+
+/// Convert the `perform_forced_recalibration` response to the applied correction.
+///
+/// # Arguments
+/// * `word` - The response word.
+///
+/// # Returns
+/// * `Option<i16>` - The correction in ppm, `None` if the recalibration failed.
+pub fn frc_correction(word: u16) -> Option<i16> {
+    if word == 0xffff {
+        return None;
+    }
+
+    Some((word as i32 - 0x8000) as i16)
+}
+
+/// Encode a command with a 16-bit argument:
+/// `command(2) + argument(2) + CRC(1)`, big-endian.
+///
+/// # Arguments
+/// * `command` - The command word.
+/// * `argument` - The argument word.
+///
+/// # Returns
+/// * `[u8; 5]` - The bytes to write.
+pub fn encode_command_with_argument(command: u16, argument: u16) -> [u8; 5] {
+    let command = command.to_be_bytes();
+    let argument = argument.to_be_bytes();
+
+    [
+        command[0],
+        command[1],
+        argument[0],
+        argument[1],
+        crc8_sensirion(&argument),
+    ]
+}
+
+/// Convert a temperature offset to the `set_temperature_offset` argument word.
+///
+/// # Arguments
+/// * `offset_c` - The offset in degrees Celsius.
+///
+/// # Returns
+/// * `u16` - The argument word, `offset * 2^16 / 175`.
+pub fn temperature_offset_word(offset_c: f32) -> u16 {
+    (offset_c * 65536.0 / 175.0)
+        .round()
+        .clamp(0.0, u16::MAX as f32) as u16
+}
+
 /// Tests.
 #[cfg(test)]
 mod tests {
@@ -141,6 +237,64 @@ mod tests {
         assert_eq!(crc8_sensirion(&data), 0x92);
     }
 
+    #[test]
+    fn command_with_argument_matches_datasheet_examples() {
+        // set_temperature_offset to 5.4 °C
+        assert_eq!(temperature_offset_word(5.4), 0x07e6);
+        assert_eq!(
+            encode_command_with_argument(0x241d, 0x07e6),
+            [0x24, 0x1d, 0x07, 0xe6, 0x48]
+        );
+        // set_sensor_altitude to 1950 m
+        assert_eq!(
+            encode_command_with_argument(0x2427, 1950),
+            [0x24, 0x27, 0x07, 0x9e, 0x09]
+        );
+    }
+
+    #[test]
+    fn parse_word_matches_datasheet_examples() {
+        // perform_forced_recalibration to 480 ppm, answered with a -50 ppm correction
+        assert_eq!(
+            encode_command_with_argument(0x362f, 480),
+            [0x36, 0x2f, 0x01, 0xe0, 0xb4]
+        );
+        assert_eq!(parse_word(&[0x7f, 0xce, 0x7b]), Ok(0x7fce));
+        assert_eq!(frc_correction(0x7fce), Some(-50));
+        assert_eq!(frc_correction(0xffff), None);
+        // perform_self_test without malfunction
+        assert_eq!(parse_word(&[0x00, 0x00, 0x81]), Ok(0));
+
+        assert_eq!(
+            parse_word(&[0x00, 0x00, 0x82]),
+            Err(ParseError::CrcMismatch { chunk_index: 0 })
+        );
+        assert_eq!(
+            parse_word(&[0x00, 0x00]),
+            Err(ParseError::InvalidLength {
+                expected: 3,
+                actual: 2
+            })
+        );
+    }
+
+    #[test]
+    fn parse_serial_number_matches_datasheet_example() {
+        let mut buffer = [0u8; 9];
+        buffer[0..3].copy_from_slice(&chunk(0xf896));
+        buffer[3..6].copy_from_slice(&chunk(0x9f07));
+        buffer[6..9].copy_from_slice(&chunk(0x3bbe));
+
+        assert_eq!(parse_serial_number(&buffer), Ok(0xf8969f073bbe));
+
+        buffer[5] ^= 1;
+        assert_eq!(
+            parse_serial_number(&buffer),
+            Err(ParseError::CrcMismatch { chunk_index: 1 })
+        );
+        assert!(parse_serial_number(&buffer[..6]).is_err());
+    }
+
     #[test]
     fn parse_measurement_ok() {
         // Use a non-zero CO2 to avoid NotReadyAllZeros.
@@ -0,0 +1,574 @@
+//! Wi-Fi station credentials, reconnection and status.
+//!
+//! Credentials are provisioned over BLE or the setup portal and stored in NVS,
+//! both in the same encoding: the SSID length, the SSID, the passphrase length and
+//! the passphrase, with UTF-8 text. An empty passphrase selects an open network.
+
+/// Longest SSID in bytes.
+pub const SSID_MAX_LEN: usize = 32;
+
+/// Shortest WPA2 passphrase.
+pub const PASSWORD_MIN_LEN: usize = 8;
+
+/// Longest WPA2 passphrase; 64 characters are a raw hexadecimal key.
+pub const PASSWORD_MAX_LEN: usize = 64;
+
+/// Longest encoded credentials.
+pub const CREDENTIALS_MAX_LEN: usize = 2 + SSID_MAX_LEN + PASSWORD_MAX_LEN;
+
+/// How long the first attempt to join a network may take before backing off.
+pub const CONNECT_TIMEOUT_MS: u64 = 15_000;
+
+/// Delay before the first reconnection attempt.
+pub const BACKOFF_INITIAL_MS: u64 = 1_000;
+
+/// Longest delay between reconnection attempts.
+pub const BACKOFF_MAX_MS: u64 = 300_000;
+
+/// Credentials error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialsError {
+    /// The value ends in the middle of a field, or has bytes after the passphrase.
+    InvalidLength,
+
+    /// The SSID is empty or longer than [`SSID_MAX_LEN`] bytes.
+    InvalidSsid,
+
+    /// The passphrase is neither empty nor 8 to 64 bytes long.
+    InvalidPassword,
+
+    /// A field is not valid UTF-8.
+    InvalidUtf8,
+}
+
+/// Implementation of the `Display` trait for `CredentialsError`.
+impl core::fmt::Display for CredentialsError {
+    /// Format the error message.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to write the error message to.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CredentialsError::InvalidLength => write!(f, "Invalid credentials length"),
+            CredentialsError::InvalidSsid => write!(f, "SSID must be 1 to 32 bytes"),
+            CredentialsError::InvalidPassword => {
+                write!(f, "Passphrase must be empty or 8 to 64 bytes")
+            }
+            CredentialsError::InvalidUtf8 => write!(f, "Credentials are not valid UTF-8"),
+        }
+    }
+}
+
+/// Implementation of the `Error` trait for `CredentialsError`.
+impl std::error::Error for CredentialsError {}
+
+/// Network to join.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    /// Network name.
+    pub ssid: String,
+
+    /// WPA2 passphrase, empty for an open network.
+    pub password: String,
+}
+
+/// Implementation of the `Debug` trait for `Credentials`, leaving out the
+/// passphrase so it never reaches the log.
+impl core::fmt::Debug for Credentials {
+    /// Format the credentials.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter.
+    ///
+    /// # Returns
+    /// * `core::fmt::Result` - The result of the formatting operation.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("ssid", &self.ssid)
+            .field("open", &self.password.is_empty())
+            .finish()
+    }
+}
+
+/// Implementation of `Credentials`.
+impl Credentials {
+    /// Validate credentials.
+    ///
+    /// # Arguments
+    /// * `ssid` - The network name.
+    /// * `password` - The passphrase, empty for an open network.
+    ///
+    /// # Returns
+    /// * `Result<Credentials, CredentialsError>` - The credentials or an error.
+    pub fn new(ssid: &str, password: &str) -> Result<Self, CredentialsError> {
+        if ssid.is_empty() || ssid.len() > SSID_MAX_LEN {
+            return Err(CredentialsError::InvalidSsid);
+        }
+        if !password.is_empty() && !(PASSWORD_MIN_LEN..=PASSWORD_MAX_LEN).contains(&password.len())
+        {
+            return Err(CredentialsError::InvalidPassword);
+        }
+
+        Ok(Self {
+            ssid: ssid.to_string(),
+            password: password.to_string(),
+        })
+    }
+
+    /// Encode the credentials.
+    ///
+    /// # Returns
+    /// * `Vec<u8>` - The length-prefixed SSID and passphrase.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(2 + self.ssid.len() + self.password.len());
+        value.push(self.ssid.len() as u8);
+        value.extend_from_slice(self.ssid.as_bytes());
+        value.push(self.password.len() as u8);
+        value.extend_from_slice(self.password.as_bytes());
+
+        value
+    }
+
+    /// Decode credentials.
+    ///
+    /// # Arguments
+    /// * `value` - The length-prefixed SSID and passphrase.
+    ///
+    /// # Returns
+    /// * `Result<Credentials, CredentialsError>` - The credentials or an error.
+    pub fn decode(value: &[u8]) -> Result<Self, CredentialsError> {
+        let (ssid, rest) = split_field(value)?;
+        let (password, rest) = split_field(rest)?;
+        if !rest.is_empty() {
+            return Err(CredentialsError::InvalidLength);
+        }
+
+        let text = |bytes| core::str::from_utf8(bytes).map_err(|_| CredentialsError::InvalidUtf8);
+        Self::new(text(ssid)?, text(password)?)
+    }
+}
+
+/// Split a length-prefixed field off the front of a value.
+///
+/// # Arguments
+/// * `value` - The value.
+///
+/// # Returns
+/// * `Result<(&[u8], &[u8]), CredentialsError>` - The field and the rest.
+fn split_field(value: &[u8]) -> Result<(&[u8], &[u8]), CredentialsError> {
+    let (&len, rest) = value.split_first().ok_or(CredentialsError::InvalidLength)?;
+    if rest.len() < len as usize {
+        return Err(CredentialsError::InvalidLength);
+    }
+
+    Ok(rest.split_at(len as usize))
+}
+
+/// Decode a credentials write from a BLE client.
+///
+/// # Arguments
+/// * `value` - Encoded credentials, or a single zero byte to forget the network.
+///
+/// # Returns
+/// * `Result<Option<Credentials>, CredentialsError>` - The credentials, `None` to
+///   forget the network, or an error.
+pub fn decode_provisioning(value: &[u8]) -> Result<Option<Credentials>, CredentialsError> {
+    match value {
+        [0] => Ok(None),
+        _ => Credentials::decode(value).map(Some),
+    }
+}
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// The delay after the last success.
+    initial_ms: u64,
+
+    /// The longest delay.
+    max_ms: u64,
+
+    /// The next delay.
+    next_ms: u64,
+}
+
+/// Implementation of the `Default` trait for `Backoff`.
+impl Default for Backoff {
+    /// Back off from [`BACKOFF_INITIAL_MS`] to [`BACKOFF_MAX_MS`].
+    ///
+    /// # Returns
+    /// * `Backoff` - The backoff.
+    fn default() -> Self {
+        Self::new(BACKOFF_INITIAL_MS, BACKOFF_MAX_MS)
+    }
+}
+
+/// Implementation of `Backoff`.
+impl Backoff {
+    /// Create a backoff.
+    ///
+    /// # Arguments
+    /// * `initial_ms` - The first delay.
+    /// * `max_ms` - The longest delay.
+    ///
+    /// # Returns
+    /// * `Backoff` - The backoff.
+    pub fn new(initial_ms: u64, max_ms: u64) -> Self {
+        Self {
+            initial_ms,
+            max_ms,
+            next_ms: initial_ms,
+        }
+    }
+
+    /// Get the delay before the next attempt, doubling the one after.
+    ///
+    /// # Returns
+    /// * `u64` - The delay in milliseconds.
+    pub fn next_delay(&mut self) -> u64 {
+        let delay = self.next_ms;
+        self.next_ms = (self.next_ms * 2).min(self.max_ms);
+        delay
+    }
+
+    /// Start over from the first delay.
+    pub fn reset(&mut self) {
+        self.next_ms = self.initial_ms;
+    }
+}
+
+/// Station link state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Link {
+    /// Not trying to connect.
+    Idle,
+
+    /// Joining the network.
+    Connecting {
+        /// When the attempt started, in milliseconds since boot.
+        since_ms: u64,
+    },
+
+    /// Connected, with an address.
+    Connected,
+
+    /// Waiting to try again.
+    Waiting {
+        /// When to try again, in milliseconds since boot.
+        until_ms: u64,
+    },
+}
+
+/// Decides when the station should try to join its network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reconnect {
+    /// The delay between attempts.
+    backoff: Backoff,
+
+    /// The link state.
+    link: Link,
+}
+
+/// Implementation of the `Default` trait for `Reconnect`.
+impl Default for Reconnect {
+    /// Connect as soon as polled, with the default backoff.
+    ///
+    /// # Returns
+    /// * `Reconnect` - The reconnection state.
+    fn default() -> Self {
+        Self::new(Backoff::default())
+    }
+}
+
+/// Implementation of `Reconnect`.
+impl Reconnect {
+    /// Create the reconnection state.
+    ///
+    /// # Arguments
+    /// * `backoff` - The delay between attempts.
+    ///
+    /// # Returns
+    /// * `Reconnect` - The state, connecting as soon as polled.
+    pub fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            link: Link::Idle,
+        }
+    }
+
+    /// Track the link and decide whether to start a connection attempt.
+    ///
+    /// # Arguments
+    /// * `now_ms` - The current time in milliseconds since boot.
+    /// * `connected` - Whether the station is connected and has an address.
+    ///
+    /// # Returns
+    /// * `bool` - Whether to start an attempt now.
+    pub fn poll(&mut self, now_ms: u64, connected: bool) -> bool {
+        if connected {
+            self.link = Link::Connected;
+            self.backoff.reset();
+            return false;
+        }
+
+        match self.link {
+            Link::Idle => {
+                self.link = Link::Connecting { since_ms: now_ms };
+                true
+            }
+            Link::Connecting { since_ms }
+                if now_ms.saturating_sub(since_ms) < CONNECT_TIMEOUT_MS =>
+            {
+                false
+            }
+            Link::Connecting { .. } | Link::Connected => {
+                self.link = Link::Waiting {
+                    until_ms: now_ms + self.backoff.next_delay(),
+                };
+                false
+            }
+            Link::Waiting { until_ms } if now_ms < until_ms => false,
+            Link::Waiting { .. } => {
+                self.link = Link::Connecting { since_ms: now_ms };
+                true
+            }
+        }
+    }
+
+    /// Connect again right away, for example with new credentials.
+    pub fn restart(&mut self) {
+        self.link = Link::Idle;
+        self.backoff.reset();
+    }
+
+    /// Whether an attempt is running.
+    ///
+    /// # Returns
+    /// * `bool` - `true` from the start of an attempt until it times out.
+    pub fn is_connecting(&self) -> bool {
+        matches!(self.link, Link::Idle | Link::Connecting { .. })
+    }
+}
+
+/// Wi-Fi connectivity, as shown on the display and published over BLE.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum WifiStatus {
+    /// Wi-Fi is not set up and the setup portal is closed.
+    #[default]
+    Off,
+
+    /// The setup portal is open.
+    Setup {
+        /// The name of the setup network.
+        ap_ssid: String,
+    },
+
+    /// Joining the network.
+    Connecting,
+
+    /// Connected.
+    Connected {
+        /// The station IPv4 address.
+        ip: [u8; 4],
+    },
+
+    /// The network is out of reach; retrying with backoff.
+    Offline,
+}
+
+/// Implementation of `WifiStatus`.
+impl WifiStatus {
+    /// Encode the BLE status characteristic value.
+    ///
+    /// # Returns
+    /// * `Vec<u8>` - A state code (`0` off, `1` setup, `2` connecting, `3`
+    ///   connected, `4` offline), followed by the IPv4 address when connected.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            WifiStatus::Off => vec![0],
+            WifiStatus::Setup { .. } => vec![1],
+            WifiStatus::Connecting => vec![2],
+            WifiStatus::Connected { ip } => vec![3, ip[0], ip[1], ip[2], ip[3]],
+            WifiStatus::Offline => vec![4],
+        }
+    }
+
+    /// Get the lines of the status screen.
+    ///
+    /// # Returns
+    /// * `Vec<String>` - The lines.
+    pub fn status_lines(&self) -> Vec<String> {
+        match self {
+            WifiStatus::Off => vec!["WiFi off".to_string()],
+            WifiStatus::Setup { ap_ssid } => {
+                vec!["WiFi setup".to_string(), format!("Join {}", ap_ssid)]
+            }
+            WifiStatus::Connecting => vec!["WiFi connecting".to_string()],
+            WifiStatus::Connected { ip } => {
+                vec![format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3])]
+            }
+            WifiStatus::Offline => vec!["WiFi offline".to_string()],
+        }
+    }
+
+    /// Get the 8x8 connectivity icon, in the same column-major format as the font.
+    ///
+    /// # Returns
+    /// * `Option<[u8; 8]>` - The icon, or `None` while Wi-Fi is not in use.
+    pub fn icon(&self) -> Option<[u8; 8]> {
+        match self {
+            WifiStatus::Off => None,
+            // Signal arcs
+            WifiStatus::Connected { .. } => Some([0x04, 0x12, 0x4a, 0xca, 0x4a, 0x12, 0x04, 0x00]),
+            // Signal arcs, struck through
+            WifiStatus::Setup { .. } | WifiStatus::Connecting | WifiStatus::Offline => {
+                Some([0x05, 0x12, 0x6a, 0xda, 0x4a, 0x16, 0x24, 0x40])
+            }
+        }
+    }
+}
+
+/// Tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_round_trip() {
+        let credentials = Credentials::new("Office", "correct horse").unwrap();
+        let value = credentials.encode();
+
+        assert_eq!(&value[..8], b"\x06Office\x0d");
+        assert_eq!(Credentials::decode(&value), Ok(credentials));
+
+        let open = Credentials::new("Café", "").unwrap();
+        assert_eq!(Credentials::decode(&open.encode()), Ok(open));
+    }
+
+    #[test]
+    fn credentials_validation() {
+        assert_eq!(
+            Credentials::new("", "password"),
+            Err(CredentialsError::InvalidSsid)
+        );
+        assert_eq!(
+            Credentials::new(&"x".repeat(33), ""),
+            Err(CredentialsError::InvalidSsid)
+        );
+        assert_eq!(
+            Credentials::new("Office", "short"),
+            Err(CredentialsError::InvalidPassword)
+        );
+        assert_eq!(
+            Credentials::new("Office", &"x".repeat(65)),
+            Err(CredentialsError::InvalidPassword)
+        );
+        assert!(Credentials::new(&"x".repeat(32), &"x".repeat(64)).is_ok());
+
+        assert_eq!(
+            Credentials::decode(b"\x06Offi"),
+            Err(CredentialsError::InvalidLength)
+        );
+        assert_eq!(
+            Credentials::decode(b"\x02ab\x00\x00"),
+            Err(CredentialsError::InvalidLength)
+        );
+        assert_eq!(
+            Credentials::decode(b"\x02\xff\xfe\x00"),
+            Err(CredentialsError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn debug_hides_password() {
+        let credentials = Credentials::new("Office", "correct horse").unwrap();
+        let debug = format!("{:?}", credentials);
+
+        assert!(debug.contains("Office"));
+        assert!(!debug.contains("horse"));
+    }
+
+    #[test]
+    fn provisioning_writes() {
+        assert_eq!(decode_provisioning(&[0]), Ok(None));
+        assert_eq!(
+            decode_provisioning(b"\x03Lab\x00"),
+            Ok(Some(Credentials::new("Lab", "").unwrap()))
+        );
+        assert_eq!(
+            decode_provisioning(&[]),
+            Err(CredentialsError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new(1_000, 5_000);
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay()).collect();
+        assert_eq!(delays, [1_000, 2_000, 4_000, 5_000, 5_000]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), 1_000);
+    }
+
+    #[test]
+    fn reconnect_with_backoff() {
+        let mut reconnect = Reconnect::new(Backoff::new(1_000, 4_000));
+
+        // First attempt right away, then wait for it to time out.
+        assert!(reconnect.poll(0, false));
+        assert!(reconnect.is_connecting());
+        assert!(!reconnect.poll(CONNECT_TIMEOUT_MS - 1, false));
+
+        // Timed out: back off 1 s, then 2 s.
+        let t = CONNECT_TIMEOUT_MS;
+        assert!(!reconnect.poll(t, false));
+        assert!(!reconnect.is_connecting());
+        assert!(!reconnect.poll(t + 999, false));
+        assert!(reconnect.poll(t + 1_000, false));
+
+        let t = t + 1_000 + CONNECT_TIMEOUT_MS;
+        assert!(!reconnect.poll(t, false));
+        assert!(!reconnect.poll(t + 1_999, false));
+        assert!(reconnect.poll(t + 2_000, false));
+
+        // Connected: the backoff starts over when the link drops.
+        let t = t + 3_000;
+        assert!(!reconnect.poll(t, true));
+        assert!(!reconnect.poll(t + 100, false));
+        assert!(reconnect.poll(t + 1_100, false));
+    }
+
+    #[test]
+    fn restart_connects_immediately() {
+        let mut reconnect = Reconnect::default();
+        assert!(reconnect.poll(0, false));
+        assert!(!reconnect.poll(CONNECT_TIMEOUT_MS, false));
+
+        reconnect.restart();
+        assert!(reconnect.poll(CONNECT_TIMEOUT_MS + 1, false));
+    }
+
+    #[test]
+    fn status_encoding_and_lines() {
+        let connected = WifiStatus::Connected {
+            ip: [192, 168, 1, 23],
+        };
+        assert_eq!(connected.encode(), [3, 192, 168, 1, 23]);
+        assert_eq!(connected.status_lines(), ["192.168.1.23"]);
+        assert!(connected.icon().is_some());
+
+        let setup = WifiStatus::Setup {
+            ap_ssid: "CO2-1A2B".to_string(),
+        };
+        assert_eq!(setup.encode(), [1]);
+        assert_eq!(setup.status_lines(), ["WiFi setup", "Join CO2-1A2B"]);
+
+        assert_eq!(WifiStatus::Off.encode(), [0]);
+        assert_eq!(WifiStatus::Off.icon(), None);
+        assert_eq!(WifiStatus::Offline.status_lines(), ["WiFi offline"]);
+    }
+}
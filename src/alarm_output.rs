@@ -0,0 +1,46 @@
+use esp_idf_svc::hal::gpio::{AnyOutputPin, Output, PinDriver};
+use log::warn;
+use scd41_core::alarm::{AlarmSink, AlarmState};
+
+/// GPIO alarm output driving an active-high buzzer or LED.
+pub struct GpioAlarmOutput<'d> {
+    /// The output pin.
+    pin: PinDriver<'d, AnyOutputPin, Output>,
+}
+
+/// The GPIO alarm output implementation.
+impl<'d> GpioAlarmOutput<'d> {
+    /// Create a new GPIO alarm output, initially off.
+    ///
+    /// # Parameters
+    /// - `pin`: The output pin driver.
+    ///
+    /// # Returns
+    /// The GPIO alarm output.
+    pub fn new(mut pin: PinDriver<'d, AnyOutputPin, Output>) -> Self {
+        if let Err(e) = pin.set_low() {
+            warn!("Failed to reset alarm output: {:?}", e);
+        }
+
+        Self { pin }
+    }
+}
+
+/// Implement the `AlarmSink` trait for `GpioAlarmOutput`.
+impl AlarmSink for GpioAlarmOutput<'_> {
+    /// Switch the output on while the alarm is sounding.
+    ///
+    /// # Parameters
+    /// - `state`: The new alarm state.
+    fn on_alarm(&mut self, state: AlarmState) {
+        let result = if state.is_alarming() {
+            self.pin.set_high()
+        } else {
+            self.pin.set_low()
+        };
+
+        if let Err(e) = result {
+            warn!("Failed to drive alarm output: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::error::AppError;
+use esp_idf_svc::hal::{
+    adc::{
+        attenuation::DB_12,
+        oneshot::{
+            config::{AdcChannelConfig, Calibration},
+            AdcChannelDriver, AdcDriver,
+        },
+        ADC1,
+    },
+    gpio::Gpio3,
+};
+use scd41_core::battery::battery_level;
+
+/// The battery is measured through a 1:1 resistor divider, halving its voltage.
+const DIVIDER_RATIO: u32 = 2;
+
+/// Number of ADC samples averaged per reading.
+const SAMPLES: u32 = 8;
+
+/// Battery voltage monitor on ADC1 channel 3 (GPIO3).
+pub struct BatteryMonitor<'a> {
+    /// The ADC channel.
+    channel: AdcChannelDriver<'a, Gpio3, AdcDriver<'a, ADC1>>,
+}
+
+/// The battery monitor implementation.
+impl<'a> BatteryMonitor<'a> {
+    /// Create a new battery monitor.
+    ///
+    /// # Parameters
+    /// - `adc`: The ADC1 peripheral.
+    /// - `pin`: The divider tap pin.
+    ///
+    /// # Returns
+    /// The battery monitor.
+    pub fn new(adc: ADC1, pin: Gpio3) -> Result<Self, AppError> {
+        let adc = AdcDriver::new(adc).map_err(|e| {
+            AppError::PeripheralsError(format!("Failed to initialize ADC: {:?}", e))
+        })?;
+        let config = AdcChannelConfig {
+            attenuation: DB_12,
+            calibration: Calibration::Curve,
+            ..Default::default()
+        };
+        let channel = AdcChannelDriver::new(adc, pin, &config).map_err(|e| {
+            AppError::PeripheralsError(format!("Failed to initialize battery ADC channel: {:?}", e))
+        })?;
+
+        Ok(Self { channel })
+    }
+
+    /// Read the battery voltage.
+    ///
+    /// # Returns
+    /// The battery voltage in millivolts.
+    pub fn read_millivolts(&mut self) -> Result<u16, AppError> {
+        let mut total = 0;
+        for _ in 0..SAMPLES {
+            let millivolts = self.channel.read().map_err(|e| {
+                AppError::PeripheralsError(format!("Failed to read battery voltage: {:?}", e))
+            })?;
+            total += u32::from(millivolts);
+        }
+
+        Ok((total / SAMPLES * DIVIDER_RATIO) as u16)
+    }
+
+    /// Read the battery level.
+    ///
+    /// # Returns
+    /// The charge in percent.
+    pub fn read_level(&mut self) -> Result<u8, AppError> {
+        self.read_millivolts().map(battery_level)
+    }
+}
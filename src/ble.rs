@@ -1,5 +1,9 @@
-use crate::error::AppError;
-use enumset::enum_set;
+use crate::{
+    clock::{uptime_ms, SharedClock},
+    error::AppError,
+    ota::FlashPartition,
+};
+use enumset::EnumSet;
 use esp_idf_svc::{
     bt::{
         ble::{
@@ -12,12 +16,82 @@ use esp_idf_svc::{
         },
         BdAddr, Ble, BtDriver, BtStatus, BtUuid,
     },
-    hal::modem::Modem,
+    hal::modem::BluetoothModem,
     nvs::{EspNvsPartition, NvsDefault},
-    sys::{EspError, ESP_FAIL},
+    sys::{
+        esp, esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC, esp_ble_adv_channel_t_ADV_CHNL_ALL,
+        esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_ANY_CON_ANY, esp_ble_adv_params_t,
+        esp_ble_adv_type_t_ADV_TYPE_SCAN_IND, esp_ble_bond_dev_t, esp_ble_conn_update_params_t,
+        esp_ble_gap_disconnect, esp_ble_gap_security_rsp, esp_ble_gap_set_security_param,
+        esp_ble_gap_start_advertising, esp_ble_gap_update_conn_params,
+        esp_ble_get_bond_device_list, esp_ble_get_bond_device_num, esp_ble_remove_bond_device,
+        esp_ble_sm_param_t_ESP_BLE_SM_AUTHEN_REQ_MODE, esp_ble_sm_param_t_ESP_BLE_SM_IOCAP_MODE,
+        esp_ble_sm_param_t_ESP_BLE_SM_MAX_KEY_SIZE,
+        esp_ble_sm_param_t_ESP_BLE_SM_ONLY_ACCEPT_SPECIFIED_SEC_AUTH,
+        esp_ble_sm_param_t_ESP_BLE_SM_SET_INIT_KEY, esp_ble_sm_param_t_ESP_BLE_SM_SET_RSP_KEY,
+        esp_mac_type_t_ESP_MAC_BT, esp_read_mac, EspError, ESP_BLE_ENC_KEY_MASK,
+        ESP_BLE_ID_KEY_MASK, ESP_BLE_ONLY_ACCEPT_SPECIFIED_AUTH_ENABLE, ESP_FAIL, ESP_IO_CAP_OUT,
+        ESP_LE_AUTH_REQ_SC_MITM_BOND,
+    },
+};
+use log::{debug, info, warn};
+use scd41_core::{
+    air_quality::AirQuality,
+    alarm::{AlarmCommand, AlarmSink, AlarmState},
+    battery::{BATTERY_LEVEL_UUID, BATTERY_SERVICE_UUID},
+    bthome::{self, Bindkey, Measurements},
+    cccd::{Cccd, Indication, IndicationQueue, InvalidCccd, Subscriptions},
+    clock::{
+        decode_current_time, encode_current_time, LocalTimeInfo, SystemClock, TimeError,
+        ADJUST_REASON_MANUAL, ADJUST_REASON_TIME_ZONE, CURRENT_TIME_SERVICE_UUID,
+        CURRENT_TIME_UUID, LOCAL_TIME_INFORMATION_UUID,
+    },
+    config::{Config, ConfigError, MAX_CONFIG_LEN},
+    connection::{is_idle, ConnectionParams, ConnectionStats},
+    control_point::{ControlRequest, ControlResponse},
+    device_info::{DeviceInfo, DisCharacteristic, DEVICE_INFORMATION_SERVICE_UUID},
+    ess::{
+        EssCharacteristic, TriggerError, TriggerSetting, ESS_SERVICE_UUID, ES_MEASUREMENT_UUID,
+        ES_TRIGGER_SETTING_UUID, VALID_RANGE_UUID,
+    },
+    framebuffer::Orientation,
+    gatt::{
+        Attribute, CharacteristicDef, Format, GattTable, GattTableError, Permissions,
+        PresentationFormat, Properties, ServiceDef, Step, Uuid, CCCD_UUID,
+        PRESENTATION_FORMAT_UUID, UNIT_UNITLESS, USER_DESCRIPTION_UUID,
+    },
+    history::{
+        notification_payload_len, History, RacpCode, RacpRequest, RacpResponse, RecordFilter,
+    },
+    ota::{decode_chunk, OtaError, OtaRequest, OtaResponse, OtaResult, OtaUpdater},
+    pairing::{Pairing, PairingScreen},
+    readings::{Readings, READINGS_LEN},
+    wifi::{decode_provisioning, Credentials, CredentialsError, WifiStatus, CREDENTIALS_MAX_LEN},
+};
+use std::{
+    collections::VecDeque,
+    ffi::c_void,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
 };
-use log::{info, warn};
-use std::sync::{Arc, Mutex};
+
+/// Air quality level characteristic UUID.
+pub const AIR_QUALITY_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e56;
+
+/// Alarm characteristic UUID.
+pub const ALARM_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e57;
+
+/// All readings characteristic UUID.
+pub const ALL_READINGS_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e5f;
+
+/// Configuration characteristic UUID.
+pub const CONFIG_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e58;
+
+/// Control point characteristic UUID.
+pub const CONTROL_POINT_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e59;
 
 /// CO2 characteristic UUID.
 pub const CO2_CHAR_UUID: u128 = 0x00002b8c00001000800000805f9b34fb;
@@ -25,20 +99,383 @@ pub const CO2_CHAR_UUID: u128 = 0x00002b8c00001000800000805f9b34fb;
 /// Humidity characteristic UUID.
 pub const HUMIDITY_CHAR_UUID: u128 = 0x00002a6f00001000800000805f9b34fb;
 
+/// History data characteristic UUID.
+pub const HISTORY_DATA_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e5a;
+
+/// History record access control point characteristic UUID.
+pub const HISTORY_RACP_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e5b;
+
+/// Display orientation characteristic UUID.
+pub const ORIENTATION_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e55;
+
+/// Firmware update control point characteristic UUID.
+pub const OTA_CONTROL_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e5d;
+
+/// Firmware update data characteristic UUID.
+pub const OTA_DATA_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e5e;
+
+/// Firmware update service UUID.
+pub const OTA_SERVICE_UUID: u128 = 0xc892f08b050249a68c52b959aa997e5c;
+
 /// Service UUID.
 pub const SERVICE_UUID: u128 = 0xc892f08b050249a68c52b959aa997e54;
 
 /// Temperature characteristic UUID.
 pub const TEMPERATURE_CHAR_UUID: u128 = 0x00002a6e00001000800000805f9b34fb;
 
+/// Wi-Fi credentials characteristic UUID.
+pub const WIFI_CREDENTIALS_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e60;
+
+/// Wi-Fi status characteristic UUID.
+pub const WIFI_STATUS_CHAR_UUID: u128 = 0xc892f08b050249a68c52b959aa997e61;
+
 /// Application ID.
 const APP_ID: u16 = 0;
 
-/// Device name.
-const DEVICE_NAME: &str = "ESP32-CO2";
+/// Maximum number of connections. The stack accepts one more, which is
+/// disconnected right away so the peer is told rather than left untracked.
+const MAX_CONNECTIONS: usize = 3;
+
+/// Firmware chunks waiting for the device manager to write them to flash.
+/// Further chunks are refused, and the client resumes from the offset reported
+/// by Status.
+const MAX_PENDING_OTA_CHUNKS: usize = 32;
+
+/// Characteristic keys of the GATT table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Attr {
+    /// Temperature reading.
+    Temperature,
+
+    /// Humidity reading.
+    Humidity,
+
+    /// CO2 reading.
+    Co2,
+
+    /// CO2, temperature, humidity and status in one value.
+    AllReadings,
+
+    /// Air quality level.
+    AirQuality,
+
+    /// Alarm state and commands.
+    Alarm,
+
+    /// Display orientation.
+    Orientation,
+
+    /// Configuration.
+    Config,
+
+    /// Control point.
+    ControlPoint,
+
+    /// History records.
+    HistoryData,
+
+    /// History record access control point.
+    HistoryRacp,
+
+    /// Environmental Sensing Service characteristic.
+    Ess(EssCharacteristic),
+
+    /// Current Time.
+    CurrentTime,
+
+    /// Local Time Information.
+    LocalTime,
+
+    /// Device Information Service characteristic.
+    DeviceInfo(DisCharacteristic),
+
+    /// Battery Level.
+    BatteryLevel,
+
+    /// Firmware update control point.
+    OtaControl,
+
+    /// Firmware update image chunks.
+    OtaData,
+
+    /// Wi-Fi network to join.
+    WifiCredentials,
+
+    /// Wi-Fi connectivity.
+    WifiStatus,
+}
+
+/// Declare the services, characteristics and descriptors of the server.
+///
+/// Reads and writes are dispatched on the keys by [`BleServer::read_value`],
+/// [`BleServer::read_descriptor`] and [`BleServer::write_value`].
+///
+/// # Returns
+///
+/// * `GattTable<Attr>` - The table.
+fn gatt_table() -> GattTable<Attr> {
+    let read = Properties::READ;
+    let read_write = read.union(Properties::WRITE);
+    let read_notify = read.union(Properties::NOTIFY);
+    let reading = read_notify.union(Properties::INDICATE);
+    let control = Properties::WRITE.union(Properties::INDICATE);
+    let protected = Permissions::READ.union(Permissions::WRITE_AUTHENTICATED);
+
+    let custom = ServiceDef::new(
+        Uuid::Long(SERVICE_UUID),
+        vec![
+            CharacteristicDef::new(
+                Attr::Temperature,
+                Uuid::Long(TEMPERATURE_CHAR_UUID),
+                reading,
+                6,
+            ),
+            CharacteristicDef::new(Attr::Humidity, Uuid::Long(HUMIDITY_CHAR_UUID), reading, 6),
+            CharacteristicDef::new(Attr::Co2, Uuid::Long(CO2_CHAR_UUID), reading, 6),
+            CharacteristicDef::new(
+                Attr::AllReadings,
+                Uuid::Long(ALL_READINGS_CHAR_UUID),
+                reading,
+                READINGS_LEN,
+            ),
+            CharacteristicDef::new(
+                Attr::AirQuality,
+                Uuid::Long(AIR_QUALITY_CHAR_UUID),
+                reading,
+                1,
+            ),
+            CharacteristicDef::new(
+                Attr::Alarm,
+                Uuid::Long(ALARM_CHAR_UUID),
+                reading.union(Properties::WRITE),
+                1,
+            ),
+            CharacteristicDef::new(
+                Attr::Orientation,
+                Uuid::Long(ORIENTATION_CHAR_UUID),
+                read_write,
+                1,
+            ),
+            CharacteristicDef::new(
+                Attr::Config,
+                Uuid::Long(CONFIG_CHAR_UUID),
+                read_write,
+                MAX_CONFIG_LEN,
+            ),
+            CharacteristicDef::new(
+                Attr::ControlPoint,
+                Uuid::Long(CONTROL_POINT_CHAR_UUID),
+                control,
+                8,
+            ),
+            CharacteristicDef::new(
+                Attr::HistoryData,
+                Uuid::Long(HISTORY_DATA_CHAR_UUID),
+                Properties::NOTIFY,
+                512,
+            ),
+            CharacteristicDef::new(
+                Attr::HistoryRacp,
+                Uuid::Long(HISTORY_RACP_CHAR_UUID),
+                control,
+                8,
+            ),
+            CharacteristicDef::new(
+                Attr::WifiCredentials,
+                Uuid::Long(WIFI_CREDENTIALS_CHAR_UUID),
+                Properties::WRITE,
+                CREDENTIALS_MAX_LEN,
+            ),
+            CharacteristicDef::new(
+                Attr::WifiStatus,
+                Uuid::Long(WIFI_STATUS_CHAR_UUID),
+                read_notify,
+                5,
+            ),
+        ]
+        .into_iter()
+        .map(describe)
+        .collect(),
+    );
+
+    let ess = ServiceDef::new(
+        Uuid::Short(ESS_SERVICE_UUID),
+        EssCharacteristic::ALL
+            .into_iter()
+            .map(|c| {
+                describe(
+                    CharacteristicDef::new(Attr::Ess(c), Uuid::Short(c.uuid()), read_notify, 2)
+                        .descriptor(ES_MEASUREMENT_UUID, Permissions::READ)
+                        .descriptor(ES_TRIGGER_SETTING_UUID, protected),
+                )
+            })
+            .collect(),
+    );
+
+    let cts = ServiceDef::new(
+        Uuid::Short(CURRENT_TIME_SERVICE_UUID),
+        vec![
+            CharacteristicDef::new(
+                Attr::CurrentTime,
+                Uuid::Short(CURRENT_TIME_UUID),
+                read_notify.union(Properties::WRITE),
+                10,
+            ),
+            CharacteristicDef::new(
+                Attr::LocalTime,
+                Uuid::Short(LOCAL_TIME_INFORMATION_UUID),
+                read_write,
+                2,
+            ),
+        ],
+    );
+
+    let dis = ServiceDef::new(
+        Uuid::Short(DEVICE_INFORMATION_SERVICE_UUID),
+        DisCharacteristic::ALL
+            .into_iter()
+            .map(|c| CharacteristicDef::new(Attr::DeviceInfo(c), Uuid::Short(c.uuid()), read, 32))
+            .collect(),
+    );
+
+    // Chunks fill a 512-byte attribute value, the most an MTU of 517 carries.
+    let ota = ServiceDef::new(
+        Uuid::Long(OTA_SERVICE_UUID),
+        vec![
+            CharacteristicDef::new(
+                Attr::OtaControl,
+                Uuid::Long(OTA_CONTROL_CHAR_UUID),
+                control,
+                41,
+            ),
+            CharacteristicDef::new(
+                Attr::OtaData,
+                Uuid::Long(OTA_DATA_CHAR_UUID),
+                Properties::WRITE_WITHOUT_RESPONSE,
+                512,
+            ),
+        ]
+        .into_iter()
+        .map(describe)
+        .collect(),
+    );
+
+    let mut services = vec![custom, ess, cts, dis, ota];
+    if cfg!(feature = "battery") {
+        services.push(ServiceDef::new(
+            Uuid::Short(BATTERY_SERVICE_UUID),
+            vec![CharacteristicDef::new(
+                Attr::BatteryLevel,
+                Uuid::Short(BATTERY_LEVEL_UUID),
+                read_notify,
+                1,
+            )],
+        ));
+    }
+
+    GattTable::new(services)
+}
+
+/// Add the User Description, Presentation Format and Valid Range descriptors a
+/// characteristic has.
+///
+/// # Arguments
+/// * `characteristic` - The characteristic declaration.
+///
+/// # Returns
+///
+/// * `CharacteristicDef<Attr>` - The declaration with its descriptors.
+fn describe(mut characteristic: CharacteristicDef<Attr>) -> CharacteristicDef<Attr> {
+    let attr = characteristic.key;
+    if user_description(attr).is_some() {
+        characteristic = characteristic.descriptor(USER_DESCRIPTION_UUID, Permissions::READ);
+    }
+    if presentation_format(attr).is_some() {
+        characteristic = characteristic.descriptor(PRESENTATION_FORMAT_UUID, Permissions::READ);
+    }
+    if reading(attr).is_some() {
+        characteristic = characteristic.descriptor(VALID_RANGE_UUID, Permissions::READ);
+    }
+
+    characteristic
+}
+
+/// Get the reading a characteristic carries in Environmental Sensing Service format.
+///
+/// # Arguments
+/// * `attr` - The characteristic.
+///
+/// # Returns
+///
+/// * `Option<EssCharacteristic>` - The reading, or `None` if it carries none.
+fn reading(attr: Attr) -> Option<EssCharacteristic> {
+    match attr {
+        Attr::Temperature => Some(EssCharacteristic::Temperature),
+        Attr::Humidity => Some(EssCharacteristic::Humidity),
+        Attr::Co2 => Some(EssCharacteristic::Co2),
+        Attr::Ess(characteristic) => Some(characteristic),
+        _ => None,
+    }
+}
+
+/// Get the User Description of a characteristic.
+///
+/// # Arguments
+/// * `attr` - The characteristic.
+///
+/// # Returns
+///
+/// * `Option<&'static str>` - The description, or `None` for standard
+///   characteristics outside the Environmental Sensing Service, which clients
+///   already know.
+fn user_description(attr: Attr) -> Option<&'static str> {
+    if let Some(characteristic) = reading(attr) {
+        return Some(characteristic.description());
+    }
+
+    match attr {
+        Attr::AllReadings => Some("All readings"),
+        Attr::AirQuality => Some("Air quality level"),
+        Attr::Alarm => Some("CO2 alarm"),
+        Attr::Orientation => Some("Display orientation"),
+        Attr::Config => Some("Configuration"),
+        Attr::ControlPoint => Some("Maintenance control point"),
+        Attr::HistoryData => Some("History records"),
+        Attr::HistoryRacp => Some("History record access"),
+        Attr::OtaControl => Some("Firmware update control point"),
+        Attr::OtaData => Some("Firmware update data"),
+        Attr::WifiCredentials => Some("Wi-Fi credentials"),
+        Attr::WifiStatus => Some("Wi-Fi status"),
+        _ => None,
+    }
+}
+
+/// Get the Presentation Format of a characteristic.
+///
+/// # Arguments
+/// * `attr` - The characteristic.
+///
+/// # Returns
+///
+/// * `Option<PresentationFormat>` - The format, or `None` for characteristics that
+///   are not measurements or levels.
+fn presentation_format(attr: Attr) -> Option<PresentationFormat> {
+    if let Some(characteristic) = reading(attr) {
+        return Some(characteristic.presentation_format());
+    }
 
-/// Maximum number of connections.
-const MAX_CONNECTIONS: usize = 2;
+    let format = match attr {
+        Attr::AllReadings => Format::Struct,
+        Attr::AirQuality | Attr::Alarm => Format::Uint8,
+        _ => return None,
+    };
+
+    Some(PresentationFormat {
+        format,
+        exponent: 0,
+        unit: UNIT_UNITLESS,
+    })
+}
 
 /// Connection interface.
 #[derive(Debug, Clone)]
@@ -49,11 +486,46 @@ struct Connection {
     /// Connection ID.
     conn_id: Handle,
 
-    /// Subscribed status.
-    subscribed: bool,
+    /// CCCD values written by the peer.
+    subscriptions: Subscriptions,
+
+    /// Indications waiting for the peer's confirmation.
+    indications: IndicationQueue,
 
     /// MTU.
     mtu: Option<u16>,
+
+    /// When the peer last sent a request, in milliseconds since boot.
+    last_activity_ms: u64,
+
+    /// Whether the connection is being closed for being idle.
+    closing: bool,
+}
+
+/// A running history transfer.
+#[derive(Debug, Clone, Copy)]
+struct HistoryTransfer {
+    /// Connection receiving the records.
+    conn_id: ConnectionId,
+
+    /// The selected records.
+    filter: RecordFilter,
+
+    /// The lowest sequence number not yet sent.
+    cursor: u32,
+
+    /// Whether a chunk is waiting to be sent out.
+    in_flight: bool,
+}
+
+/// Client settings of an Environmental Sensing Service characteristic.
+#[derive(Debug, Clone, Copy, Default)]
+struct EssAttributes {
+    /// Trigger setting written by a client.
+    trigger: TriggerSetting,
+
+    /// Last notified value and when it was sent.
+    last_notified: Option<(i32, Instant)>,
 }
 
 /// State interface.
@@ -62,26 +534,20 @@ struct State {
     /// GATT interface.
     gatt_if: Option<GattInterface>,
 
-    /// Service handle.
-    service_handle: Option<Handle>,
-
-    /// Temperature handle.
-    temp_handle: Option<Handle>,
+    /// Services, characteristics and descriptors, with their handles.
+    gatt: GattTable<Attr>,
 
-    /// Humidity handle.
-    humid_handle: Option<Handle>,
+    /// System clock, set by clients.
+    clock: Option<SharedClock>,
 
-    /// CO2 handle.
-    co2_handle: Option<Handle>,
+    /// Identification strings reported by the Device Information Service.
+    device_info: DeviceInfo,
 
-    /// Temperature CCCD handle.
-    temp_cccd_handle: Option<Handle>,
+    /// Latest battery level in percent.
+    battery_level: u8,
 
-    /// Humidity CCCD handle.
-    humid_cccd_handle: Option<Handle>,
-
-    /// CO2 CCCD handle.
-    co2_cccd_handle: Option<Handle>,
+    /// Environmental Sensing Service client settings, indexed by `EssCharacteristic::index`.
+    ess: [EssAttributes; 3],
 
     /// Connections.
     connections: heapless::Vec<Connection, MAX_CONNECTIONS>,
@@ -97,6 +563,110 @@ struct State {
 
     /// Latest CO2.
     latest_co2: u16,
+
+    /// Latest air quality level index.
+    latest_air_quality: u8,
+
+    /// Number of measurements taken, the sequence number of the latest one.
+    readings_sequence: u32,
+
+    /// Current alarm state code.
+    alarm_state: u8,
+
+    /// Alarm command sent by a client, not yet applied.
+    pending_alarm_command: Option<AlarmCommand>,
+
+    /// Current display orientation.
+    orientation: Orientation,
+
+    /// Display orientation requested by a client, not yet applied.
+    pending_orientation: Option<Orientation>,
+
+    /// Current configuration, including updates not yet applied.
+    config: Config,
+
+    /// Configuration written by a client, not yet applied.
+    pending_config: Option<Config>,
+
+    /// Connection whose control point request has not been answered yet.
+    control_requester: Option<ConnectionId>,
+
+    /// Control point request written by a client, not yet run.
+    pending_control_request: Option<ControlRequest>,
+
+    /// Control point error response to indicate once the write is acknowledged.
+    pending_control_response: Option<ControlResponse>,
+
+    /// Measurement history.
+    history: Option<Arc<Mutex<History>>>,
+
+    /// History transfer in progress.
+    history_transfer: Option<HistoryTransfer>,
+
+    /// Record access response to indicate once the write is acknowledged.
+    pending_racp_response: Option<RacpResponse>,
+
+    /// Firmware updater writing to the inactive OTA partition.
+    ota: Option<Arc<Mutex<OtaUpdater<FlashPartition>>>>,
+
+    /// Connection whose firmware update request has not been answered yet.
+    ota_requester: Option<ConnectionId>,
+
+    /// Firmware chunks received, with their offsets, not yet written to flash.
+    pending_ota_chunks: VecDeque<(u32, Vec<u8>)>,
+
+    /// Whether a client asked to verify and boot the received image.
+    pending_ota_finish: bool,
+
+    /// Firmware update response to indicate once the write is acknowledged.
+    pending_ota_response: Option<OtaResponse>,
+
+    /// Current Wi-Fi connectivity.
+    wifi_status: WifiStatus,
+
+    /// Wi-Fi credentials written by a client, not yet applied; `Some(None)` to
+    /// forget the network.
+    pending_wifi_credentials: Option<Option<Credentials>>,
+
+    /// BTHome encryption key, if broadcasts are encrypted.
+    bthome_bindkey: Option<Bindkey>,
+
+    /// Bluetooth address used in the BTHome encryption nonce.
+    bthome_mac: [u8; 6],
+
+    /// Pairing progress shown on the display.
+    pairing: Pairing,
+
+    /// Connection statistics.
+    stats: ConnectionStats,
+}
+
+/// State implementation.
+impl State {
+    /// Record a request from a peer, keeping its connection from timing out.
+    ///
+    /// # Arguments
+    /// * `conn_id` - The connection ID.
+    fn touch(&mut self, conn_id: ConnectionId) {
+        if let Some(conn) = self.connections.iter_mut().find(|c| c.conn_id == conn_id) {
+            conn.last_activity_ms = uptime_ms();
+        }
+    }
+
+    /// Look up the CCCD of a characteristic.
+    ///
+    /// # Arguments
+    /// * `value_handle` - The characteristic value handle.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Handle>` - The CCCD handle, or `None` if the characteristic has none.
+    fn cccd_handle_of(&self, value_handle: Handle) -> Option<Handle> {
+        match self.gatt.resolve(value_handle)? {
+            Attribute::Value(attr) => self.gatt.cccd_handle(attr),
+            Attribute::Descriptor(..) => None,
+        }
+    }
 }
 
 /// BLE server interface.
@@ -110,6 +680,9 @@ pub struct BleServer {
 
     /// State.
     state: Arc<Mutex<State>>,
+
+    /// Notifications and indications sent since boot.
+    notifications: Arc<AtomicU32>,
 }
 
 /// BLE server implementation.
@@ -118,14 +691,14 @@ impl BleServer {
     ///
     /// # Arguments
     ///
-    /// * `modem` - The modem to use for Bluetooth communication.
+    /// * `modem` - The Bluetooth half of the modem, shared with Wi-Fi.
     /// * `nvs` - The NVS partition to use for storing data.
     ///
     /// # Returns
     ///
     /// A new instance of `BleServer` or an error if initialization fails.
     pub fn new(
-        modem: Modem<'static>,
+        modem: BluetoothModem<'static>,
         nvs: Option<EspNvsPartition<NvsDefault>>,
     ) -> Result<Self, AppError> {
         info!("Initializing BLE server");
@@ -149,7 +722,11 @@ impl BleServer {
         let server = Self {
             gatts,
             gap,
-            state: Arc::new(Mutex::new(Default::default())),
+            state: Arc::new(Mutex::new(State {
+                gatt: gatt_table(),
+                ..Default::default()
+            })),
+            notifications: Arc::new(AtomicU32::new(0)),
         };
 
         // GAP events
@@ -163,6 +740,11 @@ impl BleServer {
                 AppError::BleError(format!("Failed to subscribe to GAP events: {:?}", e))
             })?;
 
+        server.configure_security().map_err(|e| {
+            AppError::BleError(format!("Failed to configure BLE security: {:?}", e))
+        })?;
+        info!("BLE security configured");
+
         // GATTS events
         let gatts_server = server.clone();
         server
@@ -184,6 +766,107 @@ impl BleServer {
         Ok(server)
     }
 
+    /// Require LE Secure Connections pairing with bonding before protected writes.
+    ///
+    /// The device can only display, so the phone enters the passkey shown on the
+    /// screen, which protects against man-in-the-middle attacks. Bluedroid keeps
+    /// the bonds in NVS.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn configure_security(&self) -> Result<(), EspError> {
+        let key_mask = (ESP_BLE_ENC_KEY_MASK | ESP_BLE_ID_KEY_MASK) as u8;
+
+        for (param, value) in [
+            (
+                esp_ble_sm_param_t_ESP_BLE_SM_AUTHEN_REQ_MODE,
+                ESP_LE_AUTH_REQ_SC_MITM_BOND as u8,
+            ),
+            (
+                esp_ble_sm_param_t_ESP_BLE_SM_IOCAP_MODE,
+                ESP_IO_CAP_OUT as u8,
+            ),
+            (esp_ble_sm_param_t_ESP_BLE_SM_MAX_KEY_SIZE, 16),
+            (
+                esp_ble_sm_param_t_ESP_BLE_SM_ONLY_ACCEPT_SPECIFIED_SEC_AUTH,
+                ESP_BLE_ONLY_ACCEPT_SPECIFIED_AUTH_ENABLE as u8,
+            ),
+            (esp_ble_sm_param_t_ESP_BLE_SM_SET_INIT_KEY, key_mask),
+            (esp_ble_sm_param_t_ESP_BLE_SM_SET_RSP_KEY, key_mask),
+        ] {
+            let mut value = value;
+            esp!(unsafe {
+                esp_ble_gap_set_security_param(param, &mut value as *mut u8 as *mut c_void, 1)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Disconnect a peer.
+    ///
+    /// # Arguments
+    /// * `addr` - The peer address.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn disconnect(&self, addr: BdAddr) -> Result<(), EspError> {
+        let mut bd_addr = addr.raw();
+        esp!(unsafe { esp_ble_gap_disconnect(bd_addr.as_mut_ptr()) })
+    }
+
+    /// Ask a peer to switch to other connection parameters.
+    ///
+    /// # Arguments
+    /// * `addr` - The peer address.
+    /// * `params` - The connection parameters.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn request_connection_params(
+        &self,
+        addr: BdAddr,
+        params: ConnectionParams,
+    ) -> Result<(), EspError> {
+        let mut update = esp_ble_conn_update_params_t {
+            bda: addr.raw(),
+            min_int: params.min_interval,
+            max_int: params.max_interval,
+            latency: params.latency,
+            timeout: params.timeout,
+        };
+        esp!(unsafe { esp_ble_gap_update_conn_params(&mut update) })
+    }
+
+    /// Start advertising, connectable only while a connection slot is free.
+    ///
+    /// With every slot taken the advertisement is scannable but not connectable,
+    /// so BTHome broadcasts continue without centrals connecting only to be
+    /// rejected.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn start_advertising(&self) -> Result<(), EspError> {
+        if self.state.lock().unwrap().connections.len() < MAX_CONNECTIONS {
+            return self.gap.start_advertising();
+        }
+
+        let mut params = esp_ble_adv_params_t {
+            adv_int_min: 0x20,
+            adv_int_max: 0x40,
+            adv_type: esp_ble_adv_type_t_ADV_TYPE_SCAN_IND,
+            own_addr_type: esp_ble_addr_type_t_BLE_ADDR_TYPE_PUBLIC,
+            channel_map: esp_ble_adv_channel_t_ADV_CHNL_ALL,
+            adv_filter_policy: esp_ble_adv_filter_t_ADV_FILTER_ALLOW_SCAN_ANY_CON_ANY,
+            ..Default::default()
+        };
+        esp!(unsafe { esp_ble_gap_start_advertising(&mut params) })
+    }
+
     /// Check the GATT status and return an error if it is not Ok.
     ///
     /// # Arguments
@@ -232,7 +915,7 @@ impl BleServer {
         }
     }
 
-    /// Create the service once the app is registered.
+    /// Create the services of the GATT table once the app is registered.
     ///
     /// # Arguments
     /// * `gatt_if` - The GATT interface to use.
@@ -241,153 +924,268 @@ impl BleServer {
     ///
     /// A result indicating success or failure.
     fn create_service(&self, gatt_if: GattInterface) -> Result<(), EspError> {
-        {
+        let (device_name, services) = {
             let mut state = self.state.lock().unwrap();
             state.gatt_if = Some(gatt_if);
+            let services: Vec<(Uuid, u16)> = state
+                .gatt
+                .services()
+                .map(|service| (service.uuid, service.num_handles()))
+                .collect();
+            (state.config.device_name.clone(), services)
+        };
+
+        self.configure_scan_response(&device_name)?;
+        self.configure_advertisement(None)?;
+
+        for (uuid, num_handles) in services {
+            self.gatts.create_service(
+                gatt_if,
+                &GattServiceId {
+                    id: GattId {
+                        uuid: bt_uuid(uuid),
+                        inst_id: 0,
+                    },
+                    is_primary: true,
+                },
+                num_handles,
+            )?;
         }
 
-        self.gap.set_device_name(DEVICE_NAME)?;
+        Ok(())
+    }
+
+    /// Set the device name and configure the scan response.
+    ///
+    /// The name and service UUID go in the scan response to leave room for the
+    /// BTHome service data in the advertisement.
+    ///
+    /// # Arguments
+    /// * `device_name` - The device name.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn configure_scan_response(&self, device_name: &str) -> Result<(), EspError> {
+        self.gap.set_device_name(device_name)?;
         self.gap.set_adv_conf(&AdvConfiguration {
+            set_scan_rsp: true,
             include_name: true,
-            include_txpower: true,
-            flag: 2,
             service_uuid: Some(BtUuid::uuid128(SERVICE_UUID)),
             ..Default::default()
         })?;
 
-        self.gatts.create_service(
-            gatt_if,
-            &GattServiceId {
-                id: GattId {
-                    uuid: BtUuid::uuid128(SERVICE_UUID),
-                    inst_id: 0,
-                },
-                is_primary: true,
-            },
-            16, // enough handles for 3 chars + CCCD
-        )?;
-
         Ok(())
     }
 
-    /// Configure and start the service once it is created.
+    /// Configure the advertisement.
     ///
     /// # Arguments
-    /// * `service_handle` - The handle of the service to configure and start.
+    /// * `service_data` - The BTHome service data, if any.
     ///
     /// # Returns
     ///
     /// A result indicating success or failure.
-    fn configure_and_start_service(&self, service_handle: Handle) -> Result<(), EspError> {
-        {
-            let mut state = self.state.lock().unwrap();
-            state.service_handle = Some(service_handle);
-            state.temp_handle = None;
-            state.humid_handle = None;
-            state.co2_handle = None;
-            state.temp_cccd_handle = None;
-            state.humid_cccd_handle = None;
-            state.co2_cccd_handle = None;
-        }
-
-        self.gatts.start_service(service_handle)?;
-        self.add_characteristics(service_handle)?;
+    fn configure_advertisement(&self, service_data: Option<&[u8]>) -> Result<(), EspError> {
+        self.gap.set_adv_conf(&AdvConfiguration {
+            include_txpower: true,
+            flag: 2,
+            service_data,
+            ..Default::default()
+        })?;
 
         Ok(())
     }
 
-    /// Add characteristics to the service.
+    /// Start a service once it is created and add its first characteristic.
+    ///
+    /// Attributes are added one at a time, each once the previous one is in place,
+    /// so that the GATT table can check and record every handle.
     ///
     /// # Arguments
-    /// * `service_handle` - The handle of the service to add characteristics to.
+    /// * `service_handle` - The service handle.
+    /// * `uuid` - The service UUID.
     ///
     /// # Returns
     ///
-    /// A result indicating success or failure.
-    fn add_characteristics(&self, service_handle: Handle) -> Result<(), EspError> {
-        self.gatts.add_characteristic(
-            service_handle,
-            &GattCharacteristic {
-                uuid: BtUuid::uuid128(TEMPERATURE_CHAR_UUID),
-                permissions: enum_set!(Permission::Read | Permission::Write),
-                properties: enum_set!(Property::Read | Property::Notify),
-                max_len: 6,
-                auto_rsp: AutoResponse::ByApp,
-            },
-            &[],
-        )?;
-        self.gatts.add_characteristic(
-            service_handle,
-            &GattCharacteristic {
-                uuid: BtUuid::uuid128(HUMIDITY_CHAR_UUID),
-                permissions: enum_set!(Permission::Read | Permission::Write),
-                properties: enum_set!(Property::Read | Property::Notify),
-                max_len: 6,
-                auto_rsp: AutoResponse::ByApp,
-            },
-            &[],
-        )?;
-        self.gatts.add_characteristic(
-            service_handle,
-            &GattCharacteristic {
-                uuid: BtUuid::uuid128(CO2_CHAR_UUID),
-                permissions: enum_set!(Permission::Read | Permission::Write),
-                properties: enum_set!(Property::Read | Property::Notify),
-                max_len: 6,
-                auto_rsp: AutoResponse::ByApp,
-            },
-            &[],
-        )?;
+    /// * `Result<(), EspError>` - The result of starting the service.
+    fn register_service(&self, service_handle: Handle, uuid: &BtUuid) -> Result<(), EspError> {
+        let mut state = self.state.lock().unwrap();
+        let step = state
+            .gatt
+            .on_service_created(table_uuid(uuid)?, service_handle)
+            .map_err(table_error)?;
 
-        Ok(())
+        self.gatts.start_service(service_handle)?;
+        self.add_attribute(service_handle, step)
     }
 
-    /// Handle GAP events.
+    /// Record an added characteristic and add the next attribute of its service.
     ///
     /// # Arguments
-    /// * `event` - The GAP event to handle.
+    /// * `service_handle` - The service handle.
+    /// * `attr_handle` - The attribute handle.
+    /// * `char_uuid` - The characteristic UUID.
     ///
     /// # Returns
     ///
-    /// * `Result<(), EspError>` - The result of handling the event.
-    fn on_gap_event(&self, event: BleGapEvent) -> Result<(), EspError> {
-        info!("Got GAP event: {event:?}");
-
-        if let BleGapEvent::AdvertisingConfigured(status) = event {
-            self.check_bt_status(status)?;
-            info!("Advertising configured, starting advertising...");
-            self.gap.start_advertising()?;
-        }
+    /// * `Result<(), EspError>` - The result of registering the characteristic.
+    fn register_characteristic(
+        &self,
+        service_handle: Handle,
+        attr_handle: Handle,
+        char_uuid: &BtUuid,
+    ) -> Result<(), EspError> {
+        let mut state = self.state.lock().unwrap();
+        let step = state
+            .gatt
+            .on_characteristic_added(service_handle, table_uuid(char_uuid)?, attr_handle)
+            .map_err(table_error)?;
 
-        Ok(())
+        self.add_attribute(service_handle, step)
     }
 
-    /// Handle GATT server events.
+    /// Record an added descriptor and add the next attribute of its service.
     ///
     /// # Arguments
-    /// * `gatt_if` - The GATT interface.
-    /// * `event` - The GATT event to handle.
+    /// * `service_handle` - The service handle.
+    /// * `attr_handle` - The attribute handle.
+    /// * `descr_uuid` - The descriptor UUID.
     ///
     /// # Returns
     ///
-    /// * `Result<(), EspError>` - The result of handling the event.
-    fn on_gatts_event(&self, gatt_if: GattInterface, event: GattsEvent) -> Result<(), EspError> {
-        info!("Got GATTS event: {event:?}");
+    /// * `Result<(), EspError>` - The result of registering the descriptor.
+    fn register_descriptor(
+        &self,
+        service_handle: Handle,
+        attr_handle: Handle,
+        descr_uuid: &BtUuid,
+    ) -> Result<(), EspError> {
+        let mut state = self.state.lock().unwrap();
+        let step = state
+            .gatt
+            .on_descriptor_added(service_handle, table_uuid(descr_uuid)?, attr_handle)
+            .map_err(table_error)?;
 
-        match event {
-            GattsEvent::ServiceRegistered { status, app_id } => {
-                self.check_gatt_status(status)?;
-                if APP_ID == app_id {
-                    self.create_service(gatt_if)?;
-                }
+        self.add_attribute(service_handle, step)
+    }
+
+    /// Add the next attribute of a service.
+    ///
+    /// # Arguments
+    /// * `service_handle` - The service handle.
+    /// * `step` - The attribute to add.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), EspError>` - The result of adding the attribute.
+    fn add_attribute(&self, service_handle: Handle, step: Step<'_, Attr>) -> Result<(), EspError> {
+        match step {
+            Step::AddCharacteristic(characteristic) => {
+                self.gatts.add_characteristic(
+                    service_handle,
+                    &GattCharacteristic {
+                        uuid: bt_uuid(characteristic.uuid),
+                        permissions: permissions(characteristic.permissions()),
+                        properties: properties(characteristic.properties),
+                        max_len: characteristic.max_len,
+                        auto_rsp: AutoResponse::ByApp,
+                    },
+                    &[],
+                )?;
             }
-            GattsEvent::ServiceCreated {
+            Step::AddDescriptor(descriptor) => {
+                self.gatts.add_descriptor(
+                    service_handle,
+                    &GattDescriptor {
+                        uuid: BtUuid::uuid16(descriptor.uuid),
+                        permissions: permissions(descriptor.permissions),
+                    },
+                )?;
+            }
+            Step::Done => info!("Service {} registered", service_handle),
+        }
+
+        Ok(())
+    }
+
+    /// Handle GAP events.
+    ///
+    /// # Arguments
+    /// * `event` - The GAP event to handle.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), EspError>` - The result of handling the event.
+    fn on_gap_event(&self, event: BleGapEvent) -> Result<(), EspError> {
+        info!("Got GAP event: {event:?}");
+
+        match event {
+            BleGapEvent::AdvertisingConfigured(status) => {
+                self.check_bt_status(status)?;
+                info!("Advertising configured, starting advertising...");
+                self.start_advertising()?;
+            }
+            BleGapEvent::SecurityRequest(addr) => {
+                info!("Peer {} requested pairing", addr);
+                let mut bd_addr = addr.raw();
+                esp!(unsafe { esp_ble_gap_security_rsp(bd_addr.as_mut_ptr(), true) })?;
+            }
+            BleGapEvent::PasskeyNotification { addr, passkey } => {
+                info!("Showing pairing passkey for peer {}", addr);
+                self.state
+                    .lock()
+                    .unwrap()
+                    .pairing
+                    .passkey(passkey, uptime_ms());
+            }
+            BleGapEvent::AuthenticationComplete { bd_addr, status } => {
+                let success = matches!(status, BtStatus::Success);
+                if !success {
+                    warn!("Authentication with peer {} failed: {:?}", bd_addr, status);
+                }
+                self.state
+                    .lock()
+                    .unwrap()
+                    .pairing
+                    .complete(success, uptime_ms());
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Handle GATT server events.
+    ///
+    /// # Arguments
+    /// * `gatt_if` - The GATT interface.
+    /// * `event` - The GATT event to handle.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), EspError>` - The result of handling the event.
+    fn on_gatts_event(&self, gatt_if: GattInterface, event: GattsEvent) -> Result<(), EspError> {
+        // Written values may hold the Wi-Fi passphrase or other secrets, and
+        // firmware chunks arrive by the thousand, so writes are not logged.
+        if !matches!(event, GattsEvent::Write { .. }) {
+            debug!("Got GATTS event: {event:?}");
+        }
+
+        match event {
+            GattsEvent::ServiceRegistered { status, app_id } => {
+                self.check_gatt_status(status)?;
+                if APP_ID == app_id {
+                    self.create_service(gatt_if)?;
+                }
+            }
+            GattsEvent::ServiceCreated {
                 status,
                 service_handle,
-                ..
+                service_id,
             } => {
                 self.check_gatt_status(status)?;
-                self.configure_and_start_service(service_handle)?;
+                self.register_service(service_handle, &service_id.id.uuid)?;
             }
             GattsEvent::ServiceStarted {
                 status,
@@ -403,7 +1201,7 @@ impl BleServer {
                 char_uuid,
             } => {
                 self.check_gatt_status(status)?;
-                self.register_characteristic(service_handle, attr_handle, char_uuid)?;
+                self.register_characteristic(service_handle, attr_handle, &char_uuid)?;
             }
             GattsEvent::DescriptorAdded {
                 status,
@@ -412,7 +1210,7 @@ impl BleServer {
                 descr_uuid,
             } => {
                 self.check_gatt_status(status)?;
-                self.register_descriptor(service_handle, attr_handle, descr_uuid)?;
+                self.register_descriptor(service_handle, attr_handle, &descr_uuid)?;
             }
             GattsEvent::Mtu { conn_id, mtu } => {
                 let mut state = self.state.lock().unwrap();
@@ -424,17 +1222,32 @@ impl BleServer {
             GattsEvent::PeerConnected { conn_id, addr, .. } => {
                 info!("Peer connected: conn_id = {}, addr = {}", conn_id, addr);
                 let mut state = self.state.lock().unwrap();
-                if state.connections.len() < MAX_CONNECTIONS {
-                    state
-                        .connections
-                        .push(Connection {
-                            peer: addr,
-                            conn_id,
-                            subscribed: false,
-                            mtu: None,
-                        })
-                        .ok();
+                let connection = Connection {
+                    peer: addr,
+                    conn_id,
+                    subscriptions: Subscriptions::default(),
+                    indications: IndicationQueue::default(),
+                    mtu: None,
+                    last_activity_ms: uptime_ms(),
+                    closing: false,
+                };
+
+                if state.connections.push(connection).is_err() {
+                    warn!("All connection slots taken, disconnecting {}", addr);
+                    state.stats.rejected += 1;
+                    drop(state);
+                    return self.disconnect(addr);
                 }
+                state.stats.accepted += 1;
+                drop(state);
+
+                if let Err(e) = self.request_connection_params(addr, ConnectionParams::LOW_POWER) {
+                    warn!("Failed to request connection parameters: {:?}", e);
+                }
+
+                // Advertising stops on every connection; resume it for the next peer,
+                // or for the broadcasts alone once every slot is taken.
+                self.start_advertising()?;
             }
             GattsEvent::PeerDisconnected { conn_id, addr, .. } => {
                 info!("Peer disconnected: conn_id = {}, addr = {}", conn_id, addr);
@@ -442,8 +1255,11 @@ impl BleServer {
                 if let Some(pos) = state.connections.iter().position(|c| c.conn_id == conn_id) {
                     state.connections.remove(pos);
                 }
+                if state.history_transfer.is_some_and(|t| t.conn_id == conn_id) {
+                    state.history_transfer = None;
+                }
                 drop(state);
-                self.gap.start_advertising()?;
+                self.start_advertising()?;
             }
             GattsEvent::Write {
                 conn_id,
@@ -455,25 +1271,75 @@ impl BleServer {
                 is_prep,
                 value,
             } => {
-                let handled = self.handle_write(
+                self.state.lock().unwrap().touch(conn_id);
+                let status = self.handle_write(
                     gatt_if, conn_id, trans_id, addr, handle, offset, need_rsp, is_prep, value,
                 )?;
 
-                if handled && need_rsp {
-                    self.send_write_response(
+                match status {
+                    Some(GattStatus::Ok) => self.send_write_response(
                         gatt_if, conn_id, trans_id, handle, offset, need_rsp, is_prep, value,
-                    )?;
+                    )?,
+                    Some(status) if need_rsp => {
+                        self.gatts
+                            .send_response(gatt_if, conn_id, trans_id, status, None)?;
+                    }
+                    _ => (),
+                }
+
+                // Malformed control point requests are answered right after the write.
+                let response = self.state.lock().unwrap().pending_control_response.take();
+                if let Some(response) = response {
+                    self.send_control_response(response);
+                }
+
+                let response = self.state.lock().unwrap().pending_ota_response.take();
+                if let Some(response) = response {
+                    self.send_ota_response(response);
+                }
+
+                let mut state = self.state.lock().unwrap();
+                if let Some(response) = state.pending_racp_response.take() {
+                    self.send_racp_response(&mut state, gatt_if, conn_id, response);
                 }
+                self.continue_history_transfer(&mut state, gatt_if);
             }
-            GattsEvent::Confirm { status, handle, .. } => {
-                if status == GattStatus::Ok {
-                    info!("Indication/notification confirmed for handle {}", handle);
-                } else {
+            GattsEvent::Confirm {
+                status,
+                conn_id,
+                handle,
+                ..
+            } => {
+                if status != GattStatus::Ok {
                     warn!(
                         "Indication/notification failed for handle {}: {:?}",
                         handle, status
                     );
                 }
+
+                let mut state = self.state.lock().unwrap();
+                if let Some(conn) = state.connections.iter_mut().find(|c| c.conn_id == conn_id) {
+                    let next = conn.indications.complete(handle);
+                    self.send_indications(gatt_if, conn, next);
+                }
+
+                if Some(handle) == state.gatt.handle(Attr::HistoryData) {
+                    if let Some(transfer) = state
+                        .history_transfer
+                        .as_mut()
+                        .filter(|t| t.conn_id == conn_id)
+                    {
+                        transfer.in_flight = false;
+                        if status != GattStatus::Ok {
+                            self.finish_history_transfer(
+                                &mut state,
+                                gatt_if,
+                                RacpCode::ProcedureNotCompleted,
+                            );
+                        }
+                    }
+                    self.continue_history_transfer(&mut state, gatt_if);
+                }
             }
             GattsEvent::Read {
                 conn_id,
@@ -483,23 +1349,33 @@ impl BleServer {
                 need_rsp,
                 ..
             } => {
+                self.state.lock().unwrap().touch(conn_id);
                 if need_rsp {
                     let mut response = GattResponse::new();
 
                     let data = {
                         let state = self.state.lock().unwrap();
-                        if Some(handle) == state.temp_handle {
-                            Some(state.latest_temperature.to_le_bytes().to_vec())
-                        } else if Some(handle) == state.humid_handle {
-                            Some(state.latest_humidity.to_le_bytes().to_vec())
-                        } else if Some(handle) == state.co2_handle {
-                            Some(state.latest_co2.to_le_bytes().to_vec())
-                        } else {
-                            None
+                        match state.gatt.resolve(handle) {
+                            Some(Attribute::Value(attr)) => self.read_value(&state, attr),
+                            Some(Attribute::Descriptor(_, CCCD_UUID)) => {
+                                let cccd = state
+                                    .connections
+                                    .iter()
+                                    .find(|c| c.conn_id == conn_id)
+                                    .map_or(Cccd::NONE, |c| c.subscriptions.get(handle));
+                                Some(cccd.to_bytes().to_vec())
+                            }
+                            Some(Attribute::Descriptor(attr, uuid)) => {
+                                self.read_descriptor(&state, attr, uuid)
+                            }
+                            None => None,
                         }
                     };
 
-                    if let Some(value) = data {
+                    // Long reads continue at an offset into the value.
+                    let data = data.map(|value| value.get(offset as usize..).map(<[u8]>::to_vec));
+
+                    if let Some(Some(value)) = data {
                         response
                             .attr_handle(handle)
                             .offset(offset)
@@ -515,13 +1391,13 @@ impl BleServer {
                             Some(&response),
                         )?;
                     } else {
-                        self.gatts.send_response(
-                            gatt_if,
-                            conn_id,
-                            trans_id,
-                            GattStatus::NotFound,
-                            None,
-                        )?;
+                        let status = if data.is_some() {
+                            GattStatus::InvalidOffset
+                        } else {
+                            GattStatus::NotFound
+                        };
+                        self.gatts
+                            .send_response(gatt_if, conn_id, trans_id, status, None)?;
                     }
                 }
             }
@@ -531,95 +1407,6 @@ impl BleServer {
         Ok(())
     }
 
-    /// Register a characteristic.
-    ///
-    /// # Arguments
-    /// * `service_handle` - The service handle.
-    /// * `attr_handle` - The attribute handle.
-    /// * `char_uuid` - The characteristic UUID.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<(), EspError>` - The result of registering the characteristic.
-    fn register_characteristic(
-        &self,
-        service_handle: Handle,
-        attr_handle: Handle,
-        char_uuid: BtUuid,
-    ) -> Result<(), EspError> {
-        let mut state = self.state.lock().unwrap();
-
-        if state.service_handle != Some(service_handle) {
-            return Ok(());
-        }
-
-        if char_uuid == BtUuid::uuid128(TEMPERATURE_CHAR_UUID) {
-            state.temp_handle = Some(attr_handle);
-            self.gatts.add_descriptor(
-                service_handle,
-                &GattDescriptor {
-                    uuid: BtUuid::uuid16(0x2902), // CCCD
-                    permissions: enum_set!(Permission::Read | Permission::Write),
-                },
-            )?;
-        } else if char_uuid == BtUuid::uuid128(HUMIDITY_CHAR_UUID) {
-            state.humid_handle = Some(attr_handle);
-            self.gatts.add_descriptor(
-                service_handle,
-                &GattDescriptor {
-                    uuid: BtUuid::uuid16(0x2902),
-                    permissions: enum_set!(Permission::Read | Permission::Write),
-                },
-            )?;
-        } else if char_uuid == BtUuid::uuid128(CO2_CHAR_UUID) {
-            state.co2_handle = Some(attr_handle);
-            self.gatts.add_descriptor(
-                service_handle,
-                &GattDescriptor {
-                    uuid: BtUuid::uuid16(0x2902),
-                    permissions: enum_set!(Permission::Read | Permission::Write),
-                },
-            )?;
-        }
-
-        Ok(())
-    }
-
-    /// Register a descriptor.
-    ///
-    /// # Arguments
-    /// * `service_handle` - The service handle.
-    /// * `attr_handle` - The attribute handle.
-    /// * `descr_uuid` - The descriptor UUID.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<(), EspError>` - The result of registering the descriptor.
-    fn register_descriptor(
-        &self,
-        service_handle: Handle,
-        attr_handle: Handle,
-        descr_uuid: BtUuid,
-    ) -> Result<(), EspError> {
-        let mut state = self.state.lock().unwrap();
-
-        if state.service_handle != Some(service_handle) {
-            return Ok(());
-        }
-
-        if descr_uuid == BtUuid::uuid16(0x2902) {
-            if state.temp_handle.is_some() && state.temp_cccd_handle.is_none() {
-                state.temp_cccd_handle = Some(attr_handle);
-            } else if state.humid_handle.is_some() && state.humid_cccd_handle.is_none() {
-                state.humid_cccd_handle = Some(attr_handle);
-            } else if state.co2_handle.is_some() && state.co2_cccd_handle.is_none() {
-                state.co2_cccd_handle = Some(attr_handle);
-            }
-        }
-
-        Ok(())
-    }
-
     /// Handle a write request.
     ///
     /// # Arguments
@@ -635,194 +1422,1620 @@ impl BleServer {
     ///
     /// # Returns
     ///
-    /// * `Result<bool, EspError>` - The result of handling the write request.
+    /// * `Result<Option<GattStatus>, EspError>` - The status to respond with, or `None`
+    ///   if the handle is not handled by this server.
     fn handle_write(
         &self,
-        _gatt_if: GattInterface,
+        gatt_if: GattInterface,
         conn_id: ConnectionId,
         _trans_id: TransferId,
         addr: BdAddr,
         handle: Handle,
         _offset: u16,
         _need_rsp: bool,
-        _is_prep: bool,
+        is_prep: bool,
         value: &[u8],
-    ) -> Result<bool, EspError> {
+    ) -> Result<Option<GattStatus>, EspError> {
         let mut state = self.state.lock().unwrap();
 
-        let handled = if Some(handle) == state.temp_cccd_handle
-            || Some(handle) == state.humid_cccd_handle
-            || Some(handle) == state.co2_cccd_handle
-        {
-            self.set_subscription(&mut state, conn_id, addr, value)?;
-            true
-        } else {
-            false
+        let status = match state.gatt.resolve(handle) {
+            // Requests are applied whole; long writes are not supported, split
+            // configuration fields over several writes instead.
+            Some(Attribute::Value(
+                Attr::Config
+                | Attr::ControlPoint
+                | Attr::HistoryRacp
+                | Attr::OtaControl
+                | Attr::OtaData
+                | Attr::WifiCredentials,
+            )) if is_prep => Some(GattStatus::ReqNotSupported),
+            Some(Attribute::Value(attr)) => {
+                self.write_value(&mut state, gatt_if, conn_id, addr, attr, value)
+            }
+            Some(Attribute::Descriptor(attr, CCCD_UUID)) => {
+                let supported = state
+                    .gatt
+                    .characteristic(attr)
+                    .map_or(Cccd::NONE, |c| c.properties.cccd_modes());
+                Some(self.set_subscription(&mut state, conn_id, addr, handle, supported, value))
+            }
+            Some(Attribute::Descriptor(Attr::Ess(characteristic), ES_TRIGGER_SETTING_UUID)) => {
+                Some(self.set_ess_trigger(&mut state, characteristic, addr, value))
+            }
+            Some(Attribute::Descriptor(..)) | None => None,
         };
 
-        Ok(handled)
+        Ok(status)
     }
 
-    /// Send a write response.
+    /// Read a characteristic value.
     ///
     /// # Arguments
-    /// * `gatt_if` - The GATT interface.
-    /// * `conn_id` - The connection ID.
-    /// * `trans_id` - The transfer ID.
-    /// * `handle` - The attribute handle.
-    /// * `offset` - The offset.
-    /// * `need_rsp` - Whether a response is needed.
-    /// * `is_prep` - Whether the write is a prepare write.
-    /// * `value` - The value to write.
+    /// * `state` - The state.
+    /// * `attr` - The characteristic.
     ///
     /// # Returns
     ///
-    /// * `Result<(), EspError>` - The result of sending the write response.
-    fn send_write_response(
-        &self,
-        gatt_if: GattInterface,
-        conn_id: ConnectionId,
-        trans_id: TransferId,
-        handle: Handle,
-        offset: u16,
-        need_rsp: bool,
-        is_prep: bool,
-        value: &[u8],
-    ) -> Result<(), EspError> {
-        if !need_rsp {
-            return Ok(());
-        }
+    /// * `Option<Vec<u8>>` - The value, or `None` if the characteristic cannot be read.
+    fn read_value(&self, state: &State, attr: Attr) -> Option<Vec<u8>> {
+        let value = match attr {
+            Attr::Temperature => state.latest_temperature.to_le_bytes().to_vec(),
+            Attr::Humidity => state.latest_humidity.to_le_bytes().to_vec(),
+            Attr::Co2 => state.latest_co2.to_le_bytes().to_vec(),
+            Attr::AllReadings => readings(state).encode().to_vec(),
+            Attr::AirQuality => vec![state.latest_air_quality],
+            Attr::Alarm => vec![state.alarm_state],
+            Attr::Orientation => vec![state.orientation.index()],
+            Attr::Config => state.config.encode(),
+            Attr::Ess(characteristic) => characteristic
+                .encode(ess_value(state, characteristic))
+                .to_vec(),
+            Attr::CurrentTime => current_time_value(state, 0),
+            Attr::LocalTime => clock(state).local_time(uptime_ms()).encode().to_vec(),
+            Attr::DeviceInfo(characteristic) => {
+                state.device_info.value(characteristic).as_bytes().to_vec()
+            }
+            Attr::BatteryLevel => vec![state.battery_level],
+            Attr::WifiStatus => state.wifi_status.encode(),
+            // Credentials are write-only, so the passphrase never leaves the device.
+            Attr::ControlPoint
+            | Attr::HistoryData
+            | Attr::HistoryRacp
+            | Attr::OtaControl
+            | Attr::OtaData
+            | Attr::WifiCredentials => return None,
+        };
 
-        if is_prep {
-            let mut state = self.state.lock().unwrap();
-            state
-                .response
-                .attr_handle(handle)
-                .auth_req(0)
-                .offset(offset)
-                .value(value)
-                .map_err(|_| EspError::from_infallible::<ESP_FAIL>())?;
+        Some(value)
+    }
 
-            self.gatts.send_response(
-                gatt_if,
-                conn_id,
-                trans_id,
-                GattStatus::Ok,
-                Some(&state.response),
-            )?;
-        } else {
-            self.gatts
-                .send_response(gatt_if, conn_id, trans_id, GattStatus::Ok, None)?;
+    /// Read a descriptor other than a CCCD.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `attr` - The characteristic the descriptor belongs to.
+    /// * `uuid` - The descriptor UUID.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Vec<u8>>` - The value, or `None` if the descriptor is unknown.
+    fn read_descriptor(&self, state: &State, attr: Attr, uuid: u16) -> Option<Vec<u8>> {
+        let interval_s = state.config.measurement_interval_s as u32;
+
+        match (attr, uuid) {
+            (_, USER_DESCRIPTION_UUID) => user_description(attr).map(|d| d.as_bytes().to_vec()),
+            (_, PRESENTATION_FORMAT_UUID) => presentation_format(attr).map(|f| f.encode().to_vec()),
+            (_, VALID_RANGE_UUID) => reading(attr).map(|c| c.valid_range().to_vec()),
+            (Attr::Ess(characteristic), ES_MEASUREMENT_UUID) => {
+                Some(characteristic.measurement_descriptor(interval_s).to_vec())
+            }
+            (Attr::Ess(characteristic), ES_TRIGGER_SETTING_UUID) => Some(
+                state.ess[characteristic.index()]
+                    .trigger
+                    .encode(characteristic),
+            ),
+            _ => None,
         }
-
-        Ok(())
     }
 
-    /// Set subscription status.
+    /// Apply a characteristic write.
     ///
     /// # Arguments
     /// * `state` - The state.
+    /// * `gatt_if` - The GATT interface.
     /// * `conn_id` - The connection ID.
     /// * `addr` - The address.
+    /// * `attr` - The characteristic.
     /// * `value` - The value.
     ///
     /// # Returns
     ///
-    /// * `Result<(), EspError>` - The result of setting the subscription status.
-    fn set_subscription(
+    /// * `Option<GattStatus>` - The status to respond with, or `None` if the
+    ///   characteristic cannot be written.
+    fn write_value(
         &self,
         state: &mut State,
+        gatt_if: GattInterface,
         conn_id: ConnectionId,
         addr: BdAddr,
+        attr: Attr,
         value: &[u8],
-    ) -> Result<(), EspError> {
-        if value.len() == 2 {
-            let flags = u16::from_le_bytes([value[0], value[1]]);
-            let enable_notify = flags & 0x0001 != 0; // bit 0 = notifications
-
-            if let Some(conn) = state.connections.iter_mut().find(|c| c.conn_id == conn_id) {
-                conn.subscribed = enable_notify;
-            }
-
-            if enable_notify {
-                info!("Notifications enabled by {}", addr);
-            } else {
-                info!("Notifications disabled by {}", addr);
-            }
-        }
+    ) -> Option<GattStatus> {
+        let status = match attr {
+            Attr::Alarm => self.request_alarm_command(state, addr, value),
+            Attr::Orientation => self.request_orientation(state, addr, value),
+            Attr::Config => self.request_config(state, addr, value),
+            Attr::ControlPoint => self.request_control(state, conn_id, addr, value),
+            Attr::HistoryRacp => self.request_history(state, conn_id, addr, value),
+            Attr::CurrentTime => self.set_current_time(state, gatt_if, addr, value),
+            Attr::LocalTime => self.set_local_time(state, gatt_if, addr, value),
+            Attr::OtaControl => self.request_ota(state, conn_id, addr, value),
+            Attr::OtaData => self.write_ota_chunk(state, addr, value),
+            Attr::WifiCredentials => self.request_wifi_credentials(state, addr, value),
+            Attr::Temperature
+            | Attr::Humidity
+            | Attr::Co2
+            | Attr::AllReadings
+            | Attr::AirQuality
+            | Attr::HistoryData
+            | Attr::Ess(_)
+            | Attr::DeviceInfo(_)
+            | Attr::BatteryLevel
+            | Attr::WifiStatus => return None,
+        };
 
-        Ok(())
+        Some(status)
     }
 
-    /// Update characteristic values and notify subscribers.
+    /// Validate and queue a display orientation change.
     ///
     /// # Arguments
-    /// * `temperature` - The temperature.
-    /// * `humidity` - The humidity.
-    /// * `co2` - The CO2.
+    /// * `state` - The state.
+    /// * `addr` - The address.
+    /// * `value` - The value, the number of clockwise quarter turns.
     ///
     /// # Returns
     ///
-    /// * `Result<(), EspError>` - The result of updating the values.
-    pub fn update_values(&self, temperature: i16, humidity: u16, co2: u16) {
-        let mut state = self.state.lock().unwrap();
-        state.latest_temperature = temperature;
-        state.latest_humidity = humidity;
-        state.latest_co2 = co2;
-
-        let Some(gatt_if) = state.gatt_if else {
-            return;
+    /// * `GattStatus` - The status to respond with.
+    fn request_orientation(&self, state: &mut State, addr: BdAddr, value: &[u8]) -> GattStatus {
+        let [index] = value else {
+            return GattStatus::InvalidAttrLen;
         };
 
-        if let Some(handle) = state.temp_handle {
-            let temp_bytes = temperature.to_le_bytes();
-            if let Err(e) = self.gatts.set_attr(handle, &temp_bytes) {
-                warn!("Failed to set temperature attribute: {:?}", e);
-            }
-
-            for conn in state.connections.iter() {
-                if conn.subscribed {
-                    if let Err(e) = self
-                        .gatts
-                        .notify(gatt_if, conn.conn_id, handle, &temp_bytes)
-                    {
-                        warn!("Failed to send temperature notification: {:?}", e);
-                    }
-                }
+        match Orientation::from_index(*index) {
+            Some(orientation) => {
+                info!(
+                    "Display orientation {:?} requested by {}",
+                    orientation, addr
+                );
+                state.pending_orientation = Some(orientation);
+                GattStatus::Ok
             }
+            None => GattStatus::OutOfRange,
         }
+    }
 
-        if let Some(handle) = state.humid_handle {
-            let humid_bytes = humidity.to_le_bytes();
-            if let Err(e) = self.gatts.set_attr(handle, &humid_bytes) {
-                warn!("Failed to set humidity attribute: {:?}", e);
+    /// Validate and queue a configuration update.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `addr` - The address.
+    /// * `value` - The value, a versioned TLV update.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn request_config(&self, state: &mut State, addr: BdAddr, value: &[u8]) -> GattStatus {
+        match state.config.update(value) {
+            Ok(config) => {
+                info!("Configuration {:?} written by {}", config, addr);
+                state.config = config.clone();
+                state.pending_config = Some(config);
+                GattStatus::Ok
             }
-
-            for conn in state.connections.iter() {
-                if conn.subscribed {
-                    if let Err(e) = self
-                        .gatts
-                        .notify(gatt_if, conn.conn_id, handle, &humid_bytes)
-                    {
-                        warn!("Failed to send humidity notification: {:?}", e);
+            Err(e) => {
+                warn!("Rejected configuration from {}: {}", addr, e);
+                match e {
+                    ConfigError::Truncated => GattStatus::InvalidPdu,
+                    ConfigError::UnsupportedVersion(_) | ConfigError::UnknownField(_) => {
+                        GattStatus::ReqNotSupported
                     }
+                    ConfigError::InvalidLength(_) => GattStatus::InvalidAttrLen,
+                    ConfigError::OutOfRange(_) => GattStatus::OutOfRange,
                 }
             }
         }
+    }
 
-        if let Some(handle) = state.co2_handle {
-            let co2_bytes = co2.to_le_bytes();
+    /// Validate and queue new Wi-Fi credentials.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `addr` - The address.
+    /// * `value` - The value, the length-prefixed SSID and passphrase, or a single
+    ///   zero byte to forget the network.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn request_wifi_credentials(
+        &self,
+        state: &mut State,
+        addr: BdAddr,
+        value: &[u8],
+    ) -> GattStatus {
+        match decode_provisioning(value) {
+            Ok(credentials) => {
+                info!("Wi-Fi credentials {:?} written by {}", credentials, addr);
+                state.pending_wifi_credentials = Some(credentials);
+                GattStatus::Ok
+            }
+            Err(e) => {
+                warn!("Rejected Wi-Fi credentials from {}: {}", addr, e);
+                match e {
+                    CredentialsError::InvalidLength => GattStatus::InvalidAttrLen,
+                    CredentialsError::InvalidSsid
+                    | CredentialsError::InvalidPassword
+                    | CredentialsError::InvalidUtf8 => GattStatus::OutOfRange,
+                }
+            }
+        }
+    }
+
+    /// Validate and queue a control point request.
+    ///
+    /// As with SIG control points, the write fails if the client has not enabled
+    /// indications or another request is still running; otherwise the outcome is
+    /// indicated later, including for requests that cannot be decoded.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `conn_id` - The connection ID.
+    /// * `addr` - The address.
+    /// * `value` - The value, an opcode followed by its parameters.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn request_control(
+        &self,
+        state: &mut State,
+        conn_id: ConnectionId,
+        addr: BdAddr,
+        value: &[u8],
+    ) -> GattStatus {
+        let indicate = state
+            .gatt
+            .cccd_handle(Attr::ControlPoint)
+            .is_some_and(|cccd_handle| {
+                state
+                    .connections
+                    .iter()
+                    .find(|c| c.conn_id == conn_id)
+                    .is_some_and(|c| c.subscriptions.get(cccd_handle).indicate())
+            });
+        if !indicate {
+            return GattStatus::CccCfgErr;
+        }
+
+        if state.control_requester.is_some() {
+            return GattStatus::PrcInProgress;
+        }
+
+        state.control_requester = Some(conn_id);
+        match ControlRequest::decode(value) {
+            Ok(request) => {
+                info!("Control point request {:?} sent by {}", request, addr);
+                state.pending_control_request = Some(request);
+            }
+            Err(response) => {
+                warn!("Rejected control point request from {}: {:?}", addr, value);
+                state.pending_control_response = Some(response);
+            }
+        }
+
+        GattStatus::Ok
+    }
+
+    /// Validate and run a firmware update request.
+    ///
+    /// Like the control point, the write fails if the client has not enabled
+    /// indications or a Finish is still running. Finish is left to the device
+    /// manager; every other request is answered once the write is acknowledged.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `conn_id` - The connection ID.
+    /// * `addr` - The address.
+    /// * `value` - The value, an opcode followed by its parameters.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn request_ota(
+        &self,
+        state: &mut State,
+        conn_id: ConnectionId,
+        addr: BdAddr,
+        value: &[u8],
+    ) -> GattStatus {
+        let indicate = state
+            .gatt
+            .cccd_handle(Attr::OtaControl)
+            .is_some_and(|cccd_handle| {
+                state
+                    .connections
+                    .iter()
+                    .find(|c| c.conn_id == conn_id)
+                    .is_some_and(|c| c.subscriptions.get(cccd_handle).indicate())
+            });
+        if !indicate {
+            return GattStatus::CccCfgErr;
+        }
+
+        if state.ota_requester.is_some() {
+            return GattStatus::PrcInProgress;
+        }
+
+        let Some(ota) = state.ota.clone() else {
+            return GattStatus::ReqNotSupported;
+        };
+
+        state.ota_requester = Some(conn_id);
+        let request = match OtaRequest::decode(value) {
+            Ok(request) => request,
+            Err(response) => {
+                warn!(
+                    "Rejected firmware update request from {}: {:?}",
+                    addr, value
+                );
+                state.pending_ota_response = Some(response);
+                return GattStatus::Ok;
+            }
+        };
+        info!("Firmware update request {:?} sent by {}", request, addr);
+
+        // Chunks queued for an earlier transfer must not land in a new one.
+        if matches!(request, OtaRequest::Begin(_) | OtaRequest::Abort) {
+            state.pending_ota_chunks.clear();
+        }
+
+        let mut updater = ota.lock().unwrap();
+        let result = match request {
+            OtaRequest::Begin(image) => updater.begin(image).map(Some),
+            OtaRequest::Status => updater.offset().map(Some).ok_or(OtaError::NotStarted),
+            OtaRequest::Finish => {
+                state.pending_ota_finish = true;
+                return GattStatus::Ok;
+            }
+            OtaRequest::Abort => {
+                updater.abort();
+                Ok(None)
+            }
+        };
+
+        state.pending_ota_response = Some(match result {
+            Ok(offset) => OtaResponse::success(request, offset),
+            Err(e) => {
+                warn!("Firmware update request {:?} failed: {}", request, e);
+                OtaResponse::failure(request.opcode(), e.result())
+            }
+        });
+
+        GattStatus::Ok
+    }
+
+    /// Queue a firmware image chunk for the device manager.
+    ///
+    /// Erasing and writing flash takes too long for the Bluetooth task, so the
+    /// chunk is copied and written by [`BleServer::write_ota_chunks`]. Chunks
+    /// that do not continue the image are dropped there; the client finds the
+    /// offset to resume from with a Status request.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `addr` - The address.
+    /// * `value` - The value, the offset of the chunk followed by image bytes.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with, if the chunk was written with
+    ///   a response.
+    fn write_ota_chunk(&self, state: &mut State, addr: BdAddr, value: &[u8]) -> GattStatus {
+        let Some((offset, data)) = decode_chunk(value) else {
+            return GattStatus::InvalidAttrLen;
+        };
+        if state.ota.is_none() {
+            return GattStatus::ReqNotSupported;
+        }
+        if state.pending_ota_chunks.len() >= MAX_PENDING_OTA_CHUNKS {
+            warn!("Dropped firmware chunk at offset {} from {}", offset, addr);
+            return GattStatus::Busy;
+        }
+
+        state.pending_ota_chunks.push_back((offset, data.to_vec()));
+        GattStatus::Ok
+    }
+
+    /// Validate and run a history record access request.
+    ///
+    /// The write fails if the client has not enabled history notifications and
+    /// record access indications, or if a transfer is running and the request is
+    /// not an abort. The outcome is indicated once the write is acknowledged, and
+    /// for a transfer once every record has been sent.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `conn_id` - The connection ID.
+    /// * `addr` - The address.
+    /// * `value` - The value, an opcode, operator and operand.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn request_history(
+        &self,
+        state: &mut State,
+        conn_id: ConnectionId,
+        addr: BdAddr,
+        value: &[u8],
+    ) -> GattStatus {
+        let Some(conn) = state.connections.iter().find(|c| c.conn_id == conn_id) else {
+            return GattStatus::CccCfgErr;
+        };
+        let notify = state
+            .gatt
+            .cccd_handle(Attr::HistoryData)
+            .is_some_and(|cccd_handle| conn.subscriptions.get(cccd_handle).notify());
+        let indicate = state
+            .gatt
+            .cccd_handle(Attr::HistoryRacp)
+            .is_some_and(|cccd_handle| conn.subscriptions.get(cccd_handle).indicate());
+        if !(notify && indicate) {
+            return GattStatus::CccCfgErr;
+        }
+
+        let request = match RacpRequest::decode(value) {
+            Ok(request) => request,
+            Err(response) => {
+                warn!("Rejected record access request from {}: {:?}", addr, value);
+                state.pending_racp_response = Some(response);
+                return GattStatus::Ok;
+            }
+        };
+
+        if state.history_transfer.is_some() && request != RacpRequest::Abort {
+            return GattStatus::PrcInProgress;
+        }
+
+        info!("Record access request {:?} sent by {}", request, addr);
+        let mut history = state.history.as_ref().map(|h| h.lock().unwrap());
+
+        let response = match request {
+            RacpRequest::ReportRecords(filter) => {
+                if history.as_ref().map_or(0, |h| h.count(filter)) == 0 {
+                    Some(RacpCode::NoRecordsFound)
+                } else {
+                    state.history_transfer = Some(HistoryTransfer {
+                        conn_id,
+                        filter,
+                        cursor: 0,
+                        in_flight: false,
+                    });
+                    None
+                }
+            }
+            RacpRequest::DeleteRecords(filter) => {
+                let deleted = history.as_mut().map_or(0, |h| h.delete(filter));
+                info!("Deleted {} history records", deleted);
+                Some(if deleted == 0 {
+                    RacpCode::NoRecordsFound
+                } else {
+                    RacpCode::Success
+                })
+            }
+            RacpRequest::Abort => {
+                state.history_transfer = None;
+                Some(RacpCode::Success)
+            }
+            RacpRequest::ReportNumberOfRecords(filter) => {
+                let count = history.as_ref().map_or(0, |h| h.count(filter));
+                let count = count.min(u16::MAX as usize) as u16;
+                state.pending_racp_response = Some(RacpResponse::NumberOfRecords(count));
+                None
+            }
+        };
+        drop(history);
+
+        if let Some(code) = response {
+            state.pending_racp_response = Some(RacpResponse::code(request.opcode(), code));
+        }
+
+        GattStatus::Ok
+    }
+
+    /// Send the next chunk of the history transfer, or its response code once every
+    /// record has been sent.
+    ///
+    /// Chunks are sent one at a time: the next one follows the local confirmation
+    /// of the previous notification, so the transfer never overruns the stack's
+    /// buffers.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `gatt_if` - The GATT interface.
+    fn continue_history_transfer(&self, state: &mut State, gatt_if: GattInterface) {
+        let Some(transfer) = state.history_transfer.filter(|t| !t.in_flight) else {
+            return;
+        };
+        let Some(handle) = state.gatt.handle(Attr::HistoryData) else {
+            return;
+        };
+        let Some(mtu) = state
+            .connections
+            .iter()
+            .find(|c| c.conn_id == transfer.conn_id)
+            .map(|c| c.mtu)
+        else {
+            state.history_transfer = None;
+            return;
+        };
+
+        let chunk = state.history.as_ref().and_then(|history| {
+            history.lock().unwrap().chunk(
+                transfer.filter,
+                transfer.cursor,
+                notification_payload_len(mtu),
+            )
+        });
+
+        let Some((value, cursor)) = chunk else {
+            self.finish_history_transfer(state, gatt_if, RacpCode::Success);
+            return;
+        };
+
+        match self.gatts.notify(gatt_if, transfer.conn_id, handle, &value) {
+            Ok(()) => {
+                self.count_notification();
+                state.history_transfer = Some(HistoryTransfer {
+                    cursor,
+                    in_flight: true,
+                    ..transfer
+                });
+            }
+            Err(e) => {
+                warn!("Failed to send history records: {:?}", e);
+                self.finish_history_transfer(state, gatt_if, RacpCode::ProcedureNotCompleted);
+            }
+        }
+    }
+
+    /// End the history transfer and indicate its response code.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `gatt_if` - The GATT interface.
+    /// * `code` - The response code.
+    fn finish_history_transfer(&self, state: &mut State, gatt_if: GattInterface, code: RacpCode) {
+        if let Some(transfer) = state.history_transfer.take() {
+            info!("History transfer to {} ended: {:?}", transfer.conn_id, code);
+            let response =
+                RacpResponse::code(RacpRequest::ReportRecords(transfer.filter).opcode(), code);
+            self.send_racp_response(state, gatt_if, transfer.conn_id, response);
+        }
+    }
+
+    /// Indicate a record access response.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `gatt_if` - The GATT interface.
+    /// * `conn_id` - The connection ID.
+    /// * `response` - The response.
+    fn send_racp_response(
+        &self,
+        state: &mut State,
+        gatt_if: GattInterface,
+        conn_id: ConnectionId,
+        response: RacpResponse,
+    ) {
+        let Some(handle) = state.gatt.handle(Attr::HistoryRacp) else {
+            return;
+        };
+
+        if let Some(conn) = state.connections.iter_mut().find(|c| c.conn_id == conn_id) {
+            let next = conn.indications.push(handle, &response.encode());
+            self.send_indications(gatt_if, conn, next);
+        }
+    }
+
+    /// Validate and apply a Current Time write, then notify the new time.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `gatt_if` - The GATT interface.
+    /// * `addr` - The address.
+    /// * `value` - The value, the local date and time.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn set_current_time(
+        &self,
+        state: &mut State,
+        gatt_if: GattInterface,
+        addr: BdAddr,
+        value: &[u8],
+    ) -> GattStatus {
+        let Some(clock) = state.clock.clone() else {
+            return GattStatus::ReqNotSupported;
+        };
+
+        let result = decode_current_time(value).and_then(|(local, fractions256)| {
+            clock
+                .lock()
+                .unwrap()
+                .set_local(&local, fractions256, uptime_ms())?;
+            Ok(local)
+        });
+
+        match result {
+            Ok(local) => {
+                info!("Current time {:?} set by {}", local, addr);
+                self.publish_current_time(state, gatt_if, ADJUST_REASON_MANUAL);
+                GattStatus::Ok
+            }
+            Err(TimeError::InvalidLength) => GattStatus::InvalidAttrLen,
+            Err(TimeError::OutOfRange) => GattStatus::OutOfRange,
+        }
+    }
+
+    /// Validate and apply a Local Time Information write, then notify the new time.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `gatt_if` - The GATT interface.
+    /// * `addr` - The address.
+    /// * `value` - The value, the time zone and daylight saving offset.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn set_local_time(
+        &self,
+        state: &mut State,
+        gatt_if: GattInterface,
+        addr: BdAddr,
+        value: &[u8],
+    ) -> GattStatus {
+        let Some(clock) = state.clock.clone() else {
+            return GattStatus::ReqNotSupported;
+        };
+
+        match LocalTimeInfo::decode(value) {
+            Ok(local_time) => {
+                info!("Local time {:?} set by {}", local_time, addr);
+                clock.lock().unwrap().set_local_time(local_time);
+                self.publish_current_time(state, gatt_if, ADJUST_REASON_TIME_ZONE);
+                GattStatus::Ok
+            }
+            Err(TimeError::InvalidLength) => GattStatus::InvalidAttrLen,
+            Err(TimeError::OutOfRange) => GattStatus::OutOfRange,
+        }
+    }
+
+    /// Notify the current time to subscribers.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `gatt_if` - The GATT interface.
+    /// * `adjust_reason` - Why the time changed.
+    fn publish_current_time(&self, state: &mut State, gatt_if: GattInterface, adjust_reason: u8) {
+        if let Some(handle) = state.gatt.handle(Attr::CurrentTime) {
+            let value = current_time_value(state, adjust_reason);
+            self.publish(state, gatt_if, handle, &value, "current time");
+        }
+    }
+
+    /// Validate and apply an ES Trigger Setting.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `characteristic` - The characteristic the trigger setting belongs to.
+    /// * `addr` - The address.
+    /// * `value` - The value, the encoded trigger setting.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn set_ess_trigger(
+        &self,
+        state: &mut State,
+        characteristic: EssCharacteristic,
+        addr: BdAddr,
+        value: &[u8],
+    ) -> GattStatus {
+        match TriggerSetting::decode(characteristic, value) {
+            Ok(trigger) => {
+                info!(
+                    "{:?} trigger setting {:?} set by {}",
+                    characteristic, trigger, addr
+                );
+                let attributes = &mut state.ess[characteristic.index()];
+                attributes.trigger = trigger;
+                attributes.last_notified = None;
+                GattStatus::Ok
+            }
+            Err(TriggerError::InvalidLength) => GattStatus::InvalidAttrLen,
+            Err(TriggerError::UnsupportedCondition(_)) => GattStatus::OutOfRange,
+        }
+    }
+
+    /// Validate and queue an alarm command.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `addr` - The address.
+    /// * `value` - The value, the command code.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn request_alarm_command(&self, state: &mut State, addr: BdAddr, value: &[u8]) -> GattStatus {
+        let [code] = value else {
+            return GattStatus::InvalidAttrLen;
+        };
+
+        match AlarmCommand::from_code(*code) {
+            Some(command) => {
+                info!("Alarm command {:?} sent by {}", command, addr);
+                state.pending_alarm_command = Some(command);
+                GattStatus::Ok
+            }
+            None => GattStatus::OutOfRange,
+        }
+    }
+
+    /// Take the alarm command sent by a client, if any.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<AlarmCommand>` - The command.
+    pub fn take_alarm_command(&self) -> Option<AlarmCommand> {
+        self.state.lock().unwrap().pending_alarm_command.take()
+    }
+
+    /// Take the control point request written by a client, if any.
+    ///
+    /// The request must be answered with [`BleServer::send_control_response`] before
+    /// another one is accepted.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<ControlRequest>` - The request.
+    pub fn take_control_request(&self) -> Option<ControlRequest> {
+        self.state.lock().unwrap().pending_control_request.take()
+    }
+
+    /// Indicate the response to the pending control point request.
+    ///
+    /// The response is dropped if the requesting client has disconnected or
+    /// disabled indications in the meantime.
+    ///
+    /// # Arguments
+    /// * `response` - The response.
+    pub fn send_control_response(&self, response: ControlResponse) {
+        let mut state = self.state.lock().unwrap();
+        let Some(conn_id) = state.control_requester.take() else {
+            return;
+        };
+
+        if !self.indicate_response(&mut state, conn_id, Attr::ControlPoint, &response.encode()) {
+            warn!("Dropping control point response {:?}", response);
+        }
+    }
+
+    /// Share the firmware updater with clients.
+    ///
+    /// # Arguments
+    /// * `ota` - The updater.
+    pub fn set_ota(&self, ota: Arc<Mutex<OtaUpdater<FlashPartition>>>) {
+        self.state.lock().unwrap().ota = Some(ota);
+    }
+
+    /// Write the firmware chunks received since the last call to flash.
+    ///
+    /// Runs on the device manager's thread, outside the Bluetooth task, and before
+    /// [`BleServer::take_ota_finish`] so a Finish sees every chunk sent before it.
+    pub fn write_ota_chunks(&self) {
+        let (chunks, ota) = {
+            let mut state = self.state.lock().unwrap();
+            (
+                std::mem::take(&mut state.pending_ota_chunks),
+                state.ota.clone(),
+            )
+        };
+        let Some(ota) = ota else {
+            return;
+        };
+
+        let mut updater = ota.lock().unwrap();
+        for (offset, data) in chunks {
+            match updater.write(offset, &data) {
+                Ok(true) => (),
+                Ok(false) => warn!("Dropped firmware chunk at offset {}", offset),
+                Err(e) => warn!("Failed to write firmware chunk at offset {}: {}", offset, e),
+            }
+        }
+    }
+
+    /// Take the request to verify and boot the received firmware image, if any.
+    ///
+    /// The request must be answered with [`BleServer::send_ota_response`] before
+    /// another firmware update request is accepted.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether a client sent Finish.
+    pub fn take_ota_finish(&self) -> bool {
+        std::mem::take(&mut self.state.lock().unwrap().pending_ota_finish)
+    }
+
+    /// Indicate the response to the pending firmware update request.
+    ///
+    /// # Arguments
+    /// * `response` - The response.
+    pub fn send_ota_response(&self, response: OtaResponse) {
+        let mut state = self.state.lock().unwrap();
+        let Some(conn_id) = state.ota_requester.take() else {
+            return;
+        };
+
+        if !self.indicate_response(&mut state, conn_id, Attr::OtaControl, &response.encode()) {
+            warn!("Dropping firmware update response {:?}", response);
+        }
+    }
+
+    /// Indicate a control point response to the client that sent the request.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `conn_id` - The connection of the requester.
+    /// * `attr` - The control point.
+    /// * `value` - The encoded response.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the response was queued; it is not if the requester has
+    ///   disconnected or disabled indications in the meantime.
+    fn indicate_response(
+        &self,
+        state: &mut State,
+        conn_id: ConnectionId,
+        attr: Attr,
+        value: &[u8],
+    ) -> bool {
+        let (Some(gatt_if), Some(handle), Some(cccd_handle)) = (
+            state.gatt_if,
+            state.gatt.handle(attr),
+            state.gatt.cccd_handle(attr),
+        ) else {
+            return false;
+        };
+
+        let Some(conn) = state
+            .connections
+            .iter_mut()
+            .find(|c| c.conn_id == conn_id && c.subscriptions.get(cccd_handle).indicate())
+        else {
+            return false;
+        };
+
+        let next = conn.indications.push(handle, value);
+        self.send_indications(gatt_if, conn, next);
+        true
+    }
+
+    /// Take the configuration written by a client, if any.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Config>` - The new configuration.
+    pub fn take_config_request(&self) -> Option<Config> {
+        self.state.lock().unwrap().pending_config.take()
+    }
+
+    /// Set the configuration reported to clients.
+    ///
+    /// # Arguments
+    /// * `config` - The configuration.
+    pub fn set_config(&self, config: Config) {
+        self.state.lock().unwrap().config = config;
+    }
+
+    /// Change the advertised device name.
+    ///
+    /// # Arguments
+    /// * `device_name` - The device name.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    pub fn set_device_name(&self, device_name: &str) -> Result<(), AppError> {
+        self.configure_scan_response(device_name)
+            .map_err(|e| AppError::BleError(format!("Failed to set device name: {:?}", e)))
+    }
+
+    /// Take the display orientation requested by a client, if any.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Orientation>` - The requested orientation.
+    pub fn take_orientation_request(&self) -> Option<Orientation> {
+        self.state.lock().unwrap().pending_orientation.take()
+    }
+
+    /// Encrypt BTHome broadcasts with a bindkey.
+    ///
+    /// # Arguments
+    /// * `bindkey` - The encryption key.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    pub fn set_bthome_bindkey(&self, bindkey: Bindkey) -> Result<(), AppError> {
+        let mut mac = [0u8; 6];
+        esp!(unsafe { esp_read_mac(mac.as_mut_ptr(), esp_mac_type_t_ESP_MAC_BT) }).map_err(
+            |e| AppError::BleError(format!("Failed to read Bluetooth address: {:?}", e)),
+        )?;
+
+        let mut state = self.state.lock().unwrap();
+        state.bthome_bindkey = Some(bindkey);
+        state.bthome_mac = mac;
+
+        Ok(())
+    }
+
+    /// Forget all bonded peers; they have to pair again before writing.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<usize, AppError>` - The number of bonds removed.
+    pub fn clear_bonds(&self) -> Result<usize, AppError> {
+        let mut count = unsafe { esp_ble_get_bond_device_num() };
+        if count <= 0 {
+            return Ok(0);
+        }
+
+        let mut bonds = vec![esp_ble_bond_dev_t::default(); count as usize];
+        esp!(unsafe { esp_ble_get_bond_device_list(&mut count, bonds.as_mut_ptr()) })
+            .map_err(|e| AppError::BleError(format!("Failed to list bonded peers: {:?}", e)))?;
+
+        for bond in bonds.iter_mut().take(count as usize) {
+            esp!(unsafe { esp_ble_remove_bond_device(bond.bd_addr.as_mut_ptr()) })
+                .map_err(|e| AppError::BleError(format!("Failed to remove bond: {:?}", e)))?;
+        }
+
+        Ok(count as usize)
+    }
+
+    /// Disconnect peers that are not subscribed to anything and have not sent a
+    /// request for a while, freeing their slots.
+    pub fn disconnect_idle(&self) {
+        let now_ms = uptime_ms();
+        let mut state = self.state.lock().unwrap();
+
+        let mut idle = heapless::Vec::<BdAddr, MAX_CONNECTIONS>::new();
+        for conn in state.connections.iter_mut() {
+            let subscribed = !conn.subscriptions.is_empty();
+            if !conn.closing && is_idle(conn.last_activity_ms, now_ms, subscribed) {
+                conn.closing = true;
+                idle.push(conn.peer).ok();
+            }
+        }
+        state.stats.idle_closed += idle.len() as u32;
+        drop(state);
+
+        for addr in idle {
+            info!("Disconnecting idle peer {}", addr);
+            if let Err(e) = self.disconnect(addr) {
+                warn!("Failed to disconnect idle peer {}: {:?}", addr, e);
+            }
+        }
+    }
+
+    /// Get the connection statistics.
+    ///
+    /// # Returns
+    ///
+    /// * `ConnectionStats` - The statistics.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        let state = self.state.lock().unwrap();
+        ConnectionStats {
+            active: state.connections.len(),
+            slots: MAX_CONNECTIONS,
+            notifications: self.notifications.load(Ordering::Relaxed),
+            ..state.stats
+        }
+    }
+
+    /// Count a notification or indication handed to the stack.
+    fn count_notification(&self) {
+        self.notifications.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the pairing screen to show instead of the measurements.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<PairingScreen>` - The screen, or `None` if no pairing is in progress.
+    pub fn pairing_screen(&self) -> Option<PairingScreen> {
+        self.state.lock().unwrap().pairing.screen(uptime_ms())
+    }
+
+    /// Set the identification strings reported by the Device Information Service.
+    ///
+    /// # Arguments
+    /// * `device_info` - The identification strings.
+    pub fn set_device_info(&self, device_info: DeviceInfo) {
+        self.state.lock().unwrap().device_info = device_info;
+    }
+
+    /// Let clients read and set the system clock.
+    ///
+    /// # Arguments
+    /// * `clock` - The clock.
+    pub fn set_clock(&self, clock: SharedClock) {
+        self.state.lock().unwrap().clock = Some(clock);
+    }
+
+    /// Share the measurement history with clients.
+    ///
+    /// # Arguments
+    /// * `history` - The history.
+    pub fn set_history(&self, history: Arc<Mutex<History>>) {
+        self.state.lock().unwrap().history = Some(history);
+    }
+
+    /// Take the Wi-Fi credentials written by a client, if any.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Option<Credentials>>` - The new credentials, `Some(None)` to
+    ///   forget the network.
+    pub fn take_wifi_credentials(&self) -> Option<Option<Credentials>> {
+        self.state.lock().unwrap().pending_wifi_credentials.take()
+    }
+
+    /// Update the Wi-Fi connectivity and notify subscribers when it changes.
+    ///
+    /// # Arguments
+    /// * `status` - The connectivity.
+    pub fn set_wifi_status(&self, status: &WifiStatus) {
+        let mut state = self.state.lock().unwrap();
+        if state.wifi_status == *status {
+            return;
+        }
+        state.wifi_status = status.clone();
+
+        let (Some(gatt_if), Some(handle)) = (state.gatt_if, state.gatt.handle(Attr::WifiStatus))
+        else {
+            return;
+        };
+
+        let value = status.encode();
+        if let Err(e) = self.gatts.set_attr(handle, &value) {
+            warn!("Failed to set Wi-Fi status attribute: {:?}", e);
+        }
+
+        self.publish(&mut state, gatt_if, handle, &value, "Wi-Fi status");
+    }
+
+    /// Set the display orientation reported to clients.
+    ///
+    /// # Arguments
+    /// * `orientation` - The orientation.
+    pub fn set_orientation(&self, orientation: Orientation) {
+        self.state.lock().unwrap().orientation = orientation;
+    }
+
+    /// Send a write response.
+    ///
+    /// # Arguments
+    /// * `gatt_if` - The GATT interface.
+    /// * `conn_id` - The connection ID.
+    /// * `trans_id` - The transfer ID.
+    /// * `handle` - The attribute handle.
+    /// * `offset` - The offset.
+    /// * `need_rsp` - Whether a response is needed.
+    /// * `is_prep` - Whether the write is a prepare write.
+    /// * `value` - The value to write.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), EspError>` - The result of sending the write response.
+    fn send_write_response(
+        &self,
+        gatt_if: GattInterface,
+        conn_id: ConnectionId,
+        trans_id: TransferId,
+        handle: Handle,
+        offset: u16,
+        need_rsp: bool,
+        is_prep: bool,
+        value: &[u8],
+    ) -> Result<(), EspError> {
+        if !need_rsp {
+            return Ok(());
+        }
+
+        if is_prep {
+            let mut state = self.state.lock().unwrap();
+            state
+                .response
+                .attr_handle(handle)
+                .auth_req(0)
+                .offset(offset)
+                .value(value)
+                .map_err(|_| EspError::from_infallible::<ESP_FAIL>())?;
+
+            self.gatts.send_response(
+                gatt_if,
+                conn_id,
+                trans_id,
+                GattStatus::Ok,
+                Some(&state.response),
+            )?;
+        } else {
+            self.gatts
+                .send_response(gatt_if, conn_id, trans_id, GattStatus::Ok, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Validate and store a CCCD write.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `conn_id` - The connection ID.
+    /// * `addr` - The address.
+    /// * `cccd_handle` - The CCCD handle.
+    /// * `supported` - The modes the characteristic supports.
+    /// * `value` - The value.
+    ///
+    /// # Returns
+    ///
+    /// * `GattStatus` - The status to respond with.
+    fn set_subscription(
+        &self,
+        state: &mut State,
+        conn_id: ConnectionId,
+        addr: BdAddr,
+        cccd_handle: Handle,
+        supported: Cccd,
+        value: &[u8],
+    ) -> GattStatus {
+        let cccd = match Cccd::parse(value, supported) {
+            Ok(cccd) => cccd,
+            Err(InvalidCccd::InvalidLength) => return GattStatus::InvalidAttrLen,
+            Err(InvalidCccd::Unsupported(_)) => return GattStatus::CccCfgErr,
+        };
+
+        if let Some(conn) = state.connections.iter_mut().find(|c| c.conn_id == conn_id) {
+            conn.subscriptions.set(cccd_handle, cccd);
+        }
+
+        info!(
+            "CCCD {} set to notify = {}, indicate = {} by {}",
+            cccd_handle,
+            cccd.notify(),
+            cccd.indicate(),
+            addr
+        );
+
+        GattStatus::Ok
+    }
+
+    /// Notify or indicate a new value to every subscribed connection.
+    ///
+    /// # Arguments
+    /// * `state` - The state.
+    /// * `gatt_if` - The GATT interface.
+    /// * `handle` - The characteristic value handle.
+    /// * `value` - The value.
+    /// * `name` - The characteristic name, for logging.
+    fn publish(
+        &self,
+        state: &mut State,
+        gatt_if: GattInterface,
+        handle: Handle,
+        value: &[u8],
+        name: &str,
+    ) {
+        let Some(cccd_handle) = state.cccd_handle_of(handle) else {
+            return;
+        };
+
+        for conn in state.connections.iter_mut() {
+            let cccd = conn.subscriptions.get(cccd_handle);
+
+            if cccd.notify() {
+                match self.gatts.notify(gatt_if, conn.conn_id, handle, value) {
+                    Ok(()) => self.count_notification(),
+                    Err(e) => warn!("Failed to send {} notification: {:?}", name, e),
+                }
+            } else if cccd.indicate() {
+                let next = conn.indications.push(handle, value);
+                self.send_indications(gatt_if, conn, next);
+            }
+        }
+    }
+
+    /// Send the next queued indication of a connection, skipping any that fail.
+    ///
+    /// # Arguments
+    /// * `gatt_if` - The GATT interface.
+    /// * `conn` - The connection.
+    /// * `next` - The indication to send, if any.
+    fn send_indications(
+        &self,
+        gatt_if: GattInterface,
+        conn: &mut Connection,
+        mut next: Option<Indication>,
+    ) {
+        while let Some(indication) = next {
+            match self
+                .gatts
+                .indicate(gatt_if, conn.conn_id, indication.handle, &indication.value)
+            {
+                Ok(()) => {
+                    self.count_notification();
+                    return;
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to send indication for handle {}: {:?}",
+                        indication.handle, e
+                    );
+                    next = conn.indications.complete(indication.handle);
+                }
+            }
+        }
+    }
+
+    /// Update the battery level and notify subscribers when it changes.
+    ///
+    /// # Arguments
+    /// * `level` - The charge in percent.
+    pub fn update_battery_level(&self, level: u8) {
+        let mut state = self.state.lock().unwrap();
+        if state.battery_level == level {
+            return;
+        }
+        state.battery_level = level;
+
+        let (Some(gatt_if), Some(handle)) = (state.gatt_if, state.gatt.handle(Attr::BatteryLevel))
+        else {
+            return;
+        };
+
+        if let Err(e) = self.gatts.set_attr(handle, &[level]) {
+            warn!("Failed to set battery level attribute: {:?}", e);
+        }
+
+        self.publish(&mut state, gatt_if, handle, &[level], "battery level");
+    }
+
+    /// Update characteristic values and notify subscribers.
+    ///
+    /// # Arguments
+    /// * `temperature` - The temperature.
+    /// * `humidity` - The humidity.
+    /// * `co2` - The CO2.
+    /// * `air_quality` - The air quality level.
+    /// * `bthome_counter` - The BTHome frame counter, required when broadcasts are
+    ///   encrypted; without it the readings are not broadcast.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), EspError>` - The result of updating the values.
+    pub fn update_values(
+        &self,
+        temperature: i16,
+        humidity: u16,
+        co2: u16,
+        air_quality: AirQuality,
+        bthome_counter: Option<u32>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        state.latest_temperature = temperature;
+        state.latest_humidity = humidity;
+        state.latest_co2 = co2;
+        state.latest_air_quality = air_quality.index();
+        state.readings_sequence = state.readings_sequence.wrapping_add(1);
+
+        let Some(gatt_if) = state.gatt_if else {
+            return;
+        };
+
+        let measurements = Measurements {
+            temperature,
+            humidity,
+            co2,
+        };
+        let service_data = match (state.bthome_bindkey, bthome_counter) {
+            (None, _) => Some(bthome::service_data(&measurements)),
+            (Some(bindkey), Some(counter)) => Some(bthome::encrypted_service_data(
+                &measurements,
+                &state.bthome_mac,
+                &bindkey,
+                counter,
+            )),
+            (Some(_), None) => None,
+        };
+        if let Err(e) = self.configure_advertisement(service_data.as_deref()) {
+            warn!("Failed to update BTHome advertisement: {:?}", e);
+        }
+
+        if let Some(handle) = state.gatt.handle(Attr::Temperature) {
+            let temp_bytes = temperature.to_le_bytes();
+            if let Err(e) = self.gatts.set_attr(handle, &temp_bytes) {
+                warn!("Failed to set temperature attribute: {:?}", e);
+            }
+
+            self.publish(&mut state, gatt_if, handle, &temp_bytes, "temperature");
+        }
+
+        if let Some(handle) = state.gatt.handle(Attr::Humidity) {
+            let humid_bytes = humidity.to_le_bytes();
+            if let Err(e) = self.gatts.set_attr(handle, &humid_bytes) {
+                warn!("Failed to set humidity attribute: {:?}", e);
+            }
+
+            self.publish(&mut state, gatt_if, handle, &humid_bytes, "humidity");
+        }
+
+        if let Some(handle) = state.gatt.handle(Attr::Co2) {
+            let co2_bytes = co2.to_le_bytes();
             if let Err(e) = self.gatts.set_attr(handle, &co2_bytes) {
                 warn!("Failed to set CO2 attribute: {:?}", e);
             }
 
-            for conn in state.connections.iter() {
-                if conn.subscribed {
-                    if let Err(e) = self.gatts.notify(gatt_if, conn.conn_id, handle, &co2_bytes) {
-                        warn!("Failed to send CO2 notification: {:?}", e);
-                    }
-                }
+            self.publish(&mut state, gatt_if, handle, &co2_bytes, "CO2");
+        }
+
+        if let Some(handle) = state.gatt.handle(Attr::AirQuality) {
+            let air_quality_bytes = [air_quality.index()];
+            if let Err(e) = self.gatts.set_attr(handle, &air_quality_bytes) {
+                warn!("Failed to set air quality attribute: {:?}", e);
+            }
+
+            self.publish(
+                &mut state,
+                gatt_if,
+                handle,
+                &air_quality_bytes,
+                "air quality",
+            );
+        }
+
+        if let Some(handle) = state.gatt.handle(Attr::AllReadings) {
+            let readings_bytes = readings(&state).encode();
+            if let Err(e) = self.gatts.set_attr(handle, &readings_bytes) {
+                warn!("Failed to set all readings attribute: {:?}", e);
+            }
+
+            self.publish(&mut state, gatt_if, handle, &readings_bytes, "all readings");
+        }
+
+        for characteristic in EssCharacteristic::ALL {
+            let value = ess_value(&state, characteristic);
+            let attributes = state.ess[characteristic.index()];
+            let Some(handle) = state.gatt.handle(Attr::Ess(characteristic)) else {
+                continue;
+            };
+
+            let bytes = characteristic.encode(value);
+            if let Err(e) = self.gatts.set_attr(handle, &bytes) {
+                warn!("Failed to set {:?} attribute: {:?}", characteristic, e);
             }
+
+            let last = attributes
+                .last_notified
+                .map(|(value, at)| (value, at.elapsed().as_millis() as u64));
+            if !attributes.trigger.should_notify(value, last) {
+                continue;
+            }
+
+            let name = format!("{:?}", characteristic);
+            self.publish(&mut state, gatt_if, handle, &bytes, &name);
+            state.ess[characteristic.index()].last_notified = Some((value, Instant::now()));
+        }
+    }
+}
+
+/// Get the latest sample for the all readings characteristic.
+///
+/// # Arguments
+/// * `state` - The state.
+///
+/// # Returns
+///
+/// * `Readings` - The sample.
+fn readings(state: &State) -> Readings {
+    Readings {
+        co2: state.latest_co2,
+        temperature: state.latest_temperature,
+        humidity: state.latest_humidity,
+        air_quality: state.latest_air_quality,
+        alarm: state.alarm_state,
+        sequence: state.readings_sequence,
+    }
+}
+
+/// Get the latest value of an Environmental Sensing Service characteristic.
+///
+/// # Arguments
+/// * `state` - The state.
+/// * `characteristic` - The characteristic.
+///
+/// # Returns
+///
+/// * `i32` - The value in characteristic units.
+fn ess_value(state: &State, characteristic: EssCharacteristic) -> i32 {
+    match characteristic {
+        EssCharacteristic::Temperature => state.latest_temperature as i32,
+        EssCharacteristic::Humidity => state.latest_humidity as i32,
+        EssCharacteristic::Co2 => state.latest_co2 as i32,
+    }
+}
+
+/// Get a copy of the system clock.
+///
+/// # Arguments
+/// * `state` - The state.
+///
+/// # Returns
+///
+/// * `SystemClock` - The clock, unset if no clock is shared.
+fn clock(state: &State) -> SystemClock {
+    state
+        .clock
+        .as_ref()
+        .map(|clock| *clock.lock().unwrap())
+        .unwrap_or_default()
+}
+
+/// Encode the Current Time characteristic value.
+///
+/// # Arguments
+/// * `state` - The state.
+/// * `adjust_reason` - Why the time changed, `0` for reads.
+///
+/// # Returns
+///
+/// * `Vec<u8>` - The local date and time, all zeros if the clock is not set.
+fn current_time_value(state: &State, adjust_reason: u8) -> Vec<u8> {
+    clock(state)
+        .local(uptime_ms())
+        .map_or([0; 10], |(local, fractions256)| {
+            encode_current_time(&local, fractions256, adjust_reason)
+        })
+        .to_vec()
+}
+
+/// Convert a GATT table UUID for the stack.
+///
+/// # Arguments
+/// * `uuid` - The UUID.
+///
+/// # Returns
+///
+/// * `BtUuid` - The stack UUID.
+fn bt_uuid(uuid: Uuid) -> BtUuid {
+    match uuid {
+        Uuid::Short(uuid) => BtUuid::uuid16(uuid),
+        Uuid::Long(uuid) => BtUuid::uuid128(uuid),
+    }
+}
+
+/// Convert a UUID reported by the stack for the GATT table.
+///
+/// # Arguments
+/// * `uuid` - The stack UUID.
+///
+/// # Returns
+///
+/// * `Result<Uuid, EspError>` - The UUID, or an error for 32-bit UUIDs, which the
+///   table does not use.
+fn table_uuid(uuid: &BtUuid) -> Result<Uuid, EspError> {
+    Uuid::from_le_bytes(uuid.as_bytes()).ok_or_else(|| {
+        warn!("Unsupported UUID {:?}", uuid);
+        EspError::from_infallible::<ESP_FAIL>()
+    })
+}
+
+/// Log a GATT table registration error.
+///
+/// # Arguments
+/// * `error` - The error.
+///
+/// # Returns
+///
+/// * `EspError` - A generic failure.
+fn table_error(error: GattTableError) -> EspError {
+    warn!("GATT table registration failed: {}", error);
+    EspError::from_infallible::<ESP_FAIL>()
+}
+
+/// Convert GATT table permissions for the stack.
+///
+/// # Arguments
+/// * `permissions` - The permissions.
+///
+/// # Returns
+///
+/// * `EnumSet<Permission>` - The stack permissions.
+fn permissions(permissions: Permissions) -> EnumSet<Permission> {
+    [
+        (Permissions::READ, Permission::Read),
+        (Permissions::WRITE, Permission::Write),
+        (
+            Permissions::WRITE_AUTHENTICATED,
+            Permission::WriteEncryptedMitm,
+        ),
+    ]
+    .into_iter()
+    .filter(|(permission, _)| permissions.contains(*permission))
+    .map(|(_, permission)| permission)
+    .collect()
+}
+
+/// Convert GATT table properties for the stack.
+///
+/// # Arguments
+/// * `properties` - The properties.
+///
+/// # Returns
+///
+/// * `EnumSet<Property>` - The stack properties.
+fn properties(properties: Properties) -> EnumSet<Property> {
+    [
+        (Properties::READ, Property::Read),
+        (
+            Properties::WRITE_WITHOUT_RESPONSE,
+            Property::WriteNoResponse,
+        ),
+        (Properties::WRITE, Property::Write),
+        (Properties::NOTIFY, Property::Notify),
+        (Properties::INDICATE, Property::Indicate),
+    ]
+    .into_iter()
+    .filter(|(property, _)| properties.contains(*property))
+    .map(|(_, property)| property)
+    .collect()
+}
+
+/// Implement the `AlarmSink` trait for `BleServer`.
+impl AlarmSink for BleServer {
+    /// Publish the alarm state and notify subscribers.
+    ///
+    /// # Arguments
+    /// * `alarm` - The new alarm state.
+    fn on_alarm(&mut self, alarm: AlarmState) {
+        let mut state = self.state.lock().unwrap();
+        state.alarm_state = alarm.code();
+
+        let (Some(gatt_if), Some(handle)) = (state.gatt_if, state.gatt.handle(Attr::Alarm)) else {
+            return;
+        };
+
+        let alarm_bytes = [alarm.code()];
+        if let Err(e) = self.gatts.set_attr(handle, &alarm_bytes) {
+            warn!("Failed to set alarm attribute: {:?}", e);
         }
+
+        self.publish(&mut state, gatt_if, handle, &alarm_bytes, "alarm");
     }
 }
@@ -0,0 +1,14 @@
+use esp_idf_svc::sys::esp_timer_get_time;
+use scd41_core::clock::SystemClock;
+use std::sync::{Arc, Mutex};
+
+/// The system clock, shared between the device manager and the BLE server.
+pub type SharedClock = Arc<Mutex<SystemClock>>;
+
+/// Milliseconds since boot, the time base of the system clock.
+///
+/// # Returns
+/// The uptime in milliseconds.
+pub fn uptime_ms() -> u64 {
+    (unsafe { esp_timer_get_time() } / 1000) as u64
+}
@@ -1,25 +1,200 @@
-use crate::{ble::BleServer, display::Ssd1306Display, error::AppError, sensor::Scd41Sensor};
+#[cfg(not(feature = "buzzer"))]
+use crate::alarm_output::GpioAlarmOutput;
+#[cfg(feature = "battery")]
+use crate::battery::BatteryMonitor;
+use crate::{
+    ble::BleServer,
+    clock::{uptime_ms, SharedClock},
+    display::Ssd1306Display,
+    error::AppError,
+    indicator::StatusIndicator,
+    influx::InfluxExporter,
+    mqtt::MqttPublisher,
+    ota::{self, FlashPartition},
+    sensor::Scd41Sensor,
+    settings::Settings,
+    sntp::TimeSync,
+    web::WebServer,
+    wifi::WifiManager,
+};
+#[cfg(not(feature = "buzzer"))]
+use esp_idf_svc::hal::gpio::OutputPin;
+#[cfg(feature = "buzzer")]
+use esp_idf_svc::hal::ledc::{config::TimerConfig, LedcTimerDriver};
 use esp_idf_svc::{
     hal::{
+        delay::FreeRtos,
+        gpio::{Gpio9, Input, PinDriver, Pull},
         i2c::{I2cConfig, I2cDriver},
+        ledc::LedcDriver,
         peripherals::Peripherals,
+        reset::restart,
+        rmt::{config::TransmitConfig, TxRmtDriver},
         units::Hertz,
     },
     nvs::{EspNvsPartition, NvsDefault},
+    sys::{esp_chip_info, esp_chip_info_t},
 };
 use log::{error, info};
-use std::{cell::RefCell, rc::Rc};
+use scd41_core::{
+    air_quality::{AirQuality, Classifier},
+    alarm::{AlarmConfig, AlarmEngine, AlarmSink, AlarmState},
+    api::{Current, Status},
+    bthome::{parse_bindkey, FrameCounter},
+    clock::{SystemClock, TimeZone},
+    config::Config,
+    control_point::{ControlRequest, ControlResponse, ResultCode},
+    device_info::{format_serial_number, DeviceInfo},
+    framebuffer::Orientation,
+    history::History,
+    influx::Point,
+    metrics::{Metrics, SensorErrorCounts},
+    mqtt::{DiscoveryDevice, StatePayload},
+    ota::{HealthCheck, HealthVerdict, OtaRequest, OtaResponse, OtaResult, OtaUpdater},
+    pairing::PairingScreen,
+    wifi::{Credentials, WifiStatus},
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+/// BTHome encryption key as 32 hexadecimal digits, set at build time.
+const BTHOME_BINDKEY: Option<&str> = option_env!("BTHOME_BINDKEY");
+
+/// Manufacturer name reported by the Device Information Service.
+const MANUFACTURER_NAME: &str = "arietis";
+
+/// Number of history records kept, three and a half days at the history interval.
+const HISTORY_CAPACITY: usize = 1024;
+
+/// Minimum time between history records in seconds.
+const HISTORY_INTERVAL_S: u32 = 300;
+
+/// How long the BOOT button must be held to forget all bonded peers.
+const CLEAR_BONDS_HOLD_MS: u32 = 5000;
+
+/// Interval between checks for the pairing screen and the BOOT button.
+const POLL_INTERVAL_MS: u32 = 100;
+
+/// How long the status screen is shown after a short press of the BOOT button.
+const STATUS_SCREEN_MS: u64 = 10_000;
+
+/// Time to let the reboot or firmware update response reach the client before
+/// restarting.
+const REBOOT_DELAY_MS: u32 = 1000;
+
+/// Buzzer tone frequency in hertz.
+#[cfg(feature = "buzzer")]
+const BUZZER_FREQUENCY_HZ: u32 = 2700;
+
+/// What the display shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Screen {
+    /// The latest measurement.
+    Measurements,
+
+    /// A pairing in progress.
+    Pairing(PairingScreen),
+
+    /// Device status.
+    Status,
+}
 
 /// The device manager interface.
 pub struct DeviceManager<'a> {
+    /// The CO2 alarm.
+    alarm: AlarmEngine,
+
+    /// Additional alarm outputs besides the display and BLE.
+    alarm_sinks: Vec<Box<dyn AlarmSink + 'a>>,
+
+    /// The battery monitor.
+    #[cfg(feature = "battery")]
+    battery: BatteryMonitor<'a>,
+
     /// The BLE server.
     ble: Option<BleServer>,
 
+    /// The BOOT button, pressed to show the status screen and held to forget all
+    /// bonded peers.
+    boot_button: PinDriver<'a, Gpio9, Input>,
+
+    /// How long the BOOT button has been held in milliseconds.
+    boot_button_held_ms: u32,
+
+    /// The BTHome encryption frame counter, if broadcasts are encrypted.
+    bthome_counter: Option<FrameCounter>,
+
+    /// The status LED and buzzer.
+    indicator: StatusIndicator,
+
+    /// The air quality classifier.
+    classifier: Classifier,
+
+    /// The system clock, shared with the BLE server.
+    clock: SharedClock,
+
+    /// The time or uptime last shown on the status line.
+    clock_label: String,
+
+    /// The runtime configuration.
+    config: Config,
+
+    /// The identification strings.
+    device_info: DeviceInfo,
+
     /// The SSD1306 display.
     display: Ssd1306Display<'a>,
 
+    /// The display brightness in use.
+    brightness: u8,
+
+    /// The latest measurement, redrawn when the pairing screen goes away.
+    latest_measurement: Option<(u16, f32, f32, AirQuality)>,
+
+    /// The screen on display.
+    screen: Screen,
+
+    /// When to leave the status screen, in milliseconds since boot.
+    status_until_ms: Option<u64>,
+
+    /// The measurement history, shared with the BLE server.
+    history: Arc<Mutex<History>>,
+
+    /// The health check of a newly installed firmware image.
+    health: Option<HealthCheck>,
+
+    /// The InfluxDB exporter, while a write URL is configured.
+    influx: Option<InfluxExporter>,
+
+    /// The MQTT publisher, once Wi-Fi is up and a broker is configured.
+    mqtt: Option<MqttPublisher>,
+
+    /// The firmware updater, shared with the BLE server.
+    ota: Option<Arc<Mutex<OtaUpdater<FlashPartition>>>>,
+
     /// The SCD-41 sensor.
     sensor: Scd41Sensor<'a>,
+
+    /// Failed sensor reads since boot.
+    sensor_errors: SensorErrorCounts,
+
+    /// The persistent settings.
+    settings: Option<Settings>,
+
+    /// The SNTP client, once Wi-Fi is up and a server is configured.
+    sntp: Option<TimeSync>,
+
+    /// The web server, while Wi-Fi is set up.
+    web: Option<WebServer>,
+
+    /// The Wi-Fi station.
+    wifi: Option<WifiManager>,
+
+    /// The Wi-Fi connectivity last shown and published.
+    wifi_status: WifiStatus,
 }
 
 /// The device manager implementation.
@@ -36,9 +211,53 @@ impl<'a> DeviceManager<'a> {
 
         let config = I2cConfig::default().baudrate(Hertz(100000));
 
-        let mut led = esp_idf_svc::hal::gpio::PinDriver::output(peripherals.pins.gpio8)?;
-        led.set_low()?;
-        std::mem::forget(led);
+        // Onboard WS2812 status LED
+        let led = TxRmtDriver::new(
+            peripherals.rmt.channel0,
+            peripherals.pins.gpio8,
+            &TransmitConfig::new().clock_divider(1),
+        )
+        .map_err(|e| {
+            AppError::PeripheralsError(format!("Failed to initialize status LED: {:?}", e))
+        })?;
+
+        // Passive buzzer on the alarm output pin
+        #[cfg(feature = "buzzer")]
+        let (buzzer, mut alarm_sinks): (_, Vec<Box<dyn AlarmSink + 'a>>) = {
+            let timer = LedcTimerDriver::new(
+                peripherals.ledc.timer0,
+                &TimerConfig::new().frequency(Hertz(BUZZER_FREQUENCY_HZ)),
+            )?;
+            let buzzer = LedcDriver::new(peripherals.ledc.channel0, timer, peripherals.pins.gpio10)
+                .map_err(|e| {
+                    AppError::PeripheralsError(format!("Failed to initialize buzzer: {:?}", e))
+                })?;
+
+            (Some(buzzer), Vec::new())
+        };
+
+        // On/off alarm output (active buzzer or LED, active high)
+        #[cfg(not(feature = "buzzer"))]
+        let (buzzer, mut alarm_sinks): (Option<LedcDriver>, Vec<Box<dyn AlarmSink + 'a>>) = {
+            let alarm_output = PinDriver::output(peripherals.pins.gpio10.downgrade_output())?;
+
+            (None, vec![Box::new(GpioAlarmOutput::new(alarm_output))])
+        };
+
+        // BOOT button, active low
+        let mut boot_button = PinDriver::input(peripherals.pins.gpio9)?;
+        boot_button.set_pull(Pull::Up)?;
+
+        #[cfg(feature = "battery")]
+        let battery = BatteryMonitor::new(peripherals.adc1, peripherals.pins.gpio3)?;
+
+        let indicator = StatusIndicator::new(led, buzzer)?;
+        alarm_sinks.push(Box::new(indicator.clone()));
+
+        let bthome_bindkey = BTHOME_BINDKEY
+            .map(parse_bindkey)
+            .transpose()
+            .map_err(|e| AppError::ConfigError(format!("Invalid BTHOME_BINDKEY: {e}")))?;
 
         let i2c = Rc::new(RefCell::new(
             I2cDriver::new(
@@ -50,36 +269,168 @@ impl<'a> DeviceManager<'a> {
             .map_err(|e| AppError::I2cError(format!("Failed to initialize I2C: {:?}", e)))?,
         ));
 
+        let nvs = match EspNvsPartition::<NvsDefault>::take() {
+            Ok(nvs) => Some(nvs),
+            Err(e) => {
+                error!("Failed to initialize NVS partition: {:?}", e);
+                None
+            }
+        };
+
+        let settings = nvs.clone().and_then(|nvs| match Settings::new(nvs) {
+            Ok(settings) => Some(settings),
+            Err(e) => {
+                error!("Failed to open settings: {:?}", e);
+                None
+            }
+        });
+        let orientation = settings
+            .as_ref()
+            .map(Settings::orientation)
+            .unwrap_or_default();
+        let config = settings.as_ref().map(Settings::config).unwrap_or_default();
+
+        let alarm = AlarmEngine::new(AlarmConfig {
+            rising_threshold: config.alarm_rising_ppm,
+            falling_threshold: config.alarm_falling_ppm,
+            ..AlarmConfig::default()
+        })
+        .map_err(|e| AppError::ConfigError(format!("Invalid alarm configuration: {e}")))?;
+        let bthome_counter = bthome_bindkey.and(settings.as_ref()).and_then(|settings| {
+            match settings.bthome_counter() {
+                Ok(persisted) => Some(FrameCounter::resume(persisted)),
+                Err(e) => {
+                    error!("BTHome broadcasts disabled: {:?}", e);
+                    None
+                }
+            }
+        });
+
         // Initialize display
         let mut display = Ssd1306Display::new(Rc::clone(&i2c))?;
         display.init()?;
-        display.clear()?;
+        display.set_orientation(orientation)?;
+        display.set_brightness(config.display_brightness)?;
+        indicator.set_brightness(config.led_brightness);
 
-        // Initialize sensor
+        // Initialize sensor; it may still be measuring if only the ESP32 was reset
         let mut sensor = Scd41Sensor::new(Rc::clone(&i2c))?;
+        sensor.stop_periodic_measurement()?;
+        let serial_number = match sensor.get_serial_number() {
+            Ok(serial_number) => format_serial_number(serial_number),
+            Err(e) => {
+                error!("Failed to read sensor serial number: {:?}", e);
+                String::new()
+            }
+        };
+        configure_sensor(&mut sensor, &config)?;
         sensor.start_periodic_measurement()?;
+        let device_info = device_info(serial_number);
         info!("Sensor and display ready!");
 
+        let history = Arc::new(Mutex::new(History::new(
+            HISTORY_CAPACITY,
+            HISTORY_INTERVAL_S,
+        )));
+        let clock = Arc::new(Mutex::new(SystemClock::default()));
+        clock
+            .lock()
+            .unwrap()
+            .set_time_zone(TimeZone::parse(&config.time_zone));
+        let ota = match FlashPartition::next_update() {
+            Ok(partition) => Some(Arc::new(Mutex::new(OtaUpdater::new(partition)))),
+            Err(e) => {
+                error!("Firmware updates disabled: {:?}", e);
+                None
+            }
+        };
+
+        // BLE and Wi-Fi share the radio, see CONFIG_ESP_COEX_SW_COEXIST_ENABLE
+        let (wifi_modem, bt_modem) = peripherals.modem.split();
+
         // Initialize BLE if available
-        let ble = if let Ok(nvs) = EspNvsPartition::<NvsDefault>::take() {
-            match BleServer::new(peripherals.modem, Some(nvs)) {
-                Ok(server) => Some(server),
+        let ble = if let Some(nvs) = nvs.clone() {
+            match BleServer::new(bt_modem, Some(nvs)) {
+                Ok(server) => {
+                    server.set_orientation(orientation);
+                    server.set_config(config.clone());
+                    server.set_history(Arc::clone(&history));
+                    server.set_clock(Arc::clone(&clock));
+                    if let Some(ota) = &ota {
+                        server.set_ota(Arc::clone(ota));
+                    }
+                    server.set_device_info(device_info.clone());
+                    server.set_device_name(&config.device_name)?;
+                    if let Some(bindkey) = bthome_bindkey {
+                        server.set_bthome_bindkey(bindkey)?;
+                    }
+                    Some(server)
+                }
                 Err(e) => {
                     error!("Failed to initialize BLE: {:?}", e);
                     None
                 }
             }
         } else {
-            error!("Failed to initialize NVS partition");
             None
         };
         info!("BLE server ready!");
 
-        Ok(Self {
+        // Initialize Wi-Fi, opening the setup portal if no network is stored
+        let wifi_credentials = settings.as_ref().and_then(Settings::wifi_credentials);
+        let wifi = nvs.and_then(
+            |nvs| match WifiManager::new(wifi_modem, nvs, wifi_credentials) {
+                Ok(wifi) => Some(wifi),
+                Err(e) => {
+                    error!("Failed to initialize Wi-Fi: {:?}", e);
+                    None
+                }
+            },
+        );
+
+        // A new image must take a measurement with BLE up, or the previous one
+        // is restored.
+        let health = ota::pending_verify().then(|| {
+            info!("Running a new firmware image, checking its health");
+            HealthCheck::new(uptime_ms(), ble.is_some())
+        });
+
+        let mut manager = Self {
+            alarm,
+            alarm_sinks,
+            #[cfg(feature = "battery")]
+            battery,
             ble,
+            boot_button,
+            boot_button_held_ms: 0,
+            bthome_counter,
+            indicator,
+            classifier: Classifier::default(),
+            clock,
+            clock_label: String::new(),
+            brightness: config.display_brightness,
+            latest_measurement: None,
+            screen: Screen::Measurements,
+            status_until_ms: None,
+            config,
+            device_info,
             display,
+            history,
+            health,
+            influx: None,
+            mqtt: None,
+            ota,
             sensor,
-        })
+            sensor_errors: SensorErrorCounts::default(),
+            settings,
+            sntp: None,
+            web: None,
+            wifi,
+            wifi_status: WifiStatus::Off,
+        };
+        manager.start_influx();
+
+        Ok(manager)
     }
 
     /// Update the device manager.
@@ -87,34 +438,859 @@ impl<'a> DeviceManager<'a> {
     /// # Returns
     /// The result of the operation.
     pub fn update(&mut self) -> Result<(), AppError> {
-        match self.sensor.read_measurement() {
+        if let Some(config) = self.ble.as_ref().and_then(BleServer::take_config_request) {
+            self.apply_config(config);
+        }
+
+        if let Some(config) = self.web.as_ref().and_then(WebServer::take_config_request) {
+            self.apply_config(config);
+        }
+
+        if let Some(orientation) = self
+            .ble
+            .as_ref()
+            .and_then(BleServer::take_orientation_request)
+        {
+            self.apply_orientation(orientation);
+        }
+
+        if let Some(command) = self.ble.as_ref().and_then(BleServer::take_alarm_command) {
+            if let Some(state) = self.alarm.command(uptime_ms(), command) {
+                self.dispatch_alarm(state);
+            }
+        }
+
+        self.apply_time();
+
+        // Maintenance requests run here, between measurements, so they never
+        // interleave with other sensor or display traffic on the I2C bus.
+        if let Some(request) = self.ble.as_ref().and_then(BleServer::take_control_request) {
+            self.run_control_request(request);
+        }
+
+        let measurement = self.sensor.read_measurement();
+        self.check_health(measurement.is_ok());
+
+        match measurement {
             Ok((co2, temp_value, humidity_value)) => {
+                let air_quality = self.classifier.classify(co2);
+                self.indicator.set_air_quality(air_quality);
+                if let Some(state) = self.alarm.update(uptime_ms(), co2) {
+                    self.dispatch_alarm(state);
+                }
+
                 info!(
-                    "CO2: {} ppm, Temperature: {:.2} °C, Humidity: {:.2} %",
-                    co2, temp_value, humidity_value
+                    "CO2: {} ppm ({}), Temperature: {:.2} °C, Humidity: {:.2} %",
+                    co2,
+                    air_quality.label(),
+                    temp_value,
+                    humidity_value
                 );
 
-                if let Err(e) = self
-                    .display
-                    .draw_measurements(co2, temp_value, humidity_value)
-                {
-                    error!("Failed to update display: {:?}", e);
+                self.latest_measurement = Some((co2, temp_value, humidity_value, air_quality));
+                self.redraw();
+
+                let temperature = (temp_value * 100.0).round() as i16;
+                let humidity = (humidity_value * 100.0).round() as u16;
+                let uptime_s = (uptime_ms() / 1000) as u32;
+                self.history
+                    .lock()
+                    .unwrap()
+                    .record(uptime_s, co2, temperature, humidity);
+
+                let now_ms = uptime_ms();
+                let timestamp_s = self
+                    .clock
+                    .lock()
+                    .unwrap()
+                    .unix_ms(now_ms)
+                    .map(|ms| ms / 1000);
+                if let Some(web) = &self.web {
+                    web.set_current(Current {
+                        co2,
+                        temperature,
+                        humidity,
+                        air_quality,
+                        uptime_s: now_ms / 1000,
+                        timestamp_s,
+                    });
+                }
+
+                if let Some(influx) = &self.influx {
+                    influx.push(Point {
+                        uptime_ms: uptime_ms(),
+                        co2,
+                        temperature,
+                        humidity,
+                        air_quality,
+                    });
+                }
+
+                if let Some(mqtt) = &mut self.mqtt {
+                    mqtt.publish(&StatePayload {
+                        co2,
+                        temperature,
+                        humidity,
+                        air_quality,
+                        uptime_s: now_ms / 1000,
+                        timestamp_s,
+                        serial: &self.device_info.serial_number,
+                    });
                 }
 
+                let bthome_counter = self.next_bthome_counter();
                 if let Some(ble_server) = &self.ble {
                     ble_server.update_values(
-                        (temp_value * 100.0).round() as i16,
-                        (humidity_value * 100.0).round() as u16,
+                        temperature,
+                        humidity,
                         co2,
+                        air_quality,
+                        bthome_counter,
                     );
                 }
             }
             Err(e) => {
-                error!("Failed to read measurements: {:?}", e);
-                let _ = self.display.draw_error("Sensor Error");
+                error!("Failed to read measurements: {:?}", e.error);
+                self.sensor_errors.record(e.kind);
+                if self.screen == Screen::Measurements {
+                    let _ = self.display.draw_error("Sensor Error");
+                }
             }
         }
 
+        self.publish_status();
+
+        #[cfg(feature = "battery")]
+        match self.battery.read_level() {
+            Ok(level) => {
+                if let Some(ble_server) = &self.ble {
+                    ble_server.update_battery_level(level);
+                }
+            }
+            Err(e) => error!("Failed to read battery level: {:?}", e),
+        }
+
         Ok(())
     }
+
+    /// Wait for the next measurement while keeping the display, the BOOT button
+    /// and BLE connection management responsive.
+    ///
+    /// # Parameters
+    /// - `duration_ms`: How long to wait in milliseconds.
+    pub fn idle(&mut self, duration_ms: u32) {
+        let mut remaining_ms = duration_ms;
+        while remaining_ms > 0 {
+            let step_ms = remaining_ms.min(POLL_INTERVAL_MS);
+            FreeRtos::delay_ms(step_ms);
+            remaining_ms -= step_ms;
+            self.poll();
+        }
+    }
+
+    /// Close idle BLE connections, handle the BOOT button and switch screens.
+    ///
+    /// A short press of the BOOT button shows the status screen; holding it
+    /// forgets all bonded peers. A pairing in progress takes over the display.
+    fn poll(&mut self) {
+        let now_ms = uptime_ms();
+
+        if let Some(ble_server) = &self.ble {
+            ble_server.disconnect_idle();
+            ble_server.write_ota_chunks();
+        }
+        if self.ble.as_ref().is_some_and(BleServer::take_ota_finish) {
+            self.finish_ota();
+        }
+
+        self.poll_wifi(now_ms);
+        self.poll_mqtt();
+        self.poll_clock(now_ms);
+
+        if self.boot_button.is_low() {
+            self.boot_button_held_ms += POLL_INTERVAL_MS;
+            if self.boot_button_held_ms == CLEAR_BONDS_HOLD_MS {
+                match self.clear_bonds() {
+                    Ok(count) => info!("Forgot {} bonded peers", count),
+                    Err(e) => error!("Failed to clear bonds: {:?}", e),
+                }
+            }
+        } else {
+            if (1..CLEAR_BONDS_HOLD_MS).contains(&self.boot_button_held_ms) {
+                self.status_until_ms = Some(now_ms + STATUS_SCREEN_MS);
+            }
+            self.boot_button_held_ms = 0;
+        }
+
+        if self
+            .status_until_ms
+            .is_some_and(|until_ms| now_ms >= until_ms)
+        {
+            self.status_until_ms = None;
+        }
+
+        let pairing = self.ble.as_ref().and_then(BleServer::pairing_screen);
+        let screen = match (pairing, self.status_until_ms) {
+            (Some(pairing), _) => Screen::Pairing(pairing),
+            (None, Some(_)) => Screen::Status,
+            (None, None) => Screen::Measurements,
+        };
+        if screen != self.screen {
+            self.screen = screen;
+            self.redraw();
+        }
+    }
+
+    /// Draw the current screen.
+    fn redraw(&mut self) {
+        let result = match self.screen {
+            Screen::Measurements => match self.latest_measurement {
+                Some((co2, temperature, humidity, air_quality)) => {
+                    self.display
+                        .draw_measurements(co2, temperature, humidity, air_quality)
+                }
+                None => Ok(()),
+            },
+            Screen::Pairing(pairing) => self.display.draw_pairing(pairing),
+            Screen::Status => {
+                let lines = self.status_lines();
+                self.display.draw_status(&lines)
+            }
+        };
+
+        if let Err(e) = result {
+            error!("Failed to update display: {:?}", e);
+        }
+    }
+
+    /// Collect the lines of the status screen.
+    ///
+    /// # Returns
+    /// The lines.
+    fn status_lines(&self) -> Vec<String> {
+        let mut lines = match &self.ble {
+            Some(ble_server) => ble_server.connection_stats().status_lines(),
+            None => vec!["BLE off".to_string()],
+        };
+        lines.extend(self.wifi_status.status_lines());
+
+        lines
+    }
+
+    /// Apply Wi-Fi credentials from the setup portal or BLE, keep the station
+    /// connected and show connectivity changes.
+    ///
+    /// # Parameters
+    /// - `now_ms`: The current time in milliseconds since boot.
+    fn poll_wifi(&mut self, now_ms: u64) {
+        let Some(wifi) = &mut self.wifi else {
+            return;
+        };
+
+        let requested = wifi
+            .take_portal_credentials()
+            .map(Some)
+            .or_else(|| self.ble.as_ref().and_then(BleServer::take_wifi_credentials));
+        if let Some(credentials) = requested {
+            if credentials.is_none() {
+                // The setup portal takes over the HTTP port.
+                self.web = None;
+            }
+            apply_wifi_credentials(wifi, self.settings.as_mut(), credentials);
+        }
+
+        wifi.poll(now_ms);
+
+        let status = wifi.status();
+        if status == self.wifi_status {
+            return;
+        }
+        info!("Wi-Fi status: {:?}", status);
+
+        if let Some(ble_server) = &self.ble {
+            ble_server.set_wifi_status(&status);
+        }
+        self.display.set_wifi_icon(status.icon());
+        self.wifi_status = status;
+        if let Some(influx) = &self.influx {
+            influx.set_online(matches!(self.wifi_status, WifiStatus::Connected { .. }));
+        }
+        if self.mqtt.is_none() {
+            self.start_mqtt();
+        }
+        if self.sntp.is_none() {
+            self.start_sntp();
+        }
+        if self.web.is_none() && matches!(self.wifi_status, WifiStatus::Connected { .. }) {
+            self.start_web();
+        }
+        self.publish_status();
+        self.redraw();
+    }
+
+    /// Start the web server.
+    fn start_web(&mut self) {
+        match WebServer::new(
+            self.config.clone(),
+            Arc::clone(&self.history),
+            Arc::clone(&self.clock),
+        ) {
+            Ok(web) => {
+                self.web = Some(web);
+                self.publish_status();
+            }
+            Err(e) => error!("Failed to start web server: {:?}", e),
+        }
+    }
+
+    /// Update the identity, connectivity and metrics reported by the web
+    /// server.
+    fn publish_status(&self) {
+        let Some(web) = &self.web else {
+            return;
+        };
+
+        let latest = self.latest_measurement;
+        web.set_metrics(Metrics {
+            device_name: self.config.device_name.clone(),
+            serial: self.device_info.serial_number.clone(),
+            firmware: self.device_info.firmware_revision.clone(),
+            co2: latest.map(|(co2, ..)| co2),
+            temperature: latest.map(|(_, temp_value, ..)| (temp_value * 100.0).round() as i16),
+            humidity: latest.map(|(.., humidity_value, _)| (humidity_value * 100.0).round() as u16),
+            sensor_errors: self.sensor_errors,
+            ble: self
+                .ble
+                .as_ref()
+                .map(BleServer::connection_stats)
+                .unwrap_or_default(),
+            uptime_s: 0,
+            free_heap: 0,
+        });
+
+        web.set_status(Status {
+            device_name: self.config.device_name.clone(),
+            serial: self.device_info.serial_number.clone(),
+            firmware: self.device_info.firmware_revision.clone(),
+            uptime_s: 0,
+            free_heap: 0,
+            unix_time_s: None,
+            wifi: self.wifi_status.clone(),
+            mqtt_connected: self.mqtt.as_ref().is_some_and(MqttPublisher::is_connected),
+            alarm: self.alarm.state(),
+        });
+    }
+
+    /// Announce the device to Home Assistant after every MQTT connection.
+    fn poll_mqtt(&mut self) {
+        if let Some(mqtt) = &mut self.mqtt {
+            mqtt.poll(&DiscoveryDevice {
+                name: &self.config.device_name,
+                manufacturer: &self.device_info.manufacturer_name,
+                model: &self.device_info.model_number,
+                serial: &self.device_info.serial_number,
+                sw_version: &self.device_info.firmware_revision,
+            });
+        }
+    }
+
+    /// Show the time on the status line, or the uptime until the clock is set.
+    ///
+    /// # Parameters
+    /// - `now_ms`: The current time in milliseconds since boot.
+    fn poll_clock(&mut self, now_ms: u64) {
+        let label = self.clock.lock().unwrap().label(now_ms);
+        if label == self.clock_label {
+            return;
+        }
+
+        self.display.set_clock(Some(label.clone()));
+        self.clock_label = label;
+        if self.screen == Screen::Measurements {
+            self.redraw();
+        }
+    }
+
+    /// Start the InfluxDB exporter if a write URL is configured, replacing any
+    /// previous one.
+    fn start_influx(&mut self) {
+        self.influx = None;
+        if self.config.influx_url.is_empty() {
+            return;
+        }
+
+        match InfluxExporter::new(
+            &self.config,
+            &self.device_info.serial_number,
+            Arc::clone(&self.clock),
+        ) {
+            Ok(influx) => {
+                influx.set_online(matches!(self.wifi_status, WifiStatus::Connected { .. }));
+                self.influx = Some(influx);
+            }
+            Err(e) => error!("Failed to start InfluxDB exporter: {:?}", e),
+        }
+    }
+
+    /// Connect to the configured MQTT broker, replacing any previous client.
+    ///
+    /// The client reconnects on its own, so this only runs once Wi-Fi first
+    /// connects and when the broker or topic prefix change.
+    fn start_mqtt(&mut self) {
+        self.mqtt = None;
+        if self.config.mqtt_url.is_empty()
+            || !matches!(self.wifi_status, WifiStatus::Connected { .. })
+        {
+            return;
+        }
+
+        match MqttPublisher::new(&self.config, &self.device_info.serial_number) {
+            Ok(mqtt) => self.mqtt = Some(mqtt),
+            Err(e) => error!("Failed to start MQTT: {:?}", e),
+        }
+    }
+
+    /// Synchronize the clock with the configured SNTP server, replacing any
+    /// previous client.
+    ///
+    /// The client polls on its own, so this only runs once Wi-Fi first connects
+    /// and when the server changes.
+    fn start_sntp(&mut self) {
+        self.sntp = None;
+        if self.config.ntp_server.is_empty()
+            || !matches!(self.wifi_status, WifiStatus::Connected { .. })
+        {
+            return;
+        }
+
+        match TimeSync::new(&self.config.ntp_server, Arc::clone(&self.clock)) {
+            Ok(sntp) => self.sntp = Some(sntp),
+            Err(e) => error!("Failed to start time synchronization: {:?}", e),
+        }
+    }
+
+    /// Forget all bonded peers.
+    ///
+    /// # Returns
+    /// The number of bonds removed.
+    fn clear_bonds(&self) -> Result<usize, AppError> {
+        self.ble.as_ref().map_or(Ok(0), BleServer::clear_bonds)
+    }
+
+    /// Get the measurement interval.
+    ///
+    /// # Returns
+    /// The interval in milliseconds.
+    pub fn measurement_interval_ms(&self) -> u32 {
+        self.config.measurement_interval_s as u32 * 1000
+    }
+
+    /// Apply the system clock, which clients may have set since the last update, to
+    /// the history timestamps and the display brightness.
+    fn apply_time(&mut self) {
+        let clock = *self.clock.lock().unwrap();
+        let now_ms = uptime_ms();
+
+        if let Some(boot_time_s) = clock.boot_time_s(now_ms) {
+            let boot_time_s = u32::try_from(boot_time_s).unwrap_or_default();
+            self.history.lock().unwrap().set_time_base(boot_time_s);
+        }
+
+        let night = clock
+            .local(now_ms)
+            .is_some_and(|(local, _)| self.config.is_night(local.minute_of_day()));
+        let brightness = if night {
+            self.config.night_brightness
+        } else {
+            self.config.display_brightness
+        };
+
+        if brightness != self.brightness {
+            match self.display.set_brightness(brightness) {
+                Ok(()) => self.brightness = brightness,
+                Err(e) => error!("Failed to set display brightness: {:?}", e),
+            }
+        }
+    }
+
+    /// Take the next BTHome frame counter, persisting a new reservation first if needed.
+    ///
+    /// # Returns
+    /// The counter, or `None` if broadcasts are unencrypted or the reservation could
+    /// not be persisted.
+    fn next_bthome_counter(&mut self) -> Option<u32> {
+        let settings = self.settings.as_mut()?;
+
+        match self
+            .bthome_counter
+            .as_mut()?
+            .take(|reservation| settings.set_bthome_counter(reservation))
+        {
+            Ok(counter) => Some(counter),
+            Err(e) => {
+                error!("Failed to persist BTHome frame counter: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Send an alarm state change to every alarm sink.
+    ///
+    /// # Parameters
+    /// - `state`: The new alarm state.
+    fn dispatch_alarm(&mut self, state: AlarmState) {
+        info!("Alarm state changed to {:?}", state);
+
+        self.display.on_alarm(state);
+
+        if let Some(ble_server) = &mut self.ble {
+            ble_server.on_alarm(state);
+        }
+
+        for sink in self.alarm_sinks.iter_mut() {
+            sink.on_alarm(state);
+        }
+    }
+
+    /// Apply and persist a new runtime configuration.
+    ///
+    /// # Parameters
+    /// - `config`: The configuration.
+    fn apply_config(&mut self, config: Config) {
+        info!("Applying configuration {:?}", config);
+
+        if (config.temperature_offset, config.altitude_m)
+            != (self.config.temperature_offset, self.config.altitude_m)
+        {
+            let result = self
+                .sensor
+                .stop_periodic_measurement()
+                .and_then(|_| configure_sensor(&mut self.sensor, &config))
+                .and_then(|_| self.sensor.start_periodic_measurement());
+            if let Err(e) = result {
+                error!("Failed to configure sensor: {:?}", e);
+            }
+        }
+
+        if (config.alarm_rising_ppm, config.alarm_falling_ppm)
+            != (self.config.alarm_rising_ppm, self.config.alarm_falling_ppm)
+        {
+            let alarm_config = AlarmConfig {
+                rising_threshold: config.alarm_rising_ppm,
+                falling_threshold: config.alarm_falling_ppm,
+                ..self.alarm.config()
+            };
+            match self.alarm.set_config(alarm_config) {
+                Ok(Some(state)) => self.dispatch_alarm(state),
+                Ok(None) => (),
+                Err(e) => error!("Invalid alarm configuration: {e}"),
+            }
+        }
+
+        if config.led_brightness != self.config.led_brightness {
+            self.indicator.set_brightness(config.led_brightness);
+        }
+
+        if config.device_name != self.config.device_name {
+            if let Some(ble_server) = &self.ble {
+                if let Err(e) = ble_server.set_device_name(&config.device_name) {
+                    error!("Failed to set device name: {:?}", e);
+                }
+            }
+        }
+
+        if let Some(settings) = &mut self.settings {
+            if let Err(e) = settings.set_config(&config) {
+                error!("Failed to persist configuration: {:?}", e);
+            }
+        }
+
+        let restart_mqtt = (&config.mqtt_url, &config.mqtt_prefix)
+            != (&self.config.mqtt_url, &self.config.mqtt_prefix);
+        let restart_influx = config.influx_url != self.config.influx_url;
+        let restart_sntp = config.ntp_server != self.config.ntp_server;
+        if config.time_zone != self.config.time_zone {
+            self.clock
+                .lock()
+                .unwrap()
+                .set_time_zone(TimeZone::parse(&config.time_zone));
+        }
+        let rename = config.device_name != self.config.device_name;
+        if let Some(ble_server) = &self.ble {
+            ble_server.set_config(config.clone());
+        }
+        if let Some(web) = &self.web {
+            web.set_config(config.clone());
+        }
+        self.config = config;
+        if restart_mqtt {
+            self.start_mqtt();
+        } else if let Some(mqtt) = &mut self.mqtt {
+            mqtt.set_options(&self.config);
+            if rename {
+                mqtt.announce();
+            }
+        }
+        if restart_influx {
+            self.start_influx();
+        } else if let Some(influx) = &self.influx {
+            influx.set_target(&self.config, &self.device_info.serial_number);
+        }
+        if restart_sntp {
+            self.start_sntp();
+        }
+        self.apply_time();
+    }
+
+    /// Run a control point request and indicate the result.
+    ///
+    /// # Parameters
+    /// - `request`: The request.
+    fn run_control_request(&mut self, request: ControlRequest) {
+        info!("Running control point request {:?}", request);
+
+        let response = match request {
+            ControlRequest::ForcedRecalibration { target_ppm } => self
+                .with_idle_sensor(|sensor| sensor.perform_forced_recalibration(target_ppm))
+                .map(|correction| {
+                    info!("Forced recalibration applied {} ppm", correction);
+                    ControlResponse::success(request, &correction.to_le_bytes())
+                }),
+            ControlRequest::SelfTest => self
+                .with_idle_sensor(|sensor| sensor.perform_self_test())
+                .map(|status| {
+                    if status == 0 {
+                        ControlResponse::success(request, &status.to_le_bytes())
+                    } else {
+                        error!("Sensor self-test failed: 0x{:04x}", status);
+                        ControlResponse {
+                            parameter: status.to_le_bytes().to_vec(),
+                            ..ControlResponse::failure(
+                                request.opcode(),
+                                ResultCode::OperationFailed,
+                            )
+                        }
+                    }
+                }),
+            ControlRequest::FactoryReset => self
+                .factory_reset()
+                .map(|_| ControlResponse::success(request, &[])),
+            ControlRequest::SetAutomaticSelfCalibration { enabled } => self
+                .with_idle_sensor(|sensor| {
+                    sensor.set_automatic_self_calibration(enabled)?;
+                    sensor.persist_settings()
+                })
+                .map(|_| ControlResponse::success(request, &[])),
+            ControlRequest::Reboot => Ok(ControlResponse::success(request, &[])),
+            ControlRequest::ClearBonds => self.clear_bonds().map(|count| {
+                info!("Forgot {} bonded peers", count);
+                ControlResponse::success(request, &[])
+            }),
+        };
+
+        let response = response.unwrap_or_else(|e| {
+            error!("Control point request {:?} failed: {:?}", request, e);
+            ControlResponse::failure(request.opcode(), ResultCode::OperationFailed)
+        });
+
+        if let Some(ble_server) = &self.ble {
+            ble_server.send_control_response(response);
+        }
+
+        if request == ControlRequest::Reboot {
+            info!("Rebooting on request");
+            FreeRtos::delay_ms(REBOOT_DELAY_MS);
+            restart();
+        }
+    }
+
+    /// Verify the received firmware image, boot it and indicate the result.
+    fn finish_ota(&mut self) {
+        let result = match &self.ota {
+            Some(ota) => {
+                let mut updater = ota.lock().unwrap();
+                match updater.finish() {
+                    Ok(()) => updater.partition().activate().map_err(|e| {
+                        error!("Failed to activate firmware image: {:?}", e);
+                        OtaResult::OperationFailed
+                    }),
+                    Err(e) => {
+                        error!("Firmware image rejected: {}", e);
+                        Err(e.result())
+                    }
+                }
+            }
+            None => Err(OtaResult::NotStarted),
+        };
+
+        let response = match result {
+            Ok(()) => OtaResponse::success(OtaRequest::Finish, None),
+            Err(code) => OtaResponse::failure(OtaRequest::Finish.opcode(), code),
+        };
+        if let Some(ble_server) = &self.ble {
+            ble_server.send_ota_response(response);
+        }
+
+        if result.is_ok() {
+            info!("Rebooting into the new firmware image");
+            FreeRtos::delay_ms(REBOOT_DELAY_MS);
+            restart();
+        }
+    }
+
+    /// Confirm or roll back a newly installed firmware image after a measurement.
+    ///
+    /// # Parameters
+    /// - `success`: Whether the measurement succeeded.
+    fn check_health(&mut self, success: bool) {
+        let Some(health) = &mut self.health else {
+            return;
+        };
+        health.on_measurement(success);
+
+        match health.verdict(uptime_ms()) {
+            HealthVerdict::Pending => (),
+            HealthVerdict::Healthy => {
+                self.health = None;
+                match ota::mark_valid() {
+                    Ok(()) => info!("Firmware image confirmed"),
+                    Err(e) => error!("{}", e),
+                }
+            }
+            HealthVerdict::Unhealthy => {
+                error!("Firmware image failed its health check, rolling back");
+                let e = ota::rollback();
+                error!("{}", e);
+                self.health = None;
+            }
+        }
+    }
+
+    /// Reset the sensor to its factory settings and the device to the default
+    /// configuration and orientation. The BTHome frame counter is kept, since
+    /// reusing counter values would break encryption.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    fn factory_reset(&mut self) -> Result<(), AppError> {
+        let defaults = Config::default();
+
+        self.with_idle_sensor(|sensor| {
+            sensor.perform_factory_reset()?;
+            configure_sensor(sensor, &defaults)
+        })?;
+
+        // The sensor already uses the default offset and altitude.
+        self.config.temperature_offset = defaults.temperature_offset;
+        self.config.altitude_m = defaults.altitude_m;
+
+        if let Some(ble_server) = &self.ble {
+            ble_server.set_config(defaults.clone());
+        }
+        self.apply_config(defaults);
+        self.apply_orientation(Orientation::default());
+
+        Ok(())
+    }
+
+    /// Run an operation that requires the sensor to be idle, then resume periodic
+    /// measurement.
+    ///
+    /// # Parameters
+    /// - `operation`: The operation.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    fn with_idle_sensor<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Scd41Sensor<'a>) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        self.sensor.stop_periodic_measurement()?;
+        let result = operation(&mut self.sensor);
+        self.sensor.start_periodic_measurement()?;
+
+        result
+    }
+
+    /// Apply and persist a new display orientation.
+    ///
+    /// # Parameters
+    /// - `orientation`: The orientation.
+    fn apply_orientation(&mut self, orientation: Orientation) {
+        info!("Changing display orientation to {:?}", orientation);
+
+        if let Err(e) = self.display.set_orientation(orientation) {
+            error!("Failed to set display orientation: {:?}", e);
+            return;
+        }
+
+        if let Some(ble_server) = &self.ble {
+            ble_server.set_orientation(orientation);
+        }
+
+        if let Some(settings) = &mut self.settings {
+            if let Err(e) = settings.set_orientation(orientation) {
+                error!("Failed to persist display orientation: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Persist new Wi-Fi credentials and join the network.
+///
+/// # Parameters
+/// - `wifi`: The Wi-Fi station.
+/// - `settings`: The persistent settings, if available.
+/// - `credentials`: The network to join, `None` to forget it.
+fn apply_wifi_credentials(
+    wifi: &mut WifiManager,
+    settings: Option<&mut Settings>,
+    credentials: Option<Credentials>,
+) {
+    if let Some(settings) = settings {
+        if let Err(e) = settings.set_wifi_credentials(credentials.as_ref()) {
+            error!("Failed to persist Wi-Fi credentials: {:?}", e);
+        }
+    }
+
+    if let Err(e) = wifi.set_credentials(credentials) {
+        error!("Failed to apply Wi-Fi credentials: {:?}", e);
+    }
+}
+
+/// Write the temperature offset and altitude to an idle sensor.
+///
+/// # Parameters
+/// - `sensor`: The sensor.
+/// - `config`: The configuration.
+///
+/// # Returns
+/// The result of the operation.
+fn configure_sensor(sensor: &mut Scd41Sensor, config: &Config) -> Result<(), AppError> {
+    sensor.set_temperature_offset(config.temperature_offset as f32 / 100.0)?;
+    sensor.set_sensor_altitude(config.altitude_m)
+}
+
+/// Collect the identification strings for the Device Information Service.
+///
+/// # Parameters
+/// - `serial_number`: The sensor serial number.
+///
+/// # Returns
+/// The identification strings.
+fn device_info(serial_number: String) -> DeviceInfo {
+    let mut chip_info = esp_chip_info_t::default();
+    unsafe { esp_chip_info(&mut chip_info) };
+
+    DeviceInfo {
+        manufacturer_name: MANUFACTURER_NAME.to_string(),
+        model_number: env!("CARGO_PKG_NAME").to_string(),
+        serial_number,
+        hardware_revision: format!(
+            "ESP32-C3 v{}.{}",
+            chip_info.revision / 100,
+            chip_info.revision % 100
+        ),
+        firmware_revision: env!("CARGO_PKG_VERSION").to_string(),
+    }
 }
@@ -1,11 +1,36 @@
-use crate::{error::AppError, font::FONT_6X8};
+use crate::error::AppError;
 use esp_idf_svc::hal::i2c::I2cDriver;
+use scd41_core::{
+    air_quality::AirQuality,
+    alarm::{AlarmSink, AlarmState},
+    framebuffer::{Framebuffer, Orientation, PANEL_WIDTH},
+    layout::{layout, text_width, Align, TextBox},
+    pairing::PairingScreen,
+};
 use std::{cell::RefCell, rc::Rc};
 
-/// Display width.
-const DISPLAY_WIDTH: u8 = 128;
+/// Maximum number of data bytes sent in a single I2C transaction.
+const DATA_CHUNK_SIZE: usize = 16;
+
+/// Page of the air quality status line.
+const STATUS_PAGE: u8 = 6;
+
+/// Gap between the air quality icon and its label in pixels.
+const STATUS_ICON_GAP: u8 = 4;
+
+/// First page of the error message area.
+const ERROR_FIRST_PAGE: u8 = 2;
+
+/// First page of the pairing title.
+const PAIRING_TITLE_PAGE: u8 = 1;
+
+/// First page of the pairing passkey.
+const PAIRING_DETAIL_PAGE: u8 = 4;
 
 /// Initialization sequence.
+///
+/// Segment remap and COM scan direction are sent separately, see
+/// [`Orientation::remap_commands`].
 const INIT_SEQUENCE: &[u8] = &[
     0xae, // display off
     0xd5, // set display clock
@@ -19,8 +44,6 @@ const INIT_SEQUENCE: &[u8] = &[
     0x14, // enable charge pump
     0x20, // memory mode
     0x00, // horizontal addressing
-    0xa1, // segment remap
-    0xc8, // com scan direction
     0xda, // set com pins
     0x12, //
     0x81, // set contrast
@@ -39,6 +62,18 @@ const SSD1306_ADDRESS: u8 = 0x3d;
 
 /// SSD1306 display interface.
 pub struct Ssd1306Display<'a> {
+    /// The alarm state shown as a banner.
+    alarm: AlarmState,
+
+    /// The frame being drawn.
+    framebuffer: Framebuffer,
+
+    /// The Wi-Fi connectivity icon shown on the status line.
+    wifi_icon: Option<[u8; 8]>,
+
+    /// The time or uptime shown on the status line.
+    clock: Option<String>,
+
     /// The I2C driver.
     i2c: Rc<RefCell<I2cDriver<'a>>>,
 }
@@ -53,7 +88,13 @@ impl<'a> Ssd1306Display<'a> {
     /// # Returns
     /// The SSD1306 display.
     pub fn new(i2c: Rc<RefCell<I2cDriver<'a>>>) -> Result<Self, AppError> {
-        Ok(Self { i2c })
+        Ok(Self {
+            alarm: AlarmState::Armed,
+            framebuffer: Framebuffer::default(),
+            wifi_icon: None,
+            clock: None,
+            i2c,
+        })
     }
 
     /// Initialize the display.
@@ -67,21 +108,76 @@ impl<'a> Ssd1306Display<'a> {
             self.write_command(&mut i2c, cmd)?;
         }
 
+        for cmd in self.framebuffer.orientation().remap_commands() {
+            self.write_command(&mut i2c, cmd)?;
+        }
+
         Ok(())
     }
 
+    /// Get the display orientation.
+    ///
+    /// # Returns
+    /// The orientation.
+    pub fn orientation(&self) -> Orientation {
+        self.framebuffer.orientation()
+    }
+
+    /// Set the display orientation and clear the display.
+    ///
+    /// # Parameters
+    /// - `orientation`: The orientation.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_orientation(&mut self, orientation: Orientation) -> Result<(), AppError> {
+        self.framebuffer.set_orientation(orientation);
+
+        {
+            let mut i2c = self.i2c.borrow_mut();
+            for cmd in orientation.remap_commands() {
+                self.write_command(&mut i2c, cmd)?;
+            }
+        }
+
+        self.flush()
+    }
+
+    /// Set the display brightness (contrast).
+    ///
+    /// # Parameters
+    /// - `brightness`: The brightness, `0` (dimmest) to `255` (brightest).
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_brightness(&mut self, brightness: u8) -> Result<(), AppError> {
+        let mut i2c = self.i2c.borrow_mut();
+        self.write_command(&mut i2c, 0x81)?; // set contrast
+        self.write_command(&mut i2c, brightness)
+    }
+
     /// Clear the display.
     ///
     /// # Returns
     /// The result of the operation.
     pub fn clear(&mut self) -> Result<(), AppError> {
+        self.framebuffer.clear();
+        self.flush()
+    }
+
+    /// Write the framebuffer to the display.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    fn flush(&self) -> Result<(), AppError> {
+        let frame = self.framebuffer.to_panel();
         let mut i2c = self.i2c.borrow_mut();
 
-        for page in 0..8 {
-            self.set_cursor(&mut i2c, 0, page)?;
+        for (page, row) in frame.chunks(PANEL_WIDTH).enumerate() {
+            self.set_cursor(&mut i2c, 0, page as u8)?;
 
-            for _ in 0..DISPLAY_WIDTH {
-                self.write_data(&mut i2c, 0)?;
+            for chunk in row.chunks(DATA_CHUNK_SIZE) {
+                self.write_data(&mut i2c, chunk)?;
             }
         }
 
@@ -94,6 +190,7 @@ impl<'a> Ssd1306Display<'a> {
     /// - `co2`: The CO2 measurement.
     /// - `temperature`: The temperature measurement.
     /// - `humidity`: The humidity measurement.
+    /// - `air_quality`: The air quality level.
     ///
     /// # Returns
     /// The result of the operation.
@@ -102,8 +199,9 @@ impl<'a> Ssd1306Display<'a> {
         co2: u16,
         temperature: f32,
         humidity: f32,
+        air_quality: AirQuality,
     ) -> Result<(), AppError> {
-        self.clear()?;
+        self.framebuffer.clear();
 
         // Format measurements
         let co2_str = format!("CO2: {} ppm", co2);
@@ -112,13 +210,91 @@ impl<'a> Ssd1306Display<'a> {
 
         let hum_str = format!("Hum: {:.1} %", humidity);
 
-        // Draw each line
-        let mut i2c = self.i2c.borrow_mut();
-        self.draw_text_internal(&mut i2c, &co2_str, 0)?;
-        self.draw_text_internal(&mut i2c, &temp_str, 2)?;
-        self.draw_text_internal(&mut i2c, &hum_str, 4)?;
+        // Draw each line, wrapping onto the blank page below in portrait
+        for (text, page) in [(co2_str, 0), (temp_str, 2), (hum_str, 4)] {
+            let bounds = TextBox {
+                x: 0,
+                page,
+                width: self.framebuffer.width() as u8,
+                pages: 2,
+            };
+            self.draw_text(&text, bounds, Align::Left);
+        }
 
-        Ok(())
+        // Draw the air quality icon and label
+        let icon = air_quality.icon();
+        let width = self.framebuffer.width();
+        self.framebuffer
+            .draw_columns(0, STATUS_PAGE as usize, &icon, width);
+
+        // Draw the Wi-Fi icon at the right end of the status line
+        let mut label_right = width;
+        if let Some(wifi_icon) = self.wifi_icon {
+            label_right -= wifi_icon.len() + STATUS_ICON_GAP as usize;
+            self.framebuffer.draw_columns(
+                width - wifi_icon.len(),
+                STATUS_PAGE as usize,
+                &wifi_icon,
+                width,
+            );
+        }
+
+        // Draw the clock before it, if the label still fits
+        let label_x = icon.len() as u8 + STATUS_ICON_GAP;
+        if let Some(clock) = &self.clock {
+            let clock_width = text_width(clock) as usize;
+            let label_width = text_width(air_quality.label()) as usize;
+            let needed = label_width + STATUS_ICON_GAP as usize + clock_width;
+            if label_x as usize + needed <= label_right {
+                label_right -= clock_width + STATUS_ICON_GAP as usize;
+                let bounds = TextBox {
+                    x: (label_right + STATUS_ICON_GAP as usize) as u8,
+                    page: STATUS_PAGE,
+                    width: clock_width as u8,
+                    pages: 1,
+                };
+                let clock = clock.clone();
+                self.draw_text(&clock, bounds, Align::Left);
+            }
+        }
+
+        let bounds = TextBox {
+            x: label_x,
+            page: STATUS_PAGE,
+            width: label_right as u8 - label_x,
+            pages: 1,
+        };
+        self.draw_text(air_quality.label(), bounds, Align::Left);
+
+        // Draw the alarm banner on the last page
+        if let Some(banner) = self.alarm.banner() {
+            let bounds = TextBox {
+                x: 0,
+                page: self.framebuffer.pages() as u8 - 1,
+                width: width as u8,
+                pages: 1,
+            };
+            self.draw_text(banner, bounds, Align::Center);
+        }
+
+        self.flush()
+    }
+
+    /// Set the Wi-Fi connectivity icon; it is drawn with the next measurements.
+    ///
+    /// # Parameters
+    /// - `icon`: The icon, or `None` to hide it.
+    pub fn set_wifi_icon(&mut self, icon: Option<[u8; 8]>) {
+        self.wifi_icon = icon;
+    }
+
+    /// Set the time shown on the status line; it is drawn with the next
+    /// measurements.
+    ///
+    /// # Parameters
+    /// - `clock`: The time, or `None` to hide it.
+    pub fn set_clock(&mut self, clock: Option<String>) {
+        self.clock = clock;
     }
 
     /// Draw an error message on the display.
@@ -129,10 +305,87 @@ impl<'a> Ssd1306Display<'a> {
     /// # Returns
     /// The result of the operation.
     pub fn draw_error(&mut self, error: &str) -> Result<(), AppError> {
-        self.clear()?;
+        self.framebuffer.clear();
 
-        let mut i2c = self.i2c.borrow_mut();
-        self.draw_text_internal(&mut i2c, error, 2)
+        let bounds = TextBox {
+            x: 0,
+            page: ERROR_FIRST_PAGE,
+            width: self.framebuffer.width() as u8,
+            pages: self.framebuffer.pages() as u8 - ERROR_FIRST_PAGE,
+        };
+        self.draw_text(error, bounds, Align::Center);
+
+        self.flush()
+    }
+
+    /// Draw the pairing passkey or outcome on the display.
+    ///
+    /// # Parameters
+    /// - `screen`: The pairing screen.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn draw_pairing(&mut self, screen: PairingScreen) -> Result<(), AppError> {
+        self.framebuffer.clear();
+
+        let width = self.framebuffer.width() as u8;
+        let title_bounds = TextBox {
+            x: 0,
+            page: PAIRING_TITLE_PAGE,
+            width,
+            pages: PAIRING_DETAIL_PAGE - PAIRING_TITLE_PAGE - 1,
+        };
+        self.draw_text(screen.title(), title_bounds, Align::Center);
+
+        if let Some(detail) = screen.detail() {
+            let detail_bounds = TextBox {
+                x: 0,
+                page: PAIRING_DETAIL_PAGE,
+                width,
+                pages: 2,
+            };
+            self.draw_text(&detail, detail_bounds, Align::Center);
+        }
+
+        self.flush()
+    }
+
+    /// Draw status lines on the display, one per page.
+    ///
+    /// # Parameters
+    /// - `lines`: The lines; those beyond the last page are left out.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn draw_status(&mut self, lines: &[String]) -> Result<(), AppError> {
+        self.framebuffer.clear();
+
+        let width = self.framebuffer.width() as u8;
+        for (page, line) in (0..self.framebuffer.pages() as u8).zip(lines) {
+            let bounds = TextBox {
+                x: 0,
+                page,
+                width,
+                pages: 1,
+            };
+            self.draw_text(line, bounds, Align::Left);
+        }
+
+        self.flush()
+    }
+
+    /// Lay out text in a box and draw it into the framebuffer.
+    ///
+    /// # Parameters
+    /// - `text`: The text.
+    /// - `bounds`: The bounding box.
+    /// - `align`: The horizontal alignment.
+    fn draw_text(&mut self, text: &str, bounds: TextBox, align: Align) {
+        let right = bounds.x as usize + bounds.width as usize;
+
+        for line in layout(text, bounds, align) {
+            self.framebuffer.draw_text(&line, right);
+        }
     }
 
     /// Write a command to the display.
@@ -156,17 +409,23 @@ impl<'a> Ssd1306Display<'a> {
     ///
     /// # Parameters
     /// - `i2c`: The I2C driver.
-    /// - `data`: The data.
+    /// - `data`: The data, at most `DATA_CHUNK_SIZE` bytes.
     ///
     /// # Returns
     /// The result of the operation.
-    fn write_data(&self, i2c: &mut I2cDriver<'a>, data: u8) -> Result<(), AppError> {
-        i2c.write(SSD1306_ADDRESS, &[0x40, data], 100).map_err(|e| {
-            AppError::DisplayError(format!(
-                "Failed to write data 0x{:02x} to display at address 0x{:02x}: {:?}",
-                data, SSD1306_ADDRESS, e
-            ))
-        })
+    fn write_data(&self, i2c: &mut I2cDriver<'a>, data: &[u8]) -> Result<(), AppError> {
+        let mut buffer = [0x40; DATA_CHUNK_SIZE + 1];
+        buffer[1..=data.len()].copy_from_slice(data);
+
+        i2c.write(SSD1306_ADDRESS, &buffer[..=data.len()], 100)
+            .map_err(|e| {
+                AppError::DisplayError(format!(
+                    "Failed to write {} data bytes to display at address 0x{:02x}: {:?}",
+                    data.len(),
+                    SSD1306_ADDRESS,
+                    e
+                ))
+            })
     }
 
     /// Set the cursor position.
@@ -185,38 +444,15 @@ impl<'a> Ssd1306Display<'a> {
 
         Ok(())
     }
+}
 
-    /// Draw text on the display.
+/// Implement the `AlarmSink` trait for `Ssd1306Display`.
+impl AlarmSink for Ssd1306Display<'_> {
+    /// Remember the alarm state; the banner is drawn with the next measurements.
     ///
     /// # Parameters
-    /// - `i2c`: The I2C driver.
-    /// - `text`: The text.
-    /// - `page`: The page.
-    ///
-    /// # Returns
-    /// The result of the operation.
-    fn draw_text_internal(
-        &self,
-        i2c: &mut I2cDriver<'a>,
-        text: &str,
-        page: u8,
-    ) -> Result<(), AppError> {
-        self.set_cursor(i2c, 0, page)?;
-
-        for c in text.chars() {
-            let char_index = c as usize;
-
-            if char_index >= 32 && char_index < (32 + FONT_6X8.len()) {
-                let index = char_index - 32;
-
-                let char_data = &FONT_6X8[index];
-
-                for &byte in char_data {
-                    self.write_data(i2c, byte)?;
-                }
-            }
-        }
-
-        Ok(())
+    /// - `state`: The new alarm state.
+    fn on_alarm(&mut self, state: AlarmState) {
+        self.alarm = state;
     }
 }
@@ -7,17 +7,38 @@ pub enum AppError {
     /// BLE error.
     BleError(String),
 
+    /// Configuration error.
+    ConfigError(String),
+
     /// Display error.
     DisplayError(String),
 
+    /// HTTP server error.
+    HttpError(String),
+
     /// I2C error.
     I2cError(String),
 
+    /// MQTT error.
+    MqttError(String),
+
+    /// Firmware update error.
+    OtaError(String),
+
     /// Peripherals error.
     PeripheralsError(String),
 
     /// Sensor error.
     SensorError(String),
+
+    /// Persistent storage error.
+    StorageError(String),
+
+    /// Time synchronization error.
+    TimeError(String),
+
+    /// Wi-Fi error.
+    WifiError(String),
 }
 
 /// Implement the conversion from `EspError` to `AppError`.
@@ -46,10 +67,17 @@ impl fmt::Display for AppError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AppError::BleError(msg) => write!(f, "BLE error: {}", msg),
+            AppError::ConfigError(msg) => write!(f, "Configuration error: {}", msg),
             AppError::DisplayError(msg) => write!(f, "Display error: {}", msg),
+            AppError::HttpError(msg) => write!(f, "HTTP server error: {}", msg),
             AppError::I2cError(msg) => write!(f, "I2C error: {}", msg),
+            AppError::MqttError(msg) => write!(f, "MQTT error: {}", msg),
+            AppError::OtaError(msg) => write!(f, "Firmware update error: {}", msg),
             AppError::PeripheralsError(msg) => write!(f, "Peripherals error: {}", msg),
             AppError::SensorError(msg) => write!(f, "Sensor error: {}", msg),
+            AppError::StorageError(msg) => write!(f, "Storage error: {}", msg),
+            AppError::TimeError(msg) => write!(f, "Time synchronization error: {}", msg),
+            AppError::WifiError(msg) => write!(f, "Wi-Fi error: {}", msg),
         }
     }
 }
@@ -0,0 +1,209 @@
+use crate::error::AppError;
+use esp_idf_svc::{
+    hal::{
+        delay::FreeRtos,
+        ledc::LedcDriver,
+        rmt::{FixedLengthSignal, PinState, Pulse, TxRmtDriver},
+    },
+    sys::EspError,
+};
+use log::warn;
+use scd41_core::{
+    air_quality::AirQuality,
+    alarm::{AlarmSink, AlarmState},
+    indicator::{beep_pattern, status_color, Rgb, DEFAULT_BRIGHTNESS},
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Indicator refresh period in milliseconds.
+const TICK_MS: u32 = 25;
+
+/// Indicator thread stack size in bytes.
+const STACK_SIZE: usize = 4096;
+
+/// WS2812 high time of a 0 bit.
+const T0H: Duration = Duration::from_nanos(350);
+
+/// WS2812 low time of a 0 bit.
+const T0L: Duration = Duration::from_nanos(800);
+
+/// WS2812 high time of a 1 bit.
+const T1H: Duration = Duration::from_nanos(700);
+
+/// WS2812 low time of a 1 bit.
+const T1L: Duration = Duration::from_nanos(600);
+
+/// Inputs shared with the indicator thread.
+#[derive(Debug, Clone, Copy)]
+struct Inputs {
+    /// The air quality level.
+    air_quality: Option<AirQuality>,
+
+    /// The alarm state.
+    alarm: AlarmState,
+
+    /// Time the alarm state last changed.
+    alarm_since: Instant,
+
+    /// The status LED brightness.
+    brightness: u8,
+}
+
+/// Status indicator: a WS2812 RGB LED and an optional PWM buzzer.
+///
+/// The outputs are driven from a background thread so that blink and beep
+/// patterns keep their timing independently of the measurement loop.
+#[derive(Clone)]
+pub struct StatusIndicator {
+    /// Inputs shared with the indicator thread.
+    inputs: Arc<Mutex<Inputs>>,
+}
+
+/// The status indicator implementation.
+impl StatusIndicator {
+    /// Create a new status indicator and start its thread.
+    ///
+    /// # Parameters
+    /// - `led`: The RMT driver connected to the WS2812 data line.
+    /// - `buzzer`: The LEDC driver connected to a passive buzzer, if fitted.
+    ///
+    /// # Returns
+    /// The status indicator.
+    pub fn new(
+        led: TxRmtDriver<'static>,
+        buzzer: Option<LedcDriver<'static>>,
+    ) -> Result<Self, AppError> {
+        let inputs = Arc::new(Mutex::new(Inputs {
+            air_quality: None,
+            alarm: AlarmState::Armed,
+            alarm_since: Instant::now(),
+            brightness: DEFAULT_BRIGHTNESS,
+        }));
+
+        let thread_inputs = Arc::clone(&inputs);
+        thread::Builder::new()
+            .name("indicator".into())
+            .stack_size(STACK_SIZE)
+            .spawn(move || run(led, buzzer, thread_inputs))
+            .map_err(|e| {
+                AppError::PeripheralsError(format!("Failed to start indicator thread: {:?}", e))
+            })?;
+
+        Ok(Self { inputs })
+    }
+
+    /// Set the air quality level shown on the LED.
+    ///
+    /// # Parameters
+    /// - `air_quality`: The air quality level.
+    pub fn set_air_quality(&self, air_quality: AirQuality) {
+        self.inputs.lock().unwrap().air_quality = Some(air_quality);
+    }
+
+    /// Set the LED brightness.
+    ///
+    /// # Parameters
+    /// - `brightness`: The brightness, `0` (off) to `255` (full).
+    pub fn set_brightness(&self, brightness: u8) {
+        self.inputs.lock().unwrap().brightness = brightness;
+    }
+}
+
+/// Implement the `AlarmSink` trait for `StatusIndicator`.
+impl AlarmSink for StatusIndicator {
+    /// Restart the LED and buzzer patterns for the new alarm state.
+    ///
+    /// # Parameters
+    /// - `state`: The new alarm state.
+    fn on_alarm(&mut self, state: AlarmState) {
+        let mut inputs = self.inputs.lock().unwrap();
+        inputs.alarm = state;
+        inputs.alarm_since = Instant::now();
+    }
+}
+
+/// Indicator thread: refresh the LED and buzzer whenever their output changes.
+///
+/// # Parameters
+/// - `led`: The RMT driver connected to the WS2812 data line.
+/// - `buzzer`: The LEDC driver connected to a passive buzzer, if fitted.
+/// - `inputs`: The shared inputs.
+fn run(
+    mut led: TxRmtDriver<'static>,
+    mut buzzer: Option<LedcDriver<'static>>,
+    inputs: Arc<Mutex<Inputs>>,
+) {
+    let mut shown: Option<Rgb> = None;
+    let mut sounding = false;
+
+    loop {
+        let inputs = *inputs.lock().unwrap();
+        let elapsed_ms = inputs.alarm_since.elapsed().as_millis() as u64;
+
+        let color = status_color(
+            inputs.air_quality,
+            inputs.alarm,
+            inputs.brightness,
+            elapsed_ms,
+        );
+        if shown != Some(color) {
+            match write_led(&mut led, color) {
+                Ok(()) => shown = Some(color),
+                Err(e) => warn!("Failed to update status LED: {:?}", e),
+            }
+        }
+
+        if let Some(buzzer) = buzzer.as_mut() {
+            let on = beep_pattern(inputs.alarm).is_some_and(|pattern| pattern.is_on(elapsed_ms));
+            if on != sounding {
+                let duty = if on { buzzer.get_max_duty() / 2 } else { 0 };
+                match buzzer.set_duty(duty) {
+                    Ok(()) => sounding = on,
+                    Err(e) => warn!("Failed to drive buzzer: {:?}", e),
+                }
+            }
+        }
+
+        FreeRtos::delay_ms(TICK_MS);
+    }
+}
+
+/// Send a colour to the WS2812 LED.
+///
+/// # Parameters
+/// - `led`: The RMT driver connected to the WS2812 data line.
+/// - `color`: The colour.
+///
+/// # Returns
+/// The result of the operation.
+fn write_led(led: &mut TxRmtDriver<'static>, color: Rgb) -> Result<(), AppError> {
+    let map_err = |e: EspError| AppError::PeripheralsError(format!("WS2812 RMT error: {:?}", e));
+
+    let ticks_hz = led.counter_clock().map_err(map_err)?;
+    let pulse = |state, duration| Pulse::new_with_duration(ticks_hz, state, &duration);
+    let zero = (
+        pulse(PinState::High, T0H).map_err(map_err)?,
+        pulse(PinState::Low, T0L).map_err(map_err)?,
+    );
+    let one = (
+        pulse(PinState::High, T1H).map_err(map_err)?,
+        pulse(PinState::Low, T1L).map_err(map_err)?,
+    );
+
+    let grb = color.to_grb();
+    let mut signal = FixedLengthSignal::<24>::new();
+    for bit in 0..24 {
+        let symbol = if grb & (1 << (23 - bit)) != 0 {
+            &one
+        } else {
+            &zero
+        };
+        signal.set(bit, symbol).map_err(map_err)?;
+    }
+
+    led.start_blocking(&signal).map_err(map_err)
+}
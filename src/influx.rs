@@ -0,0 +1,242 @@
+use crate::{
+    clock::{uptime_ms, SharedClock},
+    error::AppError,
+};
+use esp_idf_svc::{
+    hal::delay::FreeRtos,
+    http::client::{Client, Configuration, EspHttpConnection},
+    io::{EspIOError, Write},
+    sys::esp_crt_bundle_attach,
+};
+use log::{info, warn};
+use scd41_core::{
+    config::Config,
+    influx::{self, Exporter, Point, Transport, WriteError, WriteOutcome},
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Exporter thread stack size in bytes, with room for the TLS handshake.
+const STACK_SIZE: usize = 8 * 1024;
+
+/// Period of the exporter thread in milliseconds.
+const TICK_MS: u32 = 1000;
+
+/// Time limit of a write request.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// State shared with the exporter thread.
+struct Shared {
+    /// The buffered measurements.
+    exporter: Exporter,
+
+    /// The write URL, with the timestamp precision.
+    url: String,
+
+    /// The API token, empty for none.
+    token: String,
+
+    /// The measurement and tags of every line.
+    series: String,
+
+    /// Whether Wi-Fi is connected.
+    online: bool,
+
+    /// Set to stop the thread.
+    stop: bool,
+}
+
+/// Writes measurements to InfluxDB in the line protocol.
+///
+/// Measurements are buffered in RAM and written in batches from a background
+/// thread, so a slow or unreachable server never holds up the measurement loop.
+pub struct InfluxExporter {
+    /// State shared with the exporter thread.
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// The InfluxDB exporter implementation.
+impl InfluxExporter {
+    /// Start exporting to the server configured in `config`.
+    ///
+    /// # Parameters
+    /// - `config`: The configuration, with a write URL.
+    /// - `serial_number`: The sensor serial number, tagging every measurement.
+    /// - `clock`: The system clock, for the timestamps.
+    ///
+    /// # Returns
+    /// The exporter; nothing is written until Wi-Fi is connected.
+    pub fn new(config: &Config, serial_number: &str, clock: SharedClock) -> Result<Self, AppError> {
+        info!(
+            "Exporting measurements to InfluxDB at {}",
+            config.influx_url
+        );
+
+        let shared = Arc::new(Mutex::new(Shared {
+            exporter: Exporter::default(),
+            url: String::new(),
+            token: String::new(),
+            series: String::new(),
+            online: false,
+            stop: false,
+        }));
+        let exporter = Self { shared };
+        exporter.set_target(config, serial_number);
+
+        let thread_shared = Arc::clone(&exporter.shared);
+        thread::Builder::new()
+            .name("influx".into())
+            .stack_size(STACK_SIZE)
+            .spawn(move || run(&thread_shared, &clock))
+            .map_err(|e| {
+                AppError::HttpError(format!("Failed to start InfluxDB exporter: {:?}", e))
+            })?;
+
+        Ok(exporter)
+    }
+
+    /// Use the URL, token and device name of a configuration.
+    ///
+    /// # Parameters
+    /// - `config`: The configuration.
+    /// - `serial_number`: The sensor serial number.
+    pub fn set_target(&self, config: &Config, serial_number: &str) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.url = influx::write_url(&config.influx_url);
+        shared.token = config.influx_token.clone();
+        shared.series = influx::series_key(&config.device_name, serial_number);
+    }
+
+    /// Set whether Wi-Fi is connected; measurements are buffered while it is not.
+    ///
+    /// # Parameters
+    /// - `online`: Whether Wi-Fi is connected.
+    pub fn set_online(&self, online: bool) {
+        self.shared.lock().unwrap().online = online;
+    }
+
+    /// Buffer a measurement.
+    ///
+    /// # Parameters
+    /// - `point`: The measurement.
+    pub fn push(&self, point: Point) {
+        self.shared.lock().unwrap().exporter.push(point);
+    }
+}
+
+/// Implement the `Drop` trait for `InfluxExporter`.
+impl Drop for InfluxExporter {
+    /// Stop the exporter thread, discarding the buffered measurements.
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().stop = true;
+    }
+}
+
+/// Exporter thread: write a batch whenever one is due.
+///
+/// # Parameters
+/// - `shared`: The shared state.
+/// - `clock`: The system clock.
+fn run(shared: &Mutex<Shared>, clock: &SharedClock) {
+    loop {
+        FreeRtos::delay_ms(TICK_MS);
+
+        let (body, mut transport) = {
+            let mut shared = shared.lock().unwrap();
+            if shared.stop {
+                return;
+            }
+            if !shared.online {
+                continue;
+            }
+
+            let clock = *clock.lock().unwrap();
+            let Shared {
+                exporter,
+                url,
+                token,
+                series,
+                ..
+            } = &mut *shared;
+            let Some(body) = exporter.begin(uptime_ms(), series, &clock) else {
+                continue;
+            };
+
+            let transport = HttpTransport {
+                url: url.clone(),
+                token: token.clone(),
+            };
+            (body, transport)
+        };
+
+        // The request runs without the lock, so measurements keep coming in.
+        let result = transport.write(&body);
+        let mut shared = shared.lock().unwrap();
+        match shared.exporter.finish(uptime_ms(), result) {
+            WriteOutcome::Sent(count) => info!("Wrote {} measurements to InfluxDB", count),
+            WriteOutcome::Rejected(count, e) => {
+                warn!("InfluxDB rejected {} measurements: {}", count, e)
+            }
+            WriteOutcome::Retry(e, delay_ms) => warn!(
+                "Failed to write to InfluxDB, {} measurements buffered, retrying in {} s: {}",
+                shared.exporter.len(),
+                delay_ms / 1000,
+                e
+            ),
+        }
+    }
+}
+
+/// Writes batches with the ESP-IDF HTTP client.
+struct HttpTransport {
+    /// The write URL.
+    url: String,
+
+    /// The API token, empty for none.
+    token: String,
+}
+
+/// Implement the `Transport` trait for `HttpTransport`.
+impl Transport for HttpTransport {
+    /// Post a batch and check the response status.
+    ///
+    /// # Parameters
+    /// - `body`: The lines.
+    ///
+    /// # Returns
+    /// An error for a failed connection or an error status.
+    fn write(&mut self, body: &str) -> Result<(), WriteError> {
+        let network = |e: EspIOError| WriteError::Network(format!("{:?}", e));
+
+        let connection = EspHttpConnection::new(&Configuration {
+            timeout: Some(TIMEOUT),
+            crt_bundle_attach: Some(esp_crt_bundle_attach),
+            ..Default::default()
+        })
+        .map_err(|e| WriteError::Network(format!("{:?}", e)))?;
+        let mut client = Client::wrap(connection);
+
+        let content_length = body.len().to_string();
+        let authorization = influx::authorization(&self.token);
+        let mut headers = vec![
+            ("Content-Type", influx::CONTENT_TYPE),
+            ("Content-Length", content_length.as_str()),
+        ];
+        if let Some(authorization) = &authorization {
+            headers.push(("Authorization", authorization.as_str()));
+        }
+
+        let mut request = client.post(&self.url, &headers).map_err(network)?;
+        request.write_all(body.as_bytes()).map_err(network)?;
+        request.flush().map_err(network)?;
+        let response = request.submit().map_err(network)?;
+
+        match response.status() {
+            200..=299 => Ok(()),
+            status => Err(WriteError::Status(status)),
+        }
+    }
+}
@@ -1,21 +1,26 @@
+#[cfg(not(feature = "buzzer"))]
+mod alarm_output;
+#[cfg(feature = "battery")]
+mod battery;
 mod ble;
+mod clock;
 mod device;
 mod display;
 mod error;
-mod font;
+mod indicator;
+mod influx;
+mod mqtt;
+mod ota;
 mod sensor;
+mod settings;
+mod sntp;
+mod web;
+mod wifi;
 
 use crate::{device::DeviceManager, error::AppError};
-use esp_idf_svc::{
-    hal::{delay::FreeRtos, peripherals::Peripherals},
-    log::EspLogger,
-    sys::link_patches,
-};
+use esp_idf_svc::{hal::peripherals::Peripherals, log::EspLogger, sys::link_patches};
 use log::info;
 
-/// Measurement interval in milliseconds.
-const MEASUREMENT_INTERVAL_MS: u32 = 5000;
-
 /// This function initializes the system and starts the main loop.
 ///
 /// # Returns
@@ -36,7 +41,7 @@ fn main() -> Result<(), AppError> {
     loop {
         manager.update()?;
         info!("Manager updated!");
-        FreeRtos::delay_ms(MEASUREMENT_INTERVAL_MS);
+        manager.idle(manager.measurement_interval_ms());
         info!("Delay done!");
     }
 }
@@ -0,0 +1,200 @@
+use crate::error::AppError;
+use esp_idf_svc::{
+    mqtt::client::{EspMqttClient, EventPayload, LwtConfiguration, MqttClientConfiguration, QoS},
+    sys::{esp, esp_crt_bundle_attach, esp_efuse_mac_get_default},
+};
+use log::{info, warn};
+use scd41_core::{
+    config::Config,
+    mqtt::{self, DiscoveryDevice, Entity, StatePayload, Topics, OFFLINE, ONLINE},
+};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Publishes measurements to an MQTT broker, announcing the device to Home
+/// Assistant on every connection.
+pub struct MqttPublisher {
+    /// The client, reconnecting on its own.
+    client: EspMqttClient<'static>,
+
+    /// The device topics.
+    topics: Topics,
+
+    /// The quality of service for measurements.
+    qos: QoS,
+
+    /// Whether the broker retains the latest measurement.
+    retain: bool,
+
+    /// Whether the client is connected.
+    connected: Arc<AtomicBool>,
+
+    /// Set on every connection, until the discovery documents are published.
+    announce: Arc<AtomicBool>,
+}
+
+/// The MQTT publisher implementation.
+impl MqttPublisher {
+    /// Connect to the broker configured in `config`.
+    ///
+    /// # Parameters
+    /// - `config`: The configuration, with a broker URL.
+    /// - `serial_number`: The sensor serial number, identifying the device.
+    ///
+    /// # Returns
+    /// The publisher; the connection is made in the background.
+    pub fn new(config: &Config, serial_number: &str) -> Result<Self, AppError> {
+        let node_id = mqtt::node_id(&device_id(serial_number));
+        let topics = Topics::new(&config.mqtt_prefix, &node_id);
+        info!(
+            "Connecting to MQTT broker {} as {}",
+            mqtt::redact_url(&config.mqtt_url),
+            node_id
+        );
+
+        let connected = Arc::new(AtomicBool::new(false));
+        let announce = Arc::new(AtomicBool::new(false));
+        let (event_connected, event_announce) = (Arc::clone(&connected), Arc::clone(&announce));
+
+        let client = EspMqttClient::new_cb(
+            &config.mqtt_url,
+            &MqttClientConfiguration {
+                client_id: Some(&node_id),
+                lwt: Some(LwtConfiguration {
+                    topic: &topics.availability,
+                    payload: OFFLINE.as_bytes(),
+                    qos: QoS::AtLeastOnce,
+                    retain: true,
+                }),
+                crt_bundle_attach: Some(esp_crt_bundle_attach),
+                ..Default::default()
+            },
+            move |event| match event.payload() {
+                EventPayload::Connected(_) => {
+                    info!("MQTT connected");
+                    event_connected.store(true, Ordering::Relaxed);
+                    event_announce.store(true, Ordering::Relaxed);
+                }
+                EventPayload::Disconnected => {
+                    warn!("MQTT disconnected");
+                    event_connected.store(false, Ordering::Relaxed);
+                }
+                EventPayload::Error(e) => warn!("MQTT error: {:?}", e),
+                _ => (),
+            },
+        )
+        .map_err(|e| AppError::MqttError(format!("Failed to create MQTT client: {:?}", e)))?;
+
+        Ok(Self {
+            client,
+            topics,
+            qos: qos(config.mqtt_qos),
+            retain: config.mqtt_retain,
+            connected,
+            announce,
+        })
+    }
+
+    /// Publish the discovery documents again, such as after a rename.
+    pub fn announce(&self) {
+        self.announce.store(self.is_connected(), Ordering::Relaxed);
+    }
+
+    /// Use the quality of service and retain flag of a configuration.
+    ///
+    /// # Parameters
+    /// - `config`: The configuration.
+    pub fn set_options(&mut self, config: &Config) {
+        self.qos = qos(config.mqtt_qos);
+        self.retain = config.mqtt_retain;
+    }
+
+    /// Check whether the client is connected.
+    ///
+    /// # Returns
+    /// Whether measurements reach the broker.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Publish the availability and the discovery documents after connecting.
+    ///
+    /// # Parameters
+    /// - `device`: The device details shown in Home Assistant.
+    pub fn poll(&mut self, device: &DiscoveryDevice) {
+        if !self.announce.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        for entity in Entity::ALL {
+            let payload = entity.discovery_payload(&self.topics, device);
+            let topic = entity.discovery_topic(&self.topics);
+            self.send(&topic, QoS::AtLeastOnce, true, &payload);
+        }
+        let topic = self.topics.availability.clone();
+        self.send(&topic, QoS::AtLeastOnce, true, ONLINE);
+    }
+
+    /// Publish a measurement.
+    ///
+    /// # Parameters
+    /// - `payload`: The measurement.
+    pub fn publish(&mut self, payload: &StatePayload) {
+        if !self.is_connected() {
+            return;
+        }
+
+        let topic = self.topics.state.clone();
+        self.send(&topic, self.qos, self.retain, &payload.encode());
+    }
+
+    /// Queue a message without waiting for the network.
+    ///
+    /// # Parameters
+    /// - `topic`: The topic.
+    /// - `qos`: The quality of service.
+    /// - `retain`: Whether the broker retains the message.
+    /// - `payload`: The message.
+    fn send(&mut self, topic: &str, qos: QoS, retain: bool, payload: &str) {
+        if let Err(e) = self.client.enqueue(topic, qos, retain, payload.as_bytes()) {
+            warn!("Failed to publish to {}: {:?}", topic, e);
+        }
+    }
+}
+
+/// Map a configured quality of service.
+///
+/// # Parameters
+/// - `level`: The level, 0 to 2.
+///
+/// # Returns
+/// The quality of service.
+fn qos(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// Pick the identifier the node ID is derived from.
+///
+/// # Parameters
+/// - `serial_number`: The sensor serial number, empty if it could not be read.
+///
+/// # Returns
+/// The serial number, or the factory MAC address without it.
+fn device_id(serial_number: &str) -> String {
+    if !serial_number.is_empty() {
+        return serial_number.to_string();
+    }
+
+    let mut mac = [0u8; 6];
+    if let Err(e) = esp!(unsafe { esp_efuse_mac_get_default(mac.as_mut_ptr()) }) {
+        warn!("Failed to read MAC address: {:?}", e);
+    }
+
+    mac.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,147 @@
+use crate::error::AppError;
+use esp_idf_svc::sys::{
+    esp, esp_ota_get_next_update_partition, esp_ota_get_running_partition,
+    esp_ota_get_state_partition, esp_ota_img_states_t,
+    esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY, esp_ota_mark_app_invalid_rollback_and_reboot,
+    esp_ota_mark_app_valid_cancel_rollback, esp_ota_set_boot_partition, esp_partition_erase_range,
+    esp_partition_read, esp_partition_t, esp_partition_write, EspError,
+};
+use scd41_core::ota::Partition;
+use std::{ffi::c_void, ptr};
+
+/// The inactive OTA app partition, receiving firmware updates.
+pub struct FlashPartition {
+    /// The partition, from the partition table ESP-IDF keeps for the whole run.
+    partition: *const esp_partition_t,
+}
+
+// The partition table entry is never freed or changed, and ESP-IDF serializes
+// flash access itself.
+unsafe impl Send for FlashPartition {}
+
+/// The flash partition implementation.
+impl FlashPartition {
+    /// Find the partition the next update is written to.
+    ///
+    /// # Returns
+    /// The OTA partition not currently running.
+    pub fn next_update() -> Result<Self, AppError> {
+        let partition = unsafe { esp_ota_get_next_update_partition(ptr::null()) };
+        if partition.is_null() {
+            return Err(AppError::OtaError(
+                "No OTA partition to update, check the partition table".to_string(),
+            ));
+        }
+
+        Ok(Self { partition })
+    }
+
+    /// Boot from this partition on the next restart.
+    ///
+    /// ESP-IDF checks the image header and its digest before switching.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn activate(&self) -> Result<(), AppError> {
+        esp!(unsafe { esp_ota_set_boot_partition(self.partition) })
+            .map_err(|e| AppError::OtaError(format!("Failed to set the boot partition: {:?}", e)))
+    }
+}
+
+/// The `Partition` trait implementation for `FlashPartition`.
+impl Partition for FlashPartition {
+    /// The flash driver error.
+    type Error = EspError;
+
+    /// Get the partition size.
+    ///
+    /// # Returns
+    /// The size in bytes.
+    fn size(&self) -> u32 {
+        unsafe { (*self.partition).size }
+    }
+
+    /// Erase a range.
+    ///
+    /// # Parameters
+    /// - `offset`: The start, sector aligned.
+    /// - `len`: The length, sector aligned.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    fn erase(&mut self, offset: u32, len: u32) -> Result<(), EspError> {
+        esp!(unsafe { esp_partition_erase_range(self.partition, offset as usize, len as usize) })
+    }
+
+    /// Write to an erased range.
+    ///
+    /// # Parameters
+    /// - `offset`: The start.
+    /// - `data`: The data.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), EspError> {
+        esp!(unsafe {
+            esp_partition_write(
+                self.partition,
+                offset as usize,
+                data.as_ptr() as *const c_void,
+                data.len(),
+            )
+        })
+    }
+
+    /// Read a range.
+    ///
+    /// # Parameters
+    /// - `offset`: The start.
+    /// - `buf`: The buffer to fill.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), EspError> {
+        esp!(unsafe {
+            esp_partition_read(
+                self.partition,
+                offset as usize,
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+            )
+        })
+    }
+}
+
+/// Check whether the running image was just installed and has not been confirmed.
+///
+/// The bootloader rolls such an image back if it restarts before it is confirmed
+/// with [`mark_valid`].
+///
+/// # Returns
+/// Whether the image is waiting for its health check.
+pub fn pending_verify() -> bool {
+    let mut state: esp_ota_img_states_t = 0;
+    let result =
+        esp!(unsafe { esp_ota_get_state_partition(esp_ota_get_running_partition(), &mut state) });
+
+    result.is_ok() && state == esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY
+}
+
+/// Keep the running image.
+///
+/// # Returns
+/// The result of the operation.
+pub fn mark_valid() -> Result<(), AppError> {
+    esp!(unsafe { esp_ota_mark_app_valid_cancel_rollback() })
+        .map_err(|e| AppError::OtaError(format!("Failed to confirm the image: {:?}", e)))
+}
+
+/// Discard the running image and restart into the previous one.
+///
+/// # Returns
+/// The error if no previous image can be booted; otherwise it does not return.
+pub fn rollback() -> AppError {
+    let result = esp!(unsafe { esp_ota_mark_app_invalid_rollback_and_reboot() });
+
+    AppError::OtaError(format!("Failed to roll back: {:?}", result))
+}
@@ -1,12 +1,42 @@
 use crate::error::AppError;
 use esp_idf_svc::hal::{delay::FreeRtos, i2c::I2cDriver};
 use log::info;
-use scd41_core::scd41::parse_measurement;
+use scd41_core::{
+    metrics::SensorErrorKind,
+    scd41::{
+        encode_command_with_argument, frc_correction, parse_measurement, parse_serial_number,
+        parse_word, temperature_offset_word,
+    },
+};
 use std::{cell::RefCell, rc::Rc};
 
+/// Command to read the serial number.
+const CMD_GET_SERIAL_NUMBER: u16 = 0x3682;
+
+/// Command to perform a factory reset.
+const CMD_PERFORM_FACTORY_RESET: u16 = 0x3632;
+
+/// Command to perform forced recalibration.
+const CMD_PERFORM_FORCED_RECALIBRATION: u16 = 0x362f;
+
+/// Command to perform a self-test.
+const CMD_PERFORM_SELF_TEST: u16 = 0x3639;
+
+/// Command to persist settings to EEPROM.
+const CMD_PERSIST_SETTINGS: u16 = 0x3615;
+
 /// Command to read measurement.
 const CMD_READ_MEASUREMENT: u16 = 0xec05;
 
+/// Command to enable or disable automatic self-calibration.
+const CMD_SET_AUTOMATIC_SELF_CALIBRATION: u16 = 0x2416;
+
+/// Command to set the sensor altitude.
+const CMD_SET_SENSOR_ALTITUDE: u16 = 0x2427;
+
+/// Command to set the temperature offset.
+const CMD_SET_TEMPERATURE_OFFSET: u16 = 0x241d;
+
 /// Command to start periodic measurement.
 const CMD_START_PERIODIC_MEASUREMENT: u16 = 0x21b1;
 
@@ -16,6 +46,16 @@ const CMD_STOP_PERIODIC_MEASUREMENT: u16 = 0x3f86;
 /// SCD41 I2C address.
 const SCD41_ADDRESS: u8 = 0x62;
 
+/// A failed measurement read.
+#[derive(Debug)]
+pub struct ReadError {
+    /// What went wrong, for the error counters.
+    pub kind: SensorErrorKind,
+
+    /// The error.
+    pub error: AppError,
+}
+
 /// SCD41 sensor interface.
 pub struct Scd41Sensor<'a> {
     /// The I2C driver.
@@ -61,28 +101,169 @@ impl<'a> Scd41Sensor<'a> {
         Ok(())
     }
 
-    /// Read measurement.
+    /// Set the temperature offset. The sensor must be idle.
+    ///
+    /// # Parameters
+    /// - `offset_c`: The offset in degrees Celsius.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_temperature_offset(&mut self, offset_c: f32) -> Result<(), AppError> {
+        let mut i2c = self.i2c.borrow_mut();
+        self.send_command_with_argument(
+            &mut i2c,
+            CMD_SET_TEMPERATURE_OFFSET,
+            temperature_offset_word(offset_c),
+        )?;
+        FreeRtos::delay_ms(1);
+
+        Ok(())
+    }
+
+    /// Set the altitude used for pressure compensation. The sensor must be idle.
+    ///
+    /// # Parameters
+    /// - `altitude_m`: The altitude above sea level in metres.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_sensor_altitude(&mut self, altitude_m: u16) -> Result<(), AppError> {
+        let mut i2c = self.i2c.borrow_mut();
+        self.send_command_with_argument(&mut i2c, CMD_SET_SENSOR_ALTITUDE, altitude_m)?;
+        FreeRtos::delay_ms(1);
+
+        Ok(())
+    }
+
+    /// Read the serial number. The sensor must be idle.
     ///
     /// # Returns
-    /// The measurement.
-    pub fn read_measurement(&mut self) -> Result<(u16, f32, f32), AppError> {
+    /// The 48-bit serial number.
+    pub fn get_serial_number(&mut self) -> Result<u64, AppError> {
         let mut i2c = self.i2c.borrow_mut();
-        self.send_command(&mut i2c, CMD_READ_MEASUREMENT)?;
+        self.send_command(&mut i2c, CMD_GET_SERIAL_NUMBER)?;
         FreeRtos::delay_ms(1);
 
         let mut buffer = [0u8; 9];
         i2c.read(SCD41_ADDRESS, &mut buffer, 100).map_err(|e| {
             AppError::SensorError(format!(
-                "Failed to read measurement data from sensor at address 0x{:02x}: {:?}",
+                "Failed to read serial number from sensor at address 0x{:02x}: {:?}",
                 SCD41_ADDRESS, e
             ))
         })?;
 
-        let measurement = parse_measurement(&buffer).map_err(|e| {
+        parse_serial_number(&buffer).map_err(|e| {
             AppError::SensorError(format!(
-                "Failed to parse measurement data from sensor at address 0x{:02x}: {e}",
+                "Failed to parse serial number from sensor at address 0x{:02x}: {e}",
                 SCD41_ADDRESS
             ))
+        })
+    }
+
+    /// Perform forced recalibration. The sensor must be idle, after at least
+    /// three minutes of periodic measurement at the reference concentration.
+    ///
+    /// # Parameters
+    /// - `target_ppm`: The reference CO2 concentration in ppm.
+    ///
+    /// # Returns
+    /// The applied correction in ppm.
+    pub fn perform_forced_recalibration(&mut self, target_ppm: u16) -> Result<i16, AppError> {
+        let mut i2c = self.i2c.borrow_mut();
+        self.send_command_with_argument(&mut i2c, CMD_PERFORM_FORCED_RECALIBRATION, target_ppm)?;
+        FreeRtos::delay_ms(400);
+
+        let word = self.read_word(&mut i2c, CMD_PERFORM_FORCED_RECALIBRATION)?;
+        frc_correction(word)
+            .ok_or_else(|| AppError::SensorError("Forced recalibration failed".to_string()))
+    }
+
+    /// Perform a self-test. The sensor must be idle. Takes 10 seconds.
+    ///
+    /// # Returns
+    /// The self-test result word, `0` if no malfunction was detected.
+    pub fn perform_self_test(&mut self) -> Result<u16, AppError> {
+        let mut i2c = self.i2c.borrow_mut();
+        self.send_command(&mut i2c, CMD_PERFORM_SELF_TEST)?;
+        FreeRtos::delay_ms(10000);
+
+        self.read_word(&mut i2c, CMD_PERFORM_SELF_TEST)
+    }
+
+    /// Reset all sensor settings stored in EEPROM and erase the calibration
+    /// history. The sensor must be idle.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn perform_factory_reset(&mut self) -> Result<(), AppError> {
+        let mut i2c = self.i2c.borrow_mut();
+        self.send_command(&mut i2c, CMD_PERFORM_FACTORY_RESET)?;
+        FreeRtos::delay_ms(1200);
+
+        Ok(())
+    }
+
+    /// Enable or disable automatic self-calibration. The sensor must be idle.
+    ///
+    /// # Parameters
+    /// - `enabled`: Whether ASC is enabled.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_automatic_self_calibration(&mut self, enabled: bool) -> Result<(), AppError> {
+        let mut i2c = self.i2c.borrow_mut();
+        self.send_command_with_argument(
+            &mut i2c,
+            CMD_SET_AUTOMATIC_SELF_CALIBRATION,
+            u16::from(enabled),
+        )?;
+        FreeRtos::delay_ms(1);
+
+        Ok(())
+    }
+
+    /// Persist the current sensor settings to EEPROM. The sensor must be idle.
+    /// The EEPROM endures about 2000 writes, so only call this on user request.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn persist_settings(&mut self) -> Result<(), AppError> {
+        let mut i2c = self.i2c.borrow_mut();
+        self.send_command(&mut i2c, CMD_PERSIST_SETTINGS)?;
+        FreeRtos::delay_ms(800);
+
+        Ok(())
+    }
+
+    /// Read measurement.
+    ///
+    /// # Returns
+    /// The measurement, or the error with its kind.
+    pub fn read_measurement(&mut self) -> Result<(u16, f32, f32), ReadError> {
+        let i2c_error = |error| ReadError {
+            kind: SensorErrorKind::I2c,
+            error,
+        };
+
+        let mut i2c = self.i2c.borrow_mut();
+        self.send_command(&mut i2c, CMD_READ_MEASUREMENT)
+            .map_err(i2c_error)?;
+        FreeRtos::delay_ms(1);
+
+        let mut buffer = [0u8; 9];
+        i2c.read(SCD41_ADDRESS, &mut buffer, 100).map_err(|e| {
+            i2c_error(AppError::SensorError(format!(
+                "Failed to read measurement data from sensor at address 0x{:02x}: {:?}",
+                SCD41_ADDRESS, e
+            )))
+        })?;
+
+        let measurement = parse_measurement(&buffer).map_err(|e| ReadError {
+            kind: SensorErrorKind::from_parse_error(&e),
+            error: AppError::SensorError(format!(
+                "Failed to parse measurement data from sensor at address 0x{:02x}: {e}",
+                SCD41_ADDRESS
+            )),
         })?;
 
         Ok((
@@ -92,6 +273,55 @@ impl<'a> Scd41Sensor<'a> {
         ))
     }
 
+    /// Read the single-word response to a command.
+    ///
+    /// # Parameters
+    /// - `i2c`: The I2C driver.
+    /// - `command`: The command that was sent.
+    ///
+    /// # Returns
+    /// The response word.
+    fn read_word(&self, i2c: &mut I2cDriver<'a>, command: u16) -> Result<u16, AppError> {
+        let mut buffer = [0u8; 3];
+        i2c.read(SCD41_ADDRESS, &mut buffer, 100).map_err(|e| {
+            AppError::SensorError(format!(
+                "Failed to read response to command 0x{:04x} from sensor at address 0x{:02x}: {:?}",
+                command, SCD41_ADDRESS, e
+            ))
+        })?;
+
+        parse_word(&buffer).map_err(|e| {
+            AppError::SensorError(format!(
+                "Failed to parse response to command 0x{:04x} from sensor at address 0x{:02x}: {e}",
+                command, SCD41_ADDRESS
+            ))
+        })
+    }
+
+    /// Send a command with an argument to the sensor.
+    ///
+    /// # Parameters
+    /// - `i2c`: The I2C driver.
+    /// - `command`: The command.
+    /// - `argument`: The argument.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    fn send_command_with_argument(
+        &self,
+        i2c: &mut I2cDriver<'a>,
+        command: u16,
+        argument: u16,
+    ) -> Result<(), AppError> {
+        let bytes = encode_command_with_argument(command, argument);
+        i2c.write(SCD41_ADDRESS, &bytes, 100).map_err(|e| {
+            AppError::SensorError(format!(
+                "Failed to send command 0x{:04x} with argument 0x{:04x} to sensor at address 0x{:02x}: {:?}",
+                command, argument, SCD41_ADDRESS, e
+            ))
+        })
+    }
+
     /// Send a command to the sensor.
     ///
     /// # Parameters
@@ -0,0 +1,180 @@
+use crate::error::AppError;
+use esp_idf_svc::nvs::{EspNvs, EspNvsPartition, NvsDefault};
+use log::warn;
+use scd41_core::{
+    config::{Config, MAX_CONFIG_LEN},
+    framebuffer::Orientation,
+    wifi::{Credentials, CREDENTIALS_MAX_LEN},
+};
+
+/// NVS namespace for persisted settings.
+const NAMESPACE: &str = "co2mon";
+
+/// Key for the BTHome encryption frame counter reservation.
+const KEY_BTHOME_COUNTER: &str = "bthome_ctr";
+
+/// Key for the runtime configuration.
+const KEY_CONFIG: &str = "config";
+
+/// Key for the display orientation.
+const KEY_ORIENTATION: &str = "orientation";
+
+/// Key for the Wi-Fi credentials.
+const KEY_WIFI: &str = "wifi";
+
+/// Persistent settings stored in NVS.
+pub struct Settings {
+    /// The NVS namespace handle.
+    nvs: EspNvs<NvsDefault>,
+}
+
+/// The settings implementation.
+impl Settings {
+    /// Open the settings namespace.
+    ///
+    /// # Parameters
+    /// - `partition`: The NVS partition.
+    ///
+    /// # Returns
+    /// The settings.
+    pub fn new(partition: EspNvsPartition<NvsDefault>) -> Result<Self, AppError> {
+        let nvs = EspNvs::new(partition, NAMESPACE, true).map_err(|e| {
+            AppError::StorageError(format!(
+                "Failed to open NVS namespace '{}': {:?}",
+                NAMESPACE, e
+            ))
+        })?;
+
+        Ok(Self { nvs })
+    }
+
+    /// Read the BTHome encryption frame counter reservation.
+    ///
+    /// # Returns
+    /// The stored reservation, `0` if none is stored.
+    pub fn bthome_counter(&self) -> Result<u32, AppError> {
+        self.nvs
+            .get_u32(KEY_BTHOME_COUNTER)
+            .map(Option::unwrap_or_default)
+            .map_err(|e| {
+                AppError::StorageError(format!("Failed to read BTHome frame counter: {:?}", e))
+            })
+    }
+
+    /// Store the BTHome encryption frame counter reservation.
+    ///
+    /// # Parameters
+    /// - `reservation`: The first counter value not yet reserved.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_bthome_counter(&mut self, reservation: u32) -> Result<(), AppError> {
+        self.nvs
+            .set_u32(KEY_BTHOME_COUNTER, reservation)
+            .map_err(|e| {
+                AppError::StorageError(format!("Failed to store BTHome frame counter: {:?}", e))
+            })
+    }
+
+    /// Read the runtime configuration.
+    ///
+    /// # Returns
+    /// The stored configuration, or the default if none is stored or it is invalid.
+    pub fn config(&self) -> Config {
+        let mut buffer = [0u8; MAX_CONFIG_LEN];
+
+        match self.nvs.get_blob(KEY_CONFIG, &mut buffer) {
+            Ok(Some(payload)) => Config::decode(payload).unwrap_or_else(|e| {
+                warn!("Ignoring stored configuration: {e}");
+                Config::default()
+            }),
+            Ok(None) => Config::default(),
+            Err(e) => {
+                warn!("Failed to read configuration: {:?}", e);
+                Config::default()
+            }
+        }
+    }
+
+    /// Store the runtime configuration.
+    ///
+    /// # Parameters
+    /// - `config`: The configuration.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_config(&mut self, config: &Config) -> Result<(), AppError> {
+        self.nvs
+            .set_blob(KEY_CONFIG, &config.encode_stored())
+            .map_err(|e| AppError::StorageError(format!("Failed to store configuration: {:?}", e)))
+    }
+
+    /// Read the display orientation.
+    ///
+    /// # Returns
+    /// The stored orientation, or the default if none is stored or it is invalid.
+    pub fn orientation(&self) -> Orientation {
+        match self.nvs.get_u8(KEY_ORIENTATION) {
+            Ok(value) => value.and_then(Orientation::from_index).unwrap_or_default(),
+            Err(e) => {
+                warn!("Failed to read display orientation: {:?}", e);
+                Orientation::default()
+            }
+        }
+    }
+
+    /// Store the display orientation.
+    ///
+    /// # Parameters
+    /// - `orientation`: The orientation.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_orientation(&mut self, orientation: Orientation) -> Result<(), AppError> {
+        self.nvs
+            .set_u8(KEY_ORIENTATION, orientation.index())
+            .map_err(|e| {
+                AppError::StorageError(format!("Failed to store display orientation: {:?}", e))
+            })
+    }
+
+    /// Read the Wi-Fi credentials.
+    ///
+    /// # Returns
+    /// The stored credentials, or `None` if none are stored or they are invalid.
+    pub fn wifi_credentials(&self) -> Option<Credentials> {
+        let mut buffer = [0u8; CREDENTIALS_MAX_LEN];
+
+        match self.nvs.get_blob(KEY_WIFI, &mut buffer) {
+            Ok(Some(payload)) => Credentials::decode(payload)
+                .map_err(|e| warn!("Ignoring stored Wi-Fi credentials: {e}"))
+                .ok(),
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Failed to read Wi-Fi credentials: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Store or forget the Wi-Fi credentials.
+    ///
+    /// # Parameters
+    /// - `credentials`: The credentials, `None` to forget the network.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_wifi_credentials(
+        &mut self,
+        credentials: Option<&Credentials>,
+    ) -> Result<(), AppError> {
+        let result = match credentials {
+            Some(credentials) => self.nvs.set_blob(KEY_WIFI, &credentials.encode()),
+            None => self.nvs.remove(KEY_WIFI).map(|_| ()),
+        };
+
+        result.map_err(|e| {
+            AppError::StorageError(format!("Failed to store Wi-Fi credentials: {:?}", e))
+        })
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{
+    clock::{uptime_ms, SharedClock},
+    error::AppError,
+};
+use esp_idf_svc::sntp::{EspSntp, SntpConf};
+use log::info;
+use std::time::Duration;
+
+/// Keeps the system clock in sync with an SNTP server.
+///
+/// The SNTP client polls the server in the background once Wi-Fi is connected,
+/// and sets the clock on every response. Until the first response, the clock
+/// stays as it was, unset or set over BLE.
+pub struct TimeSync {
+    /// The SNTP client, stopped when dropped.
+    _sntp: EspSntp<'static>,
+}
+
+/// The time synchronization implementation.
+impl TimeSync {
+    /// Start synchronizing with a server.
+    ///
+    /// # Parameters
+    /// - `server`: The SNTP server name.
+    /// - `clock`: The system clock to set.
+    ///
+    /// # Returns
+    /// The time synchronization.
+    pub fn new(server: &str, clock: SharedClock) -> Result<Self, AppError> {
+        info!("Synchronizing time with {}", server);
+
+        let mut conf = SntpConf::default();
+        conf.servers[0] = server;
+
+        let sntp = EspSntp::new_with_callback(&conf, move |since_epoch: Duration| {
+            info!(
+                "Time synchronized: {} s since the epoch",
+                since_epoch.as_secs()
+            );
+            clock
+                .lock()
+                .unwrap()
+                .set_utc(since_epoch.as_millis() as i64, uptime_ms());
+        })
+        .map_err(|e| AppError::TimeError(format!("Failed to start SNTP: {:?}", e)))?;
+
+        Ok(Self { _sntp: sntp })
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    clock::{uptime_ms, SharedClock},
+    error::AppError,
+};
+use esp_idf_svc::{
+    http::{
+        server::{Configuration, EspHttpConnection, EspHttpServer, Request},
+        Method,
+    },
+    io::{EspIOError, Read, Write},
+    sys::esp_get_free_heap_size,
+};
+use log::{info, warn};
+use scd41_core::{
+    api::{
+        self, ApiError, Current, Route, Status, DASHBOARD_PAGE, HTML_CONTENT_TYPE,
+        JSON_CONTENT_TYPE, MAX_BODY_LEN,
+    },
+    config::Config,
+    history::History,
+    metrics::{self, Metrics},
+};
+use std::sync::{Arc, Mutex};
+
+/// Stack size of the server task, with room for building the history document.
+const STACK_SIZE: usize = 10 * 1024;
+
+/// The state shared with the request handlers.
+struct State {
+    /// The latest measurement.
+    current: Option<Current>,
+
+    /// The configuration reported to clients.
+    config: Config,
+
+    /// The device identity and connectivity, without the live values.
+    status: Option<Status>,
+
+    /// The device metrics, without the live values.
+    metrics: Option<Metrics>,
+
+    /// Configuration written by a client, not yet applied.
+    pending_config: Option<Config>,
+}
+
+/// The web server with the JSON API and the dashboard.
+pub struct WebServer {
+    /// The server, stopped when dropped.
+    _server: EspHttpServer<'static>,
+
+    /// The state shared with the request handlers.
+    state: Arc<Mutex<State>>,
+}
+
+/// The web server implementation.
+impl WebServer {
+    /// Start the server on port 80.
+    ///
+    /// # Parameters
+    /// - `config`: The current configuration.
+    /// - `history`: The measurement history.
+    /// - `clock`: The system clock.
+    ///
+    /// # Returns
+    /// The server.
+    pub fn new(
+        config: Config,
+        history: Arc<Mutex<History>>,
+        clock: SharedClock,
+    ) -> Result<Self, AppError> {
+        let state = Arc::new(Mutex::new(State {
+            current: None,
+            config,
+            status: None,
+            metrics: None,
+            pending_config: None,
+        }));
+
+        let mut server = EspHttpServer::new(&Configuration {
+            stack_size: STACK_SIZE,
+            uri_match_wildcard: true,
+            ..Default::default()
+        })
+        .map_err(|e| AppError::HttpError(format!("Failed to start web server: {:?}", e)))?;
+
+        for method in [Method::Get, Method::Put] {
+            let (state, history, clock) =
+                (Arc::clone(&state), Arc::clone(&history), Arc::clone(&clock));
+            server
+                .fn_handler("/*", method, move |request| {
+                    handle(request, &state, &history, &clock)
+                })
+                .map_err(|e| {
+                    AppError::HttpError(format!("Failed to register web handler: {:?}", e))
+                })?;
+        }
+        info!("Web server ready");
+
+        Ok(Self {
+            _server: server,
+            state,
+        })
+    }
+
+    /// Set the latest measurement.
+    ///
+    /// # Parameters
+    /// - `current`: The measurement.
+    pub fn set_current(&self, current: Current) {
+        self.state.lock().unwrap().current = Some(current);
+    }
+
+    /// Set the configuration reported to clients.
+    ///
+    /// # Parameters
+    /// - `config`: The configuration.
+    pub fn set_config(&self, config: Config) {
+        self.state.lock().unwrap().config = config;
+    }
+
+    /// Set the device identity and connectivity; the uptime, free heap and time
+    /// are filled in per request.
+    ///
+    /// # Parameters
+    /// - `status`: The status.
+    pub fn set_status(&self, status: Status) {
+        self.state.lock().unwrap().status = Some(status);
+    }
+
+    /// Set the device metrics; the uptime and free heap are filled in per
+    /// request.
+    ///
+    /// # Parameters
+    /// - `metrics`: The metrics.
+    pub fn set_metrics(&self, metrics: Metrics) {
+        self.state.lock().unwrap().metrics = Some(metrics);
+    }
+
+    /// Take the configuration written by a client, if any.
+    ///
+    /// # Returns
+    /// The configuration to apply.
+    pub fn take_config_request(&self) -> Option<Config> {
+        self.state.lock().unwrap().pending_config.take()
+    }
+}
+
+/// Answer a request.
+///
+/// # Parameters
+/// - `request`: The request.
+/// - `state`: The shared state.
+/// - `history`: The measurement history.
+/// - `clock`: The system clock.
+///
+/// # Returns
+/// The result of the operation.
+fn handle(
+    mut request: Request<&mut EspHttpConnection>,
+    state: &Mutex<State>,
+    history: &Mutex<History>,
+    clock: &SharedClock,
+) -> Result<(), EspIOError> {
+    let method = match request.method() {
+        Method::Get => api::Method::Get,
+        Method::Put => api::Method::Put,
+        _ => api::Method::Other,
+    };
+
+    let route = Route::resolve(method, request.uri()).and_then(|route| {
+        let authorization = request.header("Authorization");
+        route
+            .authorize(&state.lock().unwrap().config, authorization)
+            .map(|()| route)
+    });
+    let result = match route {
+        Ok(Route::Dashboard) => {
+            return respond(request, 200, HTML_CONTENT_TYPE, DASHBOARD_PAGE);
+        }
+        Ok(Route::Current) => api::current_json(state.lock().unwrap().current.as_ref()),
+        Ok(Route::History(query)) => Ok(api::history_json(&history.lock().unwrap(), &query)),
+        Ok(Route::Config) => Ok(api::config_json(&state.lock().unwrap().config)),
+        Ok(Route::UpdateConfig) => read_body(&mut request).and_then(|body| {
+            let mut state = state.lock().unwrap();
+            let config = api::update_config(&state.config, &body)?;
+            info!("Configuration {:?} written over HTTP", config);
+            state.config = config.clone();
+            state.pending_config = Some(config);
+            Ok(api::config_json(&state.config))
+        }),
+        Ok(Route::Status) => {
+            let status = state.lock().unwrap().status.clone();
+            let uptime_ms = uptime_ms();
+            let unix_time_s = clock.lock().unwrap().unix_ms(uptime_ms).map(|ms| ms / 1000);
+            status
+                .map(|status| {
+                    Status {
+                        uptime_s: uptime_ms / 1000,
+                        free_heap: unsafe { esp_get_free_heap_size() },
+                        unix_time_s,
+                        ..status
+                    }
+                    .encode()
+                })
+                .ok_or(ApiError::Unavailable("Starting up"))
+        }
+        Ok(Route::Metrics) => {
+            let metrics = state.lock().unwrap().metrics.clone();
+            match metrics {
+                Some(metrics) => {
+                    let body = Metrics {
+                        uptime_s: uptime_ms() / 1000,
+                        free_heap: unsafe { esp_get_free_heap_size() },
+                        ..metrics
+                    }
+                    .encode();
+                    return respond(request, 200, metrics::CONTENT_TYPE, &body);
+                }
+                None => Err(ApiError::Unavailable("Starting up")),
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(body) => respond(request, 200, JSON_CONTENT_TYPE, &body),
+        Err(e) => {
+            warn!("HTTP {} {}: {}", e.status(), request.uri(), e);
+            let status = e.status();
+            respond(request, status, JSON_CONTENT_TYPE, &e.body())
+        }
+    }
+}
+
+/// Read a request body of up to [`MAX_BODY_LEN`] bytes.
+///
+/// # Parameters
+/// - `request`: The request.
+///
+/// # Returns
+/// The body, or an error if it is longer.
+fn read_body(request: &mut Request<&mut EspHttpConnection>) -> Result<Vec<u8>, ApiError> {
+    let mut body = vec![0u8; MAX_BODY_LEN + 1];
+    let mut len = 0;
+    while len < body.len() {
+        match request.read(&mut body[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) => {
+                return Err(ApiError::BadRequest(format!(
+                    "Failed to read body: {:?}",
+                    e
+                )))
+            }
+        }
+    }
+    if len > MAX_BODY_LEN {
+        return Err(ApiError::PayloadTooLarge);
+    }
+
+    body.truncate(len);
+    Ok(body)
+}
+
+/// Send a response.
+///
+/// # Parameters
+/// - `request`: The request.
+/// - `status`: The status code.
+/// - `content_type`: The content type.
+/// - `body`: The body.
+///
+/// # Returns
+/// The result of the operation.
+fn respond(
+    request: Request<&mut EspHttpConnection>,
+    status: u16,
+    content_type: &str,
+    body: &str,
+) -> Result<(), EspIOError> {
+    let mut headers = vec![
+        ("Content-Type", content_type),
+        ("Cache-Control", "no-store"),
+    ];
+    if status == 401 {
+        headers.push(("WWW-Authenticate", "Bearer"));
+    }
+
+    request
+        .into_response(status, None, &headers)?
+        .write_all(body.as_bytes())
+}
@@ -0,0 +1,404 @@
+use crate::error::AppError;
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    hal::modem::WifiModem,
+    http::{
+        server::{Configuration as HttpConfiguration, EspHttpConnection, EspHttpServer, Request},
+        Method,
+    },
+    io::{EspIOError, Read, Write},
+    nvs::{EspNvsPartition, NvsDefault},
+    sys::EspError,
+    wifi::{AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration, EspWifi},
+};
+use log::{info, warn};
+use scd41_core::{
+    captive::{self, SAVED_PAGE, SETUP_PAGE},
+    wifi::{Credentials, Reconnect, WifiStatus},
+};
+use std::{
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Prefix of the setup network name, followed by the end of the MAC address.
+const AP_SSID_PREFIX: &str = "CO2-";
+
+/// Largest setup form body accepted, with room for every field percent-encoded.
+const FORM_MAX_LEN: usize = 512;
+
+/// How often the DNS responder checks whether the portal has closed.
+const DNS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Stack size of the DNS responder thread.
+const DNS_STACK_SIZE: usize = 4096;
+
+/// The setup portal: a web server with the setup form and a DNS responder
+/// pointing every name at it.
+struct Portal {
+    /// The web server, stopped when dropped.
+    _server: EspHttpServer<'static>,
+
+    /// Tells the DNS responder to stop.
+    stop: Arc<AtomicBool>,
+}
+
+/// The `Drop` trait implementation for `Portal`.
+impl Drop for Portal {
+    /// Stop the DNS responder.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The Wi-Fi station, with a setup portal while no network is configured.
+pub struct WifiManager {
+    /// The Wi-Fi driver, sharing the modem with BLE.
+    wifi: EspWifi<'static>,
+
+    /// The network to join.
+    credentials: Option<Credentials>,
+
+    /// When to try to join the network.
+    reconnect: Reconnect,
+
+    /// The setup portal, open while no network is configured.
+    portal: Option<Portal>,
+
+    /// Credentials submitted through the setup portal, not yet applied.
+    portal_credentials: Arc<Mutex<Option<Credentials>>>,
+
+    /// The name of the setup network.
+    ap_ssid: String,
+}
+
+/// The Wi-Fi manager implementation.
+impl WifiManager {
+    /// Start Wi-Fi, joining the configured network or opening the setup portal.
+    ///
+    /// # Parameters
+    /// - `modem`: The Wi-Fi half of the modem, shared with BLE.
+    /// - `nvs`: The NVS partition, for the driver's calibration data.
+    /// - `credentials`: The network to join, if configured.
+    ///
+    /// # Returns
+    /// The Wi-Fi manager.
+    pub fn new(
+        modem: WifiModem<'static>,
+        nvs: EspNvsPartition<NvsDefault>,
+        credentials: Option<Credentials>,
+    ) -> Result<Self, AppError> {
+        info!("Initializing Wi-Fi");
+
+        let sysloop = EspSystemEventLoop::take()
+            .map_err(|e| AppError::WifiError(format!("Failed to take event loop: {:?}", e)))?;
+        let wifi = EspWifi::new(modem, sysloop, Some(nvs))
+            .map_err(|e| AppError::WifiError(format!("Failed to initialize Wi-Fi: {:?}", e)))?;
+
+        let mac = wifi
+            .ap_netif()
+            .get_mac()
+            .map_err(|e| AppError::WifiError(format!("Failed to read Wi-Fi address: {:?}", e)))?;
+        let ap_ssid = format!("{}{:02X}{:02X}", AP_SSID_PREFIX, mac[4], mac[5]);
+
+        let mut manager = Self {
+            wifi,
+            credentials,
+            reconnect: Reconnect::default(),
+            portal: None,
+            portal_credentials: Arc::new(Mutex::new(None)),
+            ap_ssid,
+        };
+        manager.configure()?;
+
+        Ok(manager)
+    }
+
+    /// Join a new network, or forget the network and open the setup portal.
+    ///
+    /// # Parameters
+    /// - `credentials`: The network to join, `None` to forget it.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    pub fn set_credentials(&mut self, credentials: Option<Credentials>) -> Result<(), AppError> {
+        self.credentials = credentials;
+        self.configure()
+    }
+
+    /// Take the credentials submitted through the setup portal, if any.
+    ///
+    /// # Returns
+    /// The submitted credentials.
+    pub fn take_portal_credentials(&self) -> Option<Credentials> {
+        self.portal_credentials.lock().unwrap().take()
+    }
+
+    /// Start a connection attempt when the link is down and the backoff has
+    /// elapsed.
+    ///
+    /// # Parameters
+    /// - `now_ms`: The current time in milliseconds since boot.
+    pub fn poll(&mut self, now_ms: u64) {
+        if self.credentials.is_none() {
+            return;
+        }
+
+        let connected = self.station_ip().is_some();
+        if self.reconnect.poll(now_ms, connected) {
+            info!("Joining Wi-Fi network");
+            if let Err(e) = self.wifi.connect() {
+                warn!("Failed to start joining Wi-Fi network: {:?}", e);
+            }
+        }
+    }
+
+    /// Get the connectivity shown on the display and published over BLE.
+    ///
+    /// # Returns
+    /// The Wi-Fi status.
+    pub fn status(&self) -> WifiStatus {
+        if self.credentials.is_none() {
+            return match self.portal {
+                Some(_) => WifiStatus::Setup {
+                    ap_ssid: self.ap_ssid.clone(),
+                },
+                None => WifiStatus::Off,
+            };
+        }
+
+        match self.station_ip() {
+            Some(ip) => WifiStatus::Connected { ip },
+            None if self.reconnect.is_connecting() => WifiStatus::Connecting,
+            None => WifiStatus::Offline,
+        }
+    }
+
+    /// Get the station address.
+    ///
+    /// # Returns
+    /// The IPv4 address, or `None` unless the station is connected and has one.
+    fn station_ip(&self) -> Option<[u8; 4]> {
+        if !self.wifi.is_connected().unwrap_or(false) {
+            return None;
+        }
+
+        let ip = self.wifi.sta_netif().get_ip_info().ok()?.ip.octets();
+        (ip != [0; 4]).then_some(ip)
+    }
+
+    /// Restart the driver in station mode with the configured network, or in
+    /// access point mode with the setup portal.
+    ///
+    /// # Returns
+    /// The result of the operation.
+    fn configure(&mut self) -> Result<(), AppError> {
+        self.portal = None;
+        if self.wifi.is_started().unwrap_or(false) {
+            self.wifi
+                .stop()
+                .map_err(|e| AppError::WifiError(format!("Failed to stop Wi-Fi: {:?}", e)))?;
+        }
+
+        let configuration = match &self.credentials {
+            Some(credentials) => {
+                info!("Wi-Fi station mode, network {:?}", credentials.ssid);
+                Configuration::Client(client_configuration(credentials)?)
+            }
+            None => {
+                info!("Wi-Fi setup portal on network {}", self.ap_ssid);
+                Configuration::AccessPoint(AccessPointConfiguration {
+                    ssid: self.ap_ssid.as_str().try_into().map_err(|_| {
+                        AppError::WifiError(format!("Invalid setup network {}", self.ap_ssid))
+                    })?,
+                    auth_method: AuthMethod::None,
+                    ..Default::default()
+                })
+            }
+        };
+
+        self.wifi
+            .set_configuration(&configuration)
+            .map_err(|e| AppError::WifiError(format!("Failed to configure Wi-Fi: {:?}", e)))?;
+        self.wifi
+            .start()
+            .map_err(|e| AppError::WifiError(format!("Failed to start Wi-Fi: {:?}", e)))?;
+
+        if self.credentials.is_some() {
+            self.reconnect.restart();
+        } else {
+            self.portal = Some(self.open_portal()?);
+        }
+
+        Ok(())
+    }
+
+    /// Open the setup portal on the access point.
+    ///
+    /// # Returns
+    /// The portal, closed when dropped.
+    fn open_portal(&self) -> Result<Portal, AppError> {
+        let ip = self
+            .wifi
+            .ap_netif()
+            .get_ip_info()
+            .map_err(|e| AppError::WifiError(format!("Failed to read portal address: {:?}", e)))?
+            .ip;
+        let portal_url = format!("http://{}/", ip);
+
+        let mut server = EspHttpServer::new(&HttpConfiguration {
+            uri_match_wildcard: true,
+            ..Default::default()
+        })
+        .map_err(|e| AppError::WifiError(format!("Failed to start portal server: {:?}", e)))?;
+
+        let portal_error =
+            |e: EspError| AppError::WifiError(format!("Failed to register portal page: {:?}", e));
+        server
+            .fn_handler("/", Method::Get, |request| -> Result<(), EspIOError> {
+                request.into_ok_response()?.write_all(SETUP_PAGE.as_bytes())
+            })
+            .map_err(portal_error)?;
+
+        let submitted = Arc::clone(&self.portal_credentials);
+        server
+            .fn_handler("/", Method::Post, move |request| {
+                submit_form(request, &submitted)
+            })
+            .map_err(portal_error)?;
+
+        // Operating systems probe well-known pages to detect a sign-in page;
+        // sending them to the form makes it pop up.
+        server
+            .fn_handler(
+                "/*",
+                Method::Get,
+                move |request| -> Result<(), EspIOError> {
+                    request
+                        .into_response(302, Some("Found"), &[("Location", portal_url.as_str())])?
+                        .flush()
+                },
+            )
+            .map_err(portal_error)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let socket = UdpSocket::bind("0.0.0.0:53")
+            .and_then(|socket| {
+                socket.set_read_timeout(Some(DNS_POLL_INTERVAL))?;
+                Ok(socket)
+            })
+            .map_err(|e| AppError::WifiError(format!("Failed to open DNS socket: {:?}", e)))?;
+        let dns_stop = Arc::clone(&stop);
+        thread::Builder::new()
+            .stack_size(DNS_STACK_SIZE)
+            .spawn(move || answer_dns(socket, ip.octets(), &dns_stop))
+            .map_err(|e| AppError::WifiError(format!("Failed to start DNS responder: {:?}", e)))?;
+
+        Ok(Portal {
+            _server: server,
+            stop,
+        })
+    }
+}
+
+/// Build the station configuration for a network.
+///
+/// # Parameters
+/// - `credentials`: The network.
+///
+/// # Returns
+/// The configuration, with WPA2 or later for a passphrase and no security without.
+fn client_configuration(credentials: &Credentials) -> Result<ClientConfiguration, AppError> {
+    let invalid = || AppError::WifiError(format!("Invalid credentials {:?}", credentials));
+
+    Ok(ClientConfiguration {
+        ssid: credentials
+            .ssid
+            .as_str()
+            .try_into()
+            .map_err(|_| invalid())?,
+        password: credentials
+            .password
+            .as_str()
+            .try_into()
+            .map_err(|_| invalid())?,
+        auth_method: if credentials.password.is_empty() {
+            AuthMethod::None
+        } else {
+            AuthMethod::WPA2Personal
+        },
+        ..Default::default()
+    })
+}
+
+/// Handle the setup form, keeping valid credentials for the device manager.
+///
+/// # Parameters
+/// - `request`: The form request.
+/// - `submitted`: Where to keep the credentials.
+///
+/// # Returns
+/// The result of the operation.
+fn submit_form(
+    mut request: Request<&mut EspHttpConnection>,
+    submitted: &Mutex<Option<Credentials>>,
+) -> Result<(), EspIOError> {
+    let mut body = [0u8; FORM_MAX_LEN + 1];
+    let mut len = 0;
+    while len < body.len() {
+        match request.read(&mut body[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    if len > FORM_MAX_LEN {
+        warn!("Rejected a setup form longer than {} bytes", FORM_MAX_LEN);
+        return request
+            .into_status_response(413)?
+            .write_all(b"Form too large");
+    }
+
+    match captive::parse_form(&body[..len]) {
+        Ok(credentials) => {
+            info!(
+                "Wi-Fi credentials {:?} submitted on the portal",
+                credentials
+            );
+            *submitted.lock().unwrap() = Some(credentials);
+            request.into_ok_response()?.write_all(SAVED_PAGE.as_bytes())
+        }
+        Err(e) => {
+            warn!("Rejected Wi-Fi credentials from the portal: {}", e);
+            request
+                .into_status_response(400)?
+                .write_all(e.to_string().as_bytes())
+        }
+    }
+}
+
+/// Answer DNS queries with the portal address until told to stop.
+///
+/// # Parameters
+/// - `socket`: The DNS socket, with a read timeout.
+/// - `ip`: The portal address.
+/// - `stop`: Set when the portal closes.
+fn answer_dns(socket: UdpSocket, ip: [u8; 4], stop: &AtomicBool) {
+    let mut query = [0u8; 512];
+
+    while !stop.load(Ordering::Relaxed) {
+        let Ok((len, peer)) = socket.recv_from(&mut query) else {
+            continue;
+        };
+
+        if let Some(response) = captive::dns_response(&query[..len], ip) {
+            if let Err(e) = socket.send_to(&response, peer) {
+                warn!("Failed to answer DNS query from {}: {:?}", peer, e);
+            }
+        }
+    }
+}